@@ -6,7 +6,7 @@ include!(concat!(env!("OUT_DIR"), "/main.rs"));
 
 use fon::{mono::Mono32, Audio, Sink};
 use pasts::{prelude::*, Join};
-use wavy::{Microphone, MicrophoneStream, Speakers, SpeakersSink};
+use wavy::{AudioError, Microphone, MicrophoneStream, Speakers, SpeakersSink};
 
 /// Shared state between tasks on the thread.
 struct App {
@@ -20,13 +20,27 @@ struct App {
 
 impl App {
     /// Speaker is ready to play more audio.
-    fn play(&mut self, mut sink: SpeakersSink<Mono32>) -> Poll<()> {
+    fn play(
+        &mut self,
+        sink: Result<SpeakersSink<Mono32>, AudioError>,
+    ) -> Poll<()> {
+        let mut sink = match sink {
+            Ok(sink) => sink,
+            Err(_) => return Ready(()),
+        };
         sink.stream(self.buffer.drain());
         Pending
     }
 
     /// Microphone has recorded some audio.
-    fn record(&mut self, stream: MicrophoneStream<Mono32>) -> Poll<()> {
+    fn record(
+        &mut self,
+        stream: Result<MicrophoneStream<Mono32>, AudioError>,
+    ) -> Poll<()> {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => return Ready(()),
+        };
         self.buffer.extend(stream);
         Pending
     }
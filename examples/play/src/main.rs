@@ -6,7 +6,7 @@ include!(concat!(env!("OUT_DIR"), "/main.rs"));
 use fon::{stereo::Stereo32, Sink};
 use pasts::{prelude::*, Join};
 use twang::{Fc, Signal, Synth};
-use wavy::{Speakers, SpeakersSink};
+use wavy::{AudioError, Speakers, SpeakersSink};
 
 /// Shared state between tasks on the thread.
 struct App {
@@ -18,7 +18,14 @@ struct App {
 
 impl App {
     /// Speaker is ready to play more audio.
-    fn play(&mut self, mut sink: SpeakersSink<Stereo32>) -> Poll<()> {
+    fn play(
+        &mut self,
+        sink: Result<SpeakersSink<Stereo32>, AudioError>,
+    ) -> Poll<()> {
+        let mut sink = match sink {
+            Ok(sink) => sink,
+            Err(_) => return Ready(()),
+        };
         sink.stream(&mut self.synth);
         Pending
     }
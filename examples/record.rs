@@ -5,7 +5,7 @@ include!(concat!(env!("OUT_DIR"), "/main.rs"));
 
 use fon::{mono::Mono32, Audio, Frame};
 use pasts::{prelude::*, Join};
-use wavy::{Microphone, MicrophoneStream};
+use wavy::{AudioError, Microphone, MicrophoneStream};
 
 /// Shared state between tasks on the thread.
 struct App {
@@ -17,7 +17,14 @@ struct App {
 
 impl App {
     /// Event loop.  Return false to stop program.
-    fn record(&mut self, stream: MicrophoneStream<Mono32>) -> Poll<()> {
+    fn record(
+        &mut self,
+        stream: Result<MicrophoneStream<Mono32>, AudioError>,
+    ) -> Poll<()> {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => return Ready(()),
+        };
         self.buffer.extend(stream);
         if self.buffer.len() >= 48_000 * 10 {
             return Ready(());
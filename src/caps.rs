@@ -0,0 +1,88 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use std::ops::RangeInclusive;
+
+use crate::consts;
+
+/// Capabilities of an audio device, from [`Speakers::caps()`] or
+/// [`Microphone::caps()`], for choosing a configuration the device supports.
+///
+/// Only ALSA reports ranges of sample rates and periods (and tests which
+/// standard sample rates are supported).  Other platforms
+/// (and PipeWire) run each device at a single rate and period, resampling as
+/// needed, so the minimum and maximum are the same.
+///
+/// [`Speakers::caps()`]: crate::Speakers::caps
+/// [`Microphone::caps()`]: crate::Microphone::caps
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceCaps {
+    supported: u8,
+    sample_rates: RangeInclusive<u32>,
+    standard_rates: Vec<u32>,
+    periods: RangeInclusive<usize>,
+}
+
+impl DeviceCaps {
+    pub(crate) fn new(
+        supported: u8,
+        sample_rates: RangeInclusive<u32>,
+        standard_rates: Vec<u32>,
+        periods: RangeInclusive<usize>,
+    ) -> Self {
+        Self {
+            supported,
+            sample_rates,
+            standard_rates,
+            periods,
+        }
+    }
+
+    /// Capabilities of a device that runs at one `sample_rate` (the preferred
+    /// 48 kHz until configured) and `period` (the default period if `0`).
+    pub(crate) fn fixed(
+        supported: u8,
+        sample_rate: Option<f64>,
+        period: u16,
+    ) -> Self {
+        let sample_rate = sample_rate
+            .map_or(consts::SAMPLE_RATE.into(), |rate| rate.round() as u32);
+        let period =
+            usize::from(if period == 0 { consts::PERIOD } else { period });
+        Self::new(
+            supported,
+            sample_rate..=sample_rate,
+            vec![sample_rate],
+            period..=period,
+        )
+    }
+
+    /// Get the numbers of channels the device supports (from 1 to 8).
+    pub fn channels(&self) -> impl Iterator<Item = u8> {
+        let supported = self.supported;
+        (1..=8).filter(move |count| supported & (1 << (count - 1)) != 0)
+    }
+
+    /// Get the range of sample rates (in Hz) the device supports.
+    pub fn sample_rates(&self) -> RangeInclusive<u32> {
+        self.sample_rates.clone()
+    }
+
+    /// Get the standard sample rates (from 8 kHz to 384 kHz) the device
+    /// supports, or the single rate it runs at.  Devices may support rates
+    /// between these that aren't listed.
+    pub fn standard_sample_rates(&self) -> &[u32] {
+        &self.standard_rates
+    }
+
+    /// Get the range of period sizes (in frames) the device supports.
+    pub fn periods(&self) -> RangeInclusive<usize> {
+        self.periods.clone()
+    }
+}
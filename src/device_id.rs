@@ -0,0 +1,56 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use std::{
+    convert::Infallible,
+    fmt::{Display, Formatter, Result},
+    str::FromStr,
+};
+
+/// Stable, persistent identifier for an audio device.
+///
+/// Unlike the human-readable name from `Display`, the ID stays the same across
+/// restarts (on Linux it's the PipeWire node name or ALSA PCM name, on Windows
+/// the endpoint ID string), so it can be stored in a config file and matched
+/// against the devices returned from `query()` later (or found with
+/// [`Speakers::find_by_id()`](crate::Speakers::find_by_id)).
+///
+/// ```
+/// use wavy::{DeviceId, Speakers};
+///
+/// let saved: DeviceId = "default".parse().unwrap();
+/// let speakers = Speakers::find_by_id(&saved);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DeviceId(String);
+
+impl DeviceId {
+    pub(crate) fn new(id: &str) -> Self {
+        Self(id.to_string())
+    }
+
+    /// Get the ID as a string slice.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl Display for DeviceId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DeviceId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(Self::new(s))
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use crate::DeviceId;
+
+/// An audio device from [`Speakers::list()`] or [`Microphone::list()`], which
+/// hasn't been opened.
+///
+/// [`Speakers::list()`]: crate::Speakers::list
+/// [`Microphone::list()`]: crate::Microphone::list
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceInfo {
+    id: DeviceId,
+    name: String,
+}
+
+impl DeviceInfo {
+    pub(crate) fn new(id: &str, name: &str) -> Self {
+        Self {
+            id: DeviceId::new(id),
+            name: name.to_string(),
+        }
+    }
+
+    /// Get the stable ID of the device, for opening it with
+    /// [`Speakers::find_by_id()`](crate::Speakers::find_by_id) or
+    /// [`Microphone::find_by_id()`](crate::Microphone::find_by_id).
+    pub fn id(&self) -> &DeviceId {
+        &self.id
+    }
+
+    /// Get the human-readable name of the device.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Which ALSA PCMs [`Speakers::list()`] and [`Microphone::list()`] include.
+///
+/// ALSA lists several PCMs for each device of a sound card (`front:`,
+/// `surround51:`, `dmix:`, `hw:` and so on), as well as plugins such as
+/// `dsnoop`.  PipeWire and other platforms list each device once, so the
+/// filter has no effect there.
+///
+/// [`Speakers::list()`]: crate::Speakers::list
+/// [`Microphone::list()`]: crate::Microphone::list
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeviceFilter {
+    /// The default device, and each device of each sound card once (the
+    /// default).
+    Cards,
+    /// Only hardware PCMs (`hw:` and `plughw:`).
+    Hardware,
+    /// Every PCM, including plugins such as `dmix` and `dsnoop`.
+    All,
+}
+
+impl Default for DeviceFilter {
+    fn default() -> Self {
+        Self::Cards
+    }
+}
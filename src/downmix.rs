@@ -0,0 +1,162 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use fon::chan::Ch32;
+
+/// Gain of -3 dB, as a linear multiplier.
+const MINUS_3DB: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// How to mix frames down for speakers with fewer channels, enabled with
+/// [`Speakers::set_downmix()`](crate::Speakers::set_downmix).
+///
+/// 5.1 is mixed to stereo with the center and surround channels added to
+/// the front channels at their gains (and the LFE channel at its gain), then
+/// clamped.  Stereo is mixed to mono by averaging.  Gains are linear
+/// multipliers, by default -3 dB (about `0.707`) for the center and surround
+/// channels, with the LFE channel discarded (`0.0`, use about `0.316` for
+/// -10 dB).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Downmix {
+    center: f32,
+    surround: f32,
+    lfe: f32,
+}
+
+impl Default for Downmix {
+    fn default() -> Self {
+        Self {
+            center: MINUS_3DB,
+            surround: MINUS_3DB,
+            lfe: 0.0,
+        }
+    }
+}
+
+impl Downmix {
+    /// Set the gain of the center channel in each front channel.
+    pub fn with_center_gain(mut self, gain: f32) -> Self {
+        self.center = gain;
+        self
+    }
+
+    /// Set the gain of each surround channel in the front channel on its
+    /// side.
+    pub fn with_surround_gain(mut self, gain: f32) -> Self {
+        self.surround = gain;
+        self
+    }
+
+    /// Set the gain of the LFE channel in each front channel.
+    pub fn with_lfe_gain(mut self, gain: f32) -> Self {
+        self.lfe = gain;
+        self
+    }
+
+    /// Mix the channels of a frame (`from`, in fon order) into a frame with
+    /// fewer channels (`to`, mono or stereo).
+    pub(crate) fn mix(&self, from: &[Ch32], to: &mut [Ch32]) {
+        let mut chans = [0.0f32; 8];
+        for (chan, &sample) in chans.iter_mut().zip(from) {
+            *chan = sample.into();
+        }
+        let [left, right, center, lfe, left_back, right_back, ..] = chans;
+        let (left, right) = match from.len() {
+            6 => {
+                let shared = self.center * center + self.lfe * lfe;
+                (
+                    left + shared + self.surround * left_back,
+                    right + shared + self.surround * right_back,
+                )
+            }
+            1 => (left, left),
+            _ => (left, right),
+        };
+        match to {
+            [mono] => {
+                *mono = Ch32::new(((left + right) / 2.0).clamp(-1.0, 1.0))
+            }
+            [l, r] => {
+                *l = Ch32::new(left.clamp(-1.0, 1.0));
+                *r = Ch32::new(right.clamp(-1.0, 1.0));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mix(downmix: Downmix, from: &[f32], channels: usize) -> Vec<f32> {
+        let from: Vec<Ch32> = from.iter().map(|&chan| chan.into()).collect();
+        let mut to = vec![Ch32::default(); channels];
+        downmix.mix(&from, &mut to);
+        to.into_iter().map(f32::from).collect()
+    }
+
+    fn assert_near(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn surround_to_stereo() {
+        let downmix = Downmix::default();
+        let half = 0.5 * MINUS_3DB;
+        // Front channels pass through.
+        let out = mix(downmix, &[0.5, -0.25, 0.0, 0.0, 0.0, 0.0], 2);
+        assert_near(&out, &[0.5, -0.25]);
+        // Center at -3 dB in both front channels.
+        let out = mix(downmix, &[0.0, 0.0, 0.5, 0.0, 0.0, 0.0], 2);
+        assert_near(&out, &[half, half]);
+        // Surround channels at -3 dB on their own side.
+        let out = mix(downmix, &[0.0, 0.0, 0.0, 0.0, 0.5, 0.0], 2);
+        assert_near(&out, &[half, 0.0]);
+        let out = mix(downmix, &[0.0, 0.0, 0.0, 0.0, 0.0, 0.5], 2);
+        assert_near(&out, &[0.0, half]);
+        // LFE discarded by default.
+        let out = mix(downmix, &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0], 2);
+        assert_near(&out, &[0.0, 0.0]);
+    }
+
+    #[test]
+    fn custom_gains() {
+        let downmix = Downmix::default()
+            .with_center_gain(1.0)
+            .with_surround_gain(0.5)
+            .with_lfe_gain(0.25);
+        let out = mix(downmix, &[0.0, 0.0, 0.25, 0.0, 0.0, 0.0], 2);
+        assert_near(&out, &[0.25, 0.25]);
+        let out = mix(downmix, &[0.0, 0.0, 0.0, 0.0, 0.5, 0.5], 2);
+        assert_near(&out, &[0.25, 0.25]);
+        let out = mix(downmix, &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0], 2);
+        assert_near(&out, &[0.25, 0.25]);
+    }
+
+    #[test]
+    fn stereo_to_mono() {
+        let downmix = Downmix::default();
+        assert_near(&mix(downmix, &[0.25, 0.75], 1), &[0.5]);
+        assert_near(&mix(downmix, &[1.0, -1.0], 1), &[0.0]);
+    }
+
+    #[test]
+    fn clamped() {
+        let downmix = Downmix::default();
+        let out = mix(downmix, &[1.0; 6], 2);
+        assert_near(&out, &[1.0, 1.0]);
+        let out = mix(downmix, &[-1.0; 6], 2);
+        assert_near(&out, &[-1.0, -1.0]);
+        let out = mix(downmix, &[1.0; 6], 1);
+        assert_near(&out, &[1.0]);
+    }
+}
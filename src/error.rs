@@ -0,0 +1,133 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use std::fmt::{Display, Formatter, Result};
+
+/// State of an audio stream, as reported by the platform.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StreamState {
+    /// Opened, but not configured
+    Open,
+    /// Configured
+    Setup,
+    /// Ready to start
+    Prepared,
+    /// Running
+    Running,
+    /// Stopped: underrun (playback) or overrun (capture) detected
+    Xrun,
+    /// Draining: running (playback) or stopped (capture)
+    Draining,
+    /// Paused
+    Paused,
+    /// Hardware is suspended
+    Suspended,
+    /// Hardware is disconnected
+    Disconnected,
+}
+
+/// An error that occurred while playing or recording audio.
+#[allow(variant_size_differences)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AudioError {
+    /// The sink or stream from the last event wasn't dropped before polling
+    /// the device again.
+    DeviceLocked,
+    /// The device can't be configured for this number of channels.
+    UnsupportedChannelCount {
+        /// Number of channels in the frame type.
+        requested: usize,
+        /// Bitflags for numbers of channels the device supports (bit 0 for
+        /// mono, bit 1 for stereo, etc.).
+        supported: u8,
+    },
+    /// The device was unplugged or otherwise went away.
+    DeviceDisconnected,
+    /// Recovering from an underrun or overrun failed.
+    XrunUnrecoverable,
+    /// An underrun or overrun happened, with
+    /// [`XrunPolicy::Fail`](crate::XrunPolicy::Fail).
+    Xrun,
+    /// The device ended up in a state it shouldn't be in (such as
+    /// [`StreamState::Suspended`], if it couldn't be resumed after the system
+    /// slept).
+    InvalidState(StreamState),
+    /// There is no device to open.
+    NoDevice,
+    /// The device is in use by another program (or another handle).
+    DeviceBusy,
+    /// The device exists, but can't be used for interleaved audio in any of
+    /// the accepted [`SampleFormat`](crate::SampleFormat)s: `F32`, `S32`,
+    /// `S24` or `S16`.
+    UnsupportedDevice,
+    /// The user (or browser) didn't allow access to the device.
+    PermissionDenied,
+    /// The platform's audio library couldn't be loaded (such as
+    /// `libasound.so` on Linux), or there is no backend for this platform.
+    BackendUnavailable,
+    /// A [`FileSource`](crate::FileSource) recorded all of its audio (and
+    /// isn't looping).
+    EndOfStream,
+}
+
+impl Display for AudioError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        use AudioError::*;
+        match self {
+            DeviceLocked => f.write_str("device polled before dropping sink"),
+            UnsupportedChannelCount {
+                requested,
+                supported,
+            } => {
+                write!(
+                    f,
+                    "unsupported channel count: {} (supports",
+                    requested
+                )?;
+                for count in (1..=8).filter(|c| supported & (1 << (c - 1)) != 0)
+                {
+                    write!(f, " {}", count)?;
+                }
+                f.write_str(")")
+            }
+            DeviceDisconnected => f.write_str("device disconnected"),
+            XrunUnrecoverable => f.write_str("failed to recover from xrun"),
+            Xrun => f.write_str("xrun"),
+            InvalidState(state) => write!(f, "invalid state: {:?}", state),
+            NoDevice => f.write_str("no device"),
+            DeviceBusy => f.write_str("device busy"),
+            UnsupportedDevice => f.write_str("unsupported device"),
+            PermissionDenied => f.write_str("permission denied"),
+            BackendUnavailable => f.write_str("audio backend unavailable"),
+            EndOfStream => f.write_str("end of stream"),
+        }
+    }
+}
+
+impl AudioError {
+    /// Check that a device with `supported` bitflags can use `channels`.
+    pub(crate) fn check_channels(
+        channels: usize,
+        supported: u8,
+    ) -> std::result::Result<(), Self> {
+        if (1..=8).contains(&channels) && supported & (1 << (channels - 1)) != 0
+        {
+            Ok(())
+        } else {
+            Err(Self::UnsupportedChannelCount {
+                requested: channels,
+                supported,
+            })
+        }
+    }
+}
+
+impl std::error::Error for AudioError {}
@@ -0,0 +1,377 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+//! AAudio bindings (Android 8.0, API 26+).
+
+#![allow(unsafe_code)]
+
+use std::{
+    collections::VecDeque,
+    os::raw::c_void,
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst},
+        Mutex,
+    },
+    task::Waker,
+};
+
+use fon::chan::Ch32;
+
+const AAUDIO_OK: i32 = 0;
+const AAUDIO_DIRECTION_OUTPUT: i32 = 0;
+const AAUDIO_DIRECTION_INPUT: i32 = 1;
+const AAUDIO_FORMAT_PCM_FLOAT: i32 = 2;
+const AAUDIO_SHARING_MODE_SHARED: i32 = 1;
+const AAUDIO_PERFORMANCE_MODE_LOW_LATENCY: i32 = 12;
+const AAUDIO_CALLBACK_RESULT_CONTINUE: i32 = 0;
+
+/// Called from a high priority thread when the stream needs data (playback)
+/// or has data (capture).
+type DataCallback =
+    unsafe extern "C" fn(*mut c_void, *mut c_void, *mut c_void, i32) -> i32;
+/// Called from another thread when the stream fails (usually disconnected).
+type ErrorCallback = unsafe extern "C" fn(*mut c_void, *mut c_void, i32);
+
+// Link to libaaudio
+dl_api::linker!(extern "C" AAudio "libaaudio.so" {
+    // Stream Builder
+    fn AAudio_createStreamBuilder(builder: *mut *mut c_void) -> i32;
+    fn AAudioStreamBuilder_setDirection(builder: *mut c_void, direction: i32)
+        -> ();
+    fn AAudioStreamBuilder_setSharingMode(builder: *mut c_void, mode: i32)
+        -> ();
+    fn AAudioStreamBuilder_setPerformanceMode(builder: *mut c_void, mode: i32)
+        -> ();
+    fn AAudioStreamBuilder_setFormat(builder: *mut c_void, format: i32) -> ();
+    fn AAudioStreamBuilder_setChannelCount(builder: *mut c_void, count: i32)
+        -> ();
+    fn AAudioStreamBuilder_setDataCallback(
+        builder: *mut c_void,
+        callback: DataCallback,
+        user_data: *mut c_void,
+    ) -> ();
+    fn AAudioStreamBuilder_setErrorCallback(
+        builder: *mut c_void,
+        callback: ErrorCallback,
+        user_data: *mut c_void,
+    ) -> ();
+    fn AAudioStreamBuilder_openStream(
+        builder: *mut c_void,
+        stream: *mut *mut c_void,
+    ) -> i32;
+    fn AAudioStreamBuilder_delete(builder: *mut c_void) -> i32;
+
+    // Stream
+    fn AAudioStream_requestStart(stream: *mut c_void) -> i32;
+    fn AAudioStream_requestStop(stream: *mut c_void) -> i32;
+    fn AAudioStream_close(stream: *mut c_void) -> i32;
+    fn AAudioStream_getSampleRate(stream: *mut c_void) -> i32;
+    fn AAudioStream_getFramesPerBurst(stream: *mut c_void) -> i32;
+    fn AAudioStream_getXRunCount(stream: *mut c_void) -> i32;
+});
+
+thread_local! {
+    static AAUDIO: Option<AAudio> = AAudio::new().ok();
+}
+
+/// Returns true if AAudio is available (API 26+).
+pub(crate) fn available() -> bool {
+    AAUDIO.with(|aaudio| aaudio.is_some())
+}
+
+/// Wakes a future from the AAudio callback thread.
+#[derive(Default)]
+pub(crate) struct Notify {
+    /// Set when the stream has processed a burst.
+    ready: AtomicBool,
+    /// Waker for the future waiting on the stream.
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Notify {
+    /// Returns true if the stream hasn't processed a burst since the last
+    /// call.  If so, the waker is registered to be woken up on the next one.
+    pub(crate) fn should_yield(&self, waker: &Waker) -> bool {
+        if self.ready.swap(false, SeqCst) {
+            return false;
+        }
+        *self.waker.lock().unwrap() = Some(waker.clone());
+        // Check again, in case processed before the waker was registered.
+        !self.ready.swap(false, SeqCst)
+    }
+
+    fn wake(&self) {
+        self.ready.store(true, SeqCst);
+        if let Ok(mut waker) = self.waker.try_lock() {
+            if let Some(waker) = waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// State shared with the AAudio callback thread.
+struct Shared {
+    /// Wakes the future when a burst has been processed.
+    notify: Notify,
+    /// Interleaved samples waiting to be played, or that were recorded.
+    queue: Mutex<VecDeque<f32>>,
+    /// Number of interleaved channels.
+    channels: usize,
+    /// Number of frames per burst.
+    burst: AtomicUsize,
+    /// Set if the stream errored (usually because the device went away).
+    disconnected: AtomicBool,
+    /// Capture instead of playback.
+    input: bool,
+}
+
+unsafe extern "C" fn data_callback(
+    _stream: *mut c_void,
+    user_data: *mut c_void,
+    audio_data: *mut c_void,
+    num_frames: i32,
+) -> i32 {
+    let shared = &*user_data.cast::<Shared>();
+    let frames = num_frames.max(0) as usize;
+    let count = frames * shared.channels;
+    let samples = audio_data.cast::<f32>();
+
+    shared.burst.store(frames, SeqCst);
+    // Don't block the real-time thread, skip the burst instead.
+    if let Ok(mut queue) = shared.queue.try_lock() {
+        if shared.input {
+            for i in 0..count {
+                queue.push_back(*samples.add(i));
+            }
+            // Drop the oldest samples if nobody is recording.
+            while queue.len() > 4 * count {
+                queue.pop_front();
+            }
+        } else {
+            for i in 0..count {
+                *samples.add(i) = queue.pop_front().unwrap_or(0.0);
+            }
+        }
+    } else if !shared.input {
+        for i in 0..count {
+            *samples.add(i) = 0.0;
+        }
+    }
+    shared.notify.wake();
+
+    AAUDIO_CALLBACK_RESULT_CONTINUE
+}
+
+unsafe extern "C" fn error_callback(
+    _stream: *mut c_void,
+    user_data: *mut c_void,
+    _error: i32,
+) {
+    let shared = &*user_data.cast::<Shared>();
+    shared.disconnected.store(true, SeqCst);
+    shared.notify.wake();
+}
+
+/// An AAudio playback or capture stream.
+pub(crate) struct Stream {
+    /// The `AAudioStream`.
+    stream: *mut c_void,
+    /// Leaked shared box
+    shared: *mut Shared,
+}
+
+impl std::fmt::Debug for Stream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Stream({:?})", self.stream)
+    }
+}
+
+impl Stream {
+    /// Open and start a low latency shared mode stream with a number of
+    /// channels, at the device's native sample rate.
+    pub(crate) fn open(input: bool, channels: u8) -> Option<Self> {
+        AAUDIO.with(|aaudio| unsafe {
+            let aaudio = aaudio.as_ref()?;
+            let shared = Box::into_raw(Box::new(Shared {
+                notify: Notify::default(),
+                queue: Mutex::new(VecDeque::new()),
+                channels: channels.into(),
+                burst: AtomicUsize::new(crate::consts::PERIOD.into()),
+                disconnected: AtomicBool::new(false),
+                input,
+            }));
+            let mut builder = ptr::null_mut();
+            if (aaudio.AAudio_createStreamBuilder)(&mut builder) != AAUDIO_OK {
+                drop(Box::from_raw(shared));
+                return None;
+            }
+            (aaudio.AAudioStreamBuilder_setDirection)(
+                builder,
+                if input {
+                    AAUDIO_DIRECTION_INPUT
+                } else {
+                    AAUDIO_DIRECTION_OUTPUT
+                },
+            );
+            (aaudio.AAudioStreamBuilder_setSharingMode)(
+                builder,
+                AAUDIO_SHARING_MODE_SHARED,
+            );
+            (aaudio.AAudioStreamBuilder_setPerformanceMode)(
+                builder,
+                AAUDIO_PERFORMANCE_MODE_LOW_LATENCY,
+            );
+            (aaudio.AAudioStreamBuilder_setFormat)(
+                builder,
+                AAUDIO_FORMAT_PCM_FLOAT,
+            );
+            (aaudio.AAudioStreamBuilder_setChannelCount)(
+                builder,
+                channels.into(),
+            );
+            (aaudio.AAudioStreamBuilder_setDataCallback)(
+                builder,
+                data_callback,
+                shared.cast(),
+            );
+            (aaudio.AAudioStreamBuilder_setErrorCallback)(
+                builder,
+                error_callback,
+                shared.cast(),
+            );
+            let mut stream = ptr::null_mut();
+            let result =
+                (aaudio.AAudioStreamBuilder_openStream)(builder, &mut stream);
+            (aaudio.AAudioStreamBuilder_delete)(builder);
+            if result != AAUDIO_OK {
+                drop(Box::from_raw(shared));
+                return None;
+            }
+            let burst = (aaudio.AAudioStream_getFramesPerBurst)(stream);
+            if burst > 0 {
+                (*shared).burst.store(burst as usize, SeqCst);
+            }
+            let stream = Self { stream, shared };
+            if (aaudio.AAudioStream_requestStart)(stream.stream) != AAUDIO_OK {
+                return None;
+            }
+            Some(stream)
+        })
+    }
+
+    fn shared(&self) -> &Shared {
+        unsafe { &*self.shared }
+    }
+
+    pub(crate) fn notify(&self) -> &Notify {
+        &self.shared().notify
+    }
+
+    /// Get the sample rate chosen by AAudio.
+    pub(crate) fn sample_rate(&self) -> Option<f64> {
+        AAUDIO.with(|aaudio| {
+            let aaudio = aaudio.as_ref()?;
+            let rate =
+                unsafe { (aaudio.AAudioStream_getSampleRate)(self.stream) };
+            (rate > 0).then(|| rate.into())
+        })
+    }
+
+    /// Get the number of frames AAudio processes at a time.
+    pub(crate) fn burst(&self) -> usize {
+        self.shared().burst.load(SeqCst)
+    }
+
+    /// Get the number of underruns or overruns since the stream was opened.
+    pub(crate) fn xrun_count(&self) -> usize {
+        AAUDIO.with(|aaudio| {
+            let aaudio = match aaudio.as_ref() {
+                Some(aaudio) => aaudio,
+                None => return 0,
+            };
+            let count =
+                unsafe { (aaudio.AAudioStream_getXRunCount)(self.stream) };
+            count.max(0) as usize
+        })
+    }
+
+    /// Get the number of frames queued, plus one burst for playback.
+    pub(crate) fn delay(&self) -> usize {
+        let shared = self.shared();
+        let queued = shared.queue.lock().unwrap().len() / shared.channels;
+        if shared.input {
+            queued
+        } else {
+            queued + self.burst()
+        }
+    }
+
+    /// Queue audio for playback, returning the number of frames queued, or
+    /// `None` if disconnected.
+    pub(crate) fn write(&self, buffer: &[Ch32], period: u16) -> Option<usize> {
+        let shared = self.shared();
+        if shared.disconnected.load(SeqCst) {
+            return None;
+        }
+        let channels = shared.channels;
+        let frames = self.burst().max(period.into());
+        let mut queue = shared.queue.lock().unwrap();
+        // Keep latency to about two bursts.
+        let room = (2 * frames * channels).saturating_sub(queue.len());
+        let count = (room / channels).min(period.into());
+        queue.extend(buffer[..count * channels].iter().map(|s| f32::from(*s)));
+        Some(count)
+    }
+
+    /// Take recorded audio, returning the number of frames read, or `None` if
+    /// disconnected.
+    pub(crate) fn read(
+        &self,
+        buffer: &mut [Ch32],
+        period: u16,
+    ) -> Option<usize> {
+        let shared = self.shared();
+        if shared.disconnected.load(SeqCst) {
+            return None;
+        }
+        let channels = shared.channels;
+        let mut queue = shared.queue.lock().unwrap();
+        let count = (queue.len() / channels).min(period.into());
+        for (out, sample) in
+            buffer.iter_mut().zip(queue.drain(..count * channels))
+        {
+            *out = Ch32::from(sample);
+        }
+        // Wake again if there's already another period waiting.
+        if queue.len() >= usize::from(period) * channels {
+            shared.notify.ready.store(true, SeqCst);
+        }
+        Some(count)
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        AAUDIO.with(|aaudio| {
+            if let Some(aaudio) = aaudio {
+                unsafe {
+                    // Stops the callbacks before freeing the shared state.
+                    (aaudio.AAudioStream_requestStop)(self.stream);
+                    (aaudio.AAudioStream_close)(self.stream);
+                }
+            }
+        });
+        unsafe { drop(Box::from_raw(self.shared)) };
+    }
+}
+
+#[path = "device_list.rs"]
+pub(super) mod device_list;
@@ -0,0 +1,150 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use std::task::Waker;
+
+use fon::chan::{Ch32, Channel};
+
+use super::{available, Stream};
+use crate::AudioError;
+
+pub(crate) trait SoundDevice:
+    std::fmt::Display + From<AudioDevice>
+{
+    const INPUT: bool;
+}
+
+/// An Audio Device (input or output).
+#[derive(Debug)]
+pub(crate) struct AudioDevice {
+    /// Stable ID for the device.
+    pub(crate) id: String,
+    /// Human-readable name for the device.
+    pub(crate) name: String,
+    /// Capture instead of playback.
+    pub(crate) input: bool,
+    /// Bitflags for numbers of channels (which of 1-8 are supported)
+    pub(crate) supported: u8,
+    /// AAudio stream, `None` until configured.
+    pub(crate) stream: Option<Stream>,
+    /// Number of underruns or overruns from streams closed by reconfiguring.
+    pub(crate) xruns: usize,
+}
+
+impl AudioDevice {
+    /// Returns true if the device isn't ready, registering the waker.
+    pub(crate) fn should_yield(&self, waker: &Waker) -> bool {
+        match &self.stream {
+            Some(stream) => stream.notify().should_yield(waker),
+            None => false,
+        }
+    }
+
+    /// Register a waker after the device had no room or no audio.
+    pub(crate) fn register_waker(&self, waker: &Waker) {
+        if let Some(stream) = &self.stream {
+            if !stream.notify().should_yield(waker) {
+                waker.wake_by_ref();
+            }
+        }
+    }
+
+    /// Write interleaved audio, returning the number of frames written.
+    pub(crate) fn write(
+        &self,
+        buffer: &[Ch32],
+        period: u16,
+    ) -> Result<usize, AudioError> {
+        let stream = self.stream.as_ref().ok_or(AudioError::NoDevice)?;
+        stream
+            .write(buffer, period)
+            .ok_or(AudioError::DeviceDisconnected)
+    }
+
+    /// Read interleaved audio, returning the number of frames read.
+    pub(crate) fn read(
+        &self,
+        buffer: &mut [Ch32],
+        period: u16,
+    ) -> Result<usize, AudioError> {
+        let stream = self.stream.as_ref().ok_or(AudioError::NoDevice)?;
+        stream
+            .read(buffer, period)
+            .ok_or(AudioError::DeviceDisconnected)
+    }
+
+    /// Get the number of frames buffered between wavy and the hardware.
+    pub(crate) fn delay(&self) -> Option<usize> {
+        self.stream.as_ref().map(Stream::delay)
+    }
+
+    /// Get the number of underruns or overruns since the device was opened.
+    pub(crate) fn xrun_count(&self) -> usize {
+        self.xruns + self.stream.as_ref().map(Stream::xrun_count).unwrap_or(0)
+    }
+}
+
+/// Open the default audio device.
+pub(crate) fn open_default(input: bool) -> Result<AudioDevice, AudioError> {
+    if !available() {
+        return Err(AudioError::BackendUnavailable);
+    }
+    Ok(AudioDevice {
+        id: "default".to_string(),
+        name: "Default".to_string(),
+        input,
+        // Channels are converted by AAudio in shared mode.
+        supported: 0b0010_0011,
+        stream: None,
+        xruns: 0,
+    })
+}
+
+/// Return a list of available audio devices.
+///
+/// Only the default device is listed, other devices are only known to the Java
+/// `AudioManager`.
+pub(crate) fn device_list<D: SoundDevice, F: Fn(D) -> T, T>(
+    abstrakt: F,
+) -> Vec<Result<T, (String, AudioError)>> {
+    match open_default(D::INPUT) {
+        Ok(device) => vec![Ok(abstrakt(D::from(device)))],
+        Err(AudioError::NoDevice | AudioError::BackendUnavailable) => {
+            Vec::new()
+        }
+        Err(error) => vec![Err(("Default".to_string(), error))],
+    }
+}
+
+/// Configure the device for a number of channels (the AAudio equivalent of
+/// `pcm_hw_params` on Linux).
+pub(crate) fn configure(
+    device: &mut AudioDevice,
+    channels: u8,
+    buffer: &mut Vec<Ch32>,
+    sample_rate: &mut Option<f64>,
+    period: &mut u16,
+) -> Option<()> {
+    // The channel count is fixed when the stream is opened, so reopen it.
+    device.xruns = device.xrun_count();
+    device.stream = None;
+    let stream = Stream::open(device.input, channels)?;
+
+    // Use the device's sample rate, resampling is done by `fon`.
+    *sample_rate = Some(stream.sample_rate()?);
+
+    // Set the period of the buffer to AAudio's burst size.
+    *period = stream.burst().try_into().ok()?;
+
+    // Resize the buffer
+    buffer.resize(*period as usize * channels as usize, Ch32::MID);
+
+    device.stream = Some(stream);
+    Some(())
+}
@@ -7,4 +7,30 @@
 // At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
 // LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
 
-include!("../linux/ffi.rs");
+mod aaudio;
+#[path = "../callback/microphone.rs"]
+mod microphone;
+#[path = "../polling.rs"]
+mod polling;
+#[path = "../callback/speakers.rs"]
+mod speakers;
+
+// Implementation Expectations:
+use aaudio::device_list::{configure, open_default, AudioDevice, SoundDevice};
+pub(crate) use aaudio::{available, device_list::device_list};
+pub(crate) use microphone::{Microphone, MicrophoneStream};
+pub(crate) use polling::Watcher;
+pub(crate) use speakers::{Speakers, SpeakersSink};
+
+/// Devices can't be listed without opening them.
+pub(crate) fn device_names(
+    _input: bool,
+    _filter: crate::DeviceFilter,
+) -> Option<Vec<(String, String)>> {
+    None
+}
+
+/// The default device is picked by the system, and not reported.
+pub(crate) fn default_id(_input: bool) -> Option<String> {
+    None
+}
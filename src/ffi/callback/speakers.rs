@@ -0,0 +1,363 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+//! Speakers for platforms that pull audio from their own thread, which share
+//! a buffer with the platform's `AudioDevice` (included with `#[path]`).
+
+#![allow(unsafe_code)]
+
+use std::{
+    fmt::{Display, Error, Formatter},
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering::SeqCst},
+    task::{Context, Poll},
+};
+
+use fon::{
+    chan::{Ch32, Channel},
+    surround::Surround32,
+    Frame, Resampler, Sink,
+};
+
+use super::{configure, open_default, AudioDevice, SoundDevice};
+use crate::{AudioError, DeviceCaps, Latency, SampleFormat, SpeakerPosition};
+
+struct SpeakersInner {
+    /// Platform device for both speakers and microphones.
+    device: AudioDevice,
+    /// Index into audio frames to start writing.
+    starti: usize,
+    /// Raw buffer of audio yet to be played.
+    buffer: Vec<Ch32>,
+    /// Resampler context for speakers sink.
+    resampler: ([Ch32; 6], f64),
+    /// The number of frames in the buffer.
+    period: u16,
+    /// Speakers are locked
+    locked: AtomicBool,
+    /// Speakers were dropped while locked, so the sink frees them
+    orphaned: AtomicBool,
+}
+
+/// Callback-driven speakers connection.
+pub(crate) struct Speakers {
+    /// Number of available channels
+    pub(crate) channels: u8,
+    /// The sample rate of the speakers.
+    pub(crate) sample_rate: Option<f64>,
+    /// Leaked shared box
+    inner: *mut SpeakersInner,
+}
+
+impl Drop for Speakers {
+    fn drop(&mut self) {
+        // If the sink is still alive, it frees the speakers when dropped.
+        if unsafe { (*self.inner).locked.load(SeqCst) } {
+            unsafe { (*self.inner).orphaned.store(true, SeqCst) };
+            return;
+        }
+
+        unsafe { drop(Box::from_raw(self.inner)) };
+    }
+}
+
+impl SoundDevice for Speakers {
+    const INPUT: bool = false;
+}
+
+impl Display for Speakers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        unsafe { f.write_str((*self.inner).device.name.as_str()) }
+    }
+}
+
+impl From<AudioDevice> for Speakers {
+    fn from(device: AudioDevice) -> Self {
+        Self {
+            sample_rate: None,
+            channels: 0,
+            inner: Box::leak(Box::new(SpeakersInner {
+                device,
+                starti: 0,
+                buffer: Vec::new(),
+                resampler: ([Ch32::MID; 6], 0.0),
+                period: 0,
+                locked: AtomicBool::new(false),
+                orphaned: AtomicBool::new(false),
+            })),
+        }
+    }
+}
+
+impl Speakers {
+    /// Opening by name isn't supported, so the public layer searches the
+    /// device list instead.
+    pub(crate) fn open(_name: &str) -> Result<Self, AudioError> {
+        Err(AudioError::NoDevice)
+    }
+
+    /// Queued audio isn't drained when dropped.
+    pub(crate) fn set_drain(&mut self, _drain: bool) {}
+
+    /// The device keeps running (playing silence) while paused.
+    pub(crate) fn pause(&mut self) {}
+
+    /// The device kept running, so there's nothing to continue.
+    pub(crate) fn resume(&mut self) {}
+
+    /// Drop audio not yet handed to the device, and forget the resampler's
+    /// last frame.  Audio already in the device still plays.
+    pub(crate) fn flush(&mut self) {
+        let inner = unsafe { &mut *self.inner };
+        inner.buffer.fill(Ch32::MID);
+        inner.starti = 0;
+        inner.resampler = ([Ch32::MID; 6], 0.0);
+    }
+
+    /// Recording what's played isn't supported.
+    pub(crate) fn loopback(&self) -> Result<super::Microphone, AudioError> {
+        Err(AudioError::NoDevice)
+    }
+
+    /// Open the default speakers.
+    pub(crate) fn try_default() -> Result<Self, AudioError> {
+        Ok(Self::from(open_default(false)?))
+    }
+
+    /// Attempt to configure the speaker for a specific number of channels.
+    fn set_channels<F>(
+        &mut self,
+        inner: &mut SpeakersInner,
+    ) -> Result<bool, AudioError>
+    where
+        F: Frame<Chan = Ch32>,
+    {
+        if F::CHAN_COUNT != self.channels.into() {
+            AudioError::check_channels(F::CHAN_COUNT, inner.device.supported)?;
+            // Configure the audio client
+            configure(
+                &mut inner.device,
+                F::CHAN_COUNT as u8,
+                &mut inner.buffer,
+                &mut self.sample_rate,
+                &mut inner.period,
+            )
+            .ok_or(AudioError::UnsupportedChannelCount {
+                requested: F::CHAN_COUNT,
+                supported: inner.device.supported,
+            })?;
+            self.channels = F::CHAN_COUNT as u8;
+            inner.starti = 0;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Generate an audio sink for the user to fill.
+    pub(crate) fn play<F>(&mut self) -> Result<SpeakersSink<F>, AudioError>
+    where
+        F: Frame<Chan = Ch32>,
+    {
+        // Always called after ready, so should be safe
+        let inner = unsafe { self.inner.as_mut().unwrap() };
+        // Change number of channels, if different than last call.
+        if let Err(error) = self.set_channels::<F>(inner) {
+            // Unlock, since no sink will be created.
+            inner.locked.store(false, SeqCst);
+            return Err(error);
+        }
+        // Convert the resampler to the target speaker configuration.
+        let resampler = Resampler::<F>::new(
+            Surround32::from_channels(&inner.resampler.0[..]).convert(),
+            inner.resampler.1,
+        );
+        // Create a sink that borrows this speaker's buffer mutably.
+        Ok(SpeakersSink(
+            inner,
+            resampler,
+            PhantomData,
+            self.sample_rate.unwrap(),
+        ))
+    }
+
+    pub(crate) fn channels(&self) -> u8 {
+        // Can't reconfigure while the sink is alive.
+        if unsafe { (*self.inner).locked.load(SeqCst) } {
+            return 0;
+        }
+
+        unsafe { (*self.inner).device.supported }
+    }
+
+    pub(crate) fn id(&self) -> &str {
+        unsafe { (*self.inner).device.id.as_str() }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        unsafe { (*self.inner).device.name.as_str() }
+    }
+
+    /// Get the number of underruns since the device was opened.
+    pub(crate) fn xruns(&self) -> usize {
+        unsafe { (*self.inner).device.xrun_count() }
+    }
+
+    /// Suspends aren't reported on this platform.
+    pub(crate) fn suspends(&self) -> usize {
+        0
+    }
+
+    /// Channel positions aren't reported on this platform.
+    pub(crate) fn channel_map(&self) -> Vec<SpeakerPosition> {
+        Vec::new()
+    }
+
+    /// Get the sample rate the device was configured with, `None` until
+    /// configured.
+    pub(crate) fn sample_rate(&self) -> Option<f64> {
+        self.sample_rate
+    }
+
+    /// Get the capabilities of the device, which runs at a single sample rate
+    /// and period.
+    pub(crate) fn caps(&self) -> DeviceCaps {
+        let inner = unsafe { &*self.inner };
+        DeviceCaps::fixed(
+            inner.device.supported,
+            self.sample_rate,
+            inner.period,
+        )
+    }
+
+    /// The platform picks the period.
+    pub(crate) fn set_period(&mut self, _frames: u16, _count: u8) {}
+
+    /// Get the configured period, `0` until configured.
+    pub(crate) fn period(&self) -> u16 {
+        unsafe { (*self.inner).period }
+    }
+
+    /// Get the number of frames the device buffers, if known.
+    pub(crate) fn buffer_frames(&self) -> Option<usize> {
+        None
+    }
+
+    pub(crate) fn format(&self) -> SampleFormat {
+        // Float is always used, any conversion is done by the platform.
+        SampleFormat::F32
+    }
+}
+
+impl Future for Speakers {
+    type Output = Result<(), AudioError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Get mutable reference to speakers.
+        let this = self.get_mut();
+
+        // Safety
+        if unsafe { (*this.inner).locked.load(SeqCst) } {
+            return Poll::Ready(Err(AudioError::DeviceLocked));
+        }
+        //
+        let inner = unsafe { this.inner.as_mut().unwrap() };
+
+        // If speaker is unconfigured, return Ready to configure and play.
+        if this.channels == 0 {
+            inner.locked.store(true, SeqCst);
+            return Poll::Ready(Ok(()));
+        }
+
+        // Check if not woken, then yield.
+        if inner.device.should_yield(cx.waker()) {
+            return Poll::Pending;
+        }
+
+        // Attempt to write the internal speaker buffer to the speakers.
+        let len = inner.device.write(&inner.buffer, inner.period)?;
+        if len == 0 {
+            // No room, wait for the next callback.
+            inner.device.register_waker(cx.waker());
+            return Poll::Pending;
+        }
+
+        // Shift buffer.
+        inner.buffer.drain(..len * this.channels as usize);
+        inner.starti = inner.buffer.len() / this.channels as usize;
+        inner
+            .buffer
+            .resize(inner.period as usize * this.channels as usize, Ch32::MID);
+        // Ready for more samples.
+        inner.locked.store(true, SeqCst);
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub(crate) struct SpeakersSink<F: Frame<Chan = Ch32>>(
+    *mut SpeakersInner,
+    Resampler<F>,
+    PhantomData<F>,
+    f64,
+);
+
+impl<F: Frame<Chan = Ch32>> SpeakersSink<F> {
+    pub(crate) fn latency(&self) -> Latency {
+        let speakers = unsafe { self.0.as_ref().unwrap() };
+        // Before the device starts, assume a full buffer.
+        let delay = speakers.device.delay().unwrap_or(speakers.period.into());
+        Latency::new(speakers.starti + delay, self.3)
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Sink<F> for SpeakersSink<F> {
+    fn sample_rate(&self) -> f64 {
+        self.3
+    }
+
+    fn resampler(&mut self) -> &mut Resampler<F> {
+        &mut self.1
+    }
+
+    fn buffer(&mut self) -> &mut [F] {
+        let speakers = unsafe { self.0.as_mut().unwrap() };
+        let data = speakers.buffer.as_mut_ptr().cast();
+        let count = speakers.period.into();
+        unsafe {
+            &mut std::slice::from_raw_parts_mut(data, count)[speakers.starti..]
+        }
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Drop for SpeakersSink<F> {
+    fn drop(&mut self) {
+        //
+        let speakers = unsafe { self.0.as_mut().unwrap() };
+        // Store 5.1 surround sample to resampler.
+        let frame: Surround32 = self.1.frame().convert();
+        speakers.resampler.0 = [
+            frame.channels()[0],
+            frame.channels()[1],
+            frame.channels()[2],
+            frame.channels()[3],
+            frame.channels()[4],
+            frame.channels()[5],
+        ];
+        // Store partial index from resampler.
+        speakers.resampler.1 = self.1.index() % 1.0;
+        // Unlock
+        speakers.locked.store(false, SeqCst);
+        // Free the speakers if they were dropped first.
+        if speakers.orphaned.load(SeqCst) {
+            unsafe { drop(Box::from_raw(self.0)) };
+        }
+    }
+}
@@ -11,7 +11,25 @@ mod device_list;
 mod microphone;
 mod speakers;
 
-pub(crate) use device_list::device_list;
 use device_list::SoundDevice;
+pub(crate) use device_list::{device_list, Watcher};
 pub(super) use microphone::{Microphone, MicrophoneStream};
 pub(super) use speakers::{Speakers, SpeakersSink};
+
+/// There is no backend for this platform.
+pub(crate) fn available() -> bool {
+    false
+}
+
+/// There are no devices to list.
+pub(crate) fn device_names(
+    _input: bool,
+    _filter: crate::DeviceFilter,
+) -> Option<Vec<(String, String)>> {
+    Some(Vec::new())
+}
+
+/// There is no default device.
+pub(crate) fn default_id(_input: bool) -> Option<String> {
+    None
+}
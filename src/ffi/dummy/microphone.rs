@@ -18,6 +18,7 @@ use std::{
 use fon::{chan::Ch32, Frame, Stream};
 
 use super::SoundDevice;
+use crate::{AudioError, DeviceCaps, Latency, SampleFormat};
 
 pub(crate) struct Microphone();
 
@@ -31,26 +32,76 @@ impl Display for Microphone {
     }
 }
 
-impl Default for Microphone {
-    fn default() -> Self {
-        Microphone()
+impl Microphone {
+    /// Opening by name isn't supported, so the public layer searches the
+    /// device list instead.
+    pub(crate) fn open(_name: &str) -> Result<Self, AudioError> {
+        Err(AudioError::NoDevice)
+    }
+
+    /// Open the default microphone.
+    pub(crate) fn try_default() -> Result<Self, AudioError> {
+        Err(AudioError::BackendUnavailable)
     }
-}
 
-impl Microphone {
     pub(crate) fn record<F: Frame<Chan = Ch32>>(
         &mut self,
-    ) -> MicrophoneStream<F> {
-        MicrophoneStream(PhantomData)
+    ) -> Result<MicrophoneStream<F>, AudioError> {
+        Ok(MicrophoneStream(PhantomData))
     }
 
     pub(crate) fn channels(&self) -> u8 {
         1
     }
+
+    pub(crate) fn id(&self) -> &str {
+        "default"
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        "Default"
+    }
+
+    /// Get the number of overruns since the device was opened.
+    pub(crate) fn xruns(&self) -> usize {
+        0
+    }
+
+    /// Never suspended.
+    pub(crate) fn suspends(&self) -> usize {
+        0
+    }
+
+    /// Get the sample rate the device was configured with, `None` until
+    /// configured.
+    pub(crate) fn sample_rate(&self) -> Option<f64> {
+        None
+    }
+
+    pub(crate) fn caps(&self) -> DeviceCaps {
+        DeviceCaps::fixed(self.channels(), self.sample_rate(), 0)
+    }
+
+    /// There's no device to configure.
+    pub(crate) fn set_period(&mut self, _frames: u16, _count: u8) {}
+
+    /// Get the configured period, `0` until configured.
+    pub(crate) fn period(&self) -> u16 {
+        0
+    }
+
+    /// Get the number of frames the device buffers, if known.
+    pub(crate) fn buffer_frames(&self) -> Option<usize> {
+        None
+    }
+
+    pub(crate) fn format(&self) -> SampleFormat {
+        SampleFormat::F32
+    }
 }
 
 impl Future for Microphone {
-    type Output = ();
+    type Output = Result<(), AudioError>;
 
     fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
         Poll::Pending
@@ -61,6 +112,12 @@ pub(crate) struct MicrophoneStream<F: Frame<Chan = Ch32>>(
     PhantomData<&'static F>,
 );
 
+impl<F: Frame<Chan = Ch32>> MicrophoneStream<F> {
+    pub(crate) fn latency(&self) -> Latency {
+        Latency::new(0, crate::consts::SAMPLE_RATE.into())
+    }
+}
+
 impl<F: Frame<Chan = Ch32>> Iterator for MicrophoneStream<F> {
     type Item = F;
 
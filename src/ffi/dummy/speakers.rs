@@ -18,6 +18,7 @@ use std::{
 use fon::{chan::Ch32, Frame, Resampler, Sink};
 
 use super::SoundDevice;
+use crate::{AudioError, DeviceCaps, Latency, SampleFormat, SpeakerPosition};
 
 pub(crate) struct Speakers {
     pub(crate) sample_rate: Option<f64>,
@@ -33,26 +34,98 @@ impl Display for Speakers {
     }
 }
 
-impl Default for Speakers {
-    fn default() -> Self {
-        Speakers {
-            sample_rate: Some(48_000.0),
-        }
+impl Speakers {
+    /// Opening by name isn't supported, so the public layer searches the
+    /// device list instead.
+    pub(crate) fn open(_name: &str) -> Result<Self, AudioError> {
+        Err(AudioError::NoDevice)
     }
-}
 
-impl Speakers {
-    pub(crate) fn play<F: Frame<Chan = Ch32>>(&mut self) -> SpeakersSink<F> {
-        SpeakersSink(self, Resampler::default(), PhantomData)
+    /// Queued audio isn't drained when dropped.
+    pub(crate) fn set_drain(&mut self, _drain: bool) {}
+
+    /// The device keeps running (playing silence) while paused.
+    pub(crate) fn pause(&mut self) {}
+
+    /// The device kept running, so there's nothing to continue.
+    pub(crate) fn resume(&mut self) {}
+
+    /// Nothing is queued.
+    pub(crate) fn flush(&mut self) {}
+
+    /// Recording what's played isn't supported.
+    pub(crate) fn loopback(&self) -> Result<super::Microphone, AudioError> {
+        Err(AudioError::NoDevice)
+    }
+
+    /// Open the default speakers.
+    pub(crate) fn try_default() -> Result<Self, AudioError> {
+        Err(AudioError::BackendUnavailable)
+    }
+
+    pub(crate) fn play<F: Frame<Chan = Ch32>>(
+        &mut self,
+    ) -> Result<SpeakersSink<F>, AudioError> {
+        Ok(SpeakersSink(self, Resampler::default(), PhantomData))
     }
 
     pub(crate) fn channels(&self) -> u8 {
         1
     }
+
+    pub(crate) fn id(&self) -> &str {
+        "default"
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        "Default"
+    }
+
+    /// Get the number of underruns since the device was opened.
+    pub(crate) fn xruns(&self) -> usize {
+        0
+    }
+
+    /// Never suspended.
+    pub(crate) fn suspends(&self) -> usize {
+        0
+    }
+
+    /// No speakers to place channels on.
+    pub(crate) fn channel_map(&self) -> Vec<SpeakerPosition> {
+        Vec::new()
+    }
+
+    /// Get the sample rate the device was configured with, `None` until
+    /// configured.
+    pub(crate) fn sample_rate(&self) -> Option<f64> {
+        self.sample_rate
+    }
+
+    pub(crate) fn caps(&self) -> DeviceCaps {
+        DeviceCaps::fixed(self.channels(), self.sample_rate(), 0)
+    }
+
+    /// There's no device to configure.
+    pub(crate) fn set_period(&mut self, _frames: u16, _count: u8) {}
+
+    /// Get the configured period, `0` until configured.
+    pub(crate) fn period(&self) -> u16 {
+        0
+    }
+
+    /// Get the number of frames the device buffers, if known.
+    pub(crate) fn buffer_frames(&self) -> Option<usize> {
+        None
+    }
+
+    pub(crate) fn format(&self) -> SampleFormat {
+        SampleFormat::F32
+    }
 }
 
 impl Future for Speakers {
-    type Output = ();
+    type Output = Result<(), AudioError>;
 
     fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
         Poll::Pending
@@ -65,6 +138,12 @@ pub(crate) struct SpeakersSink<F: Frame<Chan = Ch32>>(
     PhantomData<F>,
 );
 
+impl<F: Frame<Chan = Ch32>> SpeakersSink<F> {
+    pub(crate) fn latency(&self) -> Latency {
+        Latency::new(0, self.sample_rate())
+    }
+}
+
 #[allow(unsafe_code)]
 impl<F: Frame<Chan = Ch32>> Sink<F> for SpeakersSink<F> {
     fn sample_rate(&self) -> f64 {
@@ -0,0 +1,199 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use std::task::Waker;
+
+use fon::chan::{Ch32, Channel};
+
+use super::{physical_ports, Stream};
+use crate::AudioError;
+
+pub(crate) trait SoundDevice:
+    std::fmt::Display + From<AudioDevice>
+{
+    const INPUT: bool;
+}
+
+/// An Audio Device (input or output).
+#[derive(Debug)]
+pub(crate) struct AudioDevice {
+    /// Stable ID for the device.
+    pub(crate) id: String,
+    /// Human-readable name for the device.
+    pub(crate) name: String,
+    /// Ports to connect to, one per channel (empty to leave unconnected).
+    pub(crate) ports: Vec<String>,
+    /// Capture instead of playback.
+    pub(crate) input: bool,
+    /// Bitflags for numbers of channels (which of 1-8 are supported)
+    pub(crate) supported: u8,
+    /// JACK client, `None` until configured.
+    pub(crate) stream: Option<Stream>,
+    /// Number of underruns or overruns from streams closed by reconfiguring.
+    pub(crate) xruns: usize,
+}
+
+impl AudioDevice {
+    fn new(id: String, name: String, ports: Vec<String>, input: bool) -> Self {
+        // Mono is connected to every port, other channel counts need a port
+        // for each channel.  Unconnected clients can have any number.
+        let supported = match ports.len() {
+            0 => u8::MAX,
+            count => u8::MAX >> (8 - count.min(8)),
+        };
+        Self {
+            id,
+            name,
+            ports,
+            input,
+            supported,
+            stream: None,
+            xruns: 0,
+        }
+    }
+
+    /// Returns true if the device isn't ready, registering the waker.
+    pub(crate) fn should_yield(&self, waker: &Waker) -> bool {
+        match &self.stream {
+            Some(stream) => stream.notify().should_yield(waker),
+            None => false,
+        }
+    }
+
+    /// Register a waker after the device had no room or no audio.
+    pub(crate) fn register_waker(&self, waker: &Waker) {
+        if let Some(stream) = &self.stream {
+            if !stream.notify().should_yield(waker) {
+                waker.wake_by_ref();
+            }
+        }
+    }
+
+    /// Write interleaved audio, returning the number of frames written.
+    pub(crate) fn write(
+        &self,
+        buffer: &[Ch32],
+        period: u16,
+    ) -> Result<usize, AudioError> {
+        let stream = self.stream.as_ref().ok_or(AudioError::NoDevice)?;
+        stream
+            .write(buffer, period)
+            .ok_or(AudioError::DeviceDisconnected)
+    }
+
+    /// Read interleaved audio, returning the number of frames read.
+    pub(crate) fn read(
+        &self,
+        buffer: &mut [Ch32],
+        period: u16,
+    ) -> Result<usize, AudioError> {
+        let stream = self.stream.as_ref().ok_or(AudioError::NoDevice)?;
+        stream
+            .read(buffer, period)
+            .ok_or(AudioError::DeviceDisconnected)
+    }
+
+    /// Get the number of underruns or overruns since the device was opened.
+    pub(crate) fn xrun_count(&self) -> usize {
+        self.xruns + self.stream.as_ref().map(Stream::xrun_count).unwrap_or(0)
+    }
+
+    /// Get the number of frames buffered between wavy and the hardware.
+    pub(crate) fn delay(&self) -> Option<usize> {
+        self.stream.as_ref().map(Stream::delay)
+    }
+}
+
+/// Group physical ports by the client that owns them (the part of the port
+/// name before the colon, like "system").
+fn groups(input: bool) -> Option<Vec<(String, Vec<String>)>> {
+    let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+    for port in physical_ports(input)? {
+        let client = port.split(':').next().unwrap_or_default().to_string();
+        match groups.iter_mut().find(|(name, _)| *name == client) {
+            Some((_, ports)) => ports.push(port),
+            None => groups.push((client, vec![port])),
+        }
+    }
+    Some(groups)
+}
+
+/// Open the default audio device (the first client with physical ports).
+pub(crate) fn open_default(input: bool) -> Result<AudioDevice, AudioError> {
+    if !super::available() {
+        return Err(AudioError::BackendUnavailable);
+    }
+    let (_, ports) = groups(input)
+        .and_then(|groups| groups.into_iter().next())
+        .ok_or(AudioError::NoDevice)?;
+    Ok(AudioDevice::new(
+        "default".to_string(),
+        "Default".to_string(),
+        ports,
+        input,
+    ))
+}
+
+/// Return a list of available audio devices.
+///
+/// Each client with physical ports is a device, and there's one more device
+/// that isn't connected to anything, for routing with a patchbay.
+pub(crate) fn device_list<D: SoundDevice, F: Fn(D) -> T, T>(
+    abstrakt: F,
+) -> Vec<Result<T, (String, AudioError)>> {
+    let groups = match groups(D::INPUT) {
+        Some(groups) => groups,
+        None => return Vec::new(),
+    };
+    let mut devices = Vec::new();
+    for (client, ports) in groups {
+        let device = AudioDevice::new(
+            format!("jack:{}", client),
+            client,
+            ports,
+            D::INPUT,
+        );
+        devices.push(Ok(abstrakt(D::from(device))));
+    }
+    let device = AudioDevice::new(
+        "jack".to_string(),
+        "JACK (Unconnected)".to_string(),
+        Vec::new(),
+        D::INPUT,
+    );
+    devices.push(Ok(abstrakt(D::from(device))));
+    devices
+}
+
+/// Configure the device for a number of channels (the JACK equivalent of
+/// `pcm_hw_params` on Linux).
+pub(crate) fn configure(
+    device: &mut AudioDevice,
+    channels: u8,
+    buffer: &mut Vec<Ch32>,
+    sample_rate: &mut Option<f64>,
+    period: &mut u16,
+) -> Option<()> {
+    // Ports are registered when the client is opened, so reopen it.
+    device.xruns = device.xrun_count();
+    device.stream = None;
+    let stream = Stream::open(&device.ports, device.input, channels)?;
+
+    // Use the server's sample rate, resampling is done by `fon`.
+    *sample_rate = Some(stream.sample_rate());
+
+    // Set the period of the buffer to JACK's buffer size.
+    *period = stream.slice().try_into().ok()?;
+
+    // Resize the buffer
+    buffer.resize(*period as usize * channels as usize, Ch32::MID);
+
+    device.stream = Some(stream);
+    Some(())
+}
@@ -0,0 +1,36 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+mod jack;
+#[path = "../callback/microphone.rs"]
+mod microphone;
+#[path = "../polling.rs"]
+mod polling;
+#[path = "../callback/speakers.rs"]
+mod speakers;
+
+// Implementation Expectations:
+use jack::device_list::{configure, open_default, AudioDevice, SoundDevice};
+pub(crate) use jack::{available, device_list::device_list};
+pub(crate) use microphone::{Microphone, MicrophoneStream};
+pub(crate) use polling::Watcher;
+pub(crate) use speakers::{Speakers, SpeakersSink};
+
+/// Devices can't be listed without opening them.
+pub(crate) fn device_names(
+    _input: bool,
+    _filter: crate::DeviceFilter,
+) -> Option<Vec<(String, String)>> {
+    None
+}
+
+/// JACK has no default device to report.
+pub(crate) fn default_id(_input: bool) -> Option<String> {
+    None
+}
@@ -0,0 +1,556 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+//! JACK client bindings, loaded at runtime from `libjack.so.0` (provided by
+//! JACK 1, JACK 2 and PipeWire).
+
+#![allow(unsafe_code)]
+
+use std::{
+    cell::UnsafeCell,
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int, c_ulong, c_void},
+    ptr,
+    sync::atomic::{
+        AtomicBool, AtomicUsize,
+        Ordering::{AcqRel, Acquire, Relaxed, Release, SeqCst},
+    },
+    task::Waker,
+};
+
+use fon::chan::Ch32;
+
+/// Name for clients (JACK adds a number if it's taken).
+const NAME: &[u8] = b"wavy\0";
+/// Fail instead of starting a JACK server if one isn't running.
+const JACK_NO_START_SERVER: c_int = 0x01;
+const JACK_PORT_IS_INPUT: c_ulong = 0x1;
+const JACK_PORT_IS_OUTPUT: c_ulong = 0x2;
+const JACK_PORT_IS_PHYSICAL: c_ulong = 0x4;
+/// Port type for audio (one channel of native float32 per port).
+const JACK_DEFAULT_AUDIO_TYPE: &[u8] = b"32 bit float mono audio\0";
+
+/// Called from JACK's real-time thread.
+type ProcessCallback = unsafe extern "C" fn(u32, *mut c_void) -> c_int;
+/// Called from a JACK thread when the server shuts down.
+type ShutdownCallback = unsafe extern "C" fn(*mut c_void);
+type GetBuffer = unsafe extern "C" fn(*mut c_void, u32) -> *mut c_void;
+type ClientFn = unsafe extern "C" fn(*mut c_void) -> c_int;
+
+// Link to libjack.  `jack_client_open()` is variadic, but the extra arguments
+// are only read with the `JackServerName` option, which isn't used.
+dl_api::linker!(extern "C" Jack "libjack.so.0" {
+    fn jack_client_open(
+        name: *const c_char,
+        options: c_int,
+        status: *mut c_int,
+    ) -> *mut c_void;
+    fn jack_client_close(client: *mut c_void) -> c_int;
+    fn jack_activate(client: *mut c_void) -> c_int;
+    fn jack_deactivate(client: *mut c_void) -> c_int;
+    fn jack_get_sample_rate(client: *mut c_void) -> u32;
+    fn jack_get_buffer_size(client: *mut c_void) -> u32;
+    fn jack_set_process_callback(
+        client: *mut c_void,
+        callback: ProcessCallback,
+        arg: *mut c_void,
+    ) -> c_int;
+    fn jack_on_shutdown(
+        client: *mut c_void,
+        callback: ShutdownCallback,
+        arg: *mut c_void,
+    ) -> ();
+    fn jack_port_register(
+        client: *mut c_void,
+        name: *const c_char,
+        port_type: *const c_char,
+        flags: c_ulong,
+        buffer_size: c_ulong,
+    ) -> *mut c_void;
+    fn jack_port_name(port: *mut c_void) -> *const c_char;
+    fn jack_port_get_buffer(port: *mut c_void, frames: u32) -> *mut c_void;
+    fn jack_get_ports(
+        client: *mut c_void,
+        name_pattern: *const c_char,
+        type_pattern: *const c_char,
+        flags: c_ulong,
+    ) -> *mut *const c_char;
+    fn jack_connect(
+        client: *mut c_void,
+        source: *const c_char,
+        destination: *const c_char,
+    ) -> c_int;
+    fn jack_free(ptr: *mut c_void) -> ();
+});
+
+thread_local! {
+    static JACK: Option<Jack> = Jack::new().ok();
+}
+
+/// Returns true if `libjack.so` could be loaded.
+pub(crate) fn available() -> bool {
+    JACK.with(|jack| jack.is_some())
+}
+
+/// Open a client, if the JACK server is running.
+unsafe fn open_client(jack: &Jack) -> Option<*mut c_void> {
+    let mut status = 0;
+    let client = (jack.jack_client_open)(
+        NAME.as_ptr().cast(),
+        JACK_NO_START_SERVER,
+        &mut status,
+    );
+    (!client.is_null()).then_some(client)
+}
+
+const WAITING: usize = 0;
+const REGISTERING: usize = 0b01;
+const WAKING: usize = 0b10;
+
+/// Wakes a future from JACK's real-time thread, without locking.
+pub(crate) struct Notify {
+    /// Set when JACK has processed a cycle.
+    ready: AtomicBool,
+    /// `WAITING`, or `REGISTERING` and/or `WAKING` while accessing the waker.
+    state: AtomicUsize,
+    /// Waker for the future waiting on the device.
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+impl Notify {
+    fn new() -> Self {
+        Self {
+            ready: AtomicBool::new(false),
+            state: AtomicUsize::new(WAITING),
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    /// Returns true if the device hasn't processed a cycle since the last
+    /// call.  If so, the waker is registered to be woken up on the next one.
+    pub(crate) fn should_yield(&self, waker: &Waker) -> bool {
+        if self.ready.swap(false, SeqCst) {
+            return false;
+        }
+        self.register(waker);
+        // Check again, in case processed before the waker was registered.
+        !self.ready.swap(false, SeqCst)
+    }
+
+    /// Only called from the executor thread.
+    fn register(&self, waker: &Waker) {
+        match self.state.compare_exchange(
+            WAITING,
+            REGISTERING,
+            Acquire,
+            Acquire,
+        ) {
+            Ok(_) => unsafe {
+                *self.waker.get() = Some(waker.clone());
+                // If woken while registering, wake now.
+                if self
+                    .state
+                    .compare_exchange(REGISTERING, WAITING, AcqRel, Acquire)
+                    .is_err()
+                {
+                    let waker = (*self.waker.get()).take();
+                    self.state.swap(WAITING, AcqRel);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }
+            },
+            // Currently waking, so wake again.
+            Err(_) => waker.wake_by_ref(),
+        }
+    }
+
+    /// Called from the real-time thread.
+    fn wake(&self) {
+        self.ready.store(true, SeqCst);
+        if self.state.fetch_or(WAKING, AcqRel) == WAITING {
+            let waker = unsafe { (*self.waker.get()).take() };
+            self.state.fetch_and(!WAKING, Release);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Lock-free single producer, single consumer ring buffer of samples.
+struct Ring {
+    /// Sample storage.
+    buffer: Box<[UnsafeCell<f32>]>,
+    /// Total number of samples pushed (only changed by the producer).
+    write: AtomicUsize,
+    /// Total number of samples popped (only changed by the consumer).
+    read: AtomicUsize,
+}
+
+impl Ring {
+    fn new(capacity: usize) -> Self {
+        Self {
+            buffer: (0..capacity).map(|_| UnsafeCell::new(0.0)).collect(),
+            write: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get the number of samples in the ring buffer.
+    fn len(&self) -> usize {
+        self.write
+            .load(Acquire)
+            .wrapping_sub(self.read.load(Acquire))
+    }
+
+    /// Push as many samples as fit, returning how many were pushed.
+    ///
+    /// # Safety
+    /// Must only be called from the producer thread.
+    unsafe fn push(&self, samples: impl Iterator<Item = f32>) -> usize {
+        let write = self.write.load(Relaxed);
+        let room =
+            self.buffer.len() - write.wrapping_sub(self.read.load(Acquire));
+        let mut count = 0;
+        for sample in samples.take(room) {
+            let index = write.wrapping_add(count) % self.buffer.len();
+            *self.buffer[index].get() = sample;
+            count += 1;
+        }
+        self.write.store(write.wrapping_add(count), Release);
+        count
+    }
+
+    /// Pop up to `count` samples, returning how many were popped.
+    ///
+    /// # Safety
+    /// Must only be called from the consumer thread.
+    unsafe fn pop(&self, count: usize, mut f: impl FnMut(f32)) -> usize {
+        let read = self.read.load(Relaxed);
+        let count = count.min(self.write.load(Acquire).wrapping_sub(read));
+        for i in 0..count {
+            f(*self.buffer[read.wrapping_add(i) % self.buffer.len()].get());
+        }
+        self.read.store(read.wrapping_add(count), Release);
+        count
+    }
+}
+
+/// State shared with JACK's real-time thread.
+struct Shared {
+    /// Wakes the future when a cycle has been processed.
+    notify: Notify,
+    /// Interleaved samples waiting to be played, or that were recorded.
+    ring: Ring,
+    /// Registered ports, one for each channel.
+    ports: Vec<*mut c_void>,
+    /// Number of frames in the last cycle.
+    slice: AtomicUsize,
+    /// Set if the server shut down.
+    disconnected: AtomicBool,
+    /// Number of underruns (playback) or overruns (capture).
+    xruns: AtomicUsize,
+    /// Capture instead of playback.
+    input: bool,
+    /// `jack_port_get_buffer()`, for the real-time thread.
+    get_buffer: GetBuffer,
+}
+
+unsafe impl Sync for Shared {}
+
+unsafe extern "C" fn process(frames: u32, arg: *mut c_void) -> c_int {
+    let shared = &*arg.cast::<Shared>();
+    let channels = shared.ports.len();
+    let samples = frames as usize * channels;
+    // Port buffers are only valid for this cycle.
+    let mut buffers = [ptr::null_mut::<f32>(); 8];
+    for (buffer, port) in buffers.iter_mut().zip(shared.ports.iter()) {
+        *buffer = (shared.get_buffer)(*port, frames).cast();
+    }
+    if shared.input {
+        // Interleave, samples are dropped if nobody is recording.
+        let pushed = shared.ring.push(
+            (0..samples).map(|i| *buffers[i % channels].add(i / channels)),
+        );
+        if pushed < samples {
+            shared.xruns.fetch_add(1, SeqCst);
+        }
+    } else {
+        // De-interleave.
+        let mut index = 0;
+        shared.ring.pop(samples, |sample| {
+            *buffers[index % channels].add(index / channels) = sample;
+            index += 1;
+        });
+        // Not enough audio, so play silence (an underrun, once started).
+        if index < samples && shared.ring.write.load(Relaxed) != 0 {
+            shared.xruns.fetch_add(1, SeqCst);
+        }
+        for i in index..samples {
+            *buffers[i % channels].add(i / channels) = 0.0;
+        }
+    }
+    shared.slice.store(frames as usize, SeqCst);
+    shared.notify.wake();
+    0
+}
+
+unsafe extern "C" fn shutdown(arg: *mut c_void) {
+    let shared = &*arg.cast::<Shared>();
+    shared.disconnected.store(true, SeqCst);
+    shared.notify.wake();
+}
+
+/// Get the names of the physical ports audio can be played to (or recorded
+/// from), if the JACK server is running.
+pub(crate) fn physical_ports(input: bool) -> Option<Vec<String>> {
+    JACK.with(|jack| unsafe {
+        let jack = jack.as_ref()?;
+        let client = open_client(jack)?;
+        // Playback ports are inputs to JACK, and capture ports are outputs.
+        let direction = if input {
+            JACK_PORT_IS_OUTPUT
+        } else {
+            JACK_PORT_IS_INPUT
+        };
+        let list = (jack.jack_get_ports)(
+            client,
+            ptr::null(),
+            JACK_DEFAULT_AUDIO_TYPE.as_ptr().cast(),
+            JACK_PORT_IS_PHYSICAL | direction,
+        );
+        let mut ports = Vec::new();
+        if !list.is_null() {
+            let mut port = list;
+            while !(*port).is_null() {
+                ports.push(CStr::from_ptr(*port).to_string_lossy().to_string());
+                port = port.add(1);
+            }
+            (jack.jack_free)(list.cast());
+        }
+        (jack.jack_client_close)(client);
+        Some(ports)
+    })
+}
+
+/// An activated JACK client for playback or capture.
+pub(crate) struct Stream {
+    /// The JACK client.
+    client: *mut c_void,
+    /// `jack_deactivate()`, to stop the callbacks when dropped.
+    deactivate: ClientFn,
+    /// `jack_client_close()`, to close the client when dropped.
+    close: ClientFn,
+    /// Sample rate of the server.
+    sample_rate: f64,
+    /// Leaked shared box (null until the callbacks are set).
+    shared: *mut Shared,
+}
+
+impl std::fmt::Debug for Stream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Stream({:?})", self.client)
+    }
+}
+
+impl Stream {
+    /// Open and activate a client with a port for each channel, connecting
+    /// them to `targets` (mono is connected to every target).
+    pub(crate) fn open(
+        targets: &[String],
+        input: bool,
+        channels: u8,
+    ) -> Option<Self> {
+        JACK.with(|jack| unsafe {
+            let jack = jack.as_ref()?;
+            let client = open_client(jack)?;
+            let mut stream = Self {
+                client,
+                deactivate: jack.jack_deactivate,
+                close: jack.jack_client_close,
+                sample_rate: (jack.jack_get_sample_rate)(client).into(),
+                shared: ptr::null_mut(),
+            };
+
+            // Register a port for each channel.
+            let (prefix, flags) = if input {
+                ("in", JACK_PORT_IS_INPUT)
+            } else {
+                ("out", JACK_PORT_IS_OUTPUT)
+            };
+            let mut ports = Vec::new();
+            for channel in 1..=channels {
+                let name =
+                    CString::new(format!("{}_{}", prefix, channel)).unwrap();
+                let port = (jack.jack_port_register)(
+                    client,
+                    name.as_ptr(),
+                    JACK_DEFAULT_AUDIO_TYPE.as_ptr().cast(),
+                    flags,
+                    0,
+                );
+                if port.is_null() {
+                    return None;
+                }
+                ports.push(port);
+            }
+
+            // Leave room for a few cycles, in case the buffer size changes.
+            let frames = (jack.jack_get_buffer_size)(client) as usize;
+            let capacity = 4
+                * frames.max(crate::consts::PERIOD.into())
+                * usize::from(channels);
+            stream.shared = Box::into_raw(Box::new(Shared {
+                notify: Notify::new(),
+                ring: Ring::new(capacity),
+                ports,
+                slice: AtomicUsize::new(frames),
+                disconnected: AtomicBool::new(false),
+                xruns: AtomicUsize::new(0),
+                input,
+                get_buffer: jack.jack_port_get_buffer,
+            }));
+            if (jack.jack_set_process_callback)(
+                client,
+                process,
+                stream.shared.cast(),
+            ) != 0
+            {
+                return None;
+            }
+            (jack.jack_on_shutdown)(client, shutdown, stream.shared.cast());
+            if (jack.jack_activate)(client) != 0 {
+                return None;
+            }
+
+            // Ports can only be connected once the client is active.
+            stream.connect(jack, targets);
+            Some(stream)
+        })
+    }
+
+    /// Connect the ports to `targets`, ignoring ports that went away.
+    unsafe fn connect(&self, jack: &Jack, targets: &[String]) {
+        let ports = &self.shared().ports;
+        for (i, port) in ports.iter().enumerate() {
+            let ours = (jack.jack_port_name)(*port);
+            let targets = if ports.len() == 1 {
+                targets
+            } else {
+                targets.get(i..=i).unwrap_or_default()
+            };
+            for target in targets {
+                let target = match CString::new(target.as_str()) {
+                    Ok(target) => target,
+                    Err(_) => continue,
+                };
+                let (source, destination) = if self.shared().input {
+                    (target.as_ptr(), ours)
+                } else {
+                    (ours, target.as_ptr())
+                };
+                let _ = (jack.jack_connect)(self.client, source, destination);
+            }
+        }
+    }
+
+    fn shared(&self) -> &Shared {
+        unsafe { &*self.shared }
+    }
+
+    pub(crate) fn notify(&self) -> &Notify {
+        &self.shared().notify
+    }
+
+    /// Get the sample rate of the server.
+    pub(crate) fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    /// Get the number of frames JACK processes each cycle.
+    pub(crate) fn slice(&self) -> usize {
+        self.shared().slice.load(SeqCst)
+    }
+
+    /// Get the number of underruns (playback) or overruns (capture).
+    pub(crate) fn xrun_count(&self) -> usize {
+        self.shared().xruns.load(SeqCst)
+    }
+
+    /// Get the number of frames queued, plus one cycle for playback.
+    pub(crate) fn delay(&self) -> usize {
+        let shared = self.shared();
+        let queued = shared.ring.len() / shared.ports.len();
+        if shared.input {
+            queued
+        } else {
+            queued + self.slice()
+        }
+    }
+
+    /// Queue audio for playback, returning the number of frames queued, or
+    /// `None` if disconnected.
+    pub(crate) fn write(&self, buffer: &[Ch32], period: u16) -> Option<usize> {
+        let shared = self.shared();
+        if shared.disconnected.load(SeqCst) {
+            return None;
+        }
+        let channels = shared.ports.len();
+        let frames = self.slice().max(period.into());
+        // Keep latency to about two cycles.
+        let room = (2 * frames * channels).saturating_sub(shared.ring.len());
+        let count = (room / channels).min(period.into());
+        let samples = buffer[..count * channels].iter().map(|s| f32::from(*s));
+        Some(unsafe { shared.ring.push(samples) } / channels)
+    }
+
+    /// Take recorded audio, returning the number of frames read, or `None` if
+    /// disconnected.
+    pub(crate) fn read(
+        &self,
+        buffer: &mut [Ch32],
+        period: u16,
+    ) -> Option<usize> {
+        let shared = self.shared();
+        if shared.disconnected.load(SeqCst) {
+            return None;
+        }
+        let channels = shared.ports.len();
+        let count = (shared.ring.len() / channels).min(period.into());
+        let mut index = 0;
+        unsafe {
+            shared.ring.pop(count * channels, |sample| {
+                buffer[index] = Ch32::from(sample);
+                index += 1;
+            })
+        };
+        // Wake again if there's already another period waiting.
+        if shared.ring.len() >= usize::from(period) * channels {
+            shared.notify.ready.store(true, SeqCst);
+        }
+        Some(count)
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        unsafe {
+            // Stops the callbacks before freeing the shared state.
+            (self.deactivate)(self.client);
+            (self.close)(self.client);
+            if !self.shared.is_null() {
+                drop(Box::from_raw(self.shared));
+            }
+        }
+    }
+}
+
+#[path = "device_list.rs"]
+pub(super) mod device_list;
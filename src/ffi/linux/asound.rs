@@ -199,6 +199,22 @@ pub(crate) enum SndPcmState {
     Disconnected,
 }
 
+impl From<SndPcmState> for crate::StreamState {
+    fn from(state: SndPcmState) -> Self {
+        match state {
+            SndPcmState::Open => Self::Open,
+            SndPcmState::Setup => Self::Setup,
+            SndPcmState::Prepared => Self::Prepared,
+            SndPcmState::Running => Self::Running,
+            SndPcmState::Xrun => Self::Xrun,
+            SndPcmState::Draining => Self::Draining,
+            SndPcmState::Paused => Self::Paused,
+            SndPcmState::Suspended => Self::Suspended,
+            SndPcmState::Disconnected => Self::Disconnected,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub(crate) struct PollFd {
@@ -227,9 +243,13 @@ dl_api::linker!(extern "C" Alsa "libasound.so.2" {
     ) -> c_int;
     fn snd_pcm_close(pcm: *mut c_void) -> c_int;
     fn snd_pcm_drop(pcm: *mut c_void) -> c_int;
+    fn snd_pcm_drain(pcm: *mut c_void) -> c_int;
     fn snd_pcm_prepare(pcm: *mut c_void) -> c_int;
     fn snd_pcm_resume(pcm: *mut c_void) -> c_int;
+    fn snd_pcm_pause(pcm: *mut c_void, enable: c_int) -> c_int;
     fn snd_pcm_state(pcm: *mut c_void) -> SndPcmState;
+    fn snd_pcm_get_chmap(pcm: *mut c_void) -> *mut c_uint;
+    fn snd_pcm_delay(pcm: *mut c_void, delayp: *mut c_long) -> c_int;
     fn snd_pcm_readi(
         pcm: *mut c_void,
         buffer: *mut c_void,
@@ -250,8 +270,14 @@ dl_api::linker!(extern "C" Alsa "libasound.so.2" {
     fn snd_pcm_hw_params_free(params: *mut c_void) -> ();
     fn snd_pcm_hw_params_set_rate_near(pcm: *mut c_void, params: *mut c_void, val: *mut c_uint, dir: *mut c_int) -> c_int;
     fn snd_pcm_hw_params_get_rate_numden(params: *mut c_void, rate_num: *mut c_uint, rate_den: *mut c_uint) -> c_int;
+    fn snd_pcm_hw_params_get_rate_min(params: *mut c_void, val: *mut c_uint, dir: *mut c_int) -> c_int;
+    fn snd_pcm_hw_params_get_rate_max(params: *mut c_void, val: *mut c_uint, dir: *mut c_int) -> c_int;
+    fn snd_pcm_hw_params_get_period_size_min(params: *mut c_void, val: *mut c_ulong, dir: *mut c_int) -> c_int;
+    fn snd_pcm_hw_params_get_period_size_max(params: *mut c_void, val: *mut c_ulong, dir: *mut c_int) -> c_int;
     fn snd_pcm_hw_params_any(pcm: *mut c_void, params: *mut c_void) -> c_int;
+    fn snd_pcm_hw_params_can_pause(params: *const c_void) -> c_int;
     fn snd_pcm_hw_params_test_channels(pcm: *mut c_void, params: *mut c_void, val: c_uint) -> c_int;
+    fn snd_pcm_hw_params_test_rate(pcm: *mut c_void, params: *mut c_void, val: c_uint, dir: c_int) -> c_int;
     fn snd_pcm_hw_params_set_channels(pcm: *mut c_void, params: *mut c_void, val: c_uint) -> c_int;
     fn snd_pcm_hw_params_malloc(ptr: *mut *mut c_void) -> c_int;
     fn snd_pcm_hw_params_set_access(
@@ -267,12 +293,12 @@ dl_api::linker!(extern "C" Alsa "libasound.so.2" {
     fn snd_pcm_hw_params_set_buffer_size_near(
         pcm: *mut c_void,
         params: *mut c_void,
-        val: *mut c_uint,
+        val: *mut c_ulong,
     ) -> c_int;
     fn snd_pcm_hw_params_set_period_size_near(
         pcm: *mut c_void,
         params: *mut c_void,
-        val: *mut c_uint,
+        val: *mut c_ulong,
         dir: *mut c_int,
     ) -> c_int;
 });
@@ -286,6 +312,11 @@ thread_local! {
     static ALSA: Option<Alsa> = Alsa::new().ok();
 }
 
+/// Returns true if `libasound.so` could be loaded.
+pub(super) fn available() -> bool {
+    ALSA.with(|alsa| alsa.is_some())
+}
+
 #[path = "device_list.rs"]
 pub(super) mod device_list;
 #[path = "pcm.rs"]
@@ -11,53 +11,122 @@
 
 use std::{
     convert::TryInto,
-    ffi::CStr,
+    ffi::{CStr, CString},
     mem::MaybeUninit,
-    os::raw::{c_char, c_void},
+    os::raw::{c_char, c_ulong, c_void},
+    task::Waker,
+    time::{Duration, Instant},
 };
 
 use fon::chan::{Ch32, Channel};
 
 use super::{
-    free, pcm, Alsa, SndPcmAccess, SndPcmFormat, SndPcmMode, SndPcmStream,
+    super::pipewire, free, pcm, Alsa, SndPcmAccess, SndPcmFormat, SndPcmMode,
+    SndPcmState, SndPcmStream,
+};
+use crate::{
+    AudioError, DeviceCaps, DeviceFilter, SampleFormat, SpeakerPosition,
+    StreamState,
 };
 
 pub(crate) const DEFAULT: &[u8] = b"default\0";
 
+/// Sample formats to try, best first.
+const FORMATS: [SampleFormat; 4] = [
+    SampleFormat::F32,
+    SampleFormat::S32,
+    SampleFormat::S24,
+    SampleFormat::S16,
+];
+
+/// Standard sample rates to test for.
+const SAMPLE_RATES: [u32; 12] = [
+    8_000, 11_025, 16_000, 22_050, 32_000, 44_100, 48_000, 88_200, 96_000,
+    176_400, 192_000, 384_000,
+];
+
+/// Get the native endian ALSA format for a sample format.
+fn pcm_format(format: SampleFormat) -> SndPcmFormat {
+    let little = cfg!(target_endian = "little");
+    match (format, little) {
+        (SampleFormat::S16, true) => SndPcmFormat::S16Le,
+        (SampleFormat::S16, false) => SndPcmFormat::S16Be,
+        (SampleFormat::S24, true) => SndPcmFormat::S24Le,
+        (SampleFormat::S24, false) => SndPcmFormat::S24Be,
+        (SampleFormat::S32, true) => SndPcmFormat::S32Le,
+        (SampleFormat::S32, false) => SndPcmFormat::S32Be,
+        (_, true) => SndPcmFormat::FloatLe,
+        (_, false) => SndPcmFormat::FloatBe,
+    }
+}
+
 /// Reset hardware parameters.
 pub(crate) unsafe fn reset_hwp(
     pcm: *mut c_void,
     hwp: *mut c_void,
+    format: SampleFormat,
 ) -> Option<()> {
-    let format = if cfg!(target_endian = "little") {
-        SndPcmFormat::FloatLe
-    } else if cfg!(target_endian = "big") {
-        SndPcmFormat::FloatBe
-    } else {
-        unreachable!()
-    };
+    let format = pcm_format(format);
     pcm::hw_params_any(pcm, hwp).ok()?;
     pcm::hw_params_set_access(pcm, hwp, SndPcmAccess::RwInterleaved).ok()?;
     pcm::hw_params_set_format(pcm, hwp, format).ok()?;
     Some(())
 }
 
-/// Open a PCM Device.
+/// Get the standard sample rates allowed by the hardware parameters `hwp`.
+pub(crate) fn query_supported_sample_rates(
+    pcm: *mut c_void,
+    hwp: *mut c_void,
+) -> Vec<u32> {
+    SAMPLE_RATES
+        .iter()
+        .copied()
+        .filter(|&rate| unsafe { pcm::hw_test_rate(pcm, hwp, rate) })
+        .collect()
+}
+
+/// Open a PCM Device, choosing the best supported sample format.
 pub(crate) fn open(
     name: *const c_char,
     stream: SndPcmStream,
-) -> Option<(*mut c_void, *mut c_void, u8)> {
+) -> Result<(*mut c_void, *mut c_void, u8, SampleFormat), AudioError> {
     unsafe {
-        let pcm = pcm::open(name, stream, SndPcmMode::Nonblock).ok()?;
-        let hwp = pcm::hw_params_malloc().ok()?;
+        let pcm =
+            pcm::open(name, stream, SndPcmMode::Nonblock).map_err(|error| {
+                match error {
+                    // -EBUSY
+                    -16 => AudioError::DeviceBusy,
+                    // -EACCES
+                    -13 => AudioError::PermissionDenied,
+                    // -ENOENT, -ENODEV, or ALSA isn't installed
+                    _ => AudioError::NoDevice,
+                }
+            })?;
+        let hwp = match pcm::hw_params_malloc() {
+            Ok(hwp) => hwp,
+            Err(_) => {
+                let _ = pcm::close(pcm);
+                return Err(AudioError::UnsupportedDevice);
+            }
+        };
+        let format = match FORMATS
+            .iter()
+            .find(|format| reset_hwp(pcm, hwp, **format).is_some())
+        {
+            Some(format) => *format,
+            None => {
+                pcm::hw_params_free(hwp);
+                let _ = pcm::close(pcm);
+                return Err(AudioError::UnsupportedDevice);
+            }
+        };
         let mut channels = 0;
-        reset_hwp(pcm, hwp)?;
         for i in 1..=8 {
             if pcm::hw_test_channels(pcm, hwp, i).is_ok() {
                 channels |= 1 << (i - 1);
             }
         }
-        Some((pcm, hwp, channels))
+        Ok((pcm, hwp, channels, format))
     }
 }
 
@@ -70,9 +139,107 @@ pub(crate) trait SoundDevice:
     fn hwp(&self) -> *mut c_void;
 }
 
+/// Operations on a PCM that errors from reads and writes are handled with.
+/// Implemented with ALSA by [`AudioDevice`], and faked in tests.
+pub(crate) trait Pcm {
+    /// Write interleaved audio, returning the number of frames written.
+    fn write(&mut self, buffer: &[Ch32], period: u16) -> Result<usize, isize>;
+
+    /// Get the state of the PCM.
+    fn state(&self) -> SndPcmState;
+
+    /// Prepare the PCM to start again.
+    fn prepare(&mut self) -> Result<(), i64>;
+
+    /// Resume the PCM after it was suspended.
+    fn resume(&mut self) -> Result<(), i64>;
+
+    /// Count an underrun (playback) or overrun (capture).
+    fn count_xrun(&mut self);
+
+    /// Count a recovery from being suspended.
+    fn count_suspend(&mut self);
+
+    /// Mark the device as failed with `error`, returning it.
+    fn fail_with(&mut self, error: AudioError) -> AudioError;
+
+    /// Mark the device as failed after a read or write that can't be
+    /// recovered from, returning the error.
+    fn fail(&mut self, error: isize) -> AudioError {
+        let state = match error {
+            // -ENODEV, or -ENOTTY from some USB drivers
+            -19 | -25 => StreamState::Disconnected,
+            // -EIO, -EBADFD and anything else
+            _ => self.state().into(),
+        };
+        self.fail_with(if state == StreamState::Disconnected {
+            AudioError::DeviceDisconnected
+        } else {
+            AudioError::InvalidState(state)
+        })
+    }
+
+    /// Resume the PCM after it was suspended (`-ESTRPIPE`), such as by the
+    /// system sleeping.  Returns `false` if the hardware is still waking up.
+    fn recover(&mut self) -> Result<bool, AudioError> {
+        // Not all hardware can resume, so prepare either way.
+        if let Err(-11) = self.resume() {
+            return Ok(false);
+        }
+        if self.prepare().is_err() {
+            return Err(self
+                .fail_with(AudioError::InvalidState(StreamState::Suspended)));
+        }
+        self.count_suspend();
+        Ok(true)
+    }
+}
+
+impl Pcm for AudioDevice {
+    fn write(&mut self, buffer: &[Ch32], period: u16) -> Result<usize, isize> {
+        if let Some(stream) = &self.stream {
+            return stream.write(buffer, period);
+        }
+        if self.format == SampleFormat::F32 {
+            return unsafe {
+                pcm::writei(self.pcm, buffer.as_ptr(), period.into())
+            };
+        }
+        encode(self.format, buffer, &mut self.scratch);
+        unsafe { pcm::writei(self.pcm, self.scratch.as_ptr(), period.into()) }
+    }
+
+    fn state(&self) -> SndPcmState {
+        unsafe { pcm::state(self.pcm) }
+    }
+
+    fn prepare(&mut self) -> Result<(), i64> {
+        unsafe { pcm::prepare(self.pcm) }
+    }
+
+    fn resume(&mut self) -> Result<(), i64> {
+        unsafe { pcm::resume(self.pcm) }
+    }
+
+    fn count_xrun(&mut self) {
+        self.xruns += 1;
+    }
+
+    fn count_suspend(&mut self) {
+        self.suspends += 1;
+    }
+
+    fn fail_with(&mut self, error: AudioError) -> AudioError {
+        self.failed = Some(error);
+        error
+    }
+}
+
 /// An Audio Device (input or output).
 #[derive(Debug)]
 pub(crate) struct AudioDevice {
+    /// ALSA PCM name for the device (stable across restarts).
+    pub(crate) id: String,
     /// Human-readable name for the device.
     pub(crate) name: String,
     /// PCM For Device.
@@ -83,12 +250,28 @@ pub(crate) struct AudioDevice {
     pub(crate) supported: u8,
     /// File descriptors associated with this device.
     pub(crate) fds: Vec<smelling_salts::Device>,
+    /// PipeWire stream, used instead of the PCM if PipeWire is running.
+    pub(crate) stream: Option<pipewire::Stream>,
+    /// Sample format of the PCM.
+    pub(crate) format: SampleFormat,
+    /// Samples converted to or from an integer format.
+    pub(crate) scratch: Vec<u8>,
+    /// Number of ALSA underruns (playback) or overruns (capture).
+    pub(crate) xruns: usize,
+    /// Number of times the PCM was resumed after being suspended.
+    pub(crate) suspends: usize,
+    /// Error the device failed with, so it isn't used again.
+    pub(crate) failed: Option<AudioError>,
 }
 
 impl AudioDevice {
     /// Generate file descriptors.
     pub(crate) fn start(&mut self) -> Option<()> {
         assert!(self.fds.is_empty());
+        // PipeWire wakes from its own thread.
+        if self.stream.is_some() {
+            return Some(());
+        }
         // Get file descriptor.
         let fd_list = unsafe { pcm::poll_descriptors(self.pcm).ok()? };
         // Add to list.
@@ -99,6 +282,262 @@ impl AudioDevice {
         }
         Some(())
     }
+
+    /// Stop playing without losing the configuration, with `snd_pcm_pause()`
+    /// if the hardware supports it.
+    pub(crate) fn pause(&mut self) {
+        if let Some(stream) = &self.stream {
+            stream.set_active(false);
+            return;
+        }
+        unsafe {
+            if !pcm::hw_can_pause(self.hwp)
+                || pcm::pause(self.pcm, true).is_err()
+            {
+                // Otherwise stop, dropping queued audio, and prepare on resume.
+                let _ = pcm::drop(self.pcm);
+            }
+        }
+    }
+
+    /// Continue playing after [`pause()`](Self::pause).
+    pub(crate) fn resume(&mut self) {
+        if let Some(stream) = &self.stream {
+            stream.set_active(true);
+            return;
+        }
+        unsafe {
+            match pcm::state(self.pcm) {
+                SndPcmState::Paused => {
+                    let _ = pcm::pause(self.pcm, false);
+                }
+                SndPcmState::Setup => {
+                    let _ = pcm::prepare(self.pcm);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Drop the audio queued in the device, and prepare it to play again.
+    pub(crate) fn flush(&mut self) {
+        if let Some(stream) = &self.stream {
+            stream.flush();
+            return;
+        }
+        unsafe {
+            let _ = pcm::drop(self.pcm);
+            let _ = pcm::prepare(self.pcm);
+        }
+    }
+
+    /// Returns true if the device isn't ready, registering the waker.
+    pub(crate) fn should_yield(&self, waker: &Waker) -> bool {
+        if let Some(stream) = &self.stream {
+            return stream.notify().should_yield(waker);
+        }
+        self.fds.iter().all(|fd| fd.should_yield())
+    }
+
+    /// Register a waker after the device returned -EAGAIN.
+    pub(crate) fn register_waker(&self, waker: &Waker) {
+        if let Some(stream) = &self.stream {
+            if !stream.notify().should_yield(waker) {
+                waker.wake_by_ref();
+            }
+            return;
+        }
+        for fd in &self.fds {
+            fd.register_waker(waker);
+        }
+    }
+
+    /// Get the speaker position of each channel, or an empty list if not
+    /// reported (PipeWire places channels itself).
+    pub(crate) fn channel_map(&self) -> Vec<SpeakerPosition> {
+        if self.stream.is_some() || self.pcm.is_null() {
+            return Vec::new();
+        }
+        unsafe { pcm::get_chmap(self.pcm) }
+            .into_iter()
+            .map(|position| match position {
+                2 => SpeakerPosition::Mono,
+                3 => SpeakerPosition::FrontLeft,
+                4 => SpeakerPosition::FrontRight,
+                5 => SpeakerPosition::RearLeft,
+                6 => SpeakerPosition::RearRight,
+                7 => SpeakerPosition::FrontCenter,
+                8 => SpeakerPosition::Lfe,
+                9 => SpeakerPosition::SideLeft,
+                10 => SpeakerPosition::SideRight,
+                11 => SpeakerPosition::RearCenter,
+                _ => SpeakerPosition::Unknown,
+            })
+            .collect()
+    }
+
+    /// Get the number of underruns or overruns since the device was opened.
+    pub(crate) fn xrun_count(&self) -> usize {
+        match &self.stream {
+            Some(stream) => stream.xrun_count(),
+            None => self.xruns,
+        }
+    }
+
+    /// Get the capabilities of the device, falling back to the configured
+    /// `sample_rate` and `period` where ALSA can't report ranges.
+    pub(crate) fn caps(
+        &self,
+        sample_rate: Option<f64>,
+        period: u16,
+    ) -> DeviceCaps {
+        let fixed = DeviceCaps::fixed(self.supported, sample_rate, period);
+        // PipeWire nodes run at the graph's rate and quantum.
+        if self.stream.is_some() {
+            return fixed;
+        }
+        unsafe {
+            // Refine a separate copy of the parameters, so the ones installed
+            // on a playing or recording PCM aren't touched.
+            let hwp = match pcm::hw_params_malloc() {
+                Ok(hwp) => hwp,
+                Err(_) => return fixed,
+            };
+            let ranges = reset_hwp(self.pcm, hwp, self.format).and_then(|()| {
+                Some((
+                    pcm::hw_get_rate_range(hwp)?,
+                    query_supported_sample_rates(self.pcm, hwp),
+                    pcm::hw_get_period_range(hwp)?,
+                ))
+            });
+            pcm::hw_params_free(hwp);
+            match ranges {
+                Some((range, rates, periods)) => {
+                    DeviceCaps::new(self.supported, range, rates, periods)
+                }
+                None => fixed,
+            }
+        }
+    }
+
+    /// Get the number of frames buffered between wavy and the hardware.
+    pub(crate) fn delay(&self) -> Option<usize> {
+        if let Some(stream) = &self.stream {
+            return Some(stream.delay());
+        }
+        unsafe { pcm::delay(self.pcm).ok() }
+    }
+
+    /// Play the interleaved audio left in `buffer`, then wait for the queued
+    /// audio to finish playing, for at most `timeout` altogether.
+    pub(crate) fn drain(
+        &mut self,
+        mut buffer: &[Ch32],
+        period: u16,
+        timeout: Duration,
+    ) {
+        let start = Instant::now();
+        let channels = (buffer.len() / usize::from(period).max(1)).max(1);
+        while !buffer.is_empty() && start.elapsed() < timeout {
+            let frames = (buffer.len() / channels) as u16;
+            match self.write(buffer, frames) {
+                Ok(len) => buffer = &buffer[len * channels..],
+                // -EAGAIN, -EINTR or -EBUSY, so wait for room.
+                Err(-11 | -4 | -16) => {
+                    std::thread::sleep(Duration::from_millis(1))
+                }
+                Err(_) => return,
+            }
+        }
+        if let Some(stream) = &self.stream {
+            stream.drain(timeout.saturating_sub(start.elapsed()));
+            return;
+        }
+        unsafe {
+            // Non-blocking, so this only starts draining.
+            let _ = pcm::drain(self.pcm);
+            while pcm::state(self.pcm) == SndPcmState::Draining
+                && start.elapsed() < timeout
+            {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    /// Read interleaved audio, returning the number of frames read.
+    pub(crate) fn read(
+        &mut self,
+        buffer: &mut [Ch32],
+        period: u16,
+    ) -> Result<usize, isize> {
+        if let Some(stream) = &self.stream {
+            return stream.read(buffer, period);
+        }
+        if self.format == SampleFormat::F32 {
+            return unsafe {
+                pcm::readi(self.pcm, buffer.as_mut_ptr(), period)
+            };
+        }
+        self.scratch
+            .resize(buffer.len() * sample_size(self.format), 0);
+        let len =
+            unsafe { pcm::readi(self.pcm, self.scratch.as_mut_ptr(), period)? };
+        let count = buffer.len() / usize::from(period).max(1) * len;
+        decode(self.format, &self.scratch, &mut buffer[..count]);
+        Ok(len)
+    }
+}
+
+/// Get the size of a sample in bytes.
+fn sample_size(format: SampleFormat) -> usize {
+    match format {
+        SampleFormat::S16 => 2,
+        _ => 4,
+    }
+}
+
+/// Get the maximum value of an integer sample.
+fn sample_max(format: SampleFormat) -> f64 {
+    match format {
+        SampleFormat::S16 => 32_767.0,
+        SampleFormat::S24 => 8_388_607.0,
+        _ => 2_147_483_647.0,
+    }
+}
+
+/// Convert samples to a native endian integer format.
+fn encode(format: SampleFormat, buffer: &[Ch32], scratch: &mut Vec<u8>) {
+    let max = sample_max(format);
+    scratch.clear();
+    for sample in buffer {
+        let sample = f64::from(f32::from(*sample)).clamp(-1.0, 1.0) * max;
+        match format {
+            SampleFormat::S16 => {
+                scratch.extend_from_slice(&(sample as i16).to_ne_bytes())
+            }
+            _ => scratch.extend_from_slice(&(sample as i32).to_ne_bytes()),
+        }
+    }
+}
+
+/// Convert samples from a native endian integer format.
+fn decode(format: SampleFormat, scratch: &[u8], buffer: &mut [Ch32]) {
+    let max = sample_max(format);
+    let size = sample_size(format);
+    for (sample, bytes) in buffer.iter_mut().zip(scratch.chunks_exact(size)) {
+        let value = match format {
+            SampleFormat::S16 => {
+                i16::from_ne_bytes([bytes[0], bytes[1]]).into()
+            }
+            SampleFormat::S24 => {
+                // Sign extend from the low three bytes.
+                let value = i32::from_ne_bytes(bytes.try_into().unwrap());
+                (value << 8) >> 8
+            }
+            _ => i32::from_ne_bytes(bytes.try_into().unwrap()),
+        };
+        *sample = Ch32::from((f64::from(value) / max) as f32);
+    }
 }
 
 impl Drop for AudioDevice {
@@ -107,10 +546,15 @@ impl Drop for AudioDevice {
         for fd in &mut self.fds {
             fd.old();
         }
+        // PipeWire streams don't have a PCM.
+        if self.pcm.is_null() {
+            return;
+        }
         // Free hardware parameters and close PCM
         unsafe {
             pcm::hw_params_free(self.hwp);
-            pcm::close(self.pcm).unwrap();
+            // Nothing can be done if closing fails, so ignore it.
+            let _ = pcm::close(self.pcm);
         }
     }
 }
@@ -118,7 +562,11 @@ impl Drop for AudioDevice {
 /// Return a list of available audio devices.
 pub(crate) fn device_list<D: SoundDevice, F: Fn(D) -> T, T>(
     abstrakt: F,
-) -> Vec<T> {
+) -> Vec<Result<T, (String, AudioError)>> {
+    // Prefer PipeWire nodes, which have the names users see in the mixer.
+    if let Some(devices) = pipewire::device_list(&abstrakt) {
+        return devices;
+    }
     super::ALSA.with(|alsa| {
         if let Some(alsa) = alsa {
             device_list_internal(&alsa, abstrakt)
@@ -128,10 +576,143 @@ pub(crate) fn device_list<D: SoundDevice, F: Fn(D) -> T, T>(
     })
 }
 
+/// Return the IDs and names of available audio devices, without opening them.
+pub(crate) fn device_names(
+    input: bool,
+    filter: DeviceFilter,
+) -> Option<Vec<(String, String)>> {
+    if let Some(names) = pipewire::device_names(input) {
+        return Some(names);
+    }
+    Some(super::ALSA.with(|alsa| {
+        if let Some(alsa) = alsa {
+            filter_hints(hints(alsa, input), filter)
+        } else {
+            Vec::new()
+        }
+    }))
+}
+
+/// Return the ID of the default device, if PipeWire is running (ALSA's
+/// `default` PCM doesn't tell which device it's routed to).
+pub(crate) fn default_id(input: bool) -> Option<String> {
+    pipewire::default_id(input)
+}
+
+/// Split an ALSA PCM name such as `front:CARD=PCH,DEV=0` into its prefix, card
+/// and device.
+fn pcm_parts(name: &str) -> (&str, Option<&str>, &str) {
+    let (prefix, args) = match name.split_once(':') {
+        Some(parts) => parts,
+        None => return (name, None, "0"),
+    };
+    let mut card = None;
+    let mut dev = "0";
+    for arg in args.split(',') {
+        if let Some(value) = arg.strip_prefix("CARD=") {
+            card = Some(value);
+        } else if let Some(value) = arg.strip_prefix("DEV=") {
+            dev = value;
+        }
+    }
+    (prefix, card, dev)
+}
+
+/// Filter ALSA hints (PCM name and description pairs).
+fn filter_hints(
+    hints: Vec<(String, String)>,
+    filter: DeviceFilter,
+) -> Vec<(String, String)> {
+    match filter {
+        DeviceFilter::All => hints,
+        DeviceFilter::Hardware => hints
+            .into_iter()
+            .filter(|(id, _)| matches!(pcm_parts(id).0, "hw" | "plughw"))
+            .collect(),
+        DeviceFilter::Cards => {
+            // Best PCM found so far for each device, as (key, rank, hint).
+            let mut devices: Vec<(String, u8, (String, String))> = Vec::new();
+            for hint in hints {
+                let (prefix, card, dev) = pcm_parts(&hint.0);
+                let card = match card {
+                    Some(card) => card,
+                    // Of the plugins that aren't tied to a card, only keep
+                    // the default.
+                    None if prefix == "default" => {
+                        devices.push((String::new(), 0, hint));
+                        continue;
+                    }
+                    None => continue,
+                };
+                // HDMI and S/PDIF are separate outputs from the analog ones.
+                let key = match prefix {
+                    "hdmi" | "iec958" => format!("{}:{},{}", prefix, card, dev),
+                    _ => format!("{},{}", card, dev),
+                };
+                // Prefer PCMs that use the card's configuration.
+                let rank = match prefix {
+                    "front" | "hdmi" | "iec958" => 0,
+                    "plughw" => 1,
+                    "hw" => 2,
+                    _ => 3,
+                };
+                match devices.iter_mut().find(|device| device.0 == key) {
+                    Some(device) if rank < device.1 => {
+                        *device = (key, rank, hint)
+                    }
+                    Some(_) => {}
+                    None => devices.push((key, rank, hint)),
+                }
+            }
+            devices.into_iter().map(|(_, _, hint)| hint).collect()
+        }
+    }
+}
+
 fn device_list_internal<D: SoundDevice, F: Fn(D) -> T, T>(
     alsa: &Alsa,
     abstrakt: F,
-) -> Vec<T> {
+) -> Vec<Result<T, (String, AudioError)>> {
+    let stream = if D::INPUT {
+        SndPcmStream::Capture
+    } else {
+        SndPcmStream::Playback
+    };
+    let mut devices = Vec::new();
+    for (id, name) in hints(alsa, D::INPUT) {
+        // PCM names from hints never contain NUL.
+        let pcm_name = CString::new(id.as_str()).unwrap();
+        // Try to connect to PCM.
+        match open(pcm_name.as_ptr(), stream) {
+            Ok((pcm, hwp, supported, format)) => {
+                // Add device to list of devices.
+                devices.push(Ok(abstrakt(D::from(AudioDevice {
+                    id,
+                    name,
+                    pcm,
+                    hwp,
+                    supported,
+                    fds: Vec::new(),
+                    stream: None,
+                    format,
+                    scratch: Vec::new(),
+                    xruns: 0,
+                    suspends: 0,
+                    failed: None,
+                }))));
+            }
+            // Hints include PCMs that don't exist on this hardware.
+            Err(AudioError::NoDevice) => {}
+            // Found, but can't be opened.
+            Err(error) => devices.push(Err((name, error))),
+        }
+    }
+    devices
+}
+
+/// Return the PCM names and descriptions from ALSA's device hints for the
+/// `input` or output direction, without opening the PCMs.
+fn hints(alsa: &Alsa, input: bool) -> Vec<(String, String)> {
     let tpcm = CStr::from_bytes_with_nul(b"pcm\0").unwrap();
     let tname = CStr::from_bytes_with_nul(b"NAME\0").unwrap();
     let tdesc = CStr::from_bytes_with_nul(b"DESC\0").unwrap();
@@ -153,6 +734,9 @@ fn device_list_internal<D: SoundDevice, F: Fn(D) -> T, T>(
             let io = (alsa.snd_device_name_get_hint)(*n, tioid.as_ptr());
             debug_assert_ne!(pcm_name, std::ptr::null_mut());
 
+            // PCM name is used as the device ID.
+            let id = CStr::from_ptr(pcm_name).to_string_lossy().to_string();
+
             // Convert description to Rust String
             let name = match CStr::from_ptr(pcm_name).to_str() {
                 Ok(x) if x.starts_with("sysdefault") => {
@@ -184,27 +768,8 @@ fn device_list_internal<D: SoundDevice, F: Fn(D) -> T, T>(
             }
 
             // Right input type?
-            if (D::INPUT && is_input) || (!D::INPUT && is_output) {
-                // Try to connect to PCM.
-                let dev = open(
-                    pcm_name,
-                    if D::INPUT {
-                        SndPcmStream::Capture
-                    } else {
-                        SndPcmStream::Playback
-                    },
-                );
-
-                if let Some((pcm, hwp, supported)) = dev {
-                    // Add device to list of devices.
-                    devices.push(abstrakt(D::from(AudioDevice {
-                        name,
-                        pcm,
-                        hwp,
-                        supported,
-                        fds: Vec::new(),
-                    })));
-                }
+            if (input && is_input) || (!input && is_output) {
+                devices.push((id, name));
             }
             free(pcm_name.cast());
             n = n.offset(1);
@@ -221,10 +786,20 @@ pub(crate) fn pcm_hw_params(
     buffer: &mut Vec<Ch32>,
     sample_rate: &mut Option<f64>,
     period: &mut u16,
+    request: (u16, u8),
+    buffer_frames: &mut usize,
 ) -> Option<()> {
+    // PipeWire doesn't report its buffer size.
+    *buffer_frames = 0;
+    if let Some(stream) = &device.stream {
+        return stream.configure(channels, buffer, sample_rate, period);
+    }
     unsafe {
-        // Reset hardware parameters to any interleaved native endian float32
-        reset_hwp(device.pcm, device.hwp)?;
+        // Stop the device, in case it's being reconfigured while running.
+        let _ = pcm::drop(device.pcm);
+
+        // Reset hardware parameters to any interleaved native endian samples
+        reset_hwp(device.pcm, device.hwp, device.format)?;
 
         // Set Hz near library target Hz.
         pcm::hw_params_set_rate_near(
@@ -236,8 +811,14 @@ pub(crate) fn pcm_hw_params(
         .ok()?;
         // Set the number of channels.
         pcm::hw_set_channels(device.pcm, device.hwp, channels).ok()?;
-        // Set period near library target period.
-        let mut period_size = crate::consts::PERIOD.into();
+        // Set period near the requested period, or library target period.
+        let (request_period, count) = request;
+        let mut period_size = if request_period == 0 {
+            crate::consts::PERIOD
+        } else {
+            request_period
+        }
+        .into();
         pcm::hw_params_set_period_size_near(
             device.pcm,
             device.hwp,
@@ -245,11 +826,13 @@ pub(crate) fn pcm_hw_params(
             &mut 0,
         )
         .ok()?;
-        // Some buffer size should always be available (match period).
+        // Some buffer size should always be available (match period), unless
+        // a number of periods was requested.
+        let mut buffer_size = period_size * c_ulong::from(count.max(1));
         pcm::hw_params_set_buffer_size_near(
             device.pcm,
             device.hwp,
-            &mut period_size,
+            &mut buffer_size,
         )
         .ok()?;
         // Should always be able to apply parameters that succeeded
@@ -259,8 +842,12 @@ pub(crate) fn pcm_hw_params(
         // exact sample rate.
         *sample_rate = Some(pcm::hw_get_rate(device.hwp)?);
 
-        // Set the period of the buffer.
-        *period = period_size.try_into().ok()?;
+        // Set the period of the buffer (the whole buffer is written at once,
+        // unless a number of periods was requested).
+        *period = if count == 0 { buffer_size } else { period_size }
+            .try_into()
+            .ok()?;
+        *buffer_frames = buffer_size.try_into().ok()?;
 
         // Resize the buffer
         buffer.resize(*period as usize * channels as usize, Ch32::MID);
@@ -273,3 +860,100 @@ pub(crate) fn pcm_hw_params(
 
     Some(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hints as listed for a laptop with analog and HDMI outputs.
+    fn hints() -> Vec<(String, String)> {
+        [
+            ("default", "Default ALSA Output"),
+            ("null", "Discard all samples"),
+            ("pulse", "PulseAudio Sound Server"),
+            ("hw:CARD=PCH,DEV=0", "HDA Intel PCH, ALC3246 Analog"),
+            ("plughw:CARD=PCH,DEV=0", "HDA Intel PCH, ALC3246 Analog"),
+            ("sysdefault:CARD=PCH", "HDA Intel PCH, ALC3246 Analog"),
+            ("front:CARD=PCH,DEV=0", "HDA Intel PCH, ALC3246 Analog"),
+            ("dmix:CARD=PCH,DEV=0", "HDA Intel PCH, ALC3246 Analog"),
+            ("hw:CARD=PCH,DEV=3", "HDA Intel PCH, HDMI 0"),
+            ("plughw:CARD=PCH,DEV=3", "HDA Intel PCH, HDMI 0"),
+            ("hdmi:CARD=PCH,DEV=0", "HDA Intel PCH, HDMI 0"),
+            ("hw:CARD=USB,DEV=0", "USB Audio"),
+            ("plughw:CARD=USB,DEV=0", "USB Audio"),
+        ]
+        .iter()
+        .map(|&(id, name)| (id.to_string(), name.to_string()))
+        .collect()
+    }
+
+    fn ids(hints: Vec<(String, String)>) -> Vec<String> {
+        hints.into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn parts() {
+        assert_eq!(
+            pcm_parts("front:CARD=PCH,DEV=0"),
+            ("front", Some("PCH"), "0")
+        );
+        assert_eq!(
+            pcm_parts("hdmi:CARD=NVidia,DEV=1"),
+            ("hdmi", Some("NVidia"), "1")
+        );
+        assert_eq!(
+            pcm_parts("sysdefault:CARD=PCH"),
+            ("sysdefault", Some("PCH"), "0")
+        );
+        assert_eq!(pcm_parts("hw:DEV=2,CARD=USB"), ("hw", Some("USB"), "2"));
+        assert_eq!(pcm_parts("default"), ("default", None, "0"));
+    }
+
+    #[test]
+    fn cards() {
+        assert_eq!(
+            ids(filter_hints(hints(), DeviceFilter::Cards)),
+            [
+                "default",
+                "front:CARD=PCH,DEV=0",
+                // The `hdmi` index isn't the hardware device number, so the
+                // HDMI hardware device is listed separately.
+                "plughw:CARD=PCH,DEV=3",
+                "hdmi:CARD=PCH,DEV=0",
+                "plughw:CARD=USB,DEV=0",
+            ]
+        );
+    }
+
+    #[test]
+    fn cards_prefer_plughw_over_hw() {
+        let hints = vec![
+            ("hw:CARD=USB,DEV=0".to_string(), String::new()),
+            ("plughw:CARD=USB,DEV=0".to_string(), String::new()),
+        ];
+        assert_eq!(
+            ids(filter_hints(hints, DeviceFilter::Cards)),
+            ["plughw:CARD=USB,DEV=0"]
+        );
+    }
+
+    #[test]
+    fn hardware() {
+        assert_eq!(
+            ids(filter_hints(hints(), DeviceFilter::Hardware)),
+            [
+                "hw:CARD=PCH,DEV=0",
+                "plughw:CARD=PCH,DEV=0",
+                "hw:CARD=PCH,DEV=3",
+                "plughw:CARD=PCH,DEV=3",
+                "hw:CARD=USB,DEV=0",
+                "plughw:CARD=USB,DEV=0",
+            ]
+        );
+    }
+
+    #[test]
+    fn all() {
+        assert_eq!(filter_hints(hints(), DeviceFilter::All), hints());
+    }
+}
@@ -9,13 +9,23 @@
 
 mod asound;
 mod microphone;
+mod pipewire;
 mod speakers;
+mod watcher;
 
 // Implementation Expectations:
-pub(crate) use asound::device_list::device_list;
+pub(crate) use asound::device_list::{default_id, device_list, device_names};
 use asound::{
-    device_list::{open, pcm_hw_params, AudioDevice, SoundDevice, DEFAULT},
+    device_list::{
+        open, pcm_hw_params, AudioDevice, Pcm, SoundDevice, DEFAULT,
+    },
     PollFd, SndPcmAccess, SndPcmFormat, SndPcmMode, SndPcmState, SndPcmStream,
 };
 pub(crate) use microphone::{Microphone, MicrophoneStream};
 pub(crate) use speakers::{Speakers, SpeakersSink};
+pub(crate) use watcher::Watcher;
+
+/// Returns true if PipeWire is running or ALSA could be loaded.
+pub(crate) fn available() -> bool {
+    pipewire::available() || asound::available()
+}
@@ -10,10 +10,11 @@
 #![allow(unsafe_code)]
 
 use std::{
+    ffi::CString,
     fmt::{Display, Error, Formatter},
     future::Future,
     marker::PhantomData,
-    os::raw::c_void,
+    os::raw::{c_char, c_void},
     pin::Pin,
     sync::atomic::{AtomicBool, Ordering::SeqCst},
     task::{Context, Poll},
@@ -22,9 +23,10 @@ use std::{
 use fon::{chan::Ch32, Frame, Stream};
 
 use super::{
-    asound, pcm_hw_params, AudioDevice, SndPcmState, SndPcmStream, SoundDevice,
-    DEFAULT,
+    asound, pcm_hw_params, AudioDevice, Pcm, SndPcmState, SndPcmStream,
+    SoundDevice, DEFAULT,
 };
+use crate::{AudioError, DeviceCaps, Latency, SampleFormat};
 
 struct MicrophoneInner {
     // PCM I/O Handle
@@ -33,10 +35,17 @@ struct MicrophoneInner {
     buffer: Vec<Ch32>,
     // The period of the microphone.
     period: u16,
+    /// Period and number of periods set with `set_period()`, `0` for the
+    /// defaults.
+    request: (u16, u8),
+    /// Number of frames the device buffers, `0` if unknown.
+    buffer_frames: usize,
     // Index to stop reading.
     endi: usize,
     /// Microphone are locked
     locked: AtomicBool,
+    /// Microphone was dropped while locked, so the stream frees it
+    orphaned: AtomicBool,
 }
 
 pub(crate) struct Microphone {
@@ -50,10 +59,10 @@ pub(crate) struct Microphone {
 
 impl Drop for Microphone {
     fn drop(&mut self) {
-        // Safety
+        // If the stream is still alive, it frees the microphone when dropped.
         if unsafe { (*self.inner).locked.load(SeqCst) } {
-            eprintln!("Microphone dropped before dropping stream");
-            std::process::exit(1);
+            unsafe { (*self.inner).orphaned.store(true, SeqCst) };
+            return;
         }
 
         unsafe { drop(Box::from_raw(self.inner)) };
@@ -74,12 +83,6 @@ impl SoundDevice for Microphone {
 
 impl Display for Microphone {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        // Safety
-        if unsafe { (*self.inner).locked.load(SeqCst) } {
-            eprintln!("Tried to display speakers before dropping sink");
-            std::process::exit(1);
-        }
-
         unsafe { f.write_str((*self.inner).device.name.as_str()) }
     }
 }
@@ -93,80 +96,191 @@ impl From<AudioDevice> for Microphone {
                 device,
                 buffer: Vec::new(),
                 period: 0,
+                request: (0, 0),
+                buffer_frames: 0,
                 endi: 0,
                 locked: AtomicBool::new(false),
+                orphaned: AtomicBool::new(false),
             })),
         }
     }
 }
 
-impl Default for Microphone {
-    fn default() -> Self {
-        let (pcm, hwp, supported) =
-            super::open(DEFAULT.as_ptr().cast(), SndPcmStream::Capture)
-                .unwrap();
-        Self::from(AudioDevice {
-            name: "Default".to_string(),
+impl Microphone {
+    /// Open the default microphone.
+    pub(crate) fn try_default() -> Result<Self, AudioError> {
+        if let Some(device) = super::pipewire::open_default(true) {
+            return Ok(Self::from(device));
+        }
+        if !super::available() {
+            return Err(AudioError::BackendUnavailable);
+        }
+        Self::open_pcm(
+            DEFAULT.as_ptr().cast(),
+            "default".to_string(),
+            "Default".to_string(),
+        )
+    }
+
+    /// Open the microphone with a PipeWire node name, or an ALSA PCM name
+    /// such as `hw:CARD=USB,DEV=0`.
+    pub(crate) fn open(name: &str) -> Result<Self, AudioError> {
+        if let Some(device) = super::pipewire::open(name, true) {
+            return Ok(Self::from(device));
+        }
+        // A name with a NUL byte can't name a PCM.
+        let pcm_name = CString::new(name).map_err(|_| AudioError::NoDevice)?;
+        Self::open_pcm(pcm_name.as_ptr(), name.to_string(), name.to_string())
+    }
+
+    /// Open the ALSA PCM `pcm_name`, reported with `id` and `name`.
+    fn open_pcm(
+        pcm_name: *const c_char,
+        id: String,
+        name: String,
+    ) -> Result<Self, AudioError> {
+        let (pcm, hwp, supported, format) =
+            super::open(pcm_name, SndPcmStream::Capture)?;
+        Ok(Self::from(AudioDevice {
+            id,
+            name,
             pcm,
             hwp,
             supported,
             fds: Vec::new(),
-        })
+            stream: None,
+            format,
+            scratch: Vec::new(),
+            xruns: 0,
+            suspends: 0,
+            failed: None,
+        }))
     }
-}
 
-impl Microphone {
     /// Attempt to configure the microphone for a specific number of channels.
-    fn set_channels<F>(&mut self, inner: &mut MicrophoneInner) -> Option<bool>
+    fn set_channels<F>(
+        &mut self,
+        inner: &mut MicrophoneInner,
+    ) -> Result<bool, AudioError>
     where
         F: Frame<Chan = Ch32>,
     {
         if F::CHAN_COUNT != self.channels.into() {
-            if !matches!(F::CHAN_COUNT, 1 | 2 | 6) {
-                panic!("Unknown speaker configuration")
-            }
-            self.channels = F::CHAN_COUNT as u8;
+            AudioError::check_channels(F::CHAN_COUNT, inner.device.supported)?;
             // Configure Hardware Parameters
             pcm_hw_params(
                 &inner.device,
-                self.channels,
+                F::CHAN_COUNT as u8,
                 &mut inner.buffer,
                 &mut self.sample_rate,
                 &mut inner.period,
-            )?;
-            Some(true)
+                inner.request,
+                &mut inner.buffer_frames,
+            )
+            .ok_or(AudioError::UnsupportedChannelCount {
+                requested: F::CHAN_COUNT,
+                supported: inner.device.supported,
+            })?;
+            self.channels = F::CHAN_COUNT as u8;
+            // Nothing has been recorded with the new configuration yet.
+            inner.endi = 0;
+            Ok(true)
         } else {
-            Some(false)
+            Ok(false)
         }
     }
 
     pub(crate) fn record<F: Frame<Chan = Ch32>>(
         &mut self,
-    ) -> MicrophoneStream<F> {
+    ) -> Result<MicrophoneStream<F>, AudioError> {
         // Always called after ready, so should be safe
         let inner = unsafe { self.inner.as_mut().unwrap() };
 
         // Change number of channels, if different than last call.
-        self.set_channels::<F>(inner)
-            .expect("Microphone::record() called with invalid configuration");
+        if let Err(error) = self.set_channels::<F>(inner) {
+            // Unlock, since no stream will be created.
+            inner.locked.store(false, SeqCst);
+            return Err(error);
+        }
 
         // Stream from microphone's buffer.
-        MicrophoneStream(inner, 0, PhantomData, self.sample_rate, self.channels)
+        Ok(MicrophoneStream(
+            inner,
+            0,
+            PhantomData,
+            self.sample_rate,
+            self.channels,
+        ))
     }
 
     pub(crate) fn channels(&self) -> u8 {
-        // Safety
+        // Can't reconfigure while the stream is alive.
         if unsafe { (*self.inner).locked.load(SeqCst) } {
-            eprintln!("Tried to poll speakers before dropping sink");
-            std::process::exit(1);
+            return 0;
         }
 
         unsafe { (*self.inner).device.supported }
     }
+
+    pub(crate) fn id(&self) -> &str {
+        unsafe { (*self.inner).device.id.as_str() }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        unsafe { (*self.inner).device.name.as_str() }
+    }
+
+    /// Get the number of overruns since the device was opened.
+    pub(crate) fn xruns(&self) -> usize {
+        unsafe { (*self.inner).device.xrun_count() }
+    }
+
+    /// Get the number of times the device was resumed after being
+    /// suspended.
+    pub(crate) fn suspends(&self) -> usize {
+        unsafe { (*self.inner).device.suspends }
+    }
+
+    /// Get the sample rate the device was configured with, `None` until
+    /// configured.
+    pub(crate) fn sample_rate(&self) -> Option<f64> {
+        self.sample_rate
+    }
+
+    /// Get the capabilities of the device, without reconfiguring it.
+    pub(crate) fn caps(&self) -> DeviceCaps {
+        let inner = unsafe { &*self.inner };
+        inner.device.caps(self.sample_rate, inner.period)
+    }
+
+    /// Request periods of `frames`, with room for `count` periods in the
+    /// device's buffer, reconfiguring the device for the next sink or stream.
+    pub(crate) fn set_period(&mut self, frames: u16, count: u8) {
+        unsafe { (*self.inner).request = (frames, count) };
+        self.channels = 0;
+    }
+
+    /// Get the configured period, `0` until configured.
+    pub(crate) fn period(&self) -> u16 {
+        unsafe { (*self.inner).period }
+    }
+
+    /// Get the number of frames the device buffers, if known (ALSA reports
+    /// it once configured).
+    pub(crate) fn buffer_frames(&self) -> Option<usize> {
+        match unsafe { (*self.inner).buffer_frames } {
+            0 => None,
+            frames => Some(frames),
+        }
+    }
+
+    pub(crate) fn format(&self) -> SampleFormat {
+        unsafe { (*self.inner).device.format }
+    }
 }
 
 impl Future for Microphone {
-    type Output = ();
+    type Output = Result<(), AudioError>;
 
     #[allow(unsafe_code)]
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
@@ -175,39 +289,33 @@ impl Future for Microphone {
 
         // Safety
         if unsafe { (*this.inner).locked.load(SeqCst) } {
-            eprintln!("Tried to poll microphone before dropping stream");
-            std::process::exit(1);
+            return Poll::Ready(Err(AudioError::DeviceLocked));
         }
         //
         let inner = unsafe { this.inner.as_mut().unwrap() };
 
         // If microphone is unconfigured, return Ready to configure and play.
         if this.channels == 0 {
-            let _ = inner.device.start();
+            if inner.device.fds.is_empty() {
+                let _ = inner.device.start();
+            }
             inner.locked.store(true, SeqCst);
-            return Poll::Ready(());
+            return Poll::Ready(Ok(()));
         }
 
-        // Check if not woken, then yield.
-        let mut pending = true;
-        for fd in &inner.device.fds {
-            if !fd.should_yield() {
-                pending = false;
-                break;
-            }
+        // Once failed (such as by being unplugged), the device can't be used
+        // anymore.
+        if let Some(error) = inner.device.failed {
+            return Poll::Ready(Err(error));
         }
-        if pending {
+
+        // Check if not woken, then yield.
+        if inner.device.should_yield(cx.waker()) {
             return Poll::Pending;
         }
 
         // Attempt to overwrite the internal microphone buffer.
-        let result = unsafe {
-            asound::pcm::readi(
-                inner.device.pcm,
-                inner.buffer.as_mut_slice().as_mut_ptr(),
-                inner.period,
-            )
-        };
+        let result = inner.device.read(&mut inner.buffer, inner.period);
 
         // Check if it succeeds, then return Ready.
         match result {
@@ -217,49 +325,41 @@ impl Future for Microphone {
                     // read/write call results in EAGAIN (according to epoll man
                     // page)
                     -11 => { /* Pending */ }
-                    -77 => {
-                        eprintln!(
-                            "Incorrect state (-EBADFD): Report Bug to \
-                        https://github.com/ardaku/wavy/issues/new"
-                        );
-                        unreachable!()
-                    }
+                    // -EINTR, or -EBUSY from dsnoop, so try again.
+                    -4 | -16 => cx.waker().wake_by_ref(),
                     -32 => {
                         match unsafe { asound::pcm::state(inner.device.pcm) } {
                             SndPcmState::Xrun => {
-                                eprintln!("Microphone XRUN: Latency cause?");
+                                // Samples are not read fast enough
+                                inner.device.xruns += 1;
                                 unsafe {
                                     asound::pcm::prepare(inner.device.pcm)
-                                        .unwrap();
+                                        .map_err(|_| {
+                                            inner.device.fail_with(
+                                                AudioError::XrunUnrecoverable,
+                                            )
+                                        })?;
                                 }
                             }
-                            st => {
-                                eprintln!(
-                                "Incorrect state = {:?} (XRUN): Report Bug \
-                            to https://github.com/ardaku/wavy/issues/new",
-                                st
-                            );
-                                unreachable!()
+                            _ => {
+                                return Poll::Ready(Err(inner
+                                    .device
+                                    .fail(error)))
                             }
                         }
                     }
                     -86 => {
-                        eprintln!(
-                        "Stream got suspended, trying to recover… (-ESTRPIPE)"
-                    );
-                        unsafe {
-                            if asound::pcm::resume(inner.device.pcm).is_ok() {
-                                // Prepare, so we keep getting samples.
-                                asound::pcm::prepare(inner.device.pcm).unwrap();
-                            }
+                        // Stream got suspended, try to recover.
+                        if !inner.device.recover()? {
+                            cx.waker().wake_by_ref();
+                            return Poll::Pending;
                         }
                     }
-                    _ => unreachable!(),
-                }
-                for fd in &inner.device.fds {
-                    // Register waker
-                    fd.register_waker(cx.waker());
+                    // -ENODEV, -ENOTTY, -EIO, -EBADFD and anything else
+                    _ => return Poll::Ready(Err(inner.device.fail(error))),
                 }
+                // Register waker
+                inner.device.register_waker(cx.waker());
                 // Not ready
                 Poll::Pending
             }
@@ -267,7 +367,7 @@ impl Future for Microphone {
                 inner.endi = len;
                 // Ready, audio buffer has been filled!
                 inner.locked.store(true, SeqCst);
-                Poll::Ready(())
+                Poll::Ready(Ok(()))
             }
         }
     }
@@ -281,6 +381,15 @@ pub(crate) struct MicrophoneStream<F: Frame<Chan = Ch32>>(
     u8,
 );
 
+impl<F: Frame<Chan = Ch32>> MicrophoneStream<F> {
+    pub(crate) fn latency(&self) -> Latency {
+        let mic = unsafe { self.0.as_ref().unwrap() };
+        let unread = mic.endi.saturating_sub(self.1);
+        let delay = mic.device.delay().unwrap_or(0);
+        Latency::new(unread + delay, self.3.unwrap_or_default())
+    }
+}
+
 impl<F: Frame<Chan = Ch32>> Iterator for MicrophoneStream<F> {
     type Item = F;
 
@@ -311,5 +420,9 @@ impl<F: Frame<Chan = Ch32>> Drop for MicrophoneStream<F> {
         let mic = unsafe { self.0.as_mut().unwrap() };
         // Unlock
         mic.locked.store(false, SeqCst);
+        // Free the microphone if it was dropped first.
+        if mic.orphaned.load(SeqCst) {
+            unsafe { drop(Box::from_raw(self.0)) };
+        }
     }
 }
@@ -12,7 +12,9 @@
 use std::{
     convert::TryInto,
     mem::MaybeUninit,
-    os::raw::{c_char, c_int, c_uint, c_void},
+    ops::RangeInclusive,
+    os::raw::{c_char, c_int, c_uint, c_ulong, c_void},
+    ptr,
 };
 
 use super::{
@@ -26,7 +28,7 @@ use super::{
 pub(crate) unsafe fn hw_params_set_period_size_near(
     pcm: *mut c_void,
     params: *mut c_void,
-    val: *mut c_uint,
+    val: *mut c_ulong,
     dir: *mut c_int,
 ) -> Result<(), i64> {
     ALSA.with(|alsa| {
@@ -46,7 +48,7 @@ pub(crate) unsafe fn hw_params_set_period_size_near(
 pub(crate) unsafe fn hw_params_set_buffer_size_near(
     pcm: *mut c_void,
     params: *mut c_void,
-    val: *mut c_uint,
+    val: *mut c_ulong,
 ) -> Result<(), i64> {
     ALSA.with(|alsa| {
         let alsa = if let Some(alsa) = alsa {
@@ -193,6 +195,42 @@ pub(crate) unsafe fn hw_test_channels(
     })
 }
 
+/// Test if the hardware parameters allow exactly `rate`.
+pub(crate) unsafe fn hw_test_rate(
+    pcm: *mut c_void,
+    params: *mut c_void,
+    rate: u32,
+) -> bool {
+    ALSA.with(|alsa| {
+        if let Some(alsa) = alsa {
+            (alsa.snd_pcm_hw_params_test_rate)(pcm, params, rate, 0) == 0
+        } else {
+            false
+        }
+    })
+}
+
+/// Get the channel positions (`SND_CHMAP_*`) of the configured PCM, or an
+/// empty list if the driver doesn't report them.
+pub(crate) unsafe fn get_chmap(pcm: *mut c_void) -> Vec<c_uint> {
+    ALSA.with(|alsa| {
+        let alsa = if let Some(alsa) = alsa {
+            alsa
+        } else {
+            return Vec::new();
+        };
+        let map = (alsa.snd_pcm_get_chmap)(pcm);
+        if map.is_null() {
+            return Vec::new();
+        }
+        // `snd_pcm_chmap_t` is the channel count followed by the positions.
+        let positions =
+            std::slice::from_raw_parts(map.add(1), *map as usize).to_vec();
+        super::free(map.cast());
+        positions
+    })
+}
+
 /// Set the configured channel count.
 pub(crate) unsafe fn hw_set_channels(
     pcm: *mut c_void,
@@ -239,6 +277,65 @@ pub(crate) unsafe fn hw_get_rate(hw_params: *mut c_void) -> Option<f64> {
     })
 }
 
+/// Get the range of sample rates allowed by the hardware parameters.
+pub(crate) unsafe fn hw_get_rate_range(
+    hw_params: *mut c_void,
+) -> Option<RangeInclusive<u32>> {
+    ALSA.with(|alsa| {
+        let alsa = if let Some(alsa) = alsa {
+            alsa
+        } else {
+            return None;
+        };
+        let mut min = MaybeUninit::uninit();
+        let mut max = MaybeUninit::uninit();
+        let ret = (alsa.snd_pcm_hw_params_get_rate_min)(
+            hw_params,
+            min.as_mut_ptr(),
+            ptr::null_mut(),
+        );
+        let _err: usize = ret.try_into().ok()?;
+        let ret = (alsa.snd_pcm_hw_params_get_rate_max)(
+            hw_params,
+            max.as_mut_ptr(),
+            ptr::null_mut(),
+        );
+        let _err: usize = ret.try_into().ok()?;
+        Some(min.assume_init()..=max.assume_init())
+    })
+}
+
+/// Get the range of period sizes (in frames) allowed by the hardware
+/// parameters.
+pub(crate) unsafe fn hw_get_period_range(
+    hw_params: *mut c_void,
+) -> Option<RangeInclusive<usize>> {
+    ALSA.with(|alsa| {
+        let alsa = if let Some(alsa) = alsa {
+            alsa
+        } else {
+            return None;
+        };
+        let mut min = MaybeUninit::uninit();
+        let mut max = MaybeUninit::uninit();
+        let ret = (alsa.snd_pcm_hw_params_get_period_size_min)(
+            hw_params,
+            min.as_mut_ptr(),
+            ptr::null_mut(),
+        );
+        let _err: usize = ret.try_into().ok()?;
+        let ret = (alsa.snd_pcm_hw_params_get_period_size_max)(
+            hw_params,
+            max.as_mut_ptr(),
+            ptr::null_mut(),
+        );
+        let _err: usize = ret.try_into().ok()?;
+        let min = min.assume_init().try_into().ok()?;
+        let max = max.assume_init().try_into().ok()?;
+        Some(min..=max)
+    })
+}
+
 pub(crate) unsafe fn poll_descriptors(
     pcm: *mut c_void,
 ) -> Result<Vec<PollFd>, i64> {
@@ -248,17 +345,16 @@ pub(crate) unsafe fn poll_descriptors(
         } else {
             return Err(0);
         };
-        let size: usize = (alsa.snd_pcm_poll_descriptors_count)(pcm)
-            .try_into()
-            .unwrap();
+        let count = (alsa.snd_pcm_poll_descriptors_count)(pcm);
+        let size: usize = count.try_into().map_err(|_| count)?;
         let mut poll = Vec::with_capacity(size);
         let ret = (alsa.snd_pcm_poll_descriptors)(
             pcm,
             poll.as_mut_ptr(),
-            size.try_into().unwrap(),
+            count.try_into().map_err(|_| count)?,
         );
-        poll.set_len(size);
-        let _: u64 = ret.try_into().map_err(|_| ret)?;
+        let filled: usize = ret.try_into().map_err(|_| ret)?;
+        poll.set_len(filled.min(size));
         Ok(poll)
     })
 }
@@ -309,6 +405,21 @@ pub(crate) unsafe fn drop(pcm: *mut c_void) -> Result<(), i64> {
     })
 }
 
+/// Stop the PCM after the queued audio is played (with a non-blocking PCM,
+/// this returns `-EAGAIN` and the PCM is left draining).
+pub(crate) unsafe fn drain(pcm: *mut c_void) -> Result<(), i64> {
+    ALSA.with(|alsa| {
+        let alsa = if let Some(alsa) = alsa {
+            alsa
+        } else {
+            return Err(0);
+        };
+        let ret = (alsa.snd_pcm_drain)(pcm);
+        let _: u64 = ret.try_into().map_err(|_| ret)?;
+        Ok(())
+    })
+}
+
 pub(crate) unsafe fn resume(pcm: *mut c_void) -> Result<(), i64> {
     ALSA.with(|alsa| {
         let alsa = if let Some(alsa) = alsa {
@@ -322,6 +433,32 @@ pub(crate) unsafe fn resume(pcm: *mut c_void) -> Result<(), i64> {
     })
 }
 
+/// Pause (`enable`) or continue a running PCM.
+pub(crate) unsafe fn pause(pcm: *mut c_void, enable: bool) -> Result<(), i64> {
+    ALSA.with(|alsa| {
+        let alsa = if let Some(alsa) = alsa {
+            alsa
+        } else {
+            return Err(0);
+        };
+        let ret = (alsa.snd_pcm_pause)(pcm, enable.into());
+        let _: u64 = ret.try_into().map_err(|_| ret)?;
+        Ok(())
+    })
+}
+
+/// Check if the hardware parameters allow pausing.
+pub(crate) unsafe fn hw_can_pause(hw_params: *mut c_void) -> bool {
+    ALSA.with(|alsa| {
+        let alsa = if let Some(alsa) = alsa {
+            alsa
+        } else {
+            return false;
+        };
+        (alsa.snd_pcm_hw_params_can_pause)(hw_params) == 1
+    })
+}
+
 pub(crate) unsafe fn prepare(pcm: *mut c_void) -> Result<(), i64> {
     ALSA.with(|alsa| {
         let alsa = if let Some(alsa) = alsa {
@@ -335,10 +472,27 @@ pub(crate) unsafe fn prepare(pcm: *mut c_void) -> Result<(), i64> {
     })
 }
 
-pub(crate) unsafe fn state(pcm: *mut c_void) -> SndPcmState {
+/// Get the number of frames between the application pointer and the hardware
+/// (queued for playback, or captured but not yet read).
+pub(crate) unsafe fn delay(pcm: *mut c_void) -> Result<usize, i64> {
     ALSA.with(|alsa| {
-        let alsa = alsa.as_ref().unwrap();
-        (alsa.snd_pcm_state)(pcm)
+        let alsa = if let Some(alsa) = alsa {
+            alsa
+        } else {
+            return Err(0);
+        };
+        let mut frames = 0;
+        let ret = (alsa.snd_pcm_delay)(pcm, &mut frames);
+        let _: u64 = ret.try_into().map_err(|_| ret)?;
+        // Can be negative after an xrun.
+        Ok(frames.try_into().unwrap_or(0))
+    })
+}
+
+pub(crate) unsafe fn state(pcm: *mut c_void) -> SndPcmState {
+    ALSA.with(|alsa| match alsa {
+        Some(alsa) => (alsa.snd_pcm_state)(pcm),
+        None => SndPcmState::Disconnected,
     })
 }
 
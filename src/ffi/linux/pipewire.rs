@@ -0,0 +1,1384 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+//! PipeWire backend, preferred over ALSA when the daemon is running (on most
+//! distributions the ALSA "default" device is a PipeWire shim anyway).  Only
+//! used with the `pipewire` feature (enabled by default).
+//!
+//! Only the exported `libpipewire-0.3` symbols can be loaded at runtime, so
+//! interface methods (which are inline functions in the C headers) are called
+//! through their method tables directly.
+
+#![allow(unsafe_code)]
+
+use std::{
+    collections::VecDeque,
+    ffi::CStr,
+    mem::{self, size_of},
+    os::raw::{c_char, c_int, c_void},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering::SeqCst},
+        Mutex,
+    },
+    task::Waker,
+    time::{Duration, Instant},
+};
+
+use fon::chan::{Ch32, Channel};
+
+use super::{AudioDevice, SoundDevice};
+use crate::{
+    consts::{PERIOD, SAMPLE_RATE},
+    AudioError, SampleFormat,
+};
+
+/// Name for the thread loop and streams.
+const NAME: &[u8] = b"wavy\0";
+/// Object ID of the core.
+const PW_ID_CORE: u32 = 0;
+/// Let the session manager choose the target.
+const PW_ID_ANY: u32 = 0xFFFF_FFFF;
+const PW_VERSION_REGISTRY: u32 = 3;
+const PW_VERSION_METADATA: u32 = 3;
+/// Interface type of the metadata object holding the default nodes.
+const PW_TYPE_INTERFACE_METADATA: &[u8] = b"PipeWire:Interface:Metadata\0";
+const PW_STREAM_STATE_ERROR: c_int = -1;
+const PW_STREAM_FLAG_AUTOCONNECT: c_int = 1 << 0;
+const PW_STREAM_FLAG_MAP_BUFFERS: c_int = 1 << 2;
+const PW_STREAM_FLAG_RT_PROCESS: c_int = 1 << 4;
+const SPA_DIRECTION_INPUT: c_int = 0;
+const SPA_DIRECTION_OUTPUT: c_int = 1;
+
+// SPA POD types and keys for building the format parameter.
+const SPA_TYPE_ID: u32 = 3;
+const SPA_TYPE_INT: u32 = 4;
+const SPA_TYPE_ARRAY: u32 = 13;
+const SPA_TYPE_OBJECT: u32 = 15;
+const SPA_TYPE_OBJECT_FORMAT: u32 = 0x0004_0003;
+const SPA_PARAM_ENUM_FORMAT: u32 = 3;
+const SPA_FORMAT_MEDIA_TYPE: u32 = 1;
+const SPA_FORMAT_MEDIA_SUBTYPE: u32 = 2;
+const SPA_FORMAT_AUDIO_FORMAT: u32 = 0x0001_0001;
+const SPA_FORMAT_AUDIO_RATE: u32 = 0x0001_0003;
+const SPA_FORMAT_AUDIO_CHANNELS: u32 = 0x0001_0004;
+const SPA_FORMAT_AUDIO_POSITION: u32 = 0x0001_0005;
+const SPA_MEDIA_TYPE_AUDIO: u32 = 1;
+const SPA_MEDIA_SUBTYPE_RAW: u32 = 1;
+#[cfg(target_endian = "little")]
+const SPA_AUDIO_FORMAT_F32: u32 = 0x11B;
+#[cfg(target_endian = "big")]
+const SPA_AUDIO_FORMAT_F32: u32 = 0x11C;
+
+/// Channel positions (MONO, or FL FR FC LFE RL RR).
+fn channel_positions(channels: u8) -> &'static [u32] {
+    match channels {
+        1 => &[2],
+        2 => &[3, 4],
+        _ => &[3, 4, 5, 6, 12, 13],
+    }
+}
+
+#[repr(C)]
+struct SpaList {
+    next: *mut SpaList,
+    prev: *mut SpaList,
+}
+
+#[repr(C)]
+struct SpaCallbacks {
+    funcs: *const c_void,
+    data: *mut c_void,
+}
+
+#[repr(C)]
+struct SpaHook {
+    link: SpaList,
+    cb: SpaCallbacks,
+    removed: Option<unsafe extern "C" fn(*mut SpaHook)>,
+    priv_: *mut c_void,
+}
+
+impl SpaHook {
+    fn new() -> Self {
+        // Safety: All fields are raw pointers or nullable function pointers.
+        unsafe { mem::zeroed() }
+    }
+
+    /// Equivalent of inline `spa_hook_remove()`.
+    unsafe fn remove(&mut self) {
+        if !self.link.next.is_null() {
+            (*self.link.prev).next = self.link.next;
+            (*self.link.next).prev = self.link.prev;
+            self.link.next = ptr::null_mut();
+            self.link.prev = ptr::null_mut();
+        }
+        if let Some(removed) = self.removed {
+            removed(self);
+        }
+    }
+}
+
+#[repr(C)]
+struct SpaInterface {
+    type_: *const c_char,
+    version: u32,
+    cb: SpaCallbacks,
+}
+
+#[repr(C)]
+struct SpaDictItem {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+#[repr(C)]
+struct SpaDict {
+    flags: u32,
+    n_items: u32,
+    items: *const SpaDictItem,
+}
+
+impl SpaDict {
+    unsafe fn get(&self, key: &str) -> Option<String> {
+        for i in 0..self.n_items as usize {
+            let item = &*self.items.add(i);
+            if item.key.is_null() || item.value.is_null() {
+                continue;
+            }
+            if CStr::from_ptr(item.key).to_bytes() == key.as_bytes() {
+                let value = CStr::from_ptr(item.value);
+                return Some(value.to_string_lossy().to_string());
+            }
+        }
+        None
+    }
+}
+
+#[repr(C)]
+struct SpaChunk {
+    offset: u32,
+    size: u32,
+    stride: i32,
+    flags: i32,
+}
+
+#[repr(C)]
+struct SpaData {
+    type_: u32,
+    flags: u32,
+    fd: i64,
+    mapoffset: u32,
+    maxsize: u32,
+    data: *mut c_void,
+    chunk: *mut SpaChunk,
+}
+
+#[repr(C)]
+struct SpaBuffer {
+    n_metas: u32,
+    n_datas: u32,
+    metas: *mut c_void,
+    datas: *mut SpaData,
+}
+
+#[repr(C)]
+struct PwBuffer {
+    buffer: *mut SpaBuffer,
+    user_data: *mut c_void,
+    size: u64,
+    requested: u64,
+}
+
+#[repr(C)]
+struct PwCoreMethods {
+    version: u32,
+    add_listener: unsafe extern "C" fn(
+        *mut c_void,
+        *mut SpaHook,
+        *const PwCoreEvents,
+        *mut c_void,
+    ) -> c_int,
+    hello: *const c_void,
+    sync: unsafe extern "C" fn(*mut c_void, u32, c_int) -> c_int,
+    pong: *const c_void,
+    error: *const c_void,
+    get_registry: unsafe extern "C" fn(*mut c_void, u32, usize) -> *mut c_void,
+}
+
+#[repr(C)]
+struct PwCoreEvents {
+    version: u32,
+    info: Option<unsafe extern "C" fn(*mut c_void, *const c_void)>,
+    done: Option<unsafe extern "C" fn(*mut c_void, u32, c_int)>,
+    ping: Option<unsafe extern "C" fn(*mut c_void, u32, c_int)>,
+    error: Option<
+        unsafe extern "C" fn(*mut c_void, u32, c_int, c_int, *const c_char),
+    >,
+}
+
+#[repr(C)]
+struct PwRegistryMethods {
+    version: u32,
+    add_listener: unsafe extern "C" fn(
+        *mut c_void,
+        *mut SpaHook,
+        *const PwRegistryEvents,
+        *mut c_void,
+    ) -> c_int,
+    bind: unsafe extern "C" fn(
+        *mut c_void,
+        u32,
+        *const c_char,
+        u32,
+        usize,
+    ) -> *mut c_void,
+}
+
+#[repr(C)]
+struct PwRegistryEvents {
+    version: u32,
+    global: Option<
+        unsafe extern "C" fn(
+            *mut c_void,
+            u32,
+            u32,
+            *const c_char,
+            u32,
+            *const SpaDict,
+        ),
+    >,
+    global_remove: Option<unsafe extern "C" fn(*mut c_void, u32)>,
+}
+
+#[repr(C)]
+struct PwMetadataMethods {
+    version: u32,
+    add_listener: unsafe extern "C" fn(
+        *mut c_void,
+        *mut SpaHook,
+        *const PwMetadataEvents,
+        *mut c_void,
+    ) -> c_int,
+}
+
+#[repr(C)]
+struct PwMetadataEvents {
+    version: u32,
+    property: Option<
+        unsafe extern "C" fn(
+            *mut c_void,
+            u32,
+            *const c_char,
+            *const c_char,
+            *const c_char,
+        ) -> c_int,
+    >,
+}
+
+#[repr(C)]
+struct PwStreamEvents {
+    version: u32,
+    destroy: Option<unsafe extern "C" fn(*mut c_void)>,
+    state_changed:
+        Option<unsafe extern "C" fn(*mut c_void, c_int, c_int, *const c_char)>,
+    control_info: Option<unsafe extern "C" fn()>,
+    io_changed: Option<unsafe extern "C" fn()>,
+    param_changed: Option<unsafe extern "C" fn()>,
+    add_buffer: Option<unsafe extern "C" fn()>,
+    remove_buffer: Option<unsafe extern "C" fn()>,
+    process: Option<unsafe extern "C" fn(*mut c_void)>,
+}
+
+/// Get the method table and object data from an interface pointer.
+unsafe fn methods<T>(object: *mut c_void) -> (&'static T, *mut c_void) {
+    let iface = &*object.cast::<SpaInterface>();
+    (&*iface.cb.funcs.cast::<T>(), iface.cb.data)
+}
+
+// Link to libpipewire
+dl_api::linker!(extern "C" PipeWire "libpipewire-0.3.so.0" {
+    fn pw_init(argc: *mut c_int, argv: *mut *mut *mut c_char) -> ();
+    fn pw_thread_loop_new(name: *const c_char, props: *const SpaDict)
+        -> *mut c_void;
+    fn pw_thread_loop_destroy(thread_loop: *mut c_void) -> ();
+    fn pw_thread_loop_get_loop(thread_loop: *mut c_void) -> *mut c_void;
+    fn pw_thread_loop_start(thread_loop: *mut c_void) -> c_int;
+    fn pw_thread_loop_stop(thread_loop: *mut c_void) -> ();
+    fn pw_thread_loop_lock(thread_loop: *mut c_void) -> ();
+    fn pw_thread_loop_unlock(thread_loop: *mut c_void) -> ();
+    fn pw_thread_loop_wait(thread_loop: *mut c_void) -> ();
+    fn pw_thread_loop_signal(thread_loop: *mut c_void, accept: bool) -> ();
+    fn pw_context_new(
+        main_loop: *mut c_void,
+        props: *mut c_void,
+        user_data_size: usize,
+    ) -> *mut c_void;
+    fn pw_context_destroy(context: *mut c_void) -> ();
+    fn pw_context_connect(
+        context: *mut c_void,
+        props: *mut c_void,
+        user_data_size: usize,
+    ) -> *mut c_void;
+    fn pw_proxy_destroy(proxy: *mut c_void) -> ();
+    fn pw_properties_new_string(args: *const c_char) -> *mut c_void;
+    fn pw_stream_new(
+        core: *mut c_void,
+        name: *const c_char,
+        props: *mut c_void,
+    ) -> *mut c_void;
+    fn pw_stream_add_listener(
+        stream: *mut c_void,
+        listener: *mut SpaHook,
+        events: *const PwStreamEvents,
+        data: *mut c_void,
+    ) -> ();
+    fn pw_stream_connect(
+        stream: *mut c_void,
+        direction: c_int,
+        target_id: u32,
+        flags: c_int,
+        params: *mut *const c_void,
+        n_params: u32,
+    ) -> c_int;
+    fn pw_stream_disconnect(stream: *mut c_void) -> c_int;
+    fn pw_stream_set_active(stream: *mut c_void, active: bool) -> c_int;
+    fn pw_stream_destroy(stream: *mut c_void) -> ();
+    fn pw_stream_dequeue_buffer(stream: *mut c_void) -> *mut PwBuffer;
+    fn pw_stream_queue_buffer(stream: *mut c_void, buffer: *mut PwBuffer)
+        -> c_int;
+});
+
+/// Wakes a future from the PipeWire thread.
+#[derive(Default)]
+pub(crate) struct Notify {
+    /// Set when the stream has processed a buffer.
+    ready: AtomicBool,
+    /// Waker for the future waiting on the stream.
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Notify {
+    /// Returns true if the stream hasn't processed a buffer since the last
+    /// call.  If so, the waker is registered to be woken up on the next one.
+    pub(crate) fn should_yield(&self, waker: &Waker) -> bool {
+        if self.ready.swap(false, SeqCst) {
+            return false;
+        }
+        *self.waker.lock().unwrap() = Some(waker.clone());
+        // Check again, in case processed before the waker was registered.
+        !self.ready.swap(false, SeqCst)
+    }
+
+    fn wake(&self) {
+        self.ready.store(true, SeqCst);
+        if let Ok(mut waker) = self.waker.try_lock() {
+            if let Some(waker) = waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// State for waiting on a round trip to the daemon.
+struct Roundtrip {
+    thread_loop: *mut c_void,
+    signal: unsafe extern "C" fn(*mut c_void, bool),
+    seq: AtomicI32,
+    done: AtomicBool,
+}
+
+unsafe extern "C" fn core_done(data: *mut c_void, id: u32, seq: c_int) {
+    let roundtrip = &*data.cast::<Roundtrip>();
+    if id == PW_ID_CORE && seq == roundtrip.seq.load(SeqCst) {
+        roundtrip.done.store(true, SeqCst);
+        (roundtrip.signal)(roundtrip.thread_loop, false);
+    }
+}
+
+unsafe extern "C" fn core_error(
+    data: *mut c_void,
+    id: u32,
+    _seq: c_int,
+    _res: c_int,
+    _message: *const c_char,
+) {
+    // Don't wait forever if the connection breaks.
+    let roundtrip = &*data.cast::<Roundtrip>();
+    if id == PW_ID_CORE {
+        roundtrip.done.store(true, SeqCst);
+        (roundtrip.signal)(roundtrip.thread_loop, false);
+    }
+}
+
+static CORE_EVENTS: PwCoreEvents = PwCoreEvents {
+    version: 0,
+    info: None,
+    done: Some(core_done),
+    ping: None,
+    error: Some(core_error),
+};
+
+/// A connection to the PipeWire daemon, running its own thread loop.
+struct Connection {
+    lib: PipeWire,
+    thread_loop: *mut c_void,
+    core: *mut c_void,
+    roundtrip: *mut Roundtrip,
+}
+
+impl Connection {
+    fn new() -> Option<Self> {
+        // Without the `pipewire` feature, always fall back to ALSA.
+        if !cfg!(feature = "pipewire") {
+            return None;
+        }
+        let lib = PipeWire::new().ok()?;
+        unsafe {
+            (lib.pw_init)(ptr::null_mut(), ptr::null_mut());
+            let thread_loop =
+                (lib.pw_thread_loop_new)(NAME.as_ptr().cast(), ptr::null());
+            if thread_loop.is_null() {
+                return None;
+            }
+            let context = (lib.pw_context_new)(
+                (lib.pw_thread_loop_get_loop)(thread_loop),
+                ptr::null_mut(),
+                0,
+            );
+            if context.is_null() {
+                (lib.pw_thread_loop_destroy)(thread_loop);
+                return None;
+            }
+            if (lib.pw_thread_loop_start)(thread_loop) < 0 {
+                (lib.pw_context_destroy)(context);
+                (lib.pw_thread_loop_destroy)(thread_loop);
+                return None;
+            }
+            (lib.pw_thread_loop_lock)(thread_loop);
+            // Fails if the daemon isn't running.
+            let core = (lib.pw_context_connect)(context, ptr::null_mut(), 0);
+            if core.is_null() {
+                (lib.pw_thread_loop_unlock)(thread_loop);
+                (lib.pw_thread_loop_stop)(thread_loop);
+                (lib.pw_context_destroy)(context);
+                (lib.pw_thread_loop_destroy)(thread_loop);
+                return None;
+            }
+            let roundtrip = Box::into_raw(Box::new(Roundtrip {
+                thread_loop,
+                signal: lib.pw_thread_loop_signal,
+                seq: AtomicI32::new(0),
+                done: AtomicBool::new(false),
+            }));
+            // Lives as long as the connection (forever).
+            let hook = Box::into_raw(Box::new(SpaHook::new()));
+            let (core_methods, object) = methods::<PwCoreMethods>(core);
+            (core_methods.add_listener)(
+                object,
+                hook,
+                &CORE_EVENTS,
+                roundtrip.cast(),
+            );
+            (lib.pw_thread_loop_unlock)(thread_loop);
+
+            Some(Self {
+                lib,
+                thread_loop,
+                core,
+                roundtrip,
+            })
+        }
+    }
+
+    fn lock(&self) {
+        unsafe { (self.lib.pw_thread_loop_lock)(self.thread_loop) }
+    }
+
+    fn unlock(&self) {
+        unsafe { (self.lib.pw_thread_loop_unlock)(self.thread_loop) }
+    }
+
+    /// Wait for the daemon to process all previous requests (must be locked).
+    unsafe fn roundtrip(&self) {
+        let (core_methods, object) = methods::<PwCoreMethods>(self.core);
+        let roundtrip = &*self.roundtrip;
+        roundtrip.done.store(false, SeqCst);
+        let seq = (core_methods.sync)(object, PW_ID_CORE, 0);
+        roundtrip.seq.store(seq, SeqCst);
+        while !roundtrip.done.load(SeqCst) {
+            (self.lib.pw_thread_loop_wait)(self.thread_loop);
+        }
+    }
+
+    /// List audio nodes from the registry.
+    fn nodes(&self, input: bool) -> Vec<Node> {
+        self.listing(input, |_, _| ()).nodes
+    }
+
+    /// Get the name of the default node, from the `default` metadata.
+    fn default_node(&self, input: bool) -> Option<String> {
+        let key = if input {
+            "default.audio.source"
+        } else {
+            "default.audio.sink"
+        };
+        let mut default = DefaultNode { key, value: None };
+        self.listing(input, |registry, listing| unsafe {
+            let id = match listing.metadata {
+                Some(id) => id,
+                None => return,
+            };
+            let (registry_methods, object) =
+                methods::<PwRegistryMethods>(registry);
+            let metadata = (registry_methods.bind)(
+                object,
+                id,
+                PW_TYPE_INTERFACE_METADATA.as_ptr().cast(),
+                PW_VERSION_METADATA,
+                0,
+            );
+            if metadata.is_null() {
+                return;
+            }
+            let mut hook = SpaHook::new();
+            let (metadata_methods, object) =
+                methods::<PwMetadataMethods>(metadata);
+            (metadata_methods.add_listener)(
+                object,
+                &mut hook,
+                &DEFAULT_EVENTS,
+                ptr::addr_of_mut!(default).cast(),
+            );
+            self.roundtrip();
+            hook.remove();
+            (self.lib.pw_proxy_destroy)(metadata);
+        });
+        default.value
+    }
+
+    /// List objects from the registry, calling `then` with the registry
+    /// proxy before it's destroyed.
+    fn listing(
+        &self,
+        input: bool,
+        then: impl FnOnce(*mut c_void, &Listing),
+    ) -> Listing {
+        let mut listing = Listing {
+            input,
+            nodes: Vec::new(),
+            metadata: None,
+        };
+        let mut hook = SpaHook::new();
+        self.lock();
+        unsafe {
+            let (core_methods, object) = methods::<PwCoreMethods>(self.core);
+            let registry =
+                (core_methods.get_registry)(object, PW_VERSION_REGISTRY, 0);
+            if !registry.is_null() {
+                let (registry_methods, object) =
+                    methods::<PwRegistryMethods>(registry);
+                (registry_methods.add_listener)(
+                    object,
+                    &mut hook,
+                    &REGISTRY_EVENTS,
+                    ptr::addr_of_mut!(listing).cast(),
+                );
+                self.roundtrip();
+                hook.remove();
+                then(registry, &listing);
+                (self.lib.pw_proxy_destroy)(registry);
+            }
+        }
+        self.unlock();
+        listing
+    }
+}
+
+/// An audio node from the registry.
+struct Node {
+    /// Object ID (changes between sessions).
+    id: u32,
+    /// Node name (stable).
+    name: String,
+    /// Human-readable description (as shown by pavucontrol).
+    description: String,
+}
+
+/// Registry listing in progress.
+struct Listing {
+    input: bool,
+    nodes: Vec<Node>,
+    /// Object ID of the `default` metadata, which names the default nodes.
+    metadata: Option<u32>,
+}
+
+/// Check if a registry global is the `default` metadata object.
+unsafe fn is_default_metadata(type_: *const c_char, props: &SpaDict) -> bool {
+    CStr::from_ptr(type_).to_bytes_with_nul() == PW_TYPE_INTERFACE_METADATA
+        && props.get("metadata.name").as_deref() == Some("default")
+}
+
+/// Lookup of a default node from the `default` metadata in progress.
+struct DefaultNode {
+    key: &'static str,
+    value: Option<String>,
+}
+
+unsafe extern "C" fn default_property(
+    data: *mut c_void,
+    _subject: u32,
+    key: *const c_char,
+    _type: *const c_char,
+    value: *const c_char,
+) -> c_int {
+    let default = &mut *data.cast::<DefaultNode>();
+    if key.is_null()
+        || value.is_null()
+        || CStr::from_ptr(key).to_bytes() != default.key.as_bytes()
+    {
+        return 0;
+    }
+    // The value is JSON, `{ "name": "alsa_output.pci-0000_00_1f.3" }`.
+    let value = CStr::from_ptr(value).to_string_lossy();
+    default.value = value
+        .split_once("\"name\"")
+        .and_then(|(_, rest)| rest.split('"').nth(1))
+        .map(str::to_string);
+    0
+}
+
+static DEFAULT_EVENTS: PwMetadataEvents = PwMetadataEvents {
+    version: 0,
+    property: Some(default_property),
+};
+
+unsafe extern "C" fn registry_global(
+    data: *mut c_void,
+    id: u32,
+    _permissions: u32,
+    type_: *const c_char,
+    _version: u32,
+    props: *const SpaDict,
+) {
+    let listing = &mut *data.cast::<Listing>();
+    if type_.is_null() || props.is_null() {
+        return;
+    }
+    let props = &*props;
+    if is_default_metadata(type_, props) {
+        listing.metadata = Some(id);
+        return;
+    }
+    if CStr::from_ptr(type_).to_bytes() != b"PipeWire:Interface:Node" {
+        return;
+    }
+    let class = props.get("media.class").unwrap_or_default();
+    let wanted = if listing.input {
+        "Audio/Source"
+    } else {
+        "Audio/Sink"
+    };
+    if class != wanted && class != "Audio/Duplex" {
+        return;
+    }
+    let name = match props.get("node.name") {
+        Some(name) => name,
+        None => return,
+    };
+    let description = props
+        .get("node.description")
+        .or_else(|| props.get("node.nick"))
+        .unwrap_or_else(|| name.clone());
+    listing.nodes.push(Node {
+        id,
+        name,
+        description,
+    });
+}
+
+static REGISTRY_EVENTS: PwRegistryEvents = PwRegistryEvents {
+    version: 0,
+    global: Some(registry_global),
+    global_remove: None,
+};
+
+thread_local! {
+    static PIPEWIRE: Option<&'static Connection> =
+        Connection::new().map(|c| &*Box::leak(Box::new(c)));
+}
+
+/// Returns true if connected to a running PipeWire daemon.
+pub(super) fn available() -> bool {
+    PIPEWIRE.with(|pipewire| pipewire.is_some())
+}
+
+/// State shared with the PipeWire thread.
+struct Shared {
+    /// Stream listener.
+    hook: SpaHook,
+    /// Wakes the future when a buffer has been processed.
+    notify: Notify,
+    /// Interleaved samples waiting to be played, or that were recorded.
+    queue: Mutex<VecDeque<f32>>,
+    /// Number of interleaved channels.
+    channels: AtomicUsize,
+    /// Number of frames the graph asked for last cycle.
+    requested: AtomicUsize,
+    /// Set if the stream errored (usually because the device went away).
+    disconnected: AtomicBool,
+    /// Set once audio has been queued for playback (so silence before then
+    /// isn't an underrun).
+    started: AtomicBool,
+    /// Number of underruns (playback) or overruns (capture).
+    xruns: AtomicUsize,
+    /// Capture instead of playback.
+    input: bool,
+    /// The `pw_stream`.
+    stream: *mut c_void,
+    dequeue_buffer: unsafe extern "C" fn(*mut c_void) -> *mut PwBuffer,
+    queue_buffer: unsafe extern "C" fn(*mut c_void, *mut PwBuffer) -> c_int,
+}
+
+unsafe extern "C" fn stream_state_changed(
+    data: *mut c_void,
+    _old: c_int,
+    state: c_int,
+    _error: *const c_char,
+) {
+    let shared = &*data.cast::<Shared>();
+    if state == PW_STREAM_STATE_ERROR {
+        shared.disconnected.store(true, SeqCst);
+        shared.notify.wake();
+    }
+}
+
+unsafe extern "C" fn stream_process(data: *mut c_void) {
+    let shared = &*data.cast::<Shared>();
+    let buffer = (shared.dequeue_buffer)(shared.stream);
+    if buffer.is_null() {
+        return;
+    }
+    let spa_buffer = &*(*buffer).buffer;
+    if spa_buffer.n_datas == 0 || (*spa_buffer.datas).data.is_null() {
+        (shared.queue_buffer)(shared.stream, buffer);
+        return;
+    }
+    let spa_data = &mut *spa_buffer.datas;
+    let channels = shared.channels.load(SeqCst).max(1);
+    let stride = channels * size_of::<f32>();
+    let samples = spa_data.data.cast::<f32>();
+
+    // Don't block the real-time thread, skip the cycle instead.
+    if let Ok(mut queue) = shared.queue.try_lock() {
+        if shared.input {
+            let chunk = &*spa_data.chunk;
+            let offset = chunk.offset.min(spa_data.maxsize) as usize;
+            let size = (chunk.size as usize).min(spa_data.maxsize as usize);
+            let count = size / size_of::<f32>();
+            let samples = samples.add(offset / size_of::<f32>());
+            for i in 0..count {
+                queue.push_back(*samples.add(i));
+            }
+            // Drop the oldest samples if nobody is recording.
+            let limit = 4 * channels * (count / channels).max(PERIOD.into());
+            if queue.len() > limit {
+                shared.xruns.fetch_add(1, SeqCst);
+            }
+            while queue.len() > limit {
+                queue.pop_front();
+            }
+        } else {
+            let max = spa_data.maxsize as usize / stride;
+            let requested = (*buffer).requested as usize;
+            let frames = if requested != 0 && requested <= max {
+                requested
+            } else {
+                max.min(usize::from(PERIOD))
+            };
+            shared.requested.store(frames, SeqCst);
+            if queue.len() < frames * channels && shared.started.load(SeqCst) {
+                shared.xruns.fetch_add(1, SeqCst);
+            }
+            for i in 0..frames * channels {
+                *samples.add(i) = queue.pop_front().unwrap_or(0.0);
+            }
+            let chunk = &mut *spa_data.chunk;
+            chunk.offset = 0;
+            chunk.stride = stride as i32;
+            chunk.size = (frames * stride) as u32;
+        }
+    }
+    (shared.queue_buffer)(shared.stream, buffer);
+    shared.notify.wake();
+}
+
+static STREAM_EVENTS: PwStreamEvents = PwStreamEvents {
+    version: 0,
+    destroy: None,
+    state_changed: Some(stream_state_changed),
+    control_info: None,
+    io_changed: None,
+    param_changed: None,
+    add_buffer: None,
+    remove_buffer: None,
+    process: Some(stream_process),
+};
+
+/// Build an `EnumFormat` SPA POD for interleaved native float32 audio.
+fn format_pod(channels: u8) -> Vec<u32> {
+    fn id(pod: &mut Vec<u32>, key: u32, value: u32) {
+        pod.extend_from_slice(&[key, 0, 4, SPA_TYPE_ID, value, 0]);
+    }
+    fn int(pod: &mut Vec<u32>, key: u32, value: u32) {
+        pod.extend_from_slice(&[key, 0, 4, SPA_TYPE_INT, value, 0]);
+    }
+
+    let mut pod = vec![0, SPA_TYPE_OBJECT, SPA_TYPE_OBJECT_FORMAT];
+    pod.push(SPA_PARAM_ENUM_FORMAT);
+    id(&mut pod, SPA_FORMAT_MEDIA_TYPE, SPA_MEDIA_TYPE_AUDIO);
+    id(&mut pod, SPA_FORMAT_MEDIA_SUBTYPE, SPA_MEDIA_SUBTYPE_RAW);
+    id(&mut pod, SPA_FORMAT_AUDIO_FORMAT, SPA_AUDIO_FORMAT_F32);
+    int(&mut pod, SPA_FORMAT_AUDIO_RATE, SAMPLE_RATE.into());
+    int(&mut pod, SPA_FORMAT_AUDIO_CHANNELS, channels.into());
+    let positions = channel_positions(channels);
+    pod.extend_from_slice(&[SPA_FORMAT_AUDIO_POSITION, 0]);
+    pod.extend_from_slice(&[8 + 4 * positions.len() as u32, SPA_TYPE_ARRAY]);
+    pod.extend_from_slice(&[4, SPA_TYPE_ID]);
+    pod.extend_from_slice(positions);
+    // Pad to 8 bytes.
+    if pod.len() % 2 != 0 {
+        pod.push(0);
+    }
+    // Object body size (everything after the header).
+    pod[0] = (pod.len() as u32 - 2) * 4;
+    pod
+}
+
+/// A PipeWire playback or capture stream.
+pub(crate) struct Stream {
+    connection: &'static Connection,
+    /// Target node ID, or `PW_ID_ANY` for the default.
+    target: u32,
+    /// Leaked shared box
+    shared: *mut Shared,
+}
+
+impl std::fmt::Debug for Stream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Stream({})", self.target)
+    }
+}
+
+impl Stream {
+    /// Create a stream, recording what's played through the `target` output
+    /// if `monitor` is set.
+    fn new(
+        connection: &'static Connection,
+        input: bool,
+        target: Option<&Node>,
+        monitor: bool,
+    ) -> Option<Self> {
+        let mut props = format!(
+            "media.type=Audio media.category={} media.role=Music \
+             node.latency={}/{}",
+            if input { "Capture" } else { "Playback" },
+            PERIOD,
+            SAMPLE_RATE,
+        );
+        if let Some(node) = target {
+            props.push_str(&format!(
+                " target.object=\"{}\"",
+                node.name.replace('"', "\\\""),
+            ));
+        }
+        if monitor {
+            props.push_str(" stream.capture.sink=true");
+        }
+        props.push('\0');
+
+        let lib = &connection.lib;
+        let shared = Box::into_raw(Box::new(Shared {
+            hook: SpaHook::new(),
+            notify: Notify::default(),
+            queue: Mutex::new(VecDeque::new()),
+            channels: AtomicUsize::new(0),
+            requested: AtomicUsize::new(0),
+            disconnected: AtomicBool::new(false),
+            started: AtomicBool::new(false),
+            xruns: AtomicUsize::new(0),
+            input,
+            stream: ptr::null_mut(),
+            dequeue_buffer: lib.pw_stream_dequeue_buffer,
+            queue_buffer: lib.pw_stream_queue_buffer,
+        }));
+        connection.lock();
+        unsafe {
+            let props = (lib.pw_properties_new_string)(props.as_ptr().cast());
+            let stream = (lib.pw_stream_new)(
+                connection.core,
+                NAME.as_ptr().cast(),
+                props,
+            );
+            if stream.is_null() {
+                connection.unlock();
+                drop(Box::from_raw(shared));
+                return None;
+            }
+            (*shared).stream = stream;
+            (lib.pw_stream_add_listener)(
+                stream,
+                &mut (*shared).hook,
+                &STREAM_EVENTS,
+                shared.cast(),
+            );
+        }
+        connection.unlock();
+
+        Some(Self {
+            connection,
+            target: target.map(|node| node.id).unwrap_or(PW_ID_ANY),
+            shared,
+        })
+    }
+
+    fn shared(&self) -> &Shared {
+        unsafe { &*self.shared }
+    }
+
+    pub(crate) fn notify(&self) -> &Notify {
+        &self.shared().notify
+    }
+
+    /// Connect the stream with a number of channels (the PipeWire equivalent
+    /// of `pcm_hw_params`).
+    pub(crate) fn configure(
+        &self,
+        channels: u8,
+        buffer: &mut Vec<Ch32>,
+        sample_rate: &mut Option<f64>,
+        period: &mut u16,
+    ) -> Option<()> {
+        let shared = self.shared();
+        let lib = &self.connection.lib;
+        let pod = format_pod(channels);
+        let mut params = [pod.as_ptr().cast::<c_void>()];
+        self.connection.lock();
+        let result = unsafe {
+            let _ = (lib.pw_stream_disconnect)(shared.stream);
+            shared.channels.store(channels.into(), SeqCst);
+            shared.queue.lock().unwrap().clear();
+            shared.started.store(false, SeqCst);
+            (lib.pw_stream_connect)(
+                shared.stream,
+                if shared.input {
+                    SPA_DIRECTION_INPUT
+                } else {
+                    SPA_DIRECTION_OUTPUT
+                },
+                self.target,
+                PW_STREAM_FLAG_AUTOCONNECT
+                    | PW_STREAM_FLAG_MAP_BUFFERS
+                    | PW_STREAM_FLAG_RT_PROCESS,
+                params.as_mut_ptr(),
+                1,
+            )
+        };
+        self.connection.unlock();
+        if result < 0 {
+            return None;
+        }
+
+        // PipeWire converts to and from the graph's rate.
+        *sample_rate = Some(SAMPLE_RATE.into());
+        *period = PERIOD;
+        buffer.resize(*period as usize * channels as usize, Ch32::MID);
+        Some(())
+    }
+
+    /// Stop or start processing, keeping the stream connected.
+    pub(crate) fn set_active(&self, active: bool) {
+        let shared = self.shared();
+        self.connection.lock();
+        unsafe {
+            let _ = (self.connection.lib.pw_stream_set_active)(
+                shared.stream,
+                active,
+            );
+        }
+        self.connection.unlock();
+    }
+
+    /// Queue audio for playback, returning the number of frames queued (like
+    /// `snd_pcm_writei()`, including the error codes).
+    pub(crate) fn write(
+        &self,
+        buffer: &[Ch32],
+        period: u16,
+    ) -> Result<usize, isize> {
+        let shared = self.shared();
+        if shared.disconnected.load(SeqCst) {
+            return Err(-19);
+        }
+        let channels = buffer.len() / usize::from(period).max(1);
+        let frames = shared.requested.load(SeqCst).max(period.into());
+        let mut queue = shared.queue.lock().unwrap();
+        // Keep latency to about two cycles of the graph.
+        let room = (2 * frames * channels).saturating_sub(queue.len());
+        let count = (room / channels.max(1)).min(period.into());
+        if count == 0 {
+            return Err(-11);
+        }
+        queue.extend(buffer[..count * channels].iter().map(|s| f32::from(*s)));
+        shared.started.store(true, SeqCst);
+        Ok(count)
+    }
+
+    /// Drop the queued audio, so silence plays until more is written.
+    pub(crate) fn flush(&self) {
+        let shared = self.shared();
+        shared.queue.lock().unwrap().clear();
+        // The silence that follows isn't an underrun.
+        shared.started.store(false, SeqCst);
+    }
+
+    /// Get the number of underruns (playback) or overruns (capture).
+    pub(crate) fn xrun_count(&self) -> usize {
+        self.shared().xruns.load(SeqCst)
+    }
+
+    /// Wait until the queued audio has been handed to the graph, for at most
+    /// `timeout`.
+    pub(crate) fn drain(&self, timeout: Duration) {
+        let shared = self.shared();
+        let start = Instant::now();
+        while !shared.queue.lock().unwrap().is_empty()
+            && !shared.disconnected.load(SeqCst)
+            && start.elapsed() < timeout
+        {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Get the number of frames queued, plus one graph cycle for playback.
+    pub(crate) fn delay(&self) -> usize {
+        let shared = self.shared();
+        let channels = shared.channels.load(SeqCst).max(1);
+        let queued = shared.queue.lock().unwrap().len() / channels;
+        if shared.input {
+            queued
+        } else {
+            queued + shared.requested.load(SeqCst)
+        }
+    }
+
+    /// Take recorded audio, returning the number of frames read (like
+    /// `snd_pcm_readi()`, including the error codes).
+    pub(crate) fn read(
+        &self,
+        buffer: &mut [Ch32],
+        period: u16,
+    ) -> Result<usize, isize> {
+        let shared = self.shared();
+        if shared.disconnected.load(SeqCst) {
+            return Err(-19);
+        }
+        let channels = buffer.len() / usize::from(period).max(1);
+        let mut queue = shared.queue.lock().unwrap();
+        let count = (queue.len() / channels.max(1)).min(period.into());
+        if count == 0 {
+            return Err(-11);
+        }
+        for (out, sample) in
+            buffer.iter_mut().zip(queue.drain(..count * channels))
+        {
+            *out = Ch32::from(sample);
+        }
+        // Wake again if there's already another period waiting.
+        if queue.len() >= usize::from(period) * channels {
+            shared.notify.ready.store(true, SeqCst);
+        }
+        Ok(count)
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.connection.lock();
+        unsafe {
+            (self.connection.lib.pw_stream_destroy)((*self.shared).stream)
+        };
+        self.connection.unlock();
+        unsafe { drop(Box::from_raw(self.shared)) };
+    }
+}
+
+/// State shared with the PipeWire thread for watching nodes.
+struct WatchShared {
+    /// Registry listener.
+    hook: SpaHook,
+    /// Wakes the future when nodes are added or removed, or the default
+    /// nodes change.
+    notify: Notify,
+    /// The `pw_registry` proxy, for binding the `default` metadata.
+    registry: *mut c_void,
+    /// The `pw_metadata` proxy for the `default` metadata, once bound.
+    metadata: *mut c_void,
+    /// Metadata listener.
+    metadata_hook: SpaHook,
+}
+
+unsafe extern "C" fn watch_global(
+    data: *mut c_void,
+    id: u32,
+    _permissions: u32,
+    type_: *const c_char,
+    _version: u32,
+    props: *const SpaDict,
+) {
+    let shared = &mut *data.cast::<WatchShared>();
+    if type_.is_null() {
+        return;
+    }
+    if CStr::from_ptr(type_).to_bytes() == b"PipeWire:Interface:Node" {
+        shared.notify.wake();
+    } else if !props.is_null()
+        && shared.metadata.is_null()
+        && is_default_metadata(type_, &*props)
+    {
+        let (registry_methods, object) =
+            methods::<PwRegistryMethods>(shared.registry);
+        shared.metadata = (registry_methods.bind)(
+            object,
+            id,
+            PW_TYPE_INTERFACE_METADATA.as_ptr().cast(),
+            PW_VERSION_METADATA,
+            0,
+        );
+        if !shared.metadata.is_null() {
+            let (metadata_methods, object) =
+                methods::<PwMetadataMethods>(shared.metadata);
+            (metadata_methods.add_listener)(
+                object,
+                &mut shared.metadata_hook,
+                &WATCH_METADATA_EVENTS,
+                data,
+            );
+        }
+    }
+}
+
+unsafe extern "C" fn watch_property(
+    data: *mut c_void,
+    _subject: u32,
+    _key: *const c_char,
+    _type: *const c_char,
+    _value: *const c_char,
+) -> c_int {
+    let shared = &*data.cast::<WatchShared>();
+    shared.notify.wake();
+    0
+}
+
+static WATCH_METADATA_EVENTS: PwMetadataEvents = PwMetadataEvents {
+    version: 0,
+    property: Some(watch_property),
+};
+
+unsafe extern "C" fn watch_global_remove(data: *mut c_void, _id: u32) {
+    // The type isn't known anymore, so always check.
+    let shared = &*data.cast::<WatchShared>();
+    shared.notify.wake();
+}
+
+static WATCH_EVENTS: PwRegistryEvents = PwRegistryEvents {
+    version: 0,
+    global: Some(watch_global),
+    global_remove: Some(watch_global_remove),
+};
+
+/// Registry listener that wakes when nodes are added or removed.
+pub(crate) struct Watch {
+    connection: &'static Connection,
+    /// The `pw_registry` proxy.
+    registry: *mut c_void,
+    /// Leaked shared box
+    shared: *mut WatchShared,
+}
+
+impl Watch {
+    pub(crate) fn notify(&self) -> &Notify {
+        unsafe { &(*self.shared).notify }
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        self.connection.lock();
+        unsafe {
+            (*self.shared).hook.remove();
+            if !(*self.shared).metadata.is_null() {
+                (*self.shared).metadata_hook.remove();
+                (self.connection.lib.pw_proxy_destroy)((*self.shared).metadata);
+            }
+            (self.connection.lib.pw_proxy_destroy)(self.registry);
+        }
+        self.connection.unlock();
+        unsafe { drop(Box::from_raw(self.shared)) };
+    }
+}
+
+/// Watch for nodes being added or removed, if the daemon is running.
+pub(crate) fn watch() -> Option<Watch> {
+    PIPEWIRE.with(|connection| {
+        let connection = (*connection)?;
+        let shared = Box::into_raw(Box::new(WatchShared {
+            hook: SpaHook::new(),
+            notify: Notify::default(),
+            registry: ptr::null_mut(),
+            metadata: ptr::null_mut(),
+            metadata_hook: SpaHook::new(),
+        }));
+        connection.lock();
+        let registry = unsafe {
+            let (core_methods, object) =
+                methods::<PwCoreMethods>(connection.core);
+            let registry =
+                (core_methods.get_registry)(object, PW_VERSION_REGISTRY, 0);
+            if !registry.is_null() {
+                (*shared).registry = registry;
+                let (registry_methods, object) =
+                    methods::<PwRegistryMethods>(registry);
+                (registry_methods.add_listener)(
+                    object,
+                    &mut (*shared).hook,
+                    &WATCH_EVENTS,
+                    shared.cast(),
+                );
+            }
+            registry
+        };
+        connection.unlock();
+        if registry.is_null() {
+            unsafe { drop(Box::from_raw(shared)) };
+            return None;
+        }
+        Some(Watch {
+            connection,
+            registry,
+            shared,
+        })
+    })
+}
+
+/// Create a device for a stream connected to a PipeWire node.
+fn device(id: String, name: String, stream: Stream) -> AudioDevice {
+    AudioDevice {
+        id,
+        name,
+        pcm: ptr::null_mut(),
+        hwp: ptr::null_mut(),
+        // Channels are converted by PipeWire.
+        supported: 0b0010_0011,
+        fds: Vec::new(),
+        stream: Some(stream),
+        format: SampleFormat::F32,
+        scratch: Vec::new(),
+        xruns: 0,
+        suspends: 0,
+        failed: None,
+    }
+}
+
+/// Open the default PipeWire node, if the daemon is running.
+pub(crate) fn open_default(input: bool) -> Option<AudioDevice> {
+    PIPEWIRE.with(|connection| {
+        let stream = Stream::new((*connection)?, input, None, false)?;
+        Some(device("default".to_string(), "Default".to_string(), stream))
+    })
+}
+
+/// Open the PipeWire node named `name`, if the daemon is running and the node
+/// exists.
+pub(crate) fn open(name: &str, input: bool) -> Option<AudioDevice> {
+    PIPEWIRE.with(|connection| {
+        let connection = (*connection)?;
+        let node = connection
+            .nodes(input)
+            .into_iter()
+            .find(|node| node.name == name)?;
+        let stream = Stream::new(connection, input, Some(&node), false)?;
+        Some(device(node.name, node.description, stream))
+    })
+}
+
+/// Open a stream recording what's played through the PipeWire output node
+/// `id` (or the default output), if the daemon is running and it exists.
+pub(crate) fn open_monitor(id: &str) -> Option<AudioDevice> {
+    PIPEWIRE.with(|connection| {
+        let connection = (*connection)?;
+        let node = match id {
+            "default" => None,
+            _ => Some(
+                connection
+                    .nodes(false)
+                    .into_iter()
+                    .find(|node| node.name == id)?,
+            ),
+        };
+        let stream = Stream::new(connection, true, node.as_ref(), true)?;
+        let (id, name) = match node {
+            Some(node) => (node.name, node.description),
+            None => ("default".to_string(), "Default".to_string()),
+        };
+        Some(device(
+            format!("{}.monitor", id),
+            format!("Monitor of {}", name),
+            stream,
+        ))
+    })
+}
+
+/// Return a list of PipeWire nodes, if the daemon is running.
+pub(crate) fn device_list<D: SoundDevice, F: Fn(D) -> T, T>(
+    abstrakt: &F,
+) -> Option<Vec<Result<T, (String, AudioError)>>> {
+    PIPEWIRE.with(|connection| {
+        let connection = (*connection)?;
+        let mut devices = Vec::new();
+        for node in connection.nodes(D::INPUT) {
+            if let Some(stream) =
+                Stream::new(connection, D::INPUT, Some(&node), false)
+            {
+                devices.push(Ok(abstrakt(D::from(device(
+                    node.name,
+                    node.description,
+                    stream,
+                )))));
+            }
+        }
+        Some(devices)
+    })
+}
+
+/// Return the names and descriptions of PipeWire nodes, without connecting
+/// streams to them, if the daemon is running.
+pub(crate) fn device_names(input: bool) -> Option<Vec<(String, String)>> {
+    PIPEWIRE.with(|connection| {
+        let connection = (*connection)?;
+        let nodes = connection.nodes(input).into_iter();
+        Some(nodes.map(|node| (node.name, node.description)).collect())
+    })
+}
+
+/// Return the name of the default PipeWire node (the ID it's listed with), if
+/// the daemon is running and there is one.
+pub(crate) fn default_id(input: bool) -> Option<String> {
+    PIPEWIRE.with(|connection| (*connection)?.default_node(input))
+}
@@ -10,13 +10,15 @@
 #![allow(unsafe_code)]
 
 use std::{
+    ffi::CString,
     fmt::{Display, Error, Formatter},
     future::Future,
     marker::PhantomData,
-    os::raw::c_void,
+    os::raw::{c_char, c_void},
     pin::Pin,
     sync::atomic::{AtomicBool, Ordering::SeqCst},
     task::{Context, Poll},
+    time::Duration,
 };
 
 use fon::{
@@ -26,23 +28,41 @@ use fon::{
 };
 
 use super::{
-    asound, pcm_hw_params, AudioDevice, SndPcmState, SndPcmStream, SoundDevice,
-    DEFAULT,
+    pcm_hw_params, AudioDevice, Microphone, Pcm, SndPcmState, SndPcmStream,
+    SoundDevice, DEFAULT,
 };
+use crate::{AudioError, DeviceCaps, Latency, SampleFormat, SpeakerPosition};
 
 struct SpeakersInner {
     /// ALSA PCM type for both speakers and microphones.
     device: AudioDevice,
     /// Index into audio frames to start writing.
     starti: usize,
-    /// Raw buffer of audio yet to be played.
+    /// Raw buffer of audio yet to be played, with room for two periods so
+    /// that frames left over by a short write don't need to be moved.
     buffer: Vec<Ch32>,
+    /// Index of the first frame in `buffer` that hasn't been written.
+    head: usize,
     /// Resampler context for speakers sink.
     resampler: ([Ch32; 6], f64),
     /// The number of frames in the buffer.
     period: u16,
+    /// Period and number of periods set with `set_period()`, `0` for the
+    /// defaults.
+    request: (u16, u8),
+    /// Number of frames the device buffers, `0` if unknown.
+    buffer_frames: usize,
     /// Speakers are locked
     locked: AtomicBool,
+    /// Speakers were dropped while locked, so the sink frees them
+    orphaned: AtomicBool,
+    /// Paused, so there's nothing to drain.
+    paused: bool,
+    /// Resumed after a pause (or flushed), so try writing before waiting for
+    /// the device.
+    resumed: bool,
+    /// Finish playing queued audio when dropped.
+    drain: bool,
 }
 
 /// ALSA Speakers connection.
@@ -55,12 +75,116 @@ pub(crate) struct Speakers {
     inner: *mut SpeakersInner,
 }
 
+/// Get the period of interleaved audio in `buffer` starting at frame `head`,
+/// which is written to the device next.
+fn window(buffer: &[Ch32], head: usize, period: u16) -> &[Ch32] {
+    let period = usize::from(period);
+    let channels = buffer.len() / (2 * period).max(1);
+    &buffer[head * channels..(head + period) * channels]
+}
+
+/// Result of writing a period to the device.
+#[derive(Debug, PartialEq)]
+enum Written {
+    /// Number of frames written.
+    Frames(usize),
+    /// The device is full, so wait until it wakes.
+    Wait,
+    /// Interrupted, so try again right away.
+    Retry,
+}
+
+/// Write the period in `buffer` to `pcm`, recovering from underruns and
+/// suspends.  Errors that can't be recovered from mark the device as failed.
+fn write_period<P: Pcm>(
+    pcm: &mut P,
+    buffer: &[Ch32],
+    period: u16,
+) -> Result<Written, AudioError> {
+    let error = match pcm.write(buffer, period) {
+        Ok(len) => return Ok(Written::Frames(len)),
+        Err(error) => error,
+    };
+    match error {
+        // Edge-triggered epoll should only go into pending mode if read/write
+        // call results in EAGAIN (according to epoll man page)
+        -11 => Ok(Written::Wait),
+        // -EINTR, or -EBUSY from dmix, so try again.
+        -4 | -16 => Ok(Written::Retry),
+        // Player samples are not generated fast enough
+        -32 if pcm.state() == SndPcmState::Xrun => {
+            pcm.count_xrun();
+            pcm.prepare()
+                .map_err(|_| pcm.fail_with(AudioError::XrunUnrecoverable))?;
+            pcm.write(buffer, period)
+                .map(Written::Frames)
+                .map_err(|_| pcm.fail_with(AudioError::XrunUnrecoverable))
+        }
+        // Stream got suspended, try to recover.
+        -86 => {
+            if !pcm.recover()? {
+                return Ok(Written::Retry);
+            }
+            pcm.write(buffer, period)
+                .map(Written::Frames)
+                .map_err(|_| pcm.fail_with(AudioError::XrunUnrecoverable))
+        }
+        // -ENODEV, -ENOTTY, -EIO, -EBADFD and anything else
+        _ => Err(pcm.fail(error)),
+    }
+}
+
+/// Move `head` past `len` frames written from the period starting there, in
+/// an interleaved `buffer` with room for two periods, returning the new head
+/// and the number of frames left over.  The rest of the new period is
+/// silenced, until the sink writes to it.
+///
+/// The frames left over stay where they are, unless the next period wouldn't
+/// fit after them, so this doesn't allocate or (usually) move any samples.
+fn advance(
+    buffer: &mut [Ch32],
+    head: usize,
+    len: usize,
+    period: u16,
+) -> (usize, usize) {
+    let period = usize::from(period);
+    let channels = buffer.len() / (2 * period).max(1);
+    let starti = period - len;
+    let head = if starti == 0 {
+        0
+    } else if head + len <= period {
+        head + len
+    } else {
+        let left = (head + len) * channels..(head + period) * channels;
+        buffer.copy_within(left, 0);
+        0
+    };
+    buffer[(head + starti) * channels..(head + period) * channels]
+        .fill(Ch32::MID);
+    (head, starti)
+}
+
 impl Drop for Speakers {
     fn drop(&mut self) {
-        // Safety
+        // If the sink is still alive, it frees the speakers when dropped.
         if unsafe { (*self.inner).locked.load(SeqCst) } {
-            eprintln!("Speakers dropped before dropping sink");
-            std::process::exit(1);
+            unsafe { (*self.inner).orphaned.store(true, SeqCst) };
+            return;
+        }
+
+        let inner = unsafe { &mut *self.inner };
+        if inner.drain
+            && !inner.paused
+            && self.channels != 0
+            && inner.device.failed.is_none()
+        {
+            // Wait at most twice as long as the queued audio takes to play.
+            let frames =
+                inner.device.delay().unwrap_or(0) + usize::from(inner.period);
+            let rate = self.sample_rate.unwrap_or(48_000.0);
+            let timeout = Duration::from_secs_f64(2.0 * frames as f64 / rate);
+            let window = window(&inner.buffer, inner.head, inner.period);
+            inner.device.drain(window, inner.period, timeout);
         }
 
         unsafe { drop(Box::from_raw(self.inner)) };
@@ -81,12 +205,6 @@ impl SoundDevice for Speakers {
 
 impl Display for Speakers {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        // Safety
-        if unsafe { (*self.inner).locked.load(SeqCst) } {
-            eprintln!("Tried to display speakers before dropping sink");
-            std::process::exit(1);
-        }
-
         unsafe { f.write_str((*self.inner).device.name.as_str()) }
     }
 }
@@ -100,86 +218,261 @@ impl From<AudioDevice> for Speakers {
                 device,
                 starti: 0,
                 buffer: Vec::new(),
+                head: 0,
                 resampler: ([Ch32::MID; 6], 0.0),
                 period: 0,
+                request: (0, 0),
+                buffer_frames: 0,
                 locked: AtomicBool::new(false),
+                orphaned: AtomicBool::new(false),
+                paused: false,
+                resumed: false,
+                drain: true,
             })),
         }
     }
 }
 
-impl Default for Speakers {
-    fn default() -> Self {
-        let (pcm, hwp, supported) =
-            super::open(DEFAULT.as_ptr().cast(), SndPcmStream::Playback)
-                .unwrap();
-        Self::from(AudioDevice {
-            name: "Default".to_string(),
+impl Speakers {
+    /// Open the default speakers.
+    pub(crate) fn try_default() -> Result<Self, AudioError> {
+        if let Some(device) = super::pipewire::open_default(false) {
+            return Ok(Self::from(device));
+        }
+        if !super::available() {
+            return Err(AudioError::BackendUnavailable);
+        }
+        Self::open_pcm(
+            DEFAULT.as_ptr().cast(),
+            "default".to_string(),
+            "Default".to_string(),
+        )
+    }
+
+    /// Open the speakers with a PipeWire node name, or an ALSA PCM name
+    /// such as `hw:CARD=USB,DEV=0`.
+    pub(crate) fn open(name: &str) -> Result<Self, AudioError> {
+        if let Some(device) = super::pipewire::open(name, false) {
+            return Ok(Self::from(device));
+        }
+        // A name with a NUL byte can't name a PCM.
+        let pcm_name = CString::new(name).map_err(|_| AudioError::NoDevice)?;
+        Self::open_pcm(pcm_name.as_ptr(), name.to_string(), name.to_string())
+    }
+
+    /// Open the ALSA PCM `pcm_name`, reported with `id` and `name`.
+    fn open_pcm(
+        pcm_name: *const c_char,
+        id: String,
+        name: String,
+    ) -> Result<Self, AudioError> {
+        let (pcm, hwp, supported, format) =
+            super::open(pcm_name, SndPcmStream::Playback)?;
+        Ok(Self::from(AudioDevice {
+            id,
+            name,
             pcm,
             hwp,
             supported,
             fds: Vec::new(),
-        })
+            stream: None,
+            format,
+            scratch: Vec::new(),
+            xruns: 0,
+            suspends: 0,
+            failed: None,
+        }))
+    }
+
+    /// Open a microphone that records what's played through the speakers.
+    /// Only PipeWire has monitors to record from.
+    pub(crate) fn loopback(&self) -> Result<Microphone, AudioError> {
+        let device = unsafe { &(*self.inner).device };
+        if device.stream.is_none() {
+            return Err(AudioError::NoDevice);
+        }
+        super::pipewire::open_monitor(&device.id)
+            .map(Microphone::from)
+            .ok_or(AudioError::NoDevice)
     }
-}
 
-impl Speakers {
     /// Attempt to configure the speaker for a specific number of channels.
-    fn set_channels<F>(&mut self, inner: &mut SpeakersInner) -> Option<bool>
+    fn set_channels<F>(
+        &mut self,
+        inner: &mut SpeakersInner,
+    ) -> Result<bool, AudioError>
     where
         F: Frame<Chan = Ch32>,
     {
         if F::CHAN_COUNT != self.channels.into() {
-            if !matches!(F::CHAN_COUNT, 1 | 2 | 6) {
-                panic!("Unknown speaker configuration")
-            }
-            self.channels = F::CHAN_COUNT as u8;
+            AudioError::check_channels(F::CHAN_COUNT, inner.device.supported)?;
             // Configure Hardware Parameters
             pcm_hw_params(
                 &inner.device,
-                self.channels,
+                F::CHAN_COUNT as u8,
                 &mut inner.buffer,
                 &mut self.sample_rate,
                 &mut inner.period,
-            )?;
-            Some(true)
+                inner.request,
+                &mut inner.buffer_frames,
+            )
+            .ok_or(AudioError::UnsupportedChannelCount {
+                requested: F::CHAN_COUNT,
+                supported: inner.device.supported,
+            })?;
+            // Allocate the room for two periods up front, so the buffer is
+            // never resized while playing.
+            let samples = usize::from(inner.period) * F::CHAN_COUNT;
+            inner.buffer.clear();
+            inner.buffer.resize(2 * samples, Ch32::MID);
+            inner.head = 0;
+            inner.starti = 0;
+            self.channels = F::CHAN_COUNT as u8;
+            Ok(true)
         } else {
-            Some(false)
+            Ok(false)
         }
     }
 
     /// Generate an audio sink for the user to fill.
-    pub(crate) fn play<F>(&mut self) -> SpeakersSink<F>
+    pub(crate) fn play<F>(&mut self) -> Result<SpeakersSink<F>, AudioError>
     where
         F: Frame<Chan = Ch32>,
     {
         // Always called after ready, so should be safe
         let inner = unsafe { self.inner.as_mut().unwrap() };
         // Change number of channels, if different than last call.
-        self.set_channels::<F>(inner)
-            .expect("Speaker::play() called with invalid configuration");
+        if let Err(error) = self.set_channels::<F>(inner) {
+            // Unlock, since no sink will be created.
+            inner.locked.store(false, SeqCst);
+            return Err(error);
+        }
         // Convert the resampler to the target speaker configuration.
         let resampler = Resampler::<F>::new(
             Surround32::from_channels(&inner.resampler.0[..]).convert(),
             inner.resampler.1,
         );
         // Create a sink that borrows this speaker's buffer mutably.
-        SpeakersSink(inner, resampler, PhantomData, self.sample_rate.unwrap())
+        Ok(SpeakersSink(
+            inner,
+            resampler,
+            PhantomData,
+            self.sample_rate.unwrap(),
+        ))
     }
 
     pub(crate) fn channels(&self) -> u8 {
-        // Safety
+        // Can't reconfigure while the sink is alive.
         if unsafe { (*self.inner).locked.load(SeqCst) } {
-            eprintln!("Tried to poll speakers before dropping sink");
-            std::process::exit(1);
+            return 0;
         }
 
         unsafe { (*self.inner).device.supported }
     }
+
+    pub(crate) fn id(&self) -> &str {
+        unsafe { (*self.inner).device.id.as_str() }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        unsafe { (*self.inner).device.name.as_str() }
+    }
+
+    /// Get the number of underruns since the device was opened.
+    pub(crate) fn xruns(&self) -> usize {
+        unsafe { (*self.inner).device.xrun_count() }
+    }
+
+    /// Get the number of times the device was resumed after being
+    /// suspended.
+    pub(crate) fn suspends(&self) -> usize {
+        unsafe { (*self.inner).device.suspends }
+    }
+
+    /// Get the speaker position of each channel of the configured device.
+    pub(crate) fn channel_map(&self) -> Vec<SpeakerPosition> {
+        unsafe { (*self.inner).device.channel_map() }
+    }
+
+    /// Get the sample rate the device was configured with, `None` until
+    /// configured.
+    pub(crate) fn sample_rate(&self) -> Option<f64> {
+        self.sample_rate
+    }
+
+    /// Get the capabilities of the device, without reconfiguring it.
+    pub(crate) fn caps(&self) -> DeviceCaps {
+        let inner = unsafe { &*self.inner };
+        inner.device.caps(self.sample_rate, inner.period)
+    }
+
+    /// Request periods of `frames`, with room for `count` periods in the
+    /// device's buffer, reconfiguring the device for the next sink or stream.
+    pub(crate) fn set_period(&mut self, frames: u16, count: u8) {
+        unsafe { (*self.inner).request = (frames, count) };
+        self.channels = 0;
+    }
+
+    /// Get the configured period, `0` until configured.
+    pub(crate) fn period(&self) -> u16 {
+        unsafe { (*self.inner).period }
+    }
+
+    /// Get the number of frames the device buffers, if known (ALSA reports
+    /// it once configured).
+    pub(crate) fn buffer_frames(&self) -> Option<usize> {
+        match unsafe { (*self.inner).buffer_frames } {
+            0 => None,
+            frames => Some(frames),
+        }
+    }
+
+    pub(crate) fn format(&self) -> SampleFormat {
+        unsafe { (*self.inner).device.format }
+    }
+
+    /// Set whether queued audio finishes playing when dropped.
+    pub(crate) fn set_drain(&mut self, drain: bool) {
+        unsafe { (*self.inner).drain = drain }
+    }
+
+    /// Stop playback without losing the configuration.
+    pub(crate) fn pause(&mut self) {
+        let inner = unsafe { &mut *self.inner };
+        inner.device.pause();
+        inner.paused = true;
+    }
+
+    /// Drop queued audio, in wavy's buffer and the device's, and forget the
+    /// resampler's last frame.
+    pub(crate) fn flush(&mut self) {
+        let inner = unsafe { &mut *self.inner };
+        inner.buffer.fill(Ch32::MID);
+        inner.starti = 0;
+        inner.head = 0;
+        inner.resampler = ([Ch32::MID; 6], 0.0);
+        // Unconfigured devices have nothing queued.
+        if self.channels == 0 || inner.device.failed.is_some() {
+            return;
+        }
+        inner.device.flush();
+        // A prepared PCM may not wake again until written to.
+        if !inner.paused {
+            inner.resumed = true;
+        }
+    }
+
+    /// Continue playback after [`pause()`](Self::pause).
+    pub(crate) fn resume(&mut self) {
+        let inner = unsafe { &mut *self.inner };
+        inner.device.resume();
+        inner.paused = false;
+        inner.resumed = true;
+    }
 }
 
 impl Future for Speakers {
-    type Output = ();
+    type Output = Result<(), AudioError>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         // Get mutable reference to speakers.
@@ -187,123 +480,57 @@ impl Future for Speakers {
 
         // Safety
         if unsafe { (*this.inner).locked.load(SeqCst) } {
-            eprintln!("Tried to poll speakers before dropping sink");
-            std::process::exit(1);
+            return Poll::Ready(Err(AudioError::DeviceLocked));
         }
         //
         let inner = unsafe { this.inner.as_mut().unwrap() };
 
         // If speaker is unconfigured, return Ready to configure and play.
         if this.channels == 0 {
-            let _ = inner.device.start();
+            if inner.device.fds.is_empty() {
+                let _ = inner.device.start();
+            }
             inner.locked.store(true, SeqCst);
-            return Poll::Ready(());
+            return Poll::Ready(Ok(()));
         }
 
-        // Check if not woken, then yield.
-        let mut pending = true;
-        for fd in &inner.device.fds {
-            if !fd.should_yield() {
-                pending = false;
-                break;
-            }
+        // Once failed (such as by being unplugged), the device can't be used
+        // anymore.
+        if let Some(error) = inner.device.failed {
+            return Poll::Ready(Err(error));
         }
 
-        if pending {
+        // Check if not woken, then yield.  After resuming, the device may
+        // have room without waking again, so try writing first.
+        if !std::mem::take(&mut inner.resumed)
+            && inner.device.should_yield(cx.waker())
+        {
             return Poll::Pending;
         }
 
         // Attempt to write remaining internal speaker buffer to the speakers.
-        let result = unsafe {
-            asound::pcm::writei(
-                inner.device.pcm,
-                inner.buffer.as_ptr(),
-                inner.period.into(),
-            )
-        };
-
-        // Check if it succeeds, then return Ready.
-        let len = match result {
-            Ok(len) => len,
-            Err(error) => {
-                match error {
-                    // Edge-triggered epoll should only go into pending mode if
-                    // read/write call results in EAGAIN (according to epoll man
-                    // page)
-                    -11 => {
-                        /* Pending */
-                        for fd in &inner.device.fds {
-                            // Register waker, and then return not ready.
-                            fd.register_waker(cx.waker());
-                        }
-                        return Poll::Pending;
-                    }
-                    -32 => {
-                        match unsafe { asound::pcm::state(inner.device.pcm) } {
-                            SndPcmState::Xrun => {
-                                // Player samples are not generated fast enough
-                                unsafe {
-                                    asound::pcm::prepare(inner.device.pcm)
-                                        .unwrap();
-                                    asound::pcm::writei(
-                                        inner.device.pcm,
-                                        inner.buffer.as_ptr(),
-                                        inner.period.into(),
-                                    )
-                                    .unwrap()
-                                }
-                            }
-                            st => {
-                                eprintln!(
-                            "Incorrect state = {:?} (XRUN): Report Bug to \
-                             https://github.com/ardaku/wavy/issues/new",
-                            st
-                        );
-                                unreachable!()
-                            }
-                        }
-                    }
-                    -77 => {
-                        eprintln!(
-                            "Incorrect state (-EBADFD): Report Bug to \
-                         https://github.com/ardaku/wavy/issues/new"
-                        );
-                        unreachable!()
-                    }
-                    -86 => {
-                        eprintln!(
-                            "Stream got suspended, trying to recover… \
-                         (-ESTRPIPE)"
-                        );
-
-                        // Prepare, so we keep getting samples.
-                        unsafe {
-                            // Whether this works or not, we want to prepare.
-                            let _ = asound::pcm::resume(inner.device.pcm);
-                            // Prepare
-                            asound::pcm::prepare(inner.device.pcm).unwrap();
-                            asound::pcm::writei(
-                                inner.device.pcm,
-                                inner.buffer.as_ptr(),
-                                inner.period.into(),
-                            )
-                            .unwrap()
-                        }
-                    }
-                    _ => unreachable!(),
-                }
+        let window = window(&inner.buffer, inner.head, inner.period);
+        let len = match write_period(&mut inner.device, window, inner.period) {
+            Ok(Written::Frames(len)) => len,
+            Ok(Written::Wait) => {
+                // Register waker, and then return not ready.
+                inner.device.register_waker(cx.waker());
+                return Poll::Pending;
             }
+            Ok(Written::Retry) => {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            Err(error) => return Poll::Ready(Err(error)),
         };
 
-        // Shift buffer.
-        inner.buffer.drain(..len * this.channels as usize);
-        inner.starti = inner.buffer.len() / this.channels as usize;
-        inner
-            .buffer
-            .resize(inner.period as usize * this.channels as usize, Ch32::MID);
+        let (head, starti) =
+            advance(&mut inner.buffer, inner.head, len, inner.period);
+        inner.head = head;
+        inner.starti = starti;
         // Ready for more samples.
         inner.locked.store(true, SeqCst);
-        Poll::Ready(())
+        Poll::Ready(Ok(()))
     }
 }
 
@@ -314,6 +541,15 @@ pub(crate) struct SpeakersSink<F: Frame<Chan = Ch32>>(
     f64,
 );
 
+impl<F: Frame<Chan = Ch32>> SpeakersSink<F> {
+    pub(crate) fn latency(&self) -> Latency {
+        let speakers = unsafe { self.0.as_ref().unwrap() };
+        // Before the device starts, assume a full buffer.
+        let delay = speakers.device.delay().unwrap_or(speakers.period.into());
+        Latency::new(speakers.starti + delay, self.3)
+    }
+}
+
 impl<F: Frame<Chan = Ch32>> Sink<F> for SpeakersSink<F> {
     fn sample_rate(&self) -> f64 {
         self.3
@@ -325,7 +561,8 @@ impl<F: Frame<Chan = Ch32>> Sink<F> for SpeakersSink<F> {
 
     fn buffer(&mut self) -> &mut [F] {
         let speakers = unsafe { self.0.as_mut().unwrap() };
-        let data = speakers.buffer.as_mut_ptr().cast();
+        let head = speakers.head * F::CHAN_COUNT;
+        let data = speakers.buffer[head..].as_mut_ptr().cast();
         let count = speakers.period.into();
         unsafe {
             &mut std::slice::from_raw_parts_mut(data, count)[speakers.starti..]
@@ -351,5 +588,210 @@ impl<F: Frame<Chan = Ch32>> Drop for SpeakersSink<F> {
         speakers.resampler.1 = self.1.index() % 1.0;
         // Unlock
         speakers.locked.store(false, SeqCst);
+        // Free the speakers if they were dropped first.
+        if speakers.orphaned.load(SeqCst) {
+            unsafe { drop(Box::from_raw(self.0)) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::StreamState;
+
+    /// Run the speakers' buffer through `periods` writes of the lengths from
+    /// `lengths`, like the device accepting whole periods or less, checking
+    /// that frames are played in order.
+    fn play(periods: usize, lengths: &[usize]) {
+        const PERIOD: u16 = 256;
+        const CHANNELS: usize = 2;
+        let period = usize::from(PERIOD);
+        let mut buffer = vec![Ch32::MID; 2 * period * CHANNELS];
+        let (mut head, mut starti) = (0, 0);
+        // Frames queued but not played, and the next frame to queue.
+        let mut queued = VecDeque::with_capacity(period);
+        let mut next = 0u32;
+
+        for i in 0..periods {
+            // The sink fills the rest of the period.
+            for frame in head + starti..head + period {
+                let sample = Ch32::new(next as f32);
+                buffer[frame * CHANNELS..(frame + 1) * CHANNELS].fill(sample);
+                queued.push_back(next);
+                next += 1;
+            }
+            // The device plays the first `len` frames of the period.
+            let len = lengths[i % lengths.len()];
+            let played = window(&buffer, head, PERIOD);
+            for frame in played.chunks_exact(CHANNELS).take(len) {
+                let expected = queued.pop_front().unwrap() as f32;
+                assert_eq!(f32::from(frame[0]), expected);
+                assert_eq!(f32::from(frame[1]), expected);
+            }
+            let advanced = advance(&mut buffer, head, len, PERIOD);
+            head = advanced.0;
+            starti = advanced.1;
+            assert_eq!(starti, queued.len());
+        }
+    }
+
+    /// PCM that fails writes with errors given ahead of time.
+    struct FakePcm {
+        /// Results of the following writes (full periods once empty).
+        writes: VecDeque<Result<usize, isize>>,
+        state: SndPcmState,
+        prepare: Result<(), i64>,
+        resume: Result<(), i64>,
+        xruns: usize,
+        suspends: usize,
+        failed: Option<AudioError>,
+    }
+
+    impl FakePcm {
+        fn new(writes: &[Result<usize, isize>]) -> Self {
+            Self {
+                writes: writes.iter().copied().collect(),
+                state: SndPcmState::Running,
+                prepare: Ok(()),
+                resume: Ok(()),
+                xruns: 0,
+                suspends: 0,
+                failed: None,
+            }
+        }
+
+        fn write(&mut self) -> Result<Written, AudioError> {
+            write_period(self, &[Ch32::MID; 128], 64)
+        }
+    }
+
+    impl Pcm for FakePcm {
+        fn write(&mut self, _: &[Ch32], period: u16) -> Result<usize, isize> {
+            self.writes.pop_front().unwrap_or(Ok(period.into()))
+        }
+
+        fn state(&self) -> SndPcmState {
+            self.state
+        }
+
+        fn prepare(&mut self) -> Result<(), i64> {
+            self.prepare
+        }
+
+        fn resume(&mut self) -> Result<(), i64> {
+            self.resume
+        }
+
+        fn count_xrun(&mut self) {
+            self.xruns += 1;
+        }
+
+        fn count_suspend(&mut self) {
+            self.suspends += 1;
+        }
+
+        fn fail_with(&mut self, error: AudioError) -> AudioError {
+            self.failed = Some(error);
+            error
+        }
+    }
+
+    #[test]
+    fn write_errors_retried() {
+        let mut pcm = FakePcm::new(&[Ok(40)]);
+        assert_eq!(pcm.write(), Ok(Written::Frames(40)));
+        // -EAGAIN waits for the device.
+        let mut pcm = FakePcm::new(&[Err(-11)]);
+        assert_eq!(pcm.write(), Ok(Written::Wait));
+        // -EINTR and -EBUSY try again.
+        let mut pcm = FakePcm::new(&[Err(-4)]);
+        assert_eq!(pcm.write(), Ok(Written::Retry));
+        let mut pcm = FakePcm::new(&[Err(-16)]);
+        assert_eq!(pcm.write(), Ok(Written::Retry));
+        assert_eq!(pcm.failed, None);
+    }
+
+    #[test]
+    fn write_underrun() {
+        // -EPIPE in the XRUN state prepares the PCM and writes again.
+        let mut pcm = FakePcm::new(&[Err(-32)]);
+        pcm.state = SndPcmState::Xrun;
+        assert_eq!(pcm.write(), Ok(Written::Frames(64)));
+        assert_eq!((pcm.xruns, pcm.failed), (1, None));
+        // Unless it can't be prepared,
+        let mut pcm = FakePcm::new(&[Err(-32)]);
+        pcm.state = SndPcmState::Xrun;
+        pcm.prepare = Err(-5);
+        assert_eq!(pcm.write(), Err(AudioError::XrunUnrecoverable));
+        assert_eq!(pcm.failed, Some(AudioError::XrunUnrecoverable));
+        // or the write fails again.
+        let mut pcm = FakePcm::new(&[Err(-32), Err(-32)]);
+        pcm.state = SndPcmState::Xrun;
+        assert_eq!(pcm.write(), Err(AudioError::XrunUnrecoverable));
+        assert_eq!(pcm.failed, Some(AudioError::XrunUnrecoverable));
+        // -EPIPE in another state fails with the state.
+        let mut pcm = FakePcm::new(&[Err(-32)]);
+        pcm.state = SndPcmState::Setup;
+        let error = AudioError::InvalidState(StreamState::Setup);
+        assert_eq!(pcm.write(), Err(error));
+        assert_eq!((pcm.xruns, pcm.failed), (0, Some(error)));
+    }
+
+    #[test]
+    fn write_suspended() {
+        // -ESTRPIPE resumes and prepares the PCM, and writes again.
+        let mut pcm = FakePcm::new(&[Err(-86)]);
+        assert_eq!(pcm.write(), Ok(Written::Frames(64)));
+        assert_eq!((pcm.suspends, pcm.failed), (1, None));
+        // Hardware that can't resume is prepared.
+        let mut pcm = FakePcm::new(&[Err(-86)]);
+        pcm.resume = Err(-38);
+        assert_eq!(pcm.write(), Ok(Written::Frames(64)));
+        assert_eq!((pcm.suspends, pcm.failed), (1, None));
+        // Hardware still waking up is tried again.
+        let mut pcm = FakePcm::new(&[Err(-86)]);
+        pcm.resume = Err(-11);
+        assert_eq!(pcm.write(), Ok(Written::Retry));
+        assert_eq!((pcm.suspends, pcm.failed), (0, None));
+        // Fails if it can't be prepared,
+        let mut pcm = FakePcm::new(&[Err(-86)]);
+        pcm.prepare = Err(-5);
+        let error = AudioError::InvalidState(StreamState::Suspended);
+        assert_eq!(pcm.write(), Err(error));
+        assert_eq!(pcm.failed, Some(error));
+        // or the write fails again.
+        let mut pcm = FakePcm::new(&[Err(-86), Err(-86)]);
+        assert_eq!(pcm.write(), Err(AudioError::XrunUnrecoverable));
+        assert_eq!(pcm.failed, Some(AudioError::XrunUnrecoverable));
+    }
+
+    #[test]
+    fn write_failed() {
+        // -ENODEV and -ENOTTY are unplugged devices.
+        for errno in [-19, -25] {
+            let mut pcm = FakePcm::new(&[Err(errno)]);
+            assert_eq!(pcm.write(), Err(AudioError::DeviceDisconnected));
+            assert_eq!(pcm.failed, Some(AudioError::DeviceDisconnected));
+        }
+        // Other errors (such as -EIO and -EBADFD) fail with the state.
+        let mut pcm = FakePcm::new(&[Err(-5)]);
+        pcm.state = SndPcmState::Disconnected;
+        assert_eq!(pcm.write(), Err(AudioError::DeviceDisconnected));
+        let mut pcm = FakePcm::new(&[Err(-77)]);
+        pcm.state = SndPcmState::Open;
+        let error = AudioError::InvalidState(StreamState::Open);
+        assert_eq!(pcm.write(), Err(error));
+        assert_eq!(pcm.failed, Some(error));
+    }
+
+    #[test]
+    fn frames_played_in_order() {
+        // Full periods, and short writes that leave frames over (some of
+        // which need to be moved back to the start of the buffer).
+        play(1000, &[256]);
+        play(1000, &[256, 100, 256, 0, 17, 256, 255, 1, 200, 56]);
     }
 }
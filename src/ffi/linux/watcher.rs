@@ -0,0 +1,120 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+#![allow(unsafe_code)]
+
+use std::{
+    os::raw::{c_char, c_int, c_void},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use super::pipewire;
+
+const IN_NONBLOCK: c_int = 0o4000;
+const IN_CLOEXEC: c_int = 0o2_000_000;
+const IN_CREATE: u32 = 0x100;
+const IN_DELETE: u32 = 0x200;
+const EPOLLIN: u32 = 0x001;
+
+/// Directory ALSA device nodes are created in.
+const DEV_SND: &[u8] = b"/dev/snd\0";
+
+extern "C" {
+    fn inotify_init1(flags: c_int) -> c_int;
+    fn inotify_add_watch(fd: c_int, path: *const c_char, mask: u32) -> c_int;
+    fn read(fd: c_int, buf: *mut c_void, count: usize) -> isize;
+    fn close(fd: c_int) -> c_int;
+}
+
+/// Wakes when audio devices may have been plugged in or unplugged.
+pub(crate) struct Watcher {
+    /// PipeWire registry listener, if PipeWire is running.
+    pipewire: Option<pipewire::Watch>,
+    /// Inotify file descriptor watching `/dev/snd`, otherwise.
+    inotify: Option<smelling_salts::Device>,
+}
+
+impl Watcher {
+    /// Linux has notifications, so the polling interval isn't used.
+    pub(crate) fn new(_interval: Duration) -> Self {
+        if let Some(watch) = pipewire::watch() {
+            return Self {
+                pipewire: Some(watch),
+                inotify: None,
+            };
+        }
+        let inotify = unsafe {
+            let fd = inotify_init1(IN_NONBLOCK | IN_CLOEXEC);
+            if fd < 0 {
+                None
+            } else if inotify_add_watch(
+                fd,
+                DEV_SND.as_ptr().cast(),
+                IN_CREATE | IN_DELETE,
+            ) < 0
+            {
+                close(fd);
+                None
+            } else {
+                Some(smelling_salts::Device::new(
+                    fd,
+                    smelling_salts::Watcher::from_raw(EPOLLIN),
+                ))
+            }
+        };
+        Self {
+            pipewire: None,
+            inotify,
+        }
+    }
+
+    /// Returns `Ready` when the device list should be checked again.
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(watch) = &self.pipewire {
+            if watch.notify().should_yield(cx.waker()) {
+                return Poll::Pending;
+            }
+            return Poll::Ready(());
+        }
+        let device = match &self.inotify {
+            Some(device) => device,
+            // Can't watch, so never changes.
+            None => return Poll::Pending,
+        };
+        if device.should_yield() {
+            return Poll::Pending;
+        }
+        // Drain events until -EAGAIN, any event means the list changed.
+        let mut changed = false;
+        let mut buffer = [0u8; 4096];
+        while unsafe {
+            read(device.fd(), buffer.as_mut_ptr().cast(), buffer.len())
+        } > 0
+        {
+            changed = true;
+        }
+        if changed {
+            Poll::Ready(())
+        } else {
+            device.register_waker(cx.waker());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        if let Some(device) = &mut self.inotify {
+            let fd = device.fd();
+            device.old();
+            unsafe { close(fd) };
+        }
+    }
+}
@@ -0,0 +1,776 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+//! AudioUnit bindings (AUHAL on macOS, RemoteIO on iOS).
+
+#![allow(unsafe_code)]
+
+use std::{
+    cell::UnsafeCell,
+    mem::size_of,
+    os::raw::c_void,
+    ptr,
+    sync::atomic::{
+        AtomicBool, AtomicUsize,
+        Ordering::{AcqRel, Acquire, Relaxed, Release, SeqCst},
+    },
+    task::Waker,
+};
+
+use fon::chan::Ch32;
+
+type OSStatus = i32;
+type AudioUnit = *mut c_void;
+pub(crate) type AudioDeviceId = u32;
+
+const NO_ERR: OSStatus = 0;
+
+const fn four_cc(code: &[u8; 4]) -> u32 {
+    u32::from_be_bytes(*code)
+}
+
+const K_AUDIO_UNIT_TYPE_OUTPUT: u32 = four_cc(b"auou");
+#[cfg(target_os = "macos")]
+const K_AUDIO_UNIT_SUB_TYPE_IO: u32 = four_cc(b"ahal");
+#[cfg(not(target_os = "macos"))]
+const K_AUDIO_UNIT_SUB_TYPE_IO: u32 = four_cc(b"rioc");
+const K_AUDIO_UNIT_MANUFACTURER_APPLE: u32 = four_cc(b"appl");
+const K_AUDIO_FORMAT_LINEAR_PCM: u32 = four_cc(b"lpcm");
+const K_AUDIO_FORMAT_FLAG_IS_FLOAT: u32 = 1 << 0;
+const K_AUDIO_FORMAT_FLAG_IS_PACKED: u32 = 1 << 3;
+
+const K_AUDIO_UNIT_SCOPE_GLOBAL: u32 = 0;
+const K_AUDIO_UNIT_SCOPE_INPUT: u32 = 1;
+const K_AUDIO_UNIT_SCOPE_OUTPUT: u32 = 2;
+/// Element (bus) for the output hardware.
+const OUTPUT_ELEMENT: u32 = 0;
+/// Element (bus) for the input hardware.
+const INPUT_ELEMENT: u32 = 1;
+
+const K_AUDIO_UNIT_PROPERTY_STREAM_FORMAT: u32 = 8;
+const K_AUDIO_UNIT_PROPERTY_MAXIMUM_FRAMES_PER_SLICE: u32 = 14;
+const K_AUDIO_UNIT_PROPERTY_SET_RENDER_CALLBACK: u32 = 23;
+#[cfg(target_os = "macos")]
+const K_AUDIO_OUTPUT_UNIT_PROPERTY_CURRENT_DEVICE: u32 = 2000;
+const K_AUDIO_OUTPUT_UNIT_PROPERTY_ENABLE_IO: u32 = 2003;
+const K_AUDIO_OUTPUT_UNIT_PROPERTY_SET_INPUT_CALLBACK: u32 = 2005;
+#[cfg(target_os = "macos")]
+const K_AUDIO_DEVICE_PROPERTY_BUFFER_FRAME_SIZE: u32 = four_cc(b"fsiz");
+
+#[repr(C)]
+struct AudioComponentDescription {
+    component_type: u32,
+    component_sub_type: u32,
+    component_manufacturer: u32,
+    component_flags: u32,
+    component_flags_mask: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct AudioStreamBasicDescription {
+    sample_rate: f64,
+    format_id: u32,
+    format_flags: u32,
+    bytes_per_packet: u32,
+    frames_per_packet: u32,
+    bytes_per_frame: u32,
+    channels_per_frame: u32,
+    bits_per_channel: u32,
+    reserved: u32,
+}
+
+#[repr(C)]
+pub(super) struct AudioBuffer {
+    pub(super) number_channels: u32,
+    pub(super) data_byte_size: u32,
+    pub(super) data: *mut c_void,
+}
+
+#[repr(C)]
+pub(super) struct AudioBufferList {
+    pub(super) number_buffers: u32,
+    pub(super) buffers: [AudioBuffer; 1],
+}
+
+/// Called from CoreAudio's real-time thread.
+type RenderCallback = unsafe extern "C" fn(
+    *mut c_void,
+    *mut u32,
+    *const c_void,
+    u32,
+    u32,
+    *mut AudioBufferList,
+) -> OSStatus;
+
+#[repr(C)]
+struct AuRenderCallbackStruct {
+    input_proc: RenderCallback,
+    input_proc_ref_con: *mut c_void,
+}
+
+#[link(name = "AudioToolbox", kind = "framework")]
+extern "C" {
+    fn AudioComponentFindNext(
+        component: *mut c_void,
+        desc: *const AudioComponentDescription,
+    ) -> *mut c_void;
+    fn AudioComponentInstanceNew(
+        component: *mut c_void,
+        instance: *mut AudioUnit,
+    ) -> OSStatus;
+    fn AudioComponentInstanceDispose(instance: AudioUnit) -> OSStatus;
+    fn AudioUnitSetProperty(
+        unit: AudioUnit,
+        id: u32,
+        scope: u32,
+        element: u32,
+        data: *const c_void,
+        size: u32,
+    ) -> OSStatus;
+    fn AudioUnitGetProperty(
+        unit: AudioUnit,
+        id: u32,
+        scope: u32,
+        element: u32,
+        data: *mut c_void,
+        size: *mut u32,
+    ) -> OSStatus;
+    fn AudioUnitInitialize(unit: AudioUnit) -> OSStatus;
+    fn AudioUnitUninitialize(unit: AudioUnit) -> OSStatus;
+    fn AudioUnitRender(
+        unit: AudioUnit,
+        flags: *mut u32,
+        timestamp: *const c_void,
+        bus: u32,
+        frames: u32,
+        data: *mut AudioBufferList,
+    ) -> OSStatus;
+    fn AudioOutputUnitStart(unit: AudioUnit) -> OSStatus;
+    fn AudioOutputUnitStop(unit: AudioUnit) -> OSStatus;
+}
+
+const WAITING: usize = 0;
+const REGISTERING: usize = 0b01;
+const WAKING: usize = 0b10;
+
+/// Wakes a future from CoreAudio's real-time thread, without locking.
+pub(crate) struct Notify {
+    /// Set when the device has processed a slice.
+    ready: AtomicBool,
+    /// `WAITING`, or `REGISTERING` and/or `WAKING` while accessing the waker.
+    state: AtomicUsize,
+    /// Waker for the future waiting on the device.
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+impl Notify {
+    fn new() -> Self {
+        Self {
+            ready: AtomicBool::new(false),
+            state: AtomicUsize::new(WAITING),
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    /// Returns true if the device hasn't processed a slice since the last
+    /// call.  If so, the waker is registered to be woken up on the next one.
+    pub(crate) fn should_yield(&self, waker: &Waker) -> bool {
+        if self.ready.swap(false, SeqCst) {
+            return false;
+        }
+        self.register(waker);
+        // Check again, in case processed before the waker was registered.
+        !self.ready.swap(false, SeqCst)
+    }
+
+    /// Only called from the executor thread.
+    fn register(&self, waker: &Waker) {
+        match self.state.compare_exchange(
+            WAITING,
+            REGISTERING,
+            Acquire,
+            Acquire,
+        ) {
+            Ok(_) => unsafe {
+                *self.waker.get() = Some(waker.clone());
+                // If woken while registering, wake now.
+                if self
+                    .state
+                    .compare_exchange(REGISTERING, WAITING, AcqRel, Acquire)
+                    .is_err()
+                {
+                    let waker = (*self.waker.get()).take();
+                    self.state.swap(WAITING, AcqRel);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }
+            },
+            // Currently waking, so wake again.
+            Err(_) => waker.wake_by_ref(),
+        }
+    }
+
+    /// Called from the real-time thread.
+    fn wake(&self) {
+        self.ready.store(true, SeqCst);
+        if self.state.fetch_or(WAKING, AcqRel) == WAITING {
+            let waker = unsafe { (*self.waker.get()).take() };
+            self.state.fetch_and(!WAKING, Release);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Lock-free single producer, single consumer ring buffer of samples.
+struct Ring {
+    /// Sample storage.
+    buffer: Box<[UnsafeCell<f32>]>,
+    /// Total number of samples pushed (only changed by the producer).
+    write: AtomicUsize,
+    /// Total number of samples popped (only changed by the consumer).
+    read: AtomicUsize,
+}
+
+impl Ring {
+    fn new(capacity: usize) -> Self {
+        Self {
+            buffer: (0..capacity).map(|_| UnsafeCell::new(0.0)).collect(),
+            write: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get the number of samples in the ring buffer.
+    fn len(&self) -> usize {
+        self.write
+            .load(Acquire)
+            .wrapping_sub(self.read.load(Acquire))
+    }
+
+    /// Push as many samples as fit, returning how many were pushed.
+    ///
+    /// # Safety
+    /// Must only be called from the producer thread.
+    unsafe fn push(&self, samples: impl Iterator<Item = f32>) -> usize {
+        let write = self.write.load(Relaxed);
+        let room =
+            self.buffer.len() - write.wrapping_sub(self.read.load(Acquire));
+        let mut count = 0;
+        for sample in samples.take(room) {
+            let index = write.wrapping_add(count) % self.buffer.len();
+            *self.buffer[index].get() = sample;
+            count += 1;
+        }
+        self.write.store(write.wrapping_add(count), Release);
+        count
+    }
+
+    /// Pop up to `count` samples, returning how many were popped.
+    ///
+    /// # Safety
+    /// Must only be called from the consumer thread.
+    unsafe fn pop(&self, count: usize, mut f: impl FnMut(f32)) -> usize {
+        let read = self.read.load(Relaxed);
+        let count = count.min(self.write.load(Acquire).wrapping_sub(read));
+        for i in 0..count {
+            f(*self.buffer[read.wrapping_add(i) % self.buffer.len()].get());
+        }
+        self.read.store(read.wrapping_add(count), Release);
+        count
+    }
+}
+
+/// State shared with CoreAudio's real-time thread.
+struct Shared {
+    /// Wakes the future when a slice has been processed.
+    notify: Notify,
+    /// Interleaved samples waiting to be played, or that were recorded.
+    ring: Ring,
+    /// Number of interleaved channels.
+    channels: usize,
+    /// Number of frames in the last slice.
+    slice: AtomicUsize,
+    /// Set if the device went away.
+    disconnected: AtomicBool,
+    /// Number of underruns (playback) or overruns (capture).
+    xruns: AtomicUsize,
+    /// Capture instead of playback.
+    input: bool,
+    /// The audio unit, for rendering input.
+    unit: AudioUnit,
+    /// Buffer to render input into (only used by the real-time thread).
+    scratch: UnsafeCell<Box<[f32]>>,
+}
+
+unsafe impl Sync for Shared {}
+
+unsafe extern "C" fn render_callback(
+    user_data: *mut c_void,
+    _flags: *mut u32,
+    _timestamp: *const c_void,
+    _bus: u32,
+    frames: u32,
+    data: *mut AudioBufferList,
+) -> OSStatus {
+    let shared = &*user_data.cast::<Shared>();
+    let buffer = &(*data).buffers[0];
+    let samples = std::slice::from_raw_parts_mut(
+        buffer.data.cast::<f32>(),
+        buffer.data_byte_size as usize / size_of::<f32>(),
+    );
+    let mut index = 0;
+    shared.ring.pop(samples.len(), |sample| {
+        samples[index] = sample;
+        index += 1;
+    });
+    // Not enough audio, so play silence (an underrun, once started).
+    if index < samples.len() && shared.ring.write.load(Relaxed) != 0 {
+        shared.xruns.fetch_add(1, SeqCst);
+    }
+    for sample in &mut samples[index..] {
+        *sample = 0.0;
+    }
+    shared.slice.store(frames as usize, SeqCst);
+    shared.notify.wake();
+    NO_ERR
+}
+
+unsafe extern "C" fn input_callback(
+    user_data: *mut c_void,
+    flags: *mut u32,
+    timestamp: *const c_void,
+    bus: u32,
+    frames: u32,
+    _data: *mut AudioBufferList,
+) -> OSStatus {
+    let shared = &*user_data.cast::<Shared>();
+    let scratch = &mut *shared.scratch.get();
+    let frames = (frames as usize).min(scratch.len() / shared.channels);
+    let count = frames * shared.channels;
+    let mut list = AudioBufferList {
+        number_buffers: 1,
+        buffers: [AudioBuffer {
+            number_channels: shared.channels as u32,
+            data_byte_size: (count * size_of::<f32>()) as u32,
+            data: scratch.as_mut_ptr().cast(),
+        }],
+    };
+    let status = AudioUnitRender(
+        shared.unit,
+        flags,
+        timestamp,
+        bus,
+        frames as u32,
+        &mut list,
+    );
+    if status == NO_ERR {
+        // Samples are dropped if nobody is recording.
+        if shared.ring.push(scratch[..count].iter().cloned()) < count {
+            shared.xruns.fetch_add(1, SeqCst);
+        }
+    }
+    shared.slice.store(frames, SeqCst);
+    shared.notify.wake();
+    NO_ERR
+}
+
+/// Called from a CoreAudio notification thread when the device goes away.
+#[cfg(target_os = "macos")]
+unsafe extern "C" fn alive_listener(
+    _device: AudioDeviceId,
+    _count: u32,
+    _addresses: *const c_void,
+    user_data: *mut c_void,
+) -> OSStatus {
+    let shared = &*user_data.cast::<Shared>();
+    shared.disconnected.store(true, SeqCst);
+    shared.notify.wake();
+    NO_ERR
+}
+
+/// A started AudioUnit for playback or capture.
+pub(crate) struct Stream {
+    /// The `AudioUnit`.
+    unit: AudioUnit,
+    /// Device to listen for disconnection on.
+    #[cfg(target_os = "macos")]
+    device: Option<AudioDeviceId>,
+    /// Leaked shared box (null until the callback is set).
+    shared: *mut Shared,
+}
+
+impl std::fmt::Debug for Stream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Stream({:?})", self.unit)
+    }
+}
+
+impl Stream {
+    /// Open and start an audio unit for a device (or the default device) with
+    /// a number of channels, at the device's native sample rate.
+    pub(crate) fn open(
+        device: Option<AudioDeviceId>,
+        input: bool,
+        channels: u8,
+    ) -> Option<Self> {
+        unsafe {
+            let desc = AudioComponentDescription {
+                component_type: K_AUDIO_UNIT_TYPE_OUTPUT,
+                component_sub_type: K_AUDIO_UNIT_SUB_TYPE_IO,
+                component_manufacturer: K_AUDIO_UNIT_MANUFACTURER_APPLE,
+                component_flags: 0,
+                component_flags_mask: 0,
+            };
+            let component = AudioComponentFindNext(ptr::null_mut(), &desc);
+            if component.is_null() {
+                return None;
+            }
+            let mut unit = ptr::null_mut();
+            if AudioComponentInstanceNew(component, &mut unit) != NO_ERR {
+                return None;
+            }
+            let mut stream = Self {
+                unit,
+                #[cfg(target_os = "macos")]
+                device: None,
+                shared: ptr::null_mut(),
+            };
+            stream.configure(device, input, channels)?;
+            Some(stream)
+        }
+    }
+
+    unsafe fn configure(
+        &mut self,
+        device: Option<AudioDeviceId>,
+        input: bool,
+        channels: u8,
+    ) -> Option<()> {
+        let (element, hardware_scope, client_scope) = if input {
+            (
+                INPUT_ELEMENT,
+                K_AUDIO_UNIT_SCOPE_INPUT,
+                K_AUDIO_UNIT_SCOPE_OUTPUT,
+            )
+        } else {
+            (
+                OUTPUT_ELEMENT,
+                K_AUDIO_UNIT_SCOPE_OUTPUT,
+                K_AUDIO_UNIT_SCOPE_INPUT,
+            )
+        };
+
+        // Input is disabled and output enabled by default, so switch for
+        // capture.
+        if input {
+            self.set(
+                K_AUDIO_OUTPUT_UNIT_PROPERTY_ENABLE_IO,
+                K_AUDIO_UNIT_SCOPE_INPUT,
+                INPUT_ELEMENT,
+                &1u32,
+            )?;
+            self.set(
+                K_AUDIO_OUTPUT_UNIT_PROPERTY_ENABLE_IO,
+                K_AUDIO_UNIT_SCOPE_OUTPUT,
+                OUTPUT_ELEMENT,
+                &0u32,
+            )?;
+        }
+
+        // Select the device (AUHAL defaults to the default output device).
+        #[cfg(target_os = "macos")]
+        {
+            let device = match device {
+                Some(device) => device,
+                None => device_list::default_device(input)?,
+            };
+            self.set(
+                K_AUDIO_OUTPUT_UNIT_PROPERTY_CURRENT_DEVICE,
+                K_AUDIO_UNIT_SCOPE_GLOBAL,
+                OUTPUT_ELEMENT,
+                &device,
+            )?;
+            // Prefer the library's sample rate, like ALSA, if the device
+            // supports it.
+            device_list::set_nominal_rate(
+                device,
+                crate::consts::SAMPLE_RATE.into(),
+            );
+            // Ask for a small hardware buffer for low latency, it's fine if
+            // the device doesn't allow it.
+            let frames = u32::from(crate::consts::PERIOD);
+            let _ = self.set(
+                K_AUDIO_DEVICE_PROPERTY_BUFFER_FRAME_SIZE,
+                K_AUDIO_UNIT_SCOPE_GLOBAL,
+                OUTPUT_ELEMENT,
+                &frames,
+            );
+            self.device = Some(device);
+        }
+        #[cfg(not(target_os = "macos"))]
+        let _ = device;
+
+        // Use the hardware sample rate, resampling is done by `fon`.
+        let mut hardware = AudioStreamBasicDescription::default();
+        self.get(
+            K_AUDIO_UNIT_PROPERTY_STREAM_FORMAT,
+            hardware_scope,
+            element,
+            &mut hardware,
+        )?;
+        let sample_rate = if hardware.sample_rate > 0.0 {
+            hardware.sample_rate
+        } else {
+            crate::consts::SAMPLE_RATE.into()
+        };
+
+        // Interleaved native endian float32, channels are mapped by the unit.
+        let bytes = 4 * u32::from(channels);
+        let format = AudioStreamBasicDescription {
+            sample_rate,
+            format_id: K_AUDIO_FORMAT_LINEAR_PCM,
+            format_flags: K_AUDIO_FORMAT_FLAG_IS_FLOAT
+                | K_AUDIO_FORMAT_FLAG_IS_PACKED,
+            bytes_per_packet: bytes,
+            frames_per_packet: 1,
+            bytes_per_frame: bytes,
+            channels_per_frame: channels.into(),
+            bits_per_channel: 32,
+            reserved: 0,
+        };
+        self.set(
+            K_AUDIO_UNIT_PROPERTY_STREAM_FORMAT,
+            client_scope,
+            element,
+            &format,
+        )?;
+
+        // Size the buffers for the largest slice CoreAudio may process.
+        let mut max_frames = 4096u32;
+        let _ = self.get(
+            K_AUDIO_UNIT_PROPERTY_MAXIMUM_FRAMES_PER_SLICE,
+            K_AUDIO_UNIT_SCOPE_GLOBAL,
+            OUTPUT_ELEMENT,
+            &mut max_frames,
+        );
+        let max_samples = max_frames as usize * usize::from(channels);
+        self.shared = Box::into_raw(Box::new(Shared {
+            notify: Notify::new(),
+            ring: Ring::new(4 * max_samples),
+            channels: channels.into(),
+            slice: AtomicUsize::new(crate::consts::PERIOD.into()),
+            disconnected: AtomicBool::new(false),
+            xruns: AtomicUsize::new(0),
+            input,
+            unit: self.unit,
+            scratch: UnsafeCell::new(
+                vec![0.0; if input { max_samples } else { 0 }].into(),
+            ),
+        }));
+
+        let callback = AuRenderCallbackStruct {
+            input_proc: if input {
+                input_callback
+            } else {
+                render_callback
+            },
+            input_proc_ref_con: self.shared.cast(),
+        };
+        if input {
+            self.set(
+                K_AUDIO_OUTPUT_UNIT_PROPERTY_SET_INPUT_CALLBACK,
+                K_AUDIO_UNIT_SCOPE_GLOBAL,
+                OUTPUT_ELEMENT,
+                &callback,
+            )?;
+        } else {
+            self.set(
+                K_AUDIO_UNIT_PROPERTY_SET_RENDER_CALLBACK,
+                K_AUDIO_UNIT_SCOPE_INPUT,
+                OUTPUT_ELEMENT,
+                &callback,
+            )?;
+        }
+
+        #[cfg(target_os = "macos")]
+        if let Some(device) = self.device {
+            device_list::listen_alive(
+                device,
+                alive_listener,
+                self.shared.cast(),
+                true,
+            );
+        }
+
+        if AudioUnitInitialize(self.unit) != NO_ERR {
+            return None;
+        }
+        (AudioOutputUnitStart(self.unit) == NO_ERR).then_some(())
+    }
+
+    unsafe fn set<T>(
+        &self,
+        id: u32,
+        scope: u32,
+        element: u32,
+        data: &T,
+    ) -> Option<()> {
+        let data: *const T = data;
+        let status = AudioUnitSetProperty(
+            self.unit,
+            id,
+            scope,
+            element,
+            data.cast(),
+            size_of::<T>() as u32,
+        );
+        (status == NO_ERR).then_some(())
+    }
+
+    unsafe fn get<T>(
+        &self,
+        id: u32,
+        scope: u32,
+        element: u32,
+        data: &mut T,
+    ) -> Option<()> {
+        let data: *mut T = data;
+        let mut size = size_of::<T>() as u32;
+        let status = AudioUnitGetProperty(
+            self.unit,
+            id,
+            scope,
+            element,
+            data.cast(),
+            &mut size,
+        );
+        (status == NO_ERR).then_some(())
+    }
+
+    fn shared(&self) -> &Shared {
+        unsafe { &*self.shared }
+    }
+
+    pub(crate) fn notify(&self) -> &Notify {
+        &self.shared().notify
+    }
+
+    /// Get the sample rate of the hardware.
+    pub(crate) fn sample_rate(&self) -> Option<f64> {
+        let mut format = AudioStreamBasicDescription::default();
+        let (scope, element) = if self.shared().input {
+            (K_AUDIO_UNIT_SCOPE_OUTPUT, INPUT_ELEMENT)
+        } else {
+            (K_AUDIO_UNIT_SCOPE_INPUT, OUTPUT_ELEMENT)
+        };
+        unsafe {
+            self.get(
+                K_AUDIO_UNIT_PROPERTY_STREAM_FORMAT,
+                scope,
+                element,
+                &mut format,
+            )?;
+        }
+        Some(format.sample_rate)
+    }
+
+    /// Get the number of frames CoreAudio processes at a time.
+    pub(crate) fn slice(&self) -> usize {
+        self.shared().slice.load(SeqCst)
+    }
+
+    /// Get the number of underruns (playback) or overruns (capture).
+    pub(crate) fn xrun_count(&self) -> usize {
+        self.shared().xruns.load(SeqCst)
+    }
+
+    /// Get the number of frames queued, plus one slice for playback.
+    pub(crate) fn delay(&self) -> usize {
+        let shared = self.shared();
+        let queued = shared.ring.len() / shared.channels;
+        if shared.input {
+            queued
+        } else {
+            queued + self.slice()
+        }
+    }
+
+    /// Queue audio for playback, returning the number of frames queued, or
+    /// `None` if disconnected.
+    pub(crate) fn write(&self, buffer: &[Ch32], period: u16) -> Option<usize> {
+        let shared = self.shared();
+        if shared.disconnected.load(SeqCst) {
+            return None;
+        }
+        let channels = shared.channels;
+        let frames = self.slice().max(period.into());
+        // Keep latency to about two slices.
+        let room = (2 * frames * channels).saturating_sub(shared.ring.len());
+        let count = (room / channels).min(period.into());
+        let samples = buffer[..count * channels].iter().map(|s| f32::from(*s));
+        Some(unsafe { shared.ring.push(samples) } / channels)
+    }
+
+    /// Take recorded audio, returning the number of frames read, or `None` if
+    /// disconnected.
+    pub(crate) fn read(
+        &self,
+        buffer: &mut [Ch32],
+        period: u16,
+    ) -> Option<usize> {
+        let shared = self.shared();
+        if shared.disconnected.load(SeqCst) {
+            return None;
+        }
+        let channels = shared.channels;
+        let count = (shared.ring.len() / channels).min(period.into());
+        let mut index = 0;
+        unsafe {
+            shared.ring.pop(count * channels, |sample| {
+                buffer[index] = Ch32::from(sample);
+                index += 1;
+            })
+        };
+        // Wake again if there's already another period waiting.
+        if shared.ring.len() >= usize::from(period) * channels {
+            shared.notify.ready.store(true, SeqCst);
+        }
+        Some(count)
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        unsafe {
+            // Stops the callbacks before freeing the shared state.
+            AudioOutputUnitStop(self.unit);
+            AudioUnitUninitialize(self.unit);
+            AudioComponentInstanceDispose(self.unit);
+            if !self.shared.is_null() {
+                #[cfg(target_os = "macos")]
+                if let Some(device) = self.device {
+                    device_list::listen_alive(
+                        device,
+                        alive_listener,
+                        self.shared.cast(),
+                        false,
+                    );
+                }
+                drop(Box::from_raw(self.shared));
+            }
+        }
+    }
+}
+
+#[path = "device_list.rs"]
+pub(super) mod device_list;
@@ -7,15 +7,506 @@
 // At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
 // LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
 
-use std::fmt::Display;
+use std::task::Waker;
+#[cfg(target_os = "macos")]
+use std::{mem::size_of, os::raw::c_void, ptr};
 
-pub(crate) trait SoundDevice: Display {
+use fon::chan::{Ch32, Channel};
+
+#[cfg(target_os = "macos")]
+use super::{AudioBuffer, AudioBufferList};
+use super::{AudioDeviceId, Stream};
+use crate::AudioError;
+
+pub(crate) trait SoundDevice:
+    std::fmt::Display + From<AudioDevice>
+{
     const INPUT: bool;
 }
 
+/// An Audio Device (input or output).
+#[derive(Debug)]
+pub(crate) struct AudioDevice {
+    /// Stable ID for the device.
+    pub(crate) id: String,
+    /// Human-readable name for the device.
+    pub(crate) name: String,
+    /// CoreAudio device, `None` to follow the system default.
+    pub(crate) device: Option<AudioDeviceId>,
+    /// Capture instead of playback.
+    pub(crate) input: bool,
+    /// Bitflags for numbers of channels (which of 1-8 are supported)
+    pub(crate) supported: u8,
+    /// AudioUnit stream, `None` until configured.
+    pub(crate) stream: Option<Stream>,
+    /// Number of underruns or overruns from streams closed by reconfiguring.
+    pub(crate) xruns: usize,
+}
+
+impl AudioDevice {
+    fn new(
+        id: String,
+        name: String,
+        device: Option<AudioDeviceId>,
+        input: bool,
+        channels: u32,
+    ) -> Self {
+        // Mono and stereo are always available through the AudioUnit's
+        // channel mapping.
+        let mut supported = 0b0000_0011;
+        if channels >= 6 {
+            supported |= 0b0010_0000;
+        }
+        Self {
+            id,
+            name,
+            device,
+            input,
+            supported,
+            stream: None,
+            xruns: 0,
+        }
+    }
+
+    /// Returns true if the device isn't ready, registering the waker.
+    pub(crate) fn should_yield(&self, waker: &Waker) -> bool {
+        match &self.stream {
+            Some(stream) => stream.notify().should_yield(waker),
+            None => false,
+        }
+    }
+
+    /// Register a waker after the device had no room or no audio.
+    pub(crate) fn register_waker(&self, waker: &Waker) {
+        if let Some(stream) = &self.stream {
+            if !stream.notify().should_yield(waker) {
+                waker.wake_by_ref();
+            }
+        }
+    }
+
+    /// Write interleaved audio, returning the number of frames written.
+    pub(crate) fn write(
+        &self,
+        buffer: &[Ch32],
+        period: u16,
+    ) -> Result<usize, AudioError> {
+        let stream = self.stream.as_ref().ok_or(AudioError::NoDevice)?;
+        stream
+            .write(buffer, period)
+            .ok_or(AudioError::DeviceDisconnected)
+    }
+
+    /// Read interleaved audio, returning the number of frames read.
+    pub(crate) fn read(
+        &self,
+        buffer: &mut [Ch32],
+        period: u16,
+    ) -> Result<usize, AudioError> {
+        let stream = self.stream.as_ref().ok_or(AudioError::NoDevice)?;
+        stream
+            .read(buffer, period)
+            .ok_or(AudioError::DeviceDisconnected)
+    }
+
+    /// Get the number of underruns or overruns since the device was opened.
+    pub(crate) fn xrun_count(&self) -> usize {
+        self.xruns + self.stream.as_ref().map(Stream::xrun_count).unwrap_or(0)
+    }
+
+    /// Get the number of frames buffered between wavy and the hardware.
+    pub(crate) fn delay(&self) -> Option<usize> {
+        self.stream.as_ref().map(Stream::delay)
+    }
+}
+
+/// Open the default audio device.
+pub(crate) fn open_default(input: bool) -> Result<AudioDevice, AudioError> {
+    #[cfg(target_os = "macos")]
+    let channels = {
+        let device = default_device(input).ok_or(AudioError::NoDevice)?;
+        channel_count(device, input)
+    };
+    // RemoteIO always has a mono or stereo route.
+    #[cfg(not(target_os = "macos"))]
+    let channels = 2;
+    if channels == 0 {
+        return Err(AudioError::NoDevice);
+    }
+    Ok(AudioDevice::new(
+        "default".to_string(),
+        "Default".to_string(),
+        None,
+        input,
+        channels,
+    ))
+}
+
+/// Get the UID of the system default device.
+#[cfg(target_os = "macos")]
+pub(crate) fn default_id(input: bool) -> Option<String> {
+    get_string(default_device(input)?, K_AUDIO_DEVICE_PROPERTY_DEVICE_UID)
+}
+
+/// iOS picks the route, so there's no default device to report.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn default_id(_input: bool) -> Option<String> {
+    None
+}
+
 /// Return a list of available audio devices.
+#[cfg(target_os = "macos")]
 pub(crate) fn device_list<D: SoundDevice, F: Fn(D) -> T, T>(
-    _abstrakt: F,
+    abstrakt: F,
+) -> Vec<Result<T, (String, AudioError)>> {
+    let mut devices = Vec::new();
+    for device in get_list::<AudioDeviceId>(
+        K_AUDIO_OBJECT_SYSTEM_OBJECT,
+        K_AUDIO_HARDWARE_PROPERTY_DEVICES,
+        K_AUDIO_OBJECT_PROPERTY_SCOPE_GLOBAL,
+    ) {
+        let channels = channel_count(device, D::INPUT);
+        if channels == 0 {
+            continue;
+        }
+        let id = match get_string(device, K_AUDIO_DEVICE_PROPERTY_DEVICE_UID) {
+            Some(id) => id,
+            None => continue,
+        };
+        let name = get_string(device, K_AUDIO_OBJECT_PROPERTY_NAME)
+            .unwrap_or_else(|| id.clone());
+        let device =
+            AudioDevice::new(id, name, Some(device), D::INPUT, channels);
+        devices.push(Ok(abstrakt(D::from(device))));
+    }
+    devices
+}
+
+/// Return a list of available audio devices.
+///
+/// Only the default device is listed, since iOS picks the route.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn device_list<D: SoundDevice, F: Fn(D) -> T, T>(
+    abstrakt: F,
+) -> Vec<Result<T, (String, AudioError)>> {
+    match open_default(D::INPUT) {
+        Ok(device) => vec![Ok(abstrakt(D::from(device)))],
+        Err(AudioError::NoDevice) => Vec::new(),
+        Err(error) => vec![Err(("Default".to_string(), error))],
+    }
+}
+
+/// Configure the device for a number of channels (the CoreAudio equivalent
+/// of `pcm_hw_params` on Linux).
+pub(crate) fn configure(
+    device: &mut AudioDevice,
+    channels: u8,
+    buffer: &mut Vec<Ch32>,
+    sample_rate: &mut Option<f64>,
+    period: &mut u16,
+) -> Option<()> {
+    // The stream format is fixed once the unit is initialized, so reopen it.
+    device.xruns = device.xrun_count();
+    device.stream = None;
+    let stream = Stream::open(device.device, device.input, channels)?;
+
+    // Use the device's sample rate, resampling is done by `fon`.
+    *sample_rate = Some(stream.sample_rate()?);
+
+    // Set the period of the buffer.
+    *period = crate::consts::PERIOD;
+
+    // Resize the buffer
+    buffer.resize(*period as usize * channels as usize, Ch32::MID);
+
+    device.stream = Some(stream);
+    Some(())
+}
+
+#[cfg(target_os = "macos")]
+const K_AUDIO_OBJECT_SYSTEM_OBJECT: AudioDeviceId = 1;
+#[cfg(target_os = "macos")]
+const K_AUDIO_HARDWARE_PROPERTY_DEVICES: u32 = u32::from_be_bytes(*b"dev#");
+#[cfg(target_os = "macos")]
+const K_AUDIO_HARDWARE_PROPERTY_DEFAULT_OUTPUT_DEVICE: u32 =
+    u32::from_be_bytes(*b"dOut");
+#[cfg(target_os = "macos")]
+const K_AUDIO_HARDWARE_PROPERTY_DEFAULT_INPUT_DEVICE: u32 =
+    u32::from_be_bytes(*b"dIn ");
+#[cfg(target_os = "macos")]
+const K_AUDIO_DEVICE_PROPERTY_STREAM_CONFIGURATION: u32 =
+    u32::from_be_bytes(*b"slay");
+#[cfg(target_os = "macos")]
+const K_AUDIO_DEVICE_PROPERTY_DEVICE_UID: u32 = u32::from_be_bytes(*b"uid ");
+#[cfg(target_os = "macos")]
+const K_AUDIO_DEVICE_PROPERTY_DEVICE_IS_ALIVE: u32 =
+    u32::from_be_bytes(*b"livn");
+#[cfg(target_os = "macos")]
+const K_AUDIO_DEVICE_PROPERTY_NOMINAL_SAMPLE_RATE: u32 =
+    u32::from_be_bytes(*b"nsrt");
+#[cfg(target_os = "macos")]
+const K_AUDIO_DEVICE_PROPERTY_AVAILABLE_NOMINAL_SAMPLE_RATES: u32 =
+    u32::from_be_bytes(*b"nsr#");
+#[cfg(target_os = "macos")]
+const K_AUDIO_OBJECT_PROPERTY_NAME: u32 = u32::from_be_bytes(*b"lnam");
+#[cfg(target_os = "macos")]
+const K_AUDIO_OBJECT_PROPERTY_SCOPE_GLOBAL: u32 = u32::from_be_bytes(*b"glob");
+#[cfg(target_os = "macos")]
+const K_AUDIO_OBJECT_PROPERTY_SCOPE_INPUT: u32 = u32::from_be_bytes(*b"inpt");
+#[cfg(target_os = "macos")]
+const K_AUDIO_OBJECT_PROPERTY_SCOPE_OUTPUT: u32 = u32::from_be_bytes(*b"outp");
+#[cfg(target_os = "macos")]
+const K_CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+
+#[cfg(target_os = "macos")]
+#[repr(C)]
+struct AudioObjectPropertyAddress {
+    selector: u32,
+    scope: u32,
+    element: u32,
+}
+
+/// Called from a CoreAudio notification thread when a property changes.
+#[cfg(target_os = "macos")]
+pub(super) type ListenerProc =
+    unsafe extern "C" fn(AudioDeviceId, u32, *const c_void, *mut c_void) -> i32;
+
+#[cfg(target_os = "macos")]
+#[link(name = "CoreAudio", kind = "framework")]
+extern "C" {
+    fn AudioObjectGetPropertyDataSize(
+        object: AudioDeviceId,
+        address: *const AudioObjectPropertyAddress,
+        qualifier_size: u32,
+        qualifier: *const c_void,
+        size: *mut u32,
+    ) -> i32;
+    fn AudioObjectGetPropertyData(
+        object: AudioDeviceId,
+        address: *const AudioObjectPropertyAddress,
+        qualifier_size: u32,
+        qualifier: *const c_void,
+        size: *mut u32,
+        data: *mut c_void,
+    ) -> i32;
+    fn AudioObjectSetPropertyData(
+        object: AudioDeviceId,
+        address: *const AudioObjectPropertyAddress,
+        qualifier_size: u32,
+        qualifier: *const c_void,
+        size: u32,
+        data: *const c_void,
+    ) -> i32;
+    fn AudioObjectAddPropertyListener(
+        object: AudioDeviceId,
+        address: *const AudioObjectPropertyAddress,
+        listener: ListenerProc,
+        user_data: *mut c_void,
+    ) -> i32;
+    fn AudioObjectRemovePropertyListener(
+        object: AudioDeviceId,
+        address: *const AudioObjectPropertyAddress,
+        listener: ListenerProc,
+        user_data: *mut c_void,
+    ) -> i32;
+}
+
+#[cfg(target_os = "macos")]
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFStringGetCString(
+        string: *const c_void,
+        buffer: *mut u8,
+        size: isize,
+        encoding: u32,
+    ) -> u8;
+    fn CFRelease(object: *const c_void);
+}
+
+/// Get the system default input or output device.
+#[cfg(target_os = "macos")]
+pub(super) fn default_device(input: bool) -> Option<AudioDeviceId> {
+    let selector = if input {
+        K_AUDIO_HARDWARE_PROPERTY_DEFAULT_INPUT_DEVICE
+    } else {
+        K_AUDIO_HARDWARE_PROPERTY_DEFAULT_OUTPUT_DEVICE
+    };
+    let device = get_list::<AudioDeviceId>(
+        K_AUDIO_OBJECT_SYSTEM_OBJECT,
+        selector,
+        K_AUDIO_OBJECT_PROPERTY_SCOPE_GLOBAL,
+    )
+    .pop()?;
+    // kAudioObjectUnknown
+    (device != 0).then_some(device)
+}
+
+/// Switch the device to a sample rate, if it supports it.
+#[cfg(target_os = "macos")]
+pub(super) fn set_nominal_rate(device: AudioDeviceId, rate: f64) {
+    let current = get_list::<f64>(
+        device,
+        K_AUDIO_DEVICE_PROPERTY_NOMINAL_SAMPLE_RATE,
+        K_AUDIO_OBJECT_PROPERTY_SCOPE_GLOBAL,
+    );
+    if current.first() == Some(&rate) {
+        return;
+    }
+    // List of \`AudioValueRange\`s (minimum, maximum).
+    let ranges = get_list::<[f64; 2]>(
+        device,
+        K_AUDIO_DEVICE_PROPERTY_AVAILABLE_NOMINAL_SAMPLE_RATES,
+        K_AUDIO_OBJECT_PROPERTY_SCOPE_GLOBAL,
+    );
+    if !ranges
+        .iter()
+        .any(|[min, max]| (*min..=*max).contains(&rate))
+    {
+        return;
+    }
+    let address = AudioObjectPropertyAddress {
+        selector: K_AUDIO_DEVICE_PROPERTY_NOMINAL_SAMPLE_RATE,
+        scope: K_AUDIO_OBJECT_PROPERTY_SCOPE_GLOBAL,
+        element: 0,
+    };
+    let data: *const f64 = &rate;
+    unsafe {
+        AudioObjectSetPropertyData(
+            device,
+            &address,
+            0,
+            ptr::null(),
+            size_of::<f64>() as u32,
+            data.cast(),
+        );
+    }
+}
+
+/// Add or remove a listener for the device going away.
+#[cfg(target_os = "macos")]
+pub(super) unsafe fn listen_alive(
+    device: AudioDeviceId,
+    listener: ListenerProc,
+    user_data: *mut c_void,
+    add: bool,
+) {
+    let address = AudioObjectPropertyAddress {
+        selector: K_AUDIO_DEVICE_PROPERTY_DEVICE_IS_ALIVE,
+        scope: K_AUDIO_OBJECT_PROPERTY_SCOPE_GLOBAL,
+        element: 0,
+    };
+    if add {
+        AudioObjectAddPropertyListener(device, &address, listener, user_data);
+    } else {
+        AudioObjectRemovePropertyListener(
+            device, &address, listener, user_data,
+        );
+    }
+}
+
+/// Get a property that's a list of values.
+#[cfg(target_os = "macos")]
+fn get_list<T: Copy + Default>(
+    object: AudioDeviceId,
+    selector: u32,
+    scope: u32,
 ) -> Vec<T> {
-    vec![]
+    let address = AudioObjectPropertyAddress {
+        selector,
+        scope,
+        element: 0,
+    };
+    unsafe {
+        let mut size = 0;
+        if AudioObjectGetPropertyDataSize(
+            object,
+            &address,
+            0,
+            ptr::null(),
+            &mut size,
+        ) != 0
+        {
+            return Vec::new();
+        }
+        let mut list = vec![T::default(); size as usize / size_of::<T>()];
+        if AudioObjectGetPropertyData(
+            object,
+            &address,
+            0,
+            ptr::null(),
+            &mut size,
+            list.as_mut_ptr().cast(),
+        ) != 0
+        {
+            return Vec::new();
+        }
+        list.truncate(size as usize / size_of::<T>());
+        list
+    }
+}
+
+/// Get a string property, converted from a `CFString`.
+#[cfg(target_os = "macos")]
+fn get_string(object: AudioDeviceId, selector: u32) -> Option<String> {
+    let address = AudioObjectPropertyAddress {
+        selector,
+        scope: K_AUDIO_OBJECT_PROPERTY_SCOPE_GLOBAL,
+        element: 0,
+    };
+    unsafe {
+        let mut string: *const c_void = ptr::null();
+        let mut size = size_of::<*const c_void>() as u32;
+        let data: *mut *const c_void = &mut string;
+        if AudioObjectGetPropertyData(
+            object,
+            &address,
+            0,
+            ptr::null(),
+            &mut size,
+            data.cast(),
+        ) != 0
+            || string.is_null()
+        {
+            return None;
+        }
+        let mut buffer = [0u8; 512];
+        let ok = CFStringGetCString(
+            string,
+            buffer.as_mut_ptr(),
+            buffer.len() as isize,
+            K_CF_STRING_ENCODING_UTF8,
+        );
+        CFRelease(string);
+        if ok == 0 {
+            return None;
+        }
+        let len = buffer.iter().position(|&b| b == 0)?;
+        String::from_utf8(buffer[..len].to_vec()).ok()
+    }
+}
+
+/// Get the total number of input or output channels on a device.
+#[cfg(target_os = "macos")]
+fn channel_count(device: AudioDeviceId, input: bool) -> u32 {
+    let scope = if input {
+        K_AUDIO_OBJECT_PROPERTY_SCOPE_INPUT
+    } else {
+        K_AUDIO_OBJECT_PROPERTY_SCOPE_OUTPUT
+    };
+    // Variable length `AudioBufferList`, aligned for its pointers.
+    let list = get_list::<u64>(
+        device,
+        K_AUDIO_DEVICE_PROPERTY_STREAM_CONFIGURATION,
+        scope,
+    );
+    if list.is_empty() {
+        return 0;
+    }
+    unsafe {
+        let list: *const AudioBufferList = list.as_ptr().cast();
+        let buffers = ptr::addr_of!((*list).buffers).cast::<AudioBuffer>();
+        (0..(*list).number_buffers as usize)
+            .map(|i| (*buffers.add(i)).number_channels)
+            .sum()
+    }
 }
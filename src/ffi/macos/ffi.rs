@@ -7,11 +7,32 @@
 // At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
 // LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
 
-mod device_list;
+mod audio_unit;
+#[path = "../callback/microphone.rs"]
 mod microphone;
+#[path = "../polling.rs"]
+mod polling;
+#[path = "../callback/speakers.rs"]
 mod speakers;
 
-pub(crate) use device_list::device_list;
-use device_list::SoundDevice;
-pub(super) use microphone::{Microphone, MicrophoneStream};
-pub(super) use speakers::{Speakers, SpeakersSink};
+// Implementation Expectations:
+use audio_unit::device_list::{
+    configure, open_default, AudioDevice, SoundDevice,
+};
+pub(crate) use audio_unit::device_list::{default_id, device_list};
+pub(crate) use microphone::{Microphone, MicrophoneStream};
+pub(crate) use polling::Watcher;
+pub(crate) use speakers::{Speakers, SpeakersSink};
+
+/// CoreAudio is linked, so it's always available.
+pub(crate) fn available() -> bool {
+    true
+}
+
+/// Devices can't be listed without opening them.
+pub(crate) fn device_names(
+    _input: bool,
+    _filter: crate::DeviceFilter,
+) -> Option<Vec<(String, String)>> {
+    None
+}
@@ -7,4 +7,9 @@
 // At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
 // LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
 
-include!("../macos/ffi.rs");
+//! Memory backend, which records audio held in memory without waiting.  Used
+//! by `FileSource` and `MockMicrophone`.
+
+mod microphone;
+
+pub(crate) use microphone::{End, Microphone, MicrophoneStream};
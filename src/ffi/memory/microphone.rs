@@ -0,0 +1,239 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use std::{
+    cell::Cell,
+    fmt::{Display, Error, Formatter},
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use fon::{chan::Ch32, Frame, Stream};
+
+use crate::{consts::PERIOD, AudioError, DeviceCaps, Latency, SampleFormat};
+
+/// What a memory microphone does after recording the last frame.
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum End {
+    /// Produce [`AudioError::EndOfStream`].
+    Stop,
+    /// Start over from the first frame.
+    Loop,
+    /// Panic when polled again (for tests, which shouldn't run out).
+    #[cfg(feature = "testing")]
+    Panic,
+}
+
+/// Memory microphone, which records audio given ahead of time.
+pub(crate) struct Microphone {
+    /// Name of the device.
+    name: &'static str,
+    /// Number of channels in the recorded audio.
+    channels: u8,
+    /// The sample rate of the recorded audio.
+    sample_rate: f64,
+    /// Interleaved samples to record.
+    samples: Rc<[Ch32]>,
+    /// Index of the next frame to record.
+    index: usize,
+    /// What to do after the last frame is recorded.
+    end: End,
+    /// Shared lock with the stream, so either may be dropped first.
+    locked: Rc<Cell<bool>>,
+}
+
+impl Display for Microphone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.write_str(self.name)
+    }
+}
+
+impl Microphone {
+    /// Open a memory microphone named `name` that records interleaved
+    /// `samples`.
+    pub(crate) fn new(
+        name: &'static str,
+        sample_rate: f64,
+        channels: u8,
+        samples: Vec<Ch32>,
+        end: End,
+    ) -> Self {
+        Self {
+            name,
+            channels,
+            sample_rate,
+            samples: samples.into(),
+            index: 0,
+            end,
+            locked: Rc::new(Cell::new(false)),
+        }
+    }
+
+    /// Set what to do after the last frame is recorded.
+    #[cfg(feature = "testing")]
+    pub(crate) fn set_end(&mut self, end: End) {
+        self.end = end;
+    }
+
+    pub(crate) fn record<F: Frame<Chan = Ch32>>(
+        &mut self,
+    ) -> Result<MicrophoneStream<F>, AudioError> {
+        if let Err(error) =
+            AudioError::check_channels(F::CHAN_COUNT, 1 << (self.channels - 1))
+        {
+            // Unlock, since no stream will be created.
+            self.locked.set(false);
+            return Err(error);
+        }
+        // Record up to one period of the remaining frames.
+        let frames = self.samples.len() / usize::from(self.channels);
+        if matches!(self.end, End::Loop) && self.index == frames {
+            self.index = 0;
+        }
+        let start = self.index;
+        self.index = frames.min(start + usize::from(PERIOD));
+
+        Ok(MicrophoneStream(
+            self.locked.clone(),
+            self.samples.clone(),
+            start,
+            self.index,
+            PhantomData,
+            self.sample_rate,
+        ))
+    }
+
+    pub(crate) fn channels(&self) -> u8 {
+        // Can't reconfigure while the stream is alive.
+        if self.locked.get() {
+            return 0;
+        }
+
+        1 << (self.channels - 1)
+    }
+
+    pub(crate) fn id(&self) -> &str {
+        self.name
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        self.name
+    }
+
+    /// Get the number of overruns since the device was opened.
+    pub(crate) fn xruns(&self) -> usize {
+        0
+    }
+
+    /// Never suspended.
+    pub(crate) fn suspends(&self) -> usize {
+        0
+    }
+
+    /// Get the sample rate the device was configured with, `None` until
+    /// configured.
+    pub(crate) fn sample_rate(&self) -> Option<f64> {
+        Some(self.sample_rate)
+    }
+
+    pub(crate) fn caps(&self) -> DeviceCaps {
+        DeviceCaps::fixed(1 << (self.channels - 1), self.sample_rate(), PERIOD)
+    }
+
+    /// Periods are always the default length.
+    pub(crate) fn set_period(&mut self, _frames: u16, _count: u8) {}
+
+    /// Get the configured period, `0` until configured.
+    pub(crate) fn period(&self) -> u16 {
+        PERIOD
+    }
+
+    /// Get the number of frames the device buffers, if known.
+    pub(crate) fn buffer_frames(&self) -> Option<usize> {
+        Some(PERIOD.into())
+    }
+
+    pub(crate) fn format(&self) -> SampleFormat {
+        SampleFormat::F32
+    }
+}
+
+impl Future for Microphone {
+    type Output = Result<(), AudioError>;
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.locked.get() {
+            return Poll::Ready(Err(AudioError::DeviceLocked));
+        }
+
+        // Always ready, until the audio runs out (unless looping).
+        let frames = self.samples.len() / usize::from(self.channels);
+        if self.index == frames {
+            match self.end {
+                End::Loop if frames != 0 => {}
+                #[cfg(feature = "testing")]
+                End::Panic => panic!(
+                    "MockMicrophone ran out of frames ({} were provided)",
+                    frames,
+                ),
+                _ => return Poll::Ready(Err(AudioError::EndOfStream)),
+            }
+        }
+        self.locked.set(true);
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub(crate) struct MicrophoneStream<F: Frame<Chan = Ch32>>(
+    Rc<Cell<bool>>,
+    Rc<[Ch32]>,
+    usize,
+    usize,
+    PhantomData<F>,
+    f64,
+);
+
+impl<F: Frame<Chan = Ch32>> MicrophoneStream<F> {
+    pub(crate) fn latency(&self) -> Latency {
+        Latency::new(self.3 - self.2, self.5)
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Iterator for MicrophoneStream<F> {
+    type Item = F;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.2 >= self.3 {
+            return None;
+        }
+        let start = self.2 * F::CHAN_COUNT;
+        self.2 += 1;
+        Some(F::from_channels(&self.1[start..start + F::CHAN_COUNT]))
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Stream<F> for MicrophoneStream<F> {
+    fn sample_rate(&self) -> Option<f64> {
+        Some(self.5)
+    }
+
+    fn len(&self) -> Option<usize> {
+        Some(self.3 - self.2)
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Drop for MicrophoneStream<F> {
+    fn drop(&mut self) {
+        // Unlock
+        self.0.set(false);
+    }
+}
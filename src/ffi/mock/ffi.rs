@@ -0,0 +1,15 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+//! Mock backend, which plays audio into memory without waiting.
+//! Used for testing code that uses wavy (`testing` feature).
+
+mod speakers;
+
+pub(crate) use speakers::{Shared, Speakers, SpeakersSink};
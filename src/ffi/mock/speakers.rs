@@ -0,0 +1,252 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    fmt::{Display, Error, Formatter},
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+
+use fon::{
+    chan::{Ch32, Channel},
+    surround::Surround32,
+    Frame, Resampler, Sink,
+};
+
+use crate::{
+    consts::PERIOD, AudioError, DeviceCaps, Latency, SampleFormat,
+    SpeakerPosition,
+};
+
+/// State shared between the speakers, their sinks and `MockSpeakers`.
+pub(crate) struct Shared {
+    /// Interleaved samples played so far.
+    pub(crate) played: RefCell<Vec<Ch32>>,
+    /// Resampler context for speakers sink.
+    resampler: Cell<([Ch32; 6], f64)>,
+    /// Speakers are locked
+    locked: Cell<bool>,
+    /// Number of periods the speakers were starved for.
+    pub(crate) underruns: Cell<usize>,
+    /// Frames of the last sink (a `Vec` of its frame type), reused so
+    /// playing doesn't allocate each period.
+    frames: Cell<Option<Box<dyn Any>>>,
+}
+
+/// Mock speakers, which keep played audio in memory.
+pub(crate) struct Speakers {
+    /// Number of channels of the mock's frame type.
+    channels: u8,
+    /// The sample rate of the speakers.
+    sample_rate: f64,
+    /// Shared with the sink and `MockSpeakers`.
+    shared: Rc<Shared>,
+}
+
+impl Display for Speakers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.write_str("Mock")
+    }
+}
+
+impl Speakers {
+    /// Open mock speakers for frames with `channels` channels, with room for
+    /// `capacity` played frames, returning them with the state to read played
+    /// audio from.
+    pub(crate) fn new(
+        sample_rate: u32,
+        channels: u8,
+        capacity: usize,
+    ) -> (Self, Rc<Shared>) {
+        assert_ne!(sample_rate, 0, "Sample rate must not be zero");
+        let played = Vec::with_capacity(capacity * usize::from(channels));
+        let shared = Rc::new(Shared {
+            played: RefCell::new(played),
+            resampler: Cell::new(([Ch32::MID; 6], 0.0)),
+            locked: Cell::new(false),
+            underruns: Cell::new(0),
+            frames: Cell::new(None),
+        });
+        let speakers = Self {
+            channels,
+            sample_rate: sample_rate.into(),
+            shared: shared.clone(),
+        };
+        (speakers, shared)
+    }
+
+    /// Generate an audio sink for the user to fill.
+    pub(crate) fn play<F>(&mut self) -> Result<SpeakersSink<F>, AudioError>
+    where
+        F: Frame<Chan = Ch32>,
+    {
+        if let Err(error) =
+            AudioError::check_channels(F::CHAN_COUNT, 1 << (self.channels - 1))
+        {
+            // Unlock, since no sink will be created.
+            self.shared.locked.set(false);
+            return Err(error);
+        }
+        // Convert the resampler to the target speaker configuration.
+        let (frame, index) = self.shared.resampler.get();
+        let resampler = Resampler::<F>::new(
+            Surround32::from_channels(&frame[..]).convert(),
+            index,
+        );
+        let mut frames = self
+            .shared
+            .frames
+            .take()
+            .and_then(|frames| frames.downcast::<Vec<F>>().ok())
+            .unwrap_or_default();
+        frames.clear();
+        frames.resize(PERIOD.into(), F::default());
+        Ok(SpeakersSink(
+            self.shared.clone(),
+            resampler,
+            Some(frames),
+            self.sample_rate,
+        ))
+    }
+
+    pub(crate) fn channels(&self) -> u8 {
+        // Can't reconfigure while the sink is alive.
+        if self.shared.locked.get() {
+            return 0;
+        }
+
+        1 << (self.channels - 1)
+    }
+
+    pub(crate) fn id(&self) -> &str {
+        "mock"
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        "Mock"
+    }
+
+    /// Forget the resampler's last frame (played audio is kept).
+    pub(crate) fn flush(&mut self) {
+        self.shared.resampler.set(([Ch32::MID; 6], 0.0));
+    }
+
+    /// Get the number of underruns since the device was opened.
+    pub(crate) fn xruns(&self) -> usize {
+        self.shared.underruns.get()
+    }
+
+    /// Never suspended.
+    pub(crate) fn suspends(&self) -> usize {
+        0
+    }
+
+    /// No speakers to place channels on.
+    pub(crate) fn channel_map(&self) -> Vec<SpeakerPosition> {
+        Vec::new()
+    }
+
+    /// Get the sample rate the device was configured with, `None` until
+    /// configured.
+    pub(crate) fn sample_rate(&self) -> Option<f64> {
+        Some(self.sample_rate)
+    }
+
+    pub(crate) fn caps(&self) -> DeviceCaps {
+        DeviceCaps::fixed(1 << (self.channels - 1), self.sample_rate(), PERIOD)
+    }
+
+    /// Periods are always the default length.
+    pub(crate) fn set_period(&mut self, _frames: u16, _count: u8) {}
+
+    /// Get the configured period, `0` until configured.
+    pub(crate) fn period(&self) -> u16 {
+        PERIOD
+    }
+
+    /// Get the number of frames the device buffers, if known.
+    pub(crate) fn buffer_frames(&self) -> Option<usize> {
+        Some(PERIOD.into())
+    }
+
+    pub(crate) fn format(&self) -> SampleFormat {
+        SampleFormat::F32
+    }
+}
+
+impl Future for Speakers {
+    type Output = Result<(), AudioError>;
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.shared.locked.get() {
+            return Poll::Ready(Err(AudioError::DeviceLocked));
+        }
+
+        // Always ready, audio is "played" as soon as the sink is dropped.
+        self.shared.locked.set(true);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[allow(clippy::box_collection)]
+pub(crate) struct SpeakersSink<F: Frame<Chan = Ch32>>(
+    Rc<Shared>,
+    Resampler<F>,
+    /// Frames of the period, handed back to the speakers when dropped (boxed,
+    /// so they're handed back as `dyn Any` without allocating).
+    Option<Box<Vec<F>>>,
+    f64,
+);
+
+impl<F: Frame<Chan = Ch32>> SpeakersSink<F> {
+    pub(crate) fn latency(&self) -> Latency {
+        Latency::new(PERIOD.into(), self.3)
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Sink<F> for SpeakersSink<F> {
+    fn sample_rate(&self) -> f64 {
+        self.3
+    }
+
+    fn resampler(&mut self) -> &mut Resampler<F> {
+        &mut self.1
+    }
+
+    fn buffer(&mut self) -> &mut [F] {
+        self.2
+            .as_mut()
+            .map_or(&mut [], |frames| frames.as_mut_slice())
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Drop for SpeakersSink<F> {
+    fn drop(&mut self) {
+        // "Play" the whole period, including silence that wasn't written.
+        let mut played = self.0.played.borrow_mut();
+        for frame in self.2.iter().flat_map(|frames| frames.iter()) {
+            played.extend_from_slice(frame.channels());
+        }
+        // Store 5.1 surround sample to resampler.
+        let frame: Surround32 = self.1.frame().convert();
+        let mut channels = [Ch32::MID; 6];
+        channels.copy_from_slice(frame.channels());
+        // Store partial index from resampler.
+        self.0.resampler.set((channels, self.1.index() % 1.0));
+        // Hand the frames back to reuse, and unlock
+        if let Some(frames) = self.2.take() {
+            self.0.frames.set(Some(frames));
+        }
+        self.0.locked.set(false);
+    }
+}
@@ -0,0 +1,22 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+//! Null backend, which has no hardware and is paced by the wall clock.  Used
+//! on machines without a sound card, such as CI servers.
+
+mod microphone;
+mod speakers;
+mod timer;
+
+pub(crate) use microphone::{Microphone, MicrophoneStream};
+pub(crate) use speakers::{Speakers, SpeakersSink};
+use timer::Timer;
+
+/// Bitflags for numbers of channels (mono, stereo and 5.1 surround).
+const SUPPORTED: u8 = 0b0010_0011;
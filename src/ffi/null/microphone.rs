@@ -0,0 +1,202 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use std::{
+    fmt::{Display, Error, Formatter},
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    rc::Rc,
+    sync::atomic::{AtomicBool, Ordering::SeqCst},
+    task::{Context, Poll},
+};
+
+use fon::{
+    chan::{Ch32, Channel},
+    Frame, Stream,
+};
+
+use super::{Timer, SUPPORTED};
+use crate::{consts::PERIOD, AudioError, DeviceCaps, Latency, SampleFormat};
+
+/// Null microphone, which records silence.
+pub(crate) struct Microphone {
+    // Sample Rate of The Microphone (src)
+    sample_rate: f64,
+    /// Timer for each period, started when first configured.
+    timer: Option<Timer>,
+    /// Shared lock with the stream, so either may be dropped first.
+    locked: Rc<AtomicBool>,
+}
+
+impl Display for Microphone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.write_str("Null")
+    }
+}
+
+impl Microphone {
+    /// Open a null microphone.
+    pub(crate) fn new(sample_rate: u32) -> Self {
+        assert_ne!(sample_rate, 0, "Sample rate must not be zero");
+        Self {
+            sample_rate: sample_rate.into(),
+            timer: None,
+            locked: Rc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub(crate) fn record<F: Frame<Chan = Ch32>>(
+        &mut self,
+    ) -> Result<MicrophoneStream<F>, AudioError> {
+        if let Err(error) = AudioError::check_channels(F::CHAN_COUNT, SUPPORTED)
+        {
+            // Unlock, since no stream will be created.
+            self.locked.store(false, SeqCst);
+            return Err(error);
+        }
+        // The first stream is empty, silence is recorded from then on.
+        let len = if self.timer.is_none() {
+            self.timer = Some(Timer::new(PERIOD, self.sample_rate));
+            0
+        } else {
+            PERIOD.into()
+        };
+
+        Ok(MicrophoneStream(
+            self.locked.clone(),
+            0,
+            len,
+            PhantomData,
+            self.sample_rate,
+        ))
+    }
+
+    pub(crate) fn channels(&self) -> u8 {
+        // Can't reconfigure while the stream is alive.
+        if self.locked.load(SeqCst) {
+            return 0;
+        }
+
+        SUPPORTED
+    }
+
+    pub(crate) fn id(&self) -> &str {
+        "null"
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        "Null"
+    }
+
+    /// Get the number of overruns since the device was opened.
+    pub(crate) fn xruns(&self) -> usize {
+        0
+    }
+
+    /// Never suspended.
+    pub(crate) fn suspends(&self) -> usize {
+        0
+    }
+
+    /// Get the sample rate the device was configured with, `None` until
+    /// configured.
+    pub(crate) fn sample_rate(&self) -> Option<f64> {
+        self.timer.as_ref().map(|_| self.sample_rate)
+    }
+
+    pub(crate) fn caps(&self) -> DeviceCaps {
+        DeviceCaps::fixed(SUPPORTED, self.sample_rate(), PERIOD)
+    }
+
+    /// Periods are always the default length.
+    pub(crate) fn set_period(&mut self, _frames: u16, _count: u8) {}
+
+    /// Get the configured period, `0` until configured.
+    pub(crate) fn period(&self) -> u16 {
+        PERIOD
+    }
+
+    /// Get the number of frames the device buffers, if known.
+    pub(crate) fn buffer_frames(&self) -> Option<usize> {
+        Some(PERIOD.into())
+    }
+
+    pub(crate) fn format(&self) -> SampleFormat {
+        SampleFormat::F32
+    }
+}
+
+impl Future for Microphone {
+    type Output = Result<(), AudioError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Get mutable reference to microphone.
+        let this = self.get_mut();
+
+        if this.locked.load(SeqCst) {
+            return Poll::Ready(Err(AudioError::DeviceLocked));
+        }
+
+        // If microphone is unconfigured, return Ready to configure and
+        // record, otherwise wait for the next period to be "recorded".
+        if let Some(timer) = &this.timer {
+            if timer.poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+
+        // Ready, audio buffer has been filled!
+        this.locked.store(true, SeqCst);
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub(crate) struct MicrophoneStream<F: Frame<Chan = Ch32>>(
+    Rc<AtomicBool>,
+    usize,
+    usize,
+    PhantomData<F>,
+    f64,
+);
+
+impl<F: Frame<Chan = Ch32>> MicrophoneStream<F> {
+    pub(crate) fn latency(&self) -> Latency {
+        Latency::new(self.2 - self.1, self.4)
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Iterator for MicrophoneStream<F> {
+    type Item = F;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.1 >= self.2 {
+            return None;
+        }
+        self.1 += 1;
+        Some(F::from_channels(&[Ch32::MID; 6]))
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Stream<F> for MicrophoneStream<F> {
+    fn sample_rate(&self) -> Option<f64> {
+        Some(self.4)
+    }
+
+    fn len(&self) -> Option<usize> {
+        Some(self.2)
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Drop for MicrophoneStream<F> {
+    fn drop(&mut self) {
+        // Unlock
+        self.0.store(false, SeqCst);
+    }
+}
@@ -0,0 +1,272 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+#![allow(unsafe_code)]
+
+use std::{
+    fmt::{Display, Error, Formatter},
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering::SeqCst},
+    task::{Context, Poll},
+};
+
+use fon::{
+    chan::{Ch32, Channel},
+    surround::Surround32,
+    Frame, Resampler, Sink,
+};
+
+use super::{Timer, SUPPORTED};
+use crate::{
+    consts::PERIOD, AudioError, DeviceCaps, Latency, SampleFormat,
+    SpeakerPosition,
+};
+
+struct SpeakersInner {
+    /// Raw buffer of audio, discarded each period.
+    buffer: Vec<Ch32>,
+    /// Resampler context for speakers sink.
+    resampler: ([Ch32; 6], f64),
+    /// Speakers are locked
+    locked: AtomicBool,
+    /// Speakers were dropped while locked, so the sink frees them
+    orphaned: AtomicBool,
+}
+
+/// Null speakers, which discard audio.
+pub(crate) struct Speakers {
+    /// Number of configured channels
+    channels: u8,
+    /// The sample rate of the speakers.
+    sample_rate: f64,
+    /// Timer for each period, started when first configured.
+    timer: Option<Timer>,
+    /// Leaked shared box
+    inner: *mut SpeakersInner,
+}
+
+impl Drop for Speakers {
+    fn drop(&mut self) {
+        // If the sink is still alive, it frees the speakers when dropped.
+        if unsafe { (*self.inner).locked.load(SeqCst) } {
+            unsafe { (*self.inner).orphaned.store(true, SeqCst) };
+            return;
+        }
+
+        unsafe { drop(Box::from_raw(self.inner)) };
+    }
+}
+
+impl Display for Speakers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.write_str("Null")
+    }
+}
+
+impl Speakers {
+    /// Open null speakers.
+    pub(crate) fn new(sample_rate: u32) -> Self {
+        assert_ne!(sample_rate, 0, "Sample rate must not be zero");
+        Self {
+            channels: 0,
+            sample_rate: sample_rate.into(),
+            timer: None,
+            inner: Box::leak(Box::new(SpeakersInner {
+                buffer: Vec::new(),
+                resampler: ([Ch32::MID; 6], 0.0),
+                locked: AtomicBool::new(false),
+                orphaned: AtomicBool::new(false),
+            })),
+        }
+    }
+
+    /// Generate an audio sink for the user to fill.
+    pub(crate) fn play<F>(&mut self) -> Result<SpeakersSink<F>, AudioError>
+    where
+        F: Frame<Chan = Ch32>,
+    {
+        // Always called after ready, so should be safe
+        let inner = unsafe { self.inner.as_mut().unwrap() };
+        // Change number of channels, if different than last call.
+        if F::CHAN_COUNT != self.channels.into() {
+            if let Err(error) =
+                AudioError::check_channels(F::CHAN_COUNT, SUPPORTED)
+            {
+                // Unlock, since no sink will be created.
+                inner.locked.store(false, SeqCst);
+                return Err(error);
+            }
+            self.channels = F::CHAN_COUNT as u8;
+            inner
+                .buffer
+                .resize(usize::from(PERIOD) * F::CHAN_COUNT, Ch32::MID);
+        }
+        if self.timer.is_none() {
+            self.timer = Some(Timer::new(PERIOD, self.sample_rate));
+        }
+        // Convert the resampler to the target speaker configuration.
+        let resampler = Resampler::<F>::new(
+            Surround32::from_channels(&inner.resampler.0[..]).convert(),
+            inner.resampler.1,
+        );
+        // Create a sink that borrows this speaker's buffer mutably.
+        Ok(SpeakersSink(
+            inner,
+            resampler,
+            PhantomData,
+            self.sample_rate,
+        ))
+    }
+
+    pub(crate) fn channels(&self) -> u8 {
+        // Can't reconfigure while the sink is alive.
+        if unsafe { (*self.inner).locked.load(SeqCst) } {
+            return 0;
+        }
+
+        SUPPORTED
+    }
+
+    pub(crate) fn id(&self) -> &str {
+        "null"
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        "Null"
+    }
+
+    /// Forget the resampler's last frame (audio is discarded anyway).
+    pub(crate) fn flush(&mut self) {
+        unsafe { (*self.inner).resampler = ([Ch32::MID; 6], 0.0) };
+    }
+
+    /// Get the number of underruns since the device was opened.
+    pub(crate) fn xruns(&self) -> usize {
+        0
+    }
+
+    /// Never suspended.
+    pub(crate) fn suspends(&self) -> usize {
+        0
+    }
+
+    /// No speakers to place channels on.
+    pub(crate) fn channel_map(&self) -> Vec<SpeakerPosition> {
+        Vec::new()
+    }
+
+    /// Get the sample rate the device was configured with, `None` until
+    /// configured.
+    pub(crate) fn sample_rate(&self) -> Option<f64> {
+        self.timer.as_ref().map(|_| self.sample_rate)
+    }
+
+    pub(crate) fn caps(&self) -> DeviceCaps {
+        DeviceCaps::fixed(SUPPORTED, self.sample_rate(), PERIOD)
+    }
+
+    /// Periods are always the default length.
+    pub(crate) fn set_period(&mut self, _frames: u16, _count: u8) {}
+
+    /// Get the configured period, `0` until configured.
+    pub(crate) fn period(&self) -> u16 {
+        PERIOD
+    }
+
+    /// Get the number of frames the device buffers, if known.
+    pub(crate) fn buffer_frames(&self) -> Option<usize> {
+        Some(PERIOD.into())
+    }
+
+    pub(crate) fn format(&self) -> SampleFormat {
+        SampleFormat::F32
+    }
+}
+
+impl Future for Speakers {
+    type Output = Result<(), AudioError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Get mutable reference to speakers.
+        let this = self.get_mut();
+
+        // Safety
+        if unsafe { (*this.inner).locked.load(SeqCst) } {
+            return Poll::Ready(Err(AudioError::DeviceLocked));
+        }
+
+        // If speaker is unconfigured, return Ready to configure and play,
+        // otherwise wait for the last period to be "played".
+        if let Some(timer) = &this.timer {
+            if timer.poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+
+        // Ready for more samples.
+        unsafe { (*this.inner).locked.store(true, SeqCst) };
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub(crate) struct SpeakersSink<F: Frame<Chan = Ch32>>(
+    *mut SpeakersInner,
+    Resampler<F>,
+    PhantomData<F>,
+    f64,
+);
+
+impl<F: Frame<Chan = Ch32>> SpeakersSink<F> {
+    pub(crate) fn latency(&self) -> Latency {
+        Latency::new(PERIOD.into(), self.3)
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Sink<F> for SpeakersSink<F> {
+    fn sample_rate(&self) -> f64 {
+        self.3
+    }
+
+    fn resampler(&mut self) -> &mut Resampler<F> {
+        &mut self.1
+    }
+
+    fn buffer(&mut self) -> &mut [F] {
+        let speakers = unsafe { self.0.as_mut().unwrap() };
+        let data = speakers.buffer.as_mut_ptr().cast();
+        unsafe { std::slice::from_raw_parts_mut(data, PERIOD.into()) }
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Drop for SpeakersSink<F> {
+    fn drop(&mut self) {
+        //
+        let speakers = unsafe { self.0.as_mut().unwrap() };
+        // Store 5.1 surround sample to resampler.
+        let frame: Surround32 = self.1.frame().convert();
+        speakers.resampler.0 = [
+            frame.channels()[0],
+            frame.channels()[1],
+            frame.channels()[2],
+            frame.channels()[3],
+            frame.channels()[4],
+            frame.channels()[5],
+        ];
+        // Store partial index from resampler.
+        speakers.resampler.1 = self.1.index() % 1.0;
+        // Unlock
+        speakers.locked.store(false, SeqCst);
+        // Free the speakers if they were dropped first.
+        if speakers.orphaned.load(SeqCst) {
+            unsafe { drop(Box::from_raw(self.0)) };
+        }
+    }
+}
@@ -0,0 +1,223 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use thread::Timer;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use wasm::Timer;
+
+/// Timer that ticks from its own thread.
+#[cfg(not(target_arch = "wasm32"))]
+mod thread {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst},
+            Arc, Mutex,
+        },
+        task::{Context, Poll, Waker},
+        time::{Duration, Instant},
+    };
+
+    #[derive(Default)]
+    struct Shared {
+        /// Number of periods that have elapsed and haven't been polled (at
+        /// most one, since missed periods aren't caught up on).
+        ticks: AtomicUsize,
+        /// Set when the timer is dropped, to stop the thread.
+        stop: AtomicBool,
+        /// Waker for the future waiting on the next period.
+        waker: Mutex<Option<Waker>>,
+    }
+
+    /// Wakes once every period, measured against the wall clock.
+    pub(crate) struct Timer(Arc<Shared>);
+
+    impl Timer {
+        /// Start a timer for a period of `frames` at `sample_rate`.
+        pub(crate) fn new(frames: u16, sample_rate: f64) -> Self {
+            let period =
+                Duration::from_secs_f64(f64::from(frames) / sample_rate);
+            let shared = Arc::new(Shared::default());
+            let timer = shared.clone();
+            std::thread::spawn(move || {
+                // Sleep until absolute deadlines, so that oversleeping doesn't
+                // slow down the rate over time.
+                let mut deadline = Instant::now();
+                while !timer.stop.load(SeqCst) {
+                    deadline += period;
+                    let now = Instant::now();
+                    if deadline > now {
+                        std::thread::sleep(deadline - now);
+                    } else if now - deadline > period {
+                        // Too far behind to catch up, start over from now.
+                        deadline = now;
+                    }
+                    // Periods that weren't polled in time are dropped, like a
+                    // device that underruns, instead of bursting to catch up.
+                    timer.ticks.store(1, SeqCst);
+                    if let Some(waker) = timer.waker.lock().unwrap().take() {
+                        waker.wake();
+                    }
+                }
+            });
+            Self(shared)
+        }
+
+        /// Returns `Ready` once for each period that has elapsed.
+        pub(crate) fn poll(&self, cx: &mut Context<'_>) -> Poll<()> {
+            if self.tick() {
+                return Poll::Ready(());
+            }
+            *self.0.waker.lock().unwrap() = Some(cx.waker().clone());
+            // Check again, in case the period elapsed before registering.
+            if self.tick() {
+                return Poll::Ready(());
+            }
+            Poll::Pending
+        }
+
+        fn tick(&self) -> bool {
+            self.0
+                .ticks
+                .fetch_update(SeqCst, SeqCst, |ticks| ticks.checked_sub(1))
+                .is_ok()
+        }
+    }
+
+    impl Drop for Timer {
+        fn drop(&mut self) {
+            self.0.stop.store(true, SeqCst);
+        }
+    }
+}
+
+/// Timer for the web, where threads can't be spawned: it checks the clock when
+/// polled, and sets a timeout to wake for the next period.
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use std::{
+        cell::Cell,
+        task::{Context, Poll},
+    };
+
+    use js_sys::Date;
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    /// Wakes once every period, measured against the wall clock.
+    pub(crate) struct Timer {
+        /// Length of a period in milliseconds.
+        period: f64,
+        /// Time the timer was started, in milliseconds.
+        start: f64,
+        /// Number of periods from the start that have been polled.
+        polled: Cell<f64>,
+    }
+
+    impl Timer {
+        /// Start a timer for a period of `frames` at `sample_rate`.
+        pub(crate) fn new(frames: u16, sample_rate: f64) -> Self {
+            Self {
+                period: f64::from(frames) * 1000.0 / sample_rate,
+                start: Date::now(),
+                polled: Cell::new(0.0),
+            }
+        }
+
+        /// Returns `Ready` once for each period that has elapsed.
+        pub(crate) fn poll(&self, cx: &mut Context<'_>) -> Poll<()> {
+            let elapsed = ((Date::now() - self.start) / self.period).floor();
+            if elapsed > self.polled.get() {
+                // Periods that weren't polled in time are dropped, like a
+                // device that underruns, instead of bursting to catch up.
+                self.polled.set(elapsed);
+                return Poll::Ready(());
+            }
+            let next = self.start + (elapsed + 1.0) * self.period;
+            let timeout = (next - Date::now()).ceil().max(0.0) as i32;
+            let waker = cx.waker().clone();
+            let wake = Closure::once_into_js(move || waker.wake());
+            match web_sys::window() {
+                Some(window) => {
+                    let _ = window
+                        .set_timeout_with_callback_and_timeout_and_arguments_0(
+                            wake.unchecked_ref(),
+                            timeout,
+                        );
+                }
+                // Without a window to set a timeout on, check again soon.
+                None => cx.waker().wake_by_ref(),
+            }
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::{
+        sync::Arc,
+        task::{Context, Wake, Waker},
+        thread::Thread,
+        time::{Duration, Instant},
+    };
+
+    use super::Timer;
+
+    /// Wakes the test's thread.
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Block until the next period of `timer`.
+    fn wait(timer: &Timer) {
+        let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+        while timer.poll(&mut Context::from_waker(&waker)).is_pending() {
+            std::thread::park();
+        }
+    }
+
+    /// Check if a period of `timer` has elapsed, without waiting.
+    fn ready(timer: &Timer) -> bool {
+        let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+        timer.poll(&mut Context::from_waker(&waker)).is_ready()
+    }
+
+    #[test]
+    fn paced_by_period() {
+        // 10 ms periods.
+        let timer = Timer::new(480, 48_000.0);
+        let start = Instant::now();
+        for _ in 0..10 {
+            wait(&timer);
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(90), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(500), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn missed_periods_dropped() {
+        // 50 ms periods.
+        let timer = Timer::new(2400, 48_000.0);
+        std::thread::sleep(Duration::from_millis(275));
+        // Only one of the missed periods is ready.
+        assert!(ready(&timer));
+        assert!(!ready(&timer));
+        // The next one comes within a period, not in a burst.
+        let start = Instant::now();
+        wait(&timer);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(10), "{:?}", elapsed);
+        assert!(elapsed <= Duration::from_millis(60), "{:?}", elapsed);
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+//! Device watcher for platforms without hot-plug notifications, which checks
+//! the device list again at a fixed interval.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+#[derive(Default)]
+struct Shared {
+    /// Set each time the interval elapses.
+    ready: AtomicBool,
+    /// Set when the watcher is dropped, to stop the thread.
+    stop: AtomicBool,
+    /// Waker for the future waiting on the interval.
+    waker: Mutex<Option<Waker>>,
+}
+
+/// Wakes when audio devices may have been plugged in or unplugged.
+pub(crate) struct Watcher(Arc<Shared>);
+
+impl Watcher {
+    pub(crate) fn new(interval: Duration) -> Self {
+        let shared = Arc::new(Shared::default());
+        let timer = shared.clone();
+        std::thread::spawn(move || {
+            while !timer.stop.load(SeqCst) {
+                std::thread::sleep(interval);
+                timer.ready.store(true, SeqCst);
+                if let Some(waker) = timer.waker.lock().unwrap().take() {
+                    waker.wake();
+                }
+            }
+        });
+        Self(shared)
+    }
+
+    /// Returns `Ready` when the device list should be checked again.
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0.ready.swap(false, SeqCst) {
+            return Poll::Ready(());
+        }
+        *self.0.waker.lock().unwrap() = Some(cx.waker().clone());
+        // Check again, in case the interval elapsed before registering.
+        if self.0.ready.swap(false, SeqCst) {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.0.stop.store(true, SeqCst);
+    }
+}
@@ -7,7 +7,13 @@
 // At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
 // LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
 
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::AudioError;
 
 pub(crate) trait SoundDevice: Display + Default {
     const INPUT: bool;
@@ -16,6 +22,20 @@ pub(crate) trait SoundDevice: Display + Default {
 /// Return a list of available audio devices.
 pub(crate) fn device_list<D: SoundDevice, F: Fn(D) -> T, T>(
     abstrakt: F,
-) -> Vec<T> {
-    vec![abstrakt(D::default())]
+) -> Vec<Result<T, (String, AudioError)>> {
+    vec![Ok(abstrakt(D::default()))]
+}
+
+/// Wakes when audio devices may have been plugged in or unplugged.
+pub(crate) struct Watcher;
+
+impl Watcher {
+    pub(crate) fn new(_interval: Duration) -> Self {
+        Self
+    }
+
+    /// The browser only exposes the default devices, so the list never changes.
+    pub(crate) fn poll(&mut self, _cx: &mut Context<'_>) -> Poll<()> {
+        Poll::Pending
+    }
 }
@@ -9,14 +9,19 @@
 
 use std::task::Waker;
 
-use wasm_bindgen::{closure::Closure, JsCast};
+use js_sys::{Array, Float32Array};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
 use web_sys::{
-    AudioContext, AudioDestinationNode, AudioProcessingEvent,
-    MediaStreamAudioSourceNode, ScriptProcessorNode,
+    AudioContext, AudioDestinationNode, AudioWorkletNode,
+    AudioWorkletNodeOptions, Blob, BlobPropertyBag, MediaStreamAudioSourceNode,
+    MessageEvent, Url,
 };
 
 use crate::consts::BUFFER_SIZE;
 
+/// Source of the `AudioWorkletProcessor`.
+const WORKLET: &str = include_str!("worklet.js");
+
 /// Global State of AudioContext.
 ///
 /// There are 4 possible states:
@@ -37,11 +42,9 @@ struct State {
     l_buffer: [f32; BUFFER_SIZE as usize],
     /// Right output channel buffer.
     r_buffer: [f32; BUFFER_SIZE as usize],
-    /// The processor node that wakes and executes futures.  Though this API is
-    /// deprecated, the new API does not work on Safari (yet).  This currently
-    /// works on all browsers.  Once browser support changes, this should be
-    /// changed to use `AudioWorkletNode`.
-    proc: Option<ScriptProcessorNode>,
+    /// The worklet node that wakes and executes futures, `None` until the
+    /// worklet module has loaded.
+    node: Option<AudioWorkletNode>,
     /// Waker from speaker future
     speaker_waker: Option<Waker>,
     /// Waker from microphone future.
@@ -56,63 +59,110 @@ struct State {
 
 impl State {
     fn lazy_init(&mut self) {
+        if self.context.is_some() {
+            return;
+        }
+
         // AudioContext
-        if state().context.is_none() {
-            let audio_context =
-                AudioContext::new().expect("Couldn't initialize AudioContext");
+        let audio_context =
+            AudioContext::new().expect("Couldn't initialize AudioContext");
+        self.sample_rate = Some(audio_context.sample_rate().into());
+
+        // Load the worklet from a blob, so no extra files need to be served.
+        let source = format!("const SIZE = {};\n{}", BUFFER_SIZE, WORKLET);
+        let blob = Blob::new_with_str_sequence_and_options(
+            &Array::of1(&source.into()),
+            BlobPropertyBag::new().type_("text/javascript"),
+        )
+        .unwrap();
+        let url = Url::create_object_url_with_blob(&blob).unwrap();
+        let promise = audio_context
+            .audio_worklet()
+            .expect("AudioWorklet not supported")
+            .add_module(&url)
+            .unwrap();
+        #[allow(trivial_casts)] // Actually needed here.
+        let cb = Closure::wrap(
+            Box::new(|_| state().start()) as Box<dyn FnMut(JsValue)>
+        );
+        let _ = promise.then(&cb);
+        cb.forget();
+
+        self.context = Some(audio_context);
+    }
+
+    /// Create the worklet node once the module has loaded.
+    fn start(&mut self) {
+        let node = AudioWorkletNode::new_with_options(
+            self.context.as_ref().unwrap(),
+            "wavy",
+            AudioWorkletNodeOptions::new()
+                .output_channel_count(&Array::of1(&JsValue::from(2))),
+        )
+        .unwrap();
+        #[allow(trivial_casts)] // Actually needed here.
+        let js_function: Closure<dyn Fn(MessageEvent)> =
+            Closure::wrap(Box::new(|event| state().receive(event)));
+        node.port()
+            .unwrap()
+            .set_onmessage(Some(js_function.as_ref().unchecked_ref()));
+        js_function.forget();
+
+        // Connect devices that were opened while the module was loading.
+        if let Some(speaker) = &self.speaker {
+            node.connect_with_audio_node(speaker).unwrap();
+        }
+        for microphone in self.microphone.iter() {
+            microphone.connect_with_audio_node(&node).unwrap();
+        }
+
+        self.node = Some(node);
+    }
 
-            state().sample_rate = Some(audio_context.sample_rate().into());
+    /// Handle a block from the worklet.
+    fn receive(&mut self, event: MessageEvent) {
+        let data: Array = event.data().unchecked_into();
 
-            state().context = Some(audio_context);
+        // If the worklet ran out of audio, queue silence so that the next
+        // block from the speakers isn't played late.
+        if self.speaker.is_some() && data.get(0).as_f64() == Some(0.0) {
+            self.l_buffer = [0.0; BUFFER_SIZE as usize];
+            self.r_buffer = [0.0; BUFFER_SIZE as usize];
+            self.send();
         }
 
-        // ScriptProcessorNode
-        if self.proc.is_none() {
-            let proc = self
-                .context
-                .as_ref()
-                .unwrap()
-                .create_script_processor_with_buffer_size(BUFFER_SIZE.into())
-                .unwrap();
-            #[allow(trivial_casts)] // Actually needed here.
-            let js_function: Closure<dyn Fn(AudioProcessingEvent)> =
-                Closure::wrap(Box::new(move |event| {
-                    // If a microphone is being `.await`ed, wake the thread with
-                    // the input buffer.
-                    if let Some(waker) = state().mics_waker.take() {
-                        // Grab the AudioBuffer.
-                        let inbuf = event
-                            .input_buffer()
-                            .expect("Failed to get input buffer");
-                        // Read microphone input.
-                        inbuf
-                            .copy_from_channel(&mut state().i_buffer, 0)
-                            .unwrap();
-                        // Set future to complete.
-                        state().recorded = true;
-                        // Wake the microphone future.
-                        waker.wake();
-                    }
-
-                    // If the speakers are being `.await`ed, wake the thread to
-                    // fill the output buffer.
-                    if let Some(waker) = state().speaker_waker.take() {
-                        // Set future to complete.
-                        state().played = true;
-                        // Wake the speaker future to generate audio data.
-                        waker.wake();
-                        // Grab the AudioBuffer.
-                        let out = event
-                            .output_buffer()
-                            .expect("Failed to get output buffer");
-                        // Write speaker output.
-                        out.copy_to_channel(&mut state().l_buffer, 0).unwrap();
-                        out.copy_to_channel(&mut state().r_buffer, 1).unwrap();
-                    }
-                }));
-            proc.set_onaudioprocess(Some(js_function.as_ref().unchecked_ref()));
-            js_function.forget();
-            self.proc = Some(proc);
+        // If a microphone is being `.await`ed, wake the thread with the input
+        // buffer.
+        if !self.microphone.is_empty() {
+            if let Some(waker) = self.mics_waker.take() {
+                // Read microphone input.
+                Float32Array::unchecked_from_js(data.get(1))
+                    .copy_to(&mut self.i_buffer);
+                // Set future to complete.
+                self.recorded = true;
+                // Wake the microphone future.
+                waker.wake();
+            }
+        }
+
+        // If the speakers are being `.await`ed, wake the thread to fill the
+        // output buffer.
+        if let Some(waker) = self.speaker_waker.take() {
+            // Set future to complete.
+            self.played = true;
+            // Wake the speaker future to generate audio data.
+            waker.wake();
+        }
+    }
+
+    /// Queue the output buffers to be played by the worklet.
+    fn send(&self) {
+        if let Some(node) = &self.node {
+            let block = Array::of2(
+                &Float32Array::from(&self.l_buffer[..]),
+                &Float32Array::from(&self.r_buffer[..]),
+            );
+            node.port().unwrap().post_message(&block).unwrap();
         }
     }
 }
@@ -125,7 +175,7 @@ static mut STATE: State = State {
     i_buffer: [0.0; BUFFER_SIZE as usize],
     l_buffer: [0.0; BUFFER_SIZE as usize],
     r_buffer: [0.0; BUFFER_SIZE as usize],
-    proc: None,
+    node: None,
     speaker_waker: None,
     mics_waker: None,
     played: false,
@@ -145,7 +195,25 @@ mod device_list;
 mod microphone;
 mod speakers;
 
-pub(crate) use device_list::device_list;
 use device_list::SoundDevice;
+pub(crate) use device_list::{device_list, Watcher};
 pub(super) use microphone::{Microphone, MicrophoneStream};
 pub(super) use speakers::{Speakers, SpeakersSink};
+
+/// The Web Audio API is always available.
+pub(crate) fn available() -> bool {
+    true
+}
+
+/// Devices can't be listed without opening them.
+pub(crate) fn device_names(
+    _input: bool,
+    _filter: crate::DeviceFilter,
+) -> Option<Vec<(String, String)>> {
+    None
+}
+
+/// The browser picks the default device, and doesn't report it.
+pub(crate) fn default_id(_input: bool) -> Option<String> {
+    None
+}
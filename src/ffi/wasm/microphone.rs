@@ -8,10 +8,12 @@
 // LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
 
 use std::{
+    cell::Cell,
     fmt::{Display, Error, Formatter},
     future::Future,
     marker::PhantomData,
     pin::Pin,
+    rc::Rc,
     sync::atomic::{AtomicBool, Ordering::SeqCst},
     task::{Context, Poll},
 };
@@ -24,20 +26,14 @@ use web_sys::{
 };
 
 use super::SoundDevice;
+use crate::{AudioError, DeviceCaps, Latency, SampleFormat};
 
-pub(crate) struct Microphone(*mut AtomicBool);
-
-#[allow(unsafe_code)]
-impl Drop for Microphone {
-    fn drop(&mut self) {
-        // Safety
-        if unsafe { (*self.0).load(SeqCst) } {
-            eprintln!("Microphone dropped before dropping stream");
-            std::process::exit(1);
-        }
-
-        unsafe { drop(Box::from_raw(self.0)) };
-    }
+pub(crate) struct Microphone {
+    /// Shared lock with the stream, so either may be dropped first.
+    locked: Rc<AtomicBool>,
+    /// `None` while the user is being asked for permission, then whether or
+    /// not it was granted.
+    granted: Rc<Cell<Option<bool>>>,
 }
 
 impl Display for Microphone {
@@ -52,25 +48,42 @@ impl SoundDevice for Microphone {
 
 impl Default for Microphone {
     fn default() -> Self {
+        Self::try_default().expect("Failed to open microphone")
+    }
+}
+
+impl Microphone {
+    /// Opening by name isn't supported, so the public layer searches the
+    /// device list instead.
+    pub(crate) fn open(_name: &str) -> Result<Self, AudioError> {
+        Err(AudioError::NoDevice)
+    }
+
+    /// Open the default microphone.
+    pub(crate) fn try_default() -> Result<Self, AudioError> {
         let state = super::state();
 
         // Lazily Initialize audio context & processor node.
         state.lazy_init();
 
+        // Browsers only start audio contexts after a user gesture.
+        let _ = state.context.as_ref().unwrap().resume();
+
         // Prompt User To Connect Microphone.
         let md = web_sys::window()
-            .unwrap()
+            .ok_or(AudioError::NoDevice)?
             .navigator()
             .media_devices()
-            .ok()
-            .unwrap();
+            .map_err(|_| AudioError::NoDevice)?;
         let promise = md
             .get_user_media_with_constraints(
                 MediaStreamConstraints::new().audio(&JsValue::TRUE),
             )
-            .unwrap();
+            .map_err(|_| AudioError::NoDevice)?;
+        let granted = Rc::new(Cell::new(None));
+        let allowed = granted.clone();
         #[allow(trivial_casts)] // Actually needed here.
-        let cb = Closure::wrap(Box::new(|media_stream| {
+        let cb = Closure::wrap(Box::new(move |media_stream| {
             let state = super::state();
             // Create audio source from media stream.
             let audio_src = MediaStreamAudioSourceNode::new(
@@ -81,54 +94,128 @@ impl Default for Microphone {
             )
             .unwrap();
 
-            // Connect microphones to processor node.
-            audio_src
-                .connect_with_audio_node(state.proc.as_ref().unwrap())
-                .unwrap();
+            // Connect microphones to the worklet, or wait for the worklet to
+            // connect them.
+            if let Some(node) = &state.node {
+                audio_src.connect_with_audio_node(node).unwrap();
+            }
 
             // Add to connected microphones (refresh browser to remove).
             state.microphone.push(audio_src);
+
+            // Wake the microphone future, which was waiting for permission.
+            allowed.set(Some(true));
+            if let Some(waker) = state.mics_waker.take() {
+                waker.wake();
+            }
         }) as Box<dyn FnMut(_)>);
-        let _ = promise.then(&cb);
+        let denied = granted.clone();
+        #[allow(trivial_casts)] // Actually needed here.
+        let err = Closure::wrap(Box::new(move |_| {
+            denied.set(Some(false));
+            if let Some(waker) = super::state().mics_waker.take() {
+                waker.wake();
+            }
+        }) as Box<dyn FnMut(JsValue)>);
+        let _ = promise.then2(&cb, &err);
         cb.forget();
+        err.forget();
 
-        Self(Box::leak(Box::new(AtomicBool::new(false))))
+        Ok(Self {
+            locked: Rc::new(AtomicBool::new(false)),
+            granted,
+        })
     }
-}
 
-impl Microphone {
     pub(crate) fn record<F: Frame<Chan = Ch32>>(
         &mut self,
-    ) -> MicrophoneStream<F> {
-        MicrophoneStream {
-            microphone: self.0,
+    ) -> Result<MicrophoneStream<F>, AudioError> {
+        Ok(MicrophoneStream {
+            microphone: self.locked.clone(),
             index: 0,
             _phantom: PhantomData,
-        }
+        })
     }
 
     pub(crate) fn channels(&self) -> u8 {
         0b0000_0001
     }
+
+    pub(crate) fn id(&self) -> &str {
+        "default"
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        "Default"
+    }
+
+    /// Get the number of overruns since the device was opened.
+    pub(crate) fn xruns(&self) -> usize {
+        0
+    }
+
+    /// Suspends aren't reported on this platform.
+    pub(crate) fn suspends(&self) -> usize {
+        0
+    }
+
+    /// Get the sample rate the device was configured with, `None` until
+    /// configured.
+    pub(crate) fn sample_rate(&self) -> Option<f64> {
+        super::state().sample_rate
+    }
+
+    /// Get the capabilities of the device, which runs at the `AudioContext`'s
+    /// rate and the worklet's block size.
+    pub(crate) fn caps(&self) -> DeviceCaps {
+        DeviceCaps::fixed(
+            self.channels(),
+            self.sample_rate(),
+            super::BUFFER_SIZE,
+        )
+    }
+
+    /// The worklet's block size is fixed.
+    pub(crate) fn set_period(&mut self, _frames: u16, _count: u8) {}
+
+    /// Get the configured period, `0` until configured.
+    pub(crate) fn period(&self) -> u16 {
+        super::BUFFER_SIZE
+    }
+
+    /// Get the number of frames the device buffers, if known.
+    pub(crate) fn buffer_frames(&self) -> Option<usize> {
+        None
+    }
+
+    pub(crate) fn format(&self) -> SampleFormat {
+        SampleFormat::F32
+    }
 }
 
 impl Future for Microphone {
-    type Output = ();
+    type Output = Result<(), AudioError>;
 
-    #[allow(unsafe_code)]
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        // Safety
-        if unsafe { (*self.0).load(SeqCst) } {
-            eprintln!("Tried to poll microphone before dropping stream");
-            std::process::exit(1);
+        if self.locked.load(SeqCst) {
+            return Poll::Ready(Err(AudioError::DeviceLocked));
         }
-        let inner = unsafe { self.0.as_mut().unwrap() };
 
         let state = super::state();
+        match self.granted.get() {
+            None => {
+                state.mics_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            Some(false) => {
+                return Poll::Ready(Err(AudioError::PermissionDenied))
+            }
+            Some(true) => {}
+        }
         if state.recorded {
             state.recorded = false;
-            inner.store(true, SeqCst);
-            Poll::Ready(())
+            self.locked.store(true, SeqCst);
+            Poll::Ready(Ok(()))
         } else {
             state.mics_waker = Some(cx.waker().clone());
             Poll::Pending
@@ -138,13 +225,24 @@ impl Future for Microphone {
 
 pub(crate) struct MicrophoneStream<F: Frame<Chan = Ch32>> {
     //
-    microphone: *mut AtomicBool,
+    microphone: Rc<AtomicBool>,
     // Index into buffer
     index: usize,
     //
     _phantom: PhantomData<&'static F>,
 }
 
+impl<F: Frame<Chan = Ch32>> MicrophoneStream<F> {
+    pub(crate) fn latency(&self) -> Latency {
+        // Grab global state.
+        let state = super::state();
+        // Unread samples, plus the block the worklet is filling.
+        let unread = state.i_buffer.len().saturating_sub(self.index);
+        let frames = unread + usize::from(super::BUFFER_SIZE);
+        Latency::new(frames, state.sample_rate.unwrap_or_default())
+    }
+}
+
 impl<F: Frame<Chan = Ch32>> Iterator for MicrophoneStream<F> {
     type Item = F;
 
@@ -171,11 +269,9 @@ impl<F: Frame<Chan = Ch32>> Stream<F> for MicrophoneStream<F> {
     }
 }
 
-#[allow(unsafe_code)]
 impl<F: Frame<Chan = Ch32>> Drop for MicrophoneStream<F> {
     fn drop(&mut self) {
-        let mic = unsafe { self.microphone.as_mut().unwrap() };
         // Unlock
-        mic.store(false, SeqCst);
+        self.microphone.store(false, SeqCst);
     }
 }
@@ -26,6 +26,7 @@ use fon::{
 };
 
 use super::SoundDevice;
+use crate::{AudioError, DeviceCaps, Latency, SampleFormat, SpeakerPosition};
 
 struct SpeakersInner {
     /// Interleaved buffer (must be de-interleaved for the web).
@@ -34,6 +35,8 @@ struct SpeakersInner {
     resampler: ([Ch32; 6], f64),
     ///
     locked: AtomicBool,
+    /// Speakers were dropped while locked, so the sink frees them
+    orphaned: AtomicBool,
 }
 
 pub(crate) struct Speakers {
@@ -43,10 +46,10 @@ pub(crate) struct Speakers {
 #[allow(unsafe_code)]
 impl Drop for Speakers {
     fn drop(&mut self) {
-        // Safety
+        // If the sink is still alive, it frees the speakers when dropped.
         if unsafe { (*self.inner).locked.load(SeqCst) } {
-            eprintln!("Speakers dropped before dropping sink");
-            std::process::exit(1);
+            unsafe { (*self.inner).orphaned.store(true, SeqCst) };
+            return;
         }
 
         unsafe { drop(Box::from_raw(self.inner)) };
@@ -65,6 +68,40 @@ impl Display for Speakers {
 
 impl Default for Speakers {
     fn default() -> Self {
+        Self::try_default().expect("Already connected to speakers!")
+    }
+}
+
+impl Speakers {
+    /// Opening by name isn't supported, so the public layer searches the
+    /// device list instead.
+    pub(crate) fn open(_name: &str) -> Result<Self, AudioError> {
+        Err(AudioError::NoDevice)
+    }
+
+    /// Queued audio isn't drained when dropped.
+    pub(crate) fn set_drain(&mut self, _drain: bool) {}
+
+    /// The device keeps running (playing silence) while paused.
+    pub(crate) fn pause(&mut self) {}
+
+    /// The device kept running, so there's nothing to continue.
+    pub(crate) fn resume(&mut self) {}
+
+    /// Forget the resampler's last frame.  Blocks already posted to the
+    /// worklet still play.
+    #[allow(unsafe_code)]
+    pub(crate) fn flush(&mut self) {
+        unsafe { (*self.inner).resampler = ([Ch32::MID; 6], 0.0) };
+    }
+
+    /// Recording what's played isn't supported.
+    pub(crate) fn loopback(&self) -> Result<super::Microphone, AudioError> {
+        Err(AudioError::NoDevice)
+    }
+
+    /// Open the default speakers.
+    pub(crate) fn try_default() -> Result<Self, AudioError> {
         let state = super::state();
 
         // Lazily Initialize audio context & processor node.
@@ -72,34 +109,35 @@ impl Default for Speakers {
 
         // Check if already connected
         if state.speaker.is_some() {
-            panic!("Already connected to speakers!");
+            return Err(AudioError::DeviceBusy);
         }
 
         // Initialize speakers.
         state.speaker = Some(state.context.as_mut().unwrap().destination());
 
-        // Connect speakers. FIXME
-        state
-            .proc
-            .as_ref()
-            .unwrap()
-            .connect_with_audio_node(state.speaker.as_ref().unwrap())
-            .ok()
-            .unwrap();
+        // Connect speakers, or wait for the worklet to connect them.
+        if let Some(node) = &state.node {
+            node.connect_with_audio_node(state.speaker.as_ref().unwrap())
+                .unwrap();
+        }
+
+        // Browsers only start audio contexts after a user gesture.
+        let _ = state.context.as_ref().unwrap().resume();
 
-        Self {
+        Ok(Self {
             inner: Box::leak(Box::new(SpeakersInner {
                 buffer: vec![0.0; super::BUFFER_SIZE.into()],
                 resampler: ([Ch32::MID; 6], 0.0),
                 locked: AtomicBool::new(false),
+                orphaned: AtomicBool::new(false),
             })),
-        }
+        })
     }
-}
 
-impl Speakers {
     #[allow(unsafe_code)]
-    pub(crate) fn play<F: Frame<Chan = Ch32>>(&mut self) -> SpeakersSink<F> {
+    pub(crate) fn play<F: Frame<Chan = Ch32>>(
+        &mut self,
+    ) -> Result<SpeakersSink<F>, AudioError> {
         // Always called after ready, so should be safe
         let inner = unsafe { self.inner.as_mut().unwrap() };
 
@@ -109,7 +147,12 @@ impl Speakers {
         } else if TypeId::of::<F>() == TypeId::of::<Stereo32>() {
             inner.buffer.resize(super::BUFFER_SIZE as usize * 2, 0.0);
         } else {
-            panic!("Attempted to use Speakers with invalid frame type");
+            // Unlock, since no sink will be created.
+            inner.locked.store(false, SeqCst);
+            return Err(AudioError::UnsupportedChannelCount {
+                requested: F::CHAN_COUNT,
+                supported: self.channels(),
+            });
         }
         // Convert the resampler to the target speaker configuration.
         let resampler = Resampler::<F>::new(
@@ -117,23 +160,78 @@ impl Speakers {
             inner.resampler.1,
         );
         //
-        SpeakersSink(inner, resampler, PhantomData)
+        Ok(SpeakersSink(inner, resampler, PhantomData))
     }
 
     pub(crate) fn channels(&self) -> u8 {
         0b0000_0011
     }
+
+    pub(crate) fn id(&self) -> &str {
+        "default"
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        "Default"
+    }
+
+    /// Get the number of underruns since the device was opened.
+    pub(crate) fn xruns(&self) -> usize {
+        0
+    }
+
+    /// Suspends aren't reported on this platform.
+    pub(crate) fn suspends(&self) -> usize {
+        0
+    }
+
+    /// Channel positions aren't reported on this platform.
+    pub(crate) fn channel_map(&self) -> Vec<SpeakerPosition> {
+        Vec::new()
+    }
+
+    /// Get the sample rate the device was configured with, `None` until
+    /// configured.
+    pub(crate) fn sample_rate(&self) -> Option<f64> {
+        super::state().sample_rate
+    }
+
+    /// Get the capabilities of the device, which runs at the `AudioContext`'s
+    /// rate and the worklet's block size.
+    pub(crate) fn caps(&self) -> DeviceCaps {
+        DeviceCaps::fixed(
+            self.channels(),
+            self.sample_rate(),
+            super::BUFFER_SIZE,
+        )
+    }
+
+    /// The worklet's block size is fixed.
+    pub(crate) fn set_period(&mut self, _frames: u16, _count: u8) {}
+
+    /// Get the configured period, `0` until configured.
+    pub(crate) fn period(&self) -> u16 {
+        super::BUFFER_SIZE
+    }
+
+    /// Get the number of frames the device buffers, if known.
+    pub(crate) fn buffer_frames(&self) -> Option<usize> {
+        None
+    }
+
+    pub(crate) fn format(&self) -> SampleFormat {
+        SampleFormat::F32
+    }
 }
 
 impl Future for Speakers {
-    type Output = ();
+    type Output = Result<(), AudioError>;
 
     #[allow(unsafe_code)]
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         // Safety
         if unsafe { (*self.inner).locked.load(SeqCst) } {
-            eprintln!("Tried to poll speakers before dropping sink");
-            std::process::exit(1);
+            return Poll::Ready(Err(AudioError::DeviceLocked));
         }
         let inner = unsafe { self.inner.as_mut().unwrap() };
 
@@ -141,7 +239,7 @@ impl Future for Speakers {
         if state.played {
             state.played = false;
             inner.locked.store(true, SeqCst);
-            Poll::Ready(())
+            Poll::Ready(Ok(()))
         } else {
             state.speaker_waker = Some(cx.waker().clone());
             Poll::Pending
@@ -155,6 +253,14 @@ pub(crate) struct SpeakersSink<F: Frame<Chan = Ch32>>(
     PhantomData<F>,
 );
 
+impl<F: Frame<Chan = Ch32>> SpeakersSink<F> {
+    pub(crate) fn latency(&self) -> Latency {
+        // The worklet plays this block after the one it's playing.
+        let frames = 2 * usize::from(super::BUFFER_SIZE);
+        Latency::new(frames, self.sample_rate())
+    }
+}
+
 impl<F: Frame<Chan = Ch32>> Sink<F> for SpeakersSink<F> {
     fn sample_rate(&self) -> f64 {
         super::state().sample_rate.unwrap()
@@ -201,6 +307,8 @@ impl<F: Frame<Chan = Ch32>> Drop for SpeakersSink<F> {
         } else {
             unreachable!();
         }
+        // Queue the block on the worklet.
+        super::state().send();
 
         // Store 5.1 surround sample to resampler.
         let frame: Surround32 = self.1.frame().convert();
@@ -216,5 +324,9 @@ impl<F: Frame<Chan = Ch32>> Drop for SpeakersSink<F> {
         speakers.resampler.1 = self.1.index() % 1.0;
         // Unlock
         speakers.locked.store(false, SeqCst);
+        // Free the speakers if they were dropped first.
+        if speakers.orphaned.load(SeqCst) {
+            unsafe { drop(Box::from_raw(self.0)) };
+        }
     }
 }
@@ -0,0 +1,56 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+// `SIZE` (frames per block) is prepended by `ffi.rs` before loading.
+
+// Exchanges blocks of `SIZE` frames with the main thread.
+//
+// Every `SIZE` frames the processor posts `[queued, input]` to the main
+// thread, where `queued` is the number of output blocks left to play and
+// `input` is the recorded (mono) block.  The main thread posts `[left, right]`
+// output blocks back.
+class WavyProcessor extends AudioWorkletProcessor {
+    constructor() {
+        super();
+        this.input = new Float32Array(SIZE);
+        this.index = 0;
+        this.output = [];
+        this.offset = 0;
+        this.port.onmessage = (event) => this.output.push(event.data);
+    }
+
+    process(inputs, outputs) {
+        const input = inputs[0];
+        const output = outputs[0];
+        for (let i = 0; i < output[0].length; i++) {
+            // Play queued output, or silence if there is none.
+            if (this.output.length !== 0) {
+                const block = this.output[0];
+                output[0][i] = block[0][this.offset];
+                output[1][i] = block[1][this.offset];
+                this.offset += 1;
+                if (this.offset === SIZE) {
+                    this.output.shift();
+                    this.offset = 0;
+                }
+            }
+
+            // Record input, or silence if no microphone is connected.
+            this.input[this.index] = input.length !== 0 ? input[0][i] : 0.0;
+            this.index += 1;
+            if (this.index === SIZE) {
+                this.port.postMessage([this.output.length, this.input.slice()]);
+                this.index = 0;
+            }
+        }
+        return true;
+    }
+}
+
+registerProcessor("wavy", WavyProcessor);
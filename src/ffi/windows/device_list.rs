@@ -0,0 +1,359 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+#![allow(unsafe_code)]
+
+use std::{convert::TryInto, os::raw::c_void, ptr};
+
+use fon::chan::{Ch32, Channel};
+
+use super::{
+    channel_mask, EDataFlow, Event, IAudioClient, IAudioRenderClient,
+    IMMDevice, IMMDeviceCollection, IMMDeviceEnumerator, ShareMode,
+    WaveFormatEx, WaveFormatExtensible, AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM,
+    AUDCLNT_STREAMFLAGS_EVENTCALLBACK, AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY,
+    ENUMERATOR, IID_IAUDIO_CAPTURE_CLIENT, IID_IAUDIO_RENDER_CLIENT,
+    KSDATAFORMAT_SUBTYPE_IEEE_FLOAT, WAVE_FORMAT_EXTENSIBLE,
+};
+use crate::AudioError;
+
+/// Build an interleaved native float32 format description.
+fn wave_format(channels: u8, rate: u32) -> WaveFormatExtensible {
+    let block_align = u16::from(channels) * 4;
+    WaveFormatExtensible {
+        format: WaveFormatEx {
+            format_tag: WAVE_FORMAT_EXTENSIBLE,
+            channels: channels.into(),
+            samples_per_sec: rate,
+            avg_bytes_per_sec: rate * u32::from(block_align),
+            block_align,
+            bits_per_sample: 32,
+            size: 22,
+        },
+        valid_bits_per_sample: 32,
+        channel_mask: channel_mask(channels),
+        sub_format: KSDATAFORMAT_SUBTYPE_IEEE_FLOAT,
+    }
+}
+
+/// Open an audio endpoint.
+pub(crate) fn open(
+    endpoint: *mut IMMDevice,
+    name: Option<&str>,
+) -> Option<AudioDevice> {
+    unsafe {
+        let device = open_internal(endpoint, name);
+        if device.is_none() {
+            IMMDevice::release(endpoint);
+        }
+        device
+    }
+}
+
+unsafe fn open_internal(
+    endpoint: *mut IMMDevice,
+    name: Option<&str>,
+) -> Option<AudioDevice> {
+    // The default endpoint keeps a stable ID that follows the system default.
+    let (id, name) = match name {
+        Some(name) => ("default".to_string(), name.to_string()),
+        None => (
+            IMMDevice::id(endpoint)?,
+            IMMDevice::friendly_name(endpoint)?,
+        ),
+    };
+    // Query the audio engine's format (can't initialize this client, so it's
+    // only used for probing).
+    let client = IMMDevice::activate(endpoint).ok()?;
+    let (mix_rate, mix_channels) = match IAudioClient::get_mix_format(client) {
+        Ok(mix) => mix,
+        Err(_) => {
+            IAudioClient::release(client);
+            return None;
+        }
+    };
+    // Mono and stereo are always available through the audio engine's
+    // channel conversion.
+    let mut supported = 0b0000_0011;
+    if (1..=8).contains(&mix_channels) {
+        supported |= 1 << (mix_channels - 1);
+    }
+    for i in 1..=8 {
+        let format = wave_format(i, mix_rate);
+        let format: *const WaveFormatExtensible = &format;
+        if IAudioClient::is_format_supported(
+            client,
+            ShareMode::Shared,
+            format.cast(),
+        ) {
+            supported |= 1 << (i - 1);
+        }
+    }
+    IAudioClient::release(client);
+
+    Some(AudioDevice {
+        id,
+        name,
+        endpoint,
+        client: ptr::null_mut(),
+        service: ptr::null_mut(),
+        mix_rate,
+        supported,
+        exclusive: false,
+        event: Event::new()?,
+        xruns: 0,
+    })
+}
+
+/// Open the default audio endpoint.
+pub(crate) fn open_default(input: bool) -> Result<AudioDevice, AudioError> {
+    ENUMERATOR.with(|enumerator| {
+        let enumerator =
+            enumerator.as_ref().ok_or(AudioError::BackendUnavailable)?;
+        let endpoint = unsafe {
+            IMMDeviceEnumerator::get_default_audio_endpoint(
+                enumerator.0,
+                if input {
+                    EDataFlow::Capture
+                } else {
+                    EDataFlow::Render
+                },
+            )
+            .map_err(|_| AudioError::NoDevice)?
+        };
+        open(endpoint, Some("Default")).ok_or(AudioError::UnsupportedDevice)
+    })
+}
+
+/// Get the endpoint ID of the system default device.
+pub(crate) fn default_id(input: bool) -> Option<String> {
+    ENUMERATOR.with(|enumerator| unsafe {
+        let endpoint = IMMDeviceEnumerator::get_default_audio_endpoint(
+            enumerator.as_ref()?.0,
+            if input {
+                EDataFlow::Capture
+            } else {
+                EDataFlow::Render
+            },
+        )
+        .ok()?;
+        let id = IMMDevice::id(endpoint);
+        IMMDevice::release(endpoint);
+        id
+    })
+}
+
+pub(crate) trait SoundDevice:
+    std::fmt::Display + From<AudioDevice>
+{
+    const INPUT: bool;
+}
+
+/// An Audio Device (input or output).
+#[derive(Debug)]
+pub(crate) struct AudioDevice {
+    /// Persistent endpoint ID string for the device.
+    pub(crate) id: String,
+    /// Human-readable name for the device.
+    pub(crate) name: String,
+    /// WASAPI endpoint for the device.
+    pub(crate) endpoint: *mut IMMDevice,
+    /// Audio client, null until configured.
+    pub(crate) client: *mut IAudioClient,
+    /// Render or capture client, null until configured.
+    pub(crate) service: *mut c_void,
+    /// Sample rate of the audio engine.
+    pub(crate) mix_rate: u32,
+    /// Bitflags for numbers of channels (which of 1-8 are supported)
+    pub(crate) supported: u8,
+    /// If the audio client has exclusive access to the hardware.
+    pub(crate) exclusive: bool,
+    /// Event object signaled when the device is ready for more samples.
+    pub(crate) event: Event,
+    /// Number of underruns (playback) or overruns (capture).
+    pub(crate) xruns: usize,
+}
+
+impl AudioDevice {
+    /// Stop and release the audio client, if configured.
+    unsafe fn reset(&mut self) {
+        if !self.client.is_null() {
+            let _ = IAudioClient::stop(self.client);
+        }
+        IAudioRenderClient::release(self.service.cast());
+        IAudioClient::release(self.client);
+        self.service = ptr::null_mut();
+        self.client = ptr::null_mut();
+    }
+
+    /// Get the number of frames buffered in the audio engine plus the stream
+    /// latency, or `None` if not configured.
+    pub(crate) fn delay(&self) -> Option<usize> {
+        if self.client.is_null() {
+            return None;
+        }
+        unsafe {
+            let padding =
+                IAudioClient::get_current_padding(self.client).ok()?;
+            // Convert from 100 nanosecond units to frames.
+            let latency = IAudioClient::get_stream_latency(self.client).ok()?;
+            let latency =
+                latency.max(0) as u64 * u64::from(self.mix_rate) / 10_000_000;
+            Some(padding as usize + latency as usize)
+        }
+    }
+}
+
+impl Drop for AudioDevice {
+    fn drop(&mut self) {
+        unsafe {
+            self.reset();
+            IMMDevice::release(self.endpoint);
+        }
+    }
+}
+
+/// Return a list of available audio devices.
+pub(crate) fn device_list<D: SoundDevice, F: Fn(D) -> T, T>(
+    abstrakt: F,
+) -> Vec<Result<T, (String, AudioError)>> {
+    ENUMERATOR.with(|enumerator| {
+        if let Some(enumerator) = enumerator {
+            device_list_internal(enumerator.0, abstrakt)
+        } else {
+            Vec::new()
+        }
+    })
+}
+
+fn device_list_internal<D: SoundDevice, F: Fn(D) -> T, T>(
+    enumerator: *mut IMMDeviceEnumerator,
+    abstrakt: F,
+) -> Vec<Result<T, (String, AudioError)>> {
+    let flow = if D::INPUT {
+        EDataFlow::Capture
+    } else {
+        EDataFlow::Render
+    };
+    let mut devices = Vec::new();
+    unsafe {
+        let collection =
+            match IMMDeviceEnumerator::enum_audio_endpoints(enumerator, flow) {
+                Ok(collection) => collection,
+                Err(_) => return Vec::new(),
+            };
+        let count = IMMDeviceCollection::get_count(collection).unwrap_or(0);
+        for i in 0..count {
+            let endpoint = match IMMDeviceCollection::item(collection, i) {
+                Ok(endpoint) => endpoint,
+                Err(_) => continue,
+            };
+            if let Some(device) = open(endpoint, None) {
+                devices.push(Ok(abstrakt(D::from(device))));
+            }
+        }
+        IMMDeviceCollection::release(collection);
+    }
+    devices
+}
+
+/// Initialize an audio client in exclusive mode, if the hardware supports the
+/// format and isn't in use.
+unsafe fn initialize_exclusive(
+    client: *mut IAudioClient,
+    format: *const WaveFormatExtensible,
+    duration: i64,
+) -> Option<()> {
+    if !IAudioClient::is_format_supported(
+        client,
+        ShareMode::Exclusive,
+        format.cast(),
+    ) {
+        return None;
+    }
+    let minimum = IAudioClient::get_minimum_period(client).ok()?;
+    IAudioClient::initialize(
+        client,
+        ShareMode::Exclusive,
+        AUDCLNT_STREAMFLAGS_EVENTCALLBACK,
+        duration.max(minimum),
+        format.cast(),
+    )
+    .ok()
+}
+
+/// Configure the device for a number of channels (the WASAPI equivalent of
+/// `pcm_hw_params` on Linux).
+pub(crate) fn configure(
+    device: &mut AudioDevice,
+    input: bool,
+    channels: u8,
+    buffer: &mut Vec<Ch32>,
+    sample_rate: &mut Option<f64>,
+    period: &mut u16,
+) -> Option<()> {
+    unsafe {
+        // An audio client can only be initialized once, so get a new one.
+        device.reset();
+        device.client = IMMDevice::activate(device.endpoint).ok()?;
+
+        // Use the audio engine's sample rate, resampling is done by `fon`.
+        let format = wave_format(channels, device.mix_rate);
+        let format: *const WaveFormatExtensible = &format;
+        // Buffer duration in 100 nanosecond units near library target period.
+        let duration = i64::from(crate::consts::PERIOD) * 10_000_000
+            / i64::from(device.mix_rate);
+        // Try exclusive mode first, bypassing the audio engine for the lowest
+        // latency.
+        device.exclusive =
+            initialize_exclusive(device.client, format, duration).is_some();
+        if !device.exclusive {
+            // Client can't be initialized again after failing, so get a new
+            // one and fall back to shared mode.
+            IAudioClient::release(device.client);
+            device.client = IMMDevice::activate(device.endpoint).ok()?;
+            IAudioClient::initialize(
+                device.client,
+                ShareMode::Shared,
+                AUDCLNT_STREAMFLAGS_EVENTCALLBACK
+                    | AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM
+                    | AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY,
+                duration,
+                format.cast(),
+            )
+            .ok()?;
+        }
+        IAudioClient::set_event_handle(device.client, device.event.handle)
+            .ok()?;
+        // WASAPI may round the buffer up to the audio engine's period.
+        let frames = IAudioClient::get_buffer_size(device.client).ok()?;
+        device.service = IAudioClient::get_service(
+            device.client,
+            if input {
+                &IID_IAUDIO_CAPTURE_CLIENT
+            } else {
+                &IID_IAUDIO_RENDER_CLIENT
+            },
+        )
+        .ok()?;
+
+        // Now that a configuration has been chosen, set the sample rate.
+        *sample_rate = Some(device.mix_rate.into());
+
+        // Set the period of the buffer.
+        *period = frames.try_into().ok()?;
+
+        // Resize the buffer
+        buffer.resize(*period as usize * channels as usize, Ch32::MID);
+
+        IAudioClient::start(device.client).ok()?;
+    }
+
+    Some(())
+}
@@ -7,4 +7,30 @@
 // At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
 // LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
 
-include!("../dummy/ffi.rs");
+mod microphone;
+#[path = "../polling.rs"]
+mod polling;
+mod speakers;
+mod wasapi;
+
+// Implementation Expectations:
+pub(crate) use microphone::{Microphone, MicrophoneStream};
+pub(crate) use polling::Watcher;
+pub(crate) use speakers::{Speakers, SpeakersSink};
+pub(crate) use wasapi::{
+    available,
+    device_list::{default_id, device_list},
+};
+use wasapi::{
+    device_list::{configure, open_default, AudioDevice, SoundDevice},
+    IAudioCaptureClient, IAudioClient, IAudioRenderClient,
+    AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY, AUDCLNT_BUFFERFLAGS_SILENT,
+};
+
+/// Devices can't be listed without opening them.
+pub(crate) fn device_names(
+    _input: bool,
+    _filter: crate::DeviceFilter,
+) -> Option<Vec<(String, String)>> {
+    None
+}
@@ -0,0 +1,357 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+#![allow(unsafe_code)]
+
+use std::{
+    fmt::{Display, Error, Formatter},
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering::SeqCst},
+    task::{Context, Poll},
+};
+
+use fon::{
+    chan::{Ch32, Channel},
+    Frame, Stream,
+};
+
+use super::{
+    configure, open_default, AudioDevice, IAudioCaptureClient, SoundDevice,
+    AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY, AUDCLNT_BUFFERFLAGS_SILENT,
+};
+use crate::{AudioError, DeviceCaps, Latency, SampleFormat};
+
+struct MicrophoneInner {
+    // WASAPI endpoint
+    device: AudioDevice,
+    // Interleaved Audio Buffer.
+    buffer: Vec<Ch32>,
+    // The period of the microphone.
+    period: u16,
+    // Index to stop reading.
+    endi: usize,
+    /// Microphone are locked
+    locked: AtomicBool,
+    /// Microphone was dropped while locked, so the stream frees it
+    orphaned: AtomicBool,
+}
+
+pub(crate) struct Microphone {
+    // Number of channels on the Microphone.
+    pub(crate) channels: u8,
+    // Sample Rate of The Microphone (src)
+    pub(crate) sample_rate: Option<f64>,
+    /// Leaked shared box
+    inner: *mut MicrophoneInner,
+}
+
+impl Drop for Microphone {
+    fn drop(&mut self) {
+        // If the stream is still alive, it frees the microphone when dropped.
+        if unsafe { (*self.inner).locked.load(SeqCst) } {
+            unsafe { (*self.inner).orphaned.store(true, SeqCst) };
+            return;
+        }
+
+        unsafe { drop(Box::from_raw(self.inner)) };
+    }
+}
+
+impl SoundDevice for Microphone {
+    const INPUT: bool = true;
+}
+
+impl Display for Microphone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        unsafe { f.write_str((*self.inner).device.name.as_str()) }
+    }
+}
+
+impl From<AudioDevice> for Microphone {
+    fn from(device: AudioDevice) -> Self {
+        Self {
+            channels: 0,
+            sample_rate: None,
+            inner: Box::leak(Box::new(MicrophoneInner {
+                device,
+                buffer: Vec::new(),
+                period: 0,
+                endi: 0,
+                locked: AtomicBool::new(false),
+                orphaned: AtomicBool::new(false),
+            })),
+        }
+    }
+}
+
+impl Microphone {
+    /// Opening by name isn't supported, so the public layer searches the
+    /// device list instead.
+    pub(crate) fn open(_name: &str) -> Result<Self, AudioError> {
+        Err(AudioError::NoDevice)
+    }
+
+    /// Open the default microphone.
+    pub(crate) fn try_default() -> Result<Self, AudioError> {
+        Ok(Self::from(open_default(true)?))
+    }
+
+    /// Attempt to configure the microphone for a specific number of channels.
+    fn set_channels<F>(
+        &mut self,
+        inner: &mut MicrophoneInner,
+    ) -> Result<bool, AudioError>
+    where
+        F: Frame<Chan = Ch32>,
+    {
+        if F::CHAN_COUNT != self.channels.into() {
+            AudioError::check_channels(F::CHAN_COUNT, inner.device.supported)?;
+            // Configure the audio client
+            configure(
+                &mut inner.device,
+                true,
+                F::CHAN_COUNT as u8,
+                &mut inner.buffer,
+                &mut self.sample_rate,
+                &mut inner.period,
+            )
+            .ok_or(AudioError::UnsupportedChannelCount {
+                requested: F::CHAN_COUNT,
+                supported: inner.device.supported,
+            })?;
+            self.channels = F::CHAN_COUNT as u8;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub(crate) fn record<F: Frame<Chan = Ch32>>(
+        &mut self,
+    ) -> Result<MicrophoneStream<F>, AudioError> {
+        // Always called after ready, so should be safe
+        let inner = unsafe { self.inner.as_mut().unwrap() };
+
+        // Change number of channels, if different than last call.
+        if let Err(error) = self.set_channels::<F>(inner) {
+            // Unlock, since no stream will be created.
+            inner.locked.store(false, SeqCst);
+            return Err(error);
+        }
+
+        // Stream from microphone's buffer.
+        Ok(MicrophoneStream(
+            inner,
+            0,
+            PhantomData,
+            self.sample_rate,
+            self.channels,
+        ))
+    }
+
+    pub(crate) fn channels(&self) -> u8 {
+        // Can't reconfigure while the stream is alive.
+        if unsafe { (*self.inner).locked.load(SeqCst) } {
+            return 0;
+        }
+
+        unsafe { (*self.inner).device.supported }
+    }
+
+    pub(crate) fn id(&self) -> &str {
+        unsafe { (*self.inner).device.id.as_str() }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        unsafe { (*self.inner).device.name.as_str() }
+    }
+
+    /// Get the number of overruns since the device was opened.
+    pub(crate) fn xruns(&self) -> usize {
+        unsafe { (*self.inner).device.xruns }
+    }
+
+    /// Suspends aren't reported on this platform.
+    pub(crate) fn suspends(&self) -> usize {
+        0
+    }
+
+    /// Get the sample rate the device was configured with, `None` until
+    /// configured.
+    pub(crate) fn sample_rate(&self) -> Option<f64> {
+        self.sample_rate
+    }
+
+    /// Get the capabilities of the device, which runs at a single sample rate
+    /// and period.
+    pub(crate) fn caps(&self) -> DeviceCaps {
+        let inner = unsafe { &*self.inner };
+        DeviceCaps::fixed(
+            inner.device.supported,
+            self.sample_rate,
+            inner.period,
+        )
+    }
+
+    /// The platform picks the period.
+    pub(crate) fn set_period(&mut self, _frames: u16, _count: u8) {}
+
+    /// Get the configured period, `0` until configured.
+    pub(crate) fn period(&self) -> u16 {
+        unsafe { (*self.inner).period }
+    }
+
+    /// Get the number of frames the device buffers, if known.
+    pub(crate) fn buffer_frames(&self) -> Option<usize> {
+        None
+    }
+
+    pub(crate) fn format(&self) -> SampleFormat {
+        // Float is requested, conversion is done by the platform.
+        SampleFormat::F32
+    }
+}
+
+impl Future for Microphone {
+    type Output = Result<(), AudioError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Get mutable reference to microphone.
+        let this = self.get_mut();
+
+        // Safety
+        if unsafe { (*this.inner).locked.load(SeqCst) } {
+            return Poll::Ready(Err(AudioError::DeviceLocked));
+        }
+        //
+        let inner = unsafe { this.inner.as_mut().unwrap() };
+
+        // If microphone is unconfigured, return Ready to configure and play.
+        if this.channels == 0 {
+            inner.locked.store(true, SeqCst);
+            return Poll::Ready(Ok(()));
+        }
+
+        // Check if not woken, then yield.
+        if inner.device.event.notify().should_yield(cx.waker()) {
+            return Poll::Pending;
+        }
+
+        // Read all available packets into the internal microphone buffer.
+        let channels = this.channels as usize;
+        let service = inner.device.service.cast();
+        let mut len = 0;
+        unsafe {
+            loop {
+                let frames = IAudioCaptureClient::get_next_packet_size(service)
+                    .map_err(|_| AudioError::DeviceDisconnected)?;
+                if frames == 0 {
+                    break;
+                }
+                let (data, frames, flags) =
+                    match IAudioCaptureClient::get_buffer(service) {
+                        Ok(packet) => packet,
+                        Err(_) => break,
+                    };
+                let count = frames as usize * channels;
+                let start = len * channels;
+                if inner.buffer.len() < start + count {
+                    inner.buffer.resize(start + count, Ch32::MID);
+                }
+                // Samples were dropped because they weren't read in time.
+                if flags & AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY != 0 {
+                    inner.device.xruns += 1;
+                }
+                if flags & AUDCLNT_BUFFERFLAGS_SILENT != 0 {
+                    for sample in &mut inner.buffer[start..start + count] {
+                        *sample = Ch32::MID;
+                    }
+                } else {
+                    std::ptr::copy_nonoverlapping(
+                        data.cast::<Ch32>(),
+                        inner.buffer[start..].as_mut_ptr(),
+                        count,
+                    );
+                }
+                let _ = IAudioCaptureClient::release_buffer(service, frames);
+                len += frames as usize;
+            }
+        }
+
+        // Nothing was captured, wait for the next event.
+        if len == 0 {
+            // Register waker, or try again if signaled in the meantime.
+            if !inner.device.event.notify().should_yield(cx.waker()) {
+                cx.waker().wake_by_ref();
+            }
+            return Poll::Pending;
+        }
+
+        inner.endi = len;
+        // Ready, audio buffer has been filled!
+        inner.locked.store(true, SeqCst);
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub(crate) struct MicrophoneStream<F: Frame<Chan = Ch32>>(
+    *mut MicrophoneInner,
+    usize,
+    PhantomData<F>,
+    Option<f64>,
+    u8,
+);
+
+impl<F: Frame<Chan = Ch32>> MicrophoneStream<F> {
+    pub(crate) fn latency(&self) -> Latency {
+        let mic = unsafe { self.0.as_ref().unwrap() };
+        let unread = mic.endi.saturating_sub(self.1);
+        let delay = mic.device.delay().unwrap_or(0);
+        Latency::new(unread + delay, self.3.unwrap_or_default())
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Iterator for MicrophoneStream<F> {
+    type Item = F;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mic = unsafe { self.0.as_mut().unwrap() };
+        if self.1 >= mic.endi {
+            return None;
+        }
+        let frame = F::from_channels(&mic.buffer[self.1 * self.4 as usize..]);
+        self.1 += 1;
+        Some(frame)
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Stream<F> for MicrophoneStream<F> {
+    fn sample_rate(&self) -> Option<f64> {
+        self.3
+    }
+
+    fn len(&self) -> Option<usize> {
+        let mic = unsafe { self.0.as_mut().unwrap() };
+        Some(mic.endi)
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Drop for MicrophoneStream<F> {
+    fn drop(&mut self) {
+        let mic = unsafe { self.0.as_mut().unwrap() };
+        // Unlock
+        mic.locked.store(false, SeqCst);
+        // Free the microphone if it was dropped first.
+        if mic.orphaned.load(SeqCst) {
+            unsafe { drop(Box::from_raw(self.0)) };
+        }
+    }
+}
@@ -0,0 +1,830 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+#![allow(unsafe_code)]
+
+use std::{
+    os::raw::{c_int, c_void},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Mutex,
+    },
+    task::Waker,
+};
+
+pub(super) type HResult = c_int;
+pub(super) type Handle = *mut c_void;
+
+/// COM Globally Unique Identifier
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(super) struct Guid {
+    data1: u32,
+    data2: u16,
+    data3: u16,
+    data4: [u8; 8],
+}
+
+/// Property key (for reading the device friendly name).
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(super) struct PropertyKey {
+    fmtid: Guid,
+    pid: u32,
+}
+
+/// Only the string variant of PROPVARIANT is used.
+#[repr(C)]
+pub(super) struct PropVariant {
+    vt: u16,
+    reserved: [u16; 3],
+    data: [usize; 2],
+}
+
+/// Audio format description (packed in the C API).
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub(super) struct WaveFormatEx {
+    pub(super) format_tag: u16,
+    pub(super) channels: u16,
+    pub(super) samples_per_sec: u32,
+    pub(super) avg_bytes_per_sec: u32,
+    pub(super) block_align: u16,
+    pub(super) bits_per_sample: u16,
+    pub(super) size: u16,
+}
+
+/// Extensible audio format description (packed in the C API).
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub(super) struct WaveFormatExtensible {
+    pub(super) format: WaveFormatEx,
+    pub(super) valid_bits_per_sample: u16,
+    pub(super) channel_mask: u32,
+    pub(super) sub_format: Guid,
+}
+
+/// Data flow direction of an endpoint
+#[allow(unused)]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(super) enum EDataFlow {
+    /// Speakers
+    Render = 0,
+    /// Microphones
+    Capture,
+}
+
+/// Sharing mode of the audio client
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(super) enum ShareMode {
+    /// Mix with other applications through the audio engine
+    Shared = 0,
+    /// Exclusive access to the hardware
+    Exclusive,
+}
+
+pub(super) const CLSID_MM_DEVICE_ENUMERATOR: Guid = Guid {
+    data1: 0xBCDE0395,
+    data2: 0xE52F,
+    data3: 0x467C,
+    data4: [0x8E, 0x3D, 0xC4, 0x57, 0x92, 0x91, 0x69, 0x2E],
+};
+pub(super) const IID_IMM_DEVICE_ENUMERATOR: Guid = Guid {
+    data1: 0xA95664D2,
+    data2: 0x9614,
+    data3: 0x4F35,
+    data4: [0xA7, 0x46, 0xDE, 0x8D, 0xB6, 0x36, 0x17, 0xE6],
+};
+pub(super) const IID_IAUDIO_CLIENT: Guid = Guid {
+    data1: 0x1CB9AD4C,
+    data2: 0xDBFA,
+    data3: 0x4C32,
+    data4: [0xB1, 0x78, 0xC2, 0xF5, 0x68, 0xA7, 0x03, 0xB2],
+};
+pub(super) const IID_IAUDIO_RENDER_CLIENT: Guid = Guid {
+    data1: 0xF294ACFC,
+    data2: 0x3146,
+    data3: 0x4483,
+    data4: [0xA7, 0xBF, 0xAD, 0xDC, 0xA7, 0xC2, 0x60, 0xE2],
+};
+pub(super) const IID_IAUDIO_CAPTURE_CLIENT: Guid = Guid {
+    data1: 0xC8ADBD64,
+    data2: 0xE71E,
+    data3: 0x48A0,
+    data4: [0xA4, 0xDE, 0x18, 0x5C, 0x39, 0x5C, 0xD3, 0x17],
+};
+pub(super) const KSDATAFORMAT_SUBTYPE_IEEE_FLOAT: Guid = Guid {
+    data1: 0x00000003,
+    data2: 0x0000,
+    data3: 0x0010,
+    data4: [0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71],
+};
+pub(super) const PKEY_DEVICE_FRIENDLY_NAME: PropertyKey = PropertyKey {
+    fmtid: Guid {
+        data1: 0xA45C254E,
+        data2: 0xDF1C,
+        data3: 0x4EFD,
+        data4: [0x80, 0x20, 0x67, 0xD1, 0x46, 0xA8, 0x50, 0xE0],
+    },
+    pid: 14,
+};
+
+pub(super) const CLSCTX_ALL: u32 = 23;
+pub(super) const COINIT_MULTITHREADED: u32 = 0;
+pub(super) const RPC_E_CHANGED_MODE: HResult = 0x80010106_u32 as HResult;
+pub(super) const DEVICE_STATE_ACTIVE: u32 = 1;
+pub(super) const ROLE_CONSOLE: u32 = 0;
+pub(super) const STGM_READ: u32 = 0;
+pub(super) const VT_LPWSTR: u16 = 31;
+pub(super) const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+pub(super) const AUDCLNT_STREAMFLAGS_EVENTCALLBACK: u32 = 0x0004_0000;
+pub(super) const AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM: u32 = 0x8000_0000;
+pub(super) const AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY: u32 = 0x0800_0000;
+pub(super) const AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY: u32 = 0x1;
+pub(super) const AUDCLNT_BUFFERFLAGS_SILENT: u32 = 0x2;
+pub(super) const INFINITE: u32 = 0xFFFF_FFFF;
+pub(super) const INVALID_HANDLE_VALUE: Handle = usize::MAX as Handle;
+
+/// Speaker position bitflags for 1, 2 & 6 channel configurations.
+pub(super) const fn channel_mask(channels: u8) -> u32 {
+    match channels {
+        1 => 0x4,
+        2 => 0x3,
+        6 => 0x3F,
+        _ => 0,
+    }
+}
+
+#[repr(C)]
+struct IUnknownVtbl {
+    query_interface: unsafe extern "system" fn(
+        *mut c_void,
+        *const Guid,
+        *mut *mut c_void,
+    ) -> HResult,
+    add_ref: unsafe extern "system" fn(*mut c_void) -> u32,
+    release: unsafe extern "system" fn(*mut c_void) -> u32,
+}
+
+#[repr(C)]
+struct IMMDeviceEnumeratorVtbl {
+    unknown: IUnknownVtbl,
+    enum_audio_endpoints: unsafe extern "system" fn(
+        *mut IMMDeviceEnumerator,
+        EDataFlow,
+        u32,
+        *mut *mut IMMDeviceCollection,
+    ) -> HResult,
+    get_default_audio_endpoint: unsafe extern "system" fn(
+        *mut IMMDeviceEnumerator,
+        EDataFlow,
+        u32,
+        *mut *mut IMMDevice,
+    ) -> HResult,
+    get_device: unsafe extern "system" fn(
+        *mut IMMDeviceEnumerator,
+        *const u16,
+        *mut *mut IMMDevice,
+    ) -> HResult,
+    register_endpoint_notification_callback:
+        unsafe extern "system" fn(
+            *mut IMMDeviceEnumerator,
+            *mut c_void,
+        ) -> HResult,
+    unregister_endpoint_notification_callback:
+        unsafe extern "system" fn(
+            *mut IMMDeviceEnumerator,
+            *mut c_void,
+        ) -> HResult,
+}
+
+#[repr(C)]
+struct IMMDeviceCollectionVtbl {
+    unknown: IUnknownVtbl,
+    get_count: unsafe extern "system" fn(
+        *mut IMMDeviceCollection,
+        *mut u32,
+    ) -> HResult,
+    item: unsafe extern "system" fn(
+        *mut IMMDeviceCollection,
+        u32,
+        *mut *mut IMMDevice,
+    ) -> HResult,
+}
+
+#[repr(C)]
+struct IMMDeviceVtbl {
+    unknown: IUnknownVtbl,
+    activate: unsafe extern "system" fn(
+        *mut IMMDevice,
+        *const Guid,
+        u32,
+        *mut PropVariant,
+        *mut *mut c_void,
+    ) -> HResult,
+    open_property_store: unsafe extern "system" fn(
+        *mut IMMDevice,
+        u32,
+        *mut *mut IPropertyStore,
+    ) -> HResult,
+    get_id: unsafe extern "system" fn(*mut IMMDevice, *mut *mut u16) -> HResult,
+    get_state: unsafe extern "system" fn(*mut IMMDevice, *mut u32) -> HResult,
+}
+
+#[repr(C)]
+struct IPropertyStoreVtbl {
+    unknown: IUnknownVtbl,
+    get_count:
+        unsafe extern "system" fn(*mut IPropertyStore, *mut u32) -> HResult,
+    get_at: unsafe extern "system" fn(
+        *mut IPropertyStore,
+        u32,
+        *mut PropertyKey,
+    ) -> HResult,
+    get_value: unsafe extern "system" fn(
+        *mut IPropertyStore,
+        *const PropertyKey,
+        *mut PropVariant,
+    ) -> HResult,
+    set_value: unsafe extern "system" fn(
+        *mut IPropertyStore,
+        *const PropertyKey,
+        *const PropVariant,
+    ) -> HResult,
+    commit: unsafe extern "system" fn(*mut IPropertyStore) -> HResult,
+}
+
+#[repr(C)]
+struct IAudioClientVtbl {
+    unknown: IUnknownVtbl,
+    initialize: unsafe extern "system" fn(
+        *mut IAudioClient,
+        ShareMode,
+        u32,
+        i64,
+        i64,
+        *const WaveFormatEx,
+        *const Guid,
+    ) -> HResult,
+    get_buffer_size:
+        unsafe extern "system" fn(*mut IAudioClient, *mut u32) -> HResult,
+    get_stream_latency:
+        unsafe extern "system" fn(*mut IAudioClient, *mut i64) -> HResult,
+    get_current_padding:
+        unsafe extern "system" fn(*mut IAudioClient, *mut u32) -> HResult,
+    is_format_supported: unsafe extern "system" fn(
+        *mut IAudioClient,
+        ShareMode,
+        *const WaveFormatEx,
+        *mut *mut WaveFormatEx,
+    ) -> HResult,
+    get_mix_format: unsafe extern "system" fn(
+        *mut IAudioClient,
+        *mut *mut WaveFormatEx,
+    ) -> HResult,
+    get_device_period: unsafe extern "system" fn(
+        *mut IAudioClient,
+        *mut i64,
+        *mut i64,
+    ) -> HResult,
+    start: unsafe extern "system" fn(*mut IAudioClient) -> HResult,
+    stop: unsafe extern "system" fn(*mut IAudioClient) -> HResult,
+    reset: unsafe extern "system" fn(*mut IAudioClient) -> HResult,
+    set_event_handle:
+        unsafe extern "system" fn(*mut IAudioClient, Handle) -> HResult,
+    get_service: unsafe extern "system" fn(
+        *mut IAudioClient,
+        *const Guid,
+        *mut *mut c_void,
+    ) -> HResult,
+}
+
+#[repr(C)]
+struct IAudioRenderClientVtbl {
+    unknown: IUnknownVtbl,
+    get_buffer: unsafe extern "system" fn(
+        *mut IAudioRenderClient,
+        u32,
+        *mut *mut u8,
+    ) -> HResult,
+    release_buffer:
+        unsafe extern "system" fn(*mut IAudioRenderClient, u32, u32) -> HResult,
+}
+
+#[repr(C)]
+struct IAudioCaptureClientVtbl {
+    unknown: IUnknownVtbl,
+    get_buffer: unsafe extern "system" fn(
+        *mut IAudioCaptureClient,
+        *mut *mut u8,
+        *mut u32,
+        *mut u32,
+        *mut u64,
+        *mut u64,
+    ) -> HResult,
+    release_buffer:
+        unsafe extern "system" fn(*mut IAudioCaptureClient, u32) -> HResult,
+    get_next_packet_size: unsafe extern "system" fn(
+        *mut IAudioCaptureClient,
+        *mut u32,
+    ) -> HResult,
+}
+
+macro_rules! interface {
+    ($name:ident, $vtbl:ident) => {
+        #[repr(C)]
+        pub(crate) struct $name {
+            vtbl: *const $vtbl,
+        }
+
+        impl $name {
+            /// Decrement the reference count of the COM object.
+            #[allow(unused)]
+            pub(super) unsafe fn release(this: *mut Self) {
+                if !this.is_null() {
+                    ((*(*this).vtbl).unknown.release)(this.cast());
+                }
+            }
+        }
+    };
+}
+
+interface!(IMMDeviceEnumerator, IMMDeviceEnumeratorVtbl);
+interface!(IMMDeviceCollection, IMMDeviceCollectionVtbl);
+interface!(IMMDevice, IMMDeviceVtbl);
+interface!(IPropertyStore, IPropertyStoreVtbl);
+interface!(IAudioClient, IAudioClientVtbl);
+interface!(IAudioRenderClient, IAudioRenderClientVtbl);
+interface!(IAudioCaptureClient, IAudioCaptureClientVtbl);
+
+/// Convert an HRESULT into a `Result`.
+fn check(hr: HResult) -> Result<(), HResult> {
+    if hr < 0 {
+        Err(hr)
+    } else {
+        Ok(())
+    }
+}
+
+impl IMMDeviceEnumerator {
+    pub(super) unsafe fn enum_audio_endpoints(
+        this: *mut Self,
+        flow: EDataFlow,
+    ) -> Result<*mut IMMDeviceCollection, HResult> {
+        let mut collection = ptr::null_mut();
+        check(((*(*this).vtbl).enum_audio_endpoints)(
+            this,
+            flow,
+            DEVICE_STATE_ACTIVE,
+            &mut collection,
+        ))?;
+        Ok(collection)
+    }
+
+    pub(super) unsafe fn get_default_audio_endpoint(
+        this: *mut Self,
+        flow: EDataFlow,
+    ) -> Result<*mut IMMDevice, HResult> {
+        let mut device = ptr::null_mut();
+        check(((*(*this).vtbl).get_default_audio_endpoint)(
+            this,
+            flow,
+            ROLE_CONSOLE,
+            &mut device,
+        ))?;
+        Ok(device)
+    }
+}
+
+impl IMMDeviceCollection {
+    pub(super) unsafe fn get_count(this: *mut Self) -> Result<u32, HResult> {
+        let mut count = 0;
+        check(((*(*this).vtbl).get_count)(this, &mut count))?;
+        Ok(count)
+    }
+
+    pub(super) unsafe fn item(
+        this: *mut Self,
+        index: u32,
+    ) -> Result<*mut IMMDevice, HResult> {
+        let mut device = ptr::null_mut();
+        check(((*(*this).vtbl).item)(this, index, &mut device))?;
+        Ok(device)
+    }
+}
+
+impl IMMDevice {
+    pub(super) unsafe fn activate(
+        this: *mut Self,
+    ) -> Result<*mut IAudioClient, HResult> {
+        let mut client = ptr::null_mut();
+        check(((*(*this).vtbl).activate)(
+            this,
+            &IID_IAUDIO_CLIENT,
+            CLSCTX_ALL,
+            ptr::null_mut(),
+            &mut client,
+        ))?;
+        Ok(client.cast())
+    }
+
+    /// Get the persistent endpoint ID string.
+    pub(super) unsafe fn id(this: *mut Self) -> Option<String> {
+        let mut id = ptr::null_mut();
+        check(((*(*this).vtbl).get_id)(this, &mut id)).ok()?;
+        let string = from_wide(id);
+        CoTaskMemFree(id.cast());
+        Some(string)
+    }
+
+    /// Get the human-readable name of the endpoint.
+    pub(super) unsafe fn friendly_name(this: *mut Self) -> Option<String> {
+        let mut store = ptr::null_mut();
+        check(((*(*this).vtbl).open_property_store)(
+            this, STGM_READ, &mut store,
+        ))
+        .ok()?;
+        let mut value = PropVariant {
+            vt: 0,
+            reserved: [0; 3],
+            data: [0; 2],
+        };
+        let hr = ((*(*store).vtbl).get_value)(
+            store,
+            &PKEY_DEVICE_FRIENDLY_NAME,
+            &mut value,
+        );
+        IPropertyStore::release(store);
+        check(hr).ok()?;
+        let name = if value.vt == VT_LPWSTR {
+            Some(from_wide(value.data[0] as *const u16))
+        } else {
+            None
+        };
+        PropVariantClear(&mut value);
+        name
+    }
+}
+
+impl IAudioClient {
+    pub(super) unsafe fn initialize(
+        this: *mut Self,
+        mode: ShareMode,
+        flags: u32,
+        duration: i64,
+        format: *const WaveFormatEx,
+    ) -> Result<(), HResult> {
+        let periodicity = if mode == ShareMode::Exclusive {
+            duration
+        } else {
+            0
+        };
+        check(((*(*this).vtbl).initialize)(
+            this,
+            mode,
+            flags,
+            duration,
+            periodicity,
+            format,
+            ptr::null(),
+        ))
+    }
+
+    pub(super) unsafe fn get_buffer_size(
+        this: *mut Self,
+    ) -> Result<u32, HResult> {
+        let mut frames = 0;
+        check(((*(*this).vtbl).get_buffer_size)(this, &mut frames))?;
+        Ok(frames)
+    }
+
+    /// Get the maximum latency of the stream, in 100 nanosecond units.
+    pub(super) unsafe fn get_stream_latency(
+        this: *mut Self,
+    ) -> Result<i64, HResult> {
+        let mut latency = 0;
+        check(((*(*this).vtbl).get_stream_latency)(this, &mut latency))?;
+        Ok(latency)
+    }
+
+    pub(super) unsafe fn get_current_padding(
+        this: *mut Self,
+    ) -> Result<u32, HResult> {
+        let mut frames = 0;
+        check(((*(*this).vtbl).get_current_padding)(this, &mut frames))?;
+        Ok(frames)
+    }
+
+    /// Returns true only if the format is supported exactly.
+    pub(super) unsafe fn is_format_supported(
+        this: *mut Self,
+        mode: ShareMode,
+        format: *const WaveFormatEx,
+    ) -> bool {
+        let mut closest = ptr::null_mut();
+        // Exclusive mode doesn't suggest a closest match.
+        let closest_ptr: *mut *mut WaveFormatEx = match mode {
+            ShareMode::Shared => &mut closest,
+            ShareMode::Exclusive => ptr::null_mut(),
+        };
+        let hr = ((*(*this).vtbl).is_format_supported)(
+            this,
+            mode,
+            format,
+            closest_ptr,
+        );
+        CoTaskMemFree(closest.cast());
+        hr == 0
+    }
+
+    /// Get the minimum period for exclusive mode, in 100 nanosecond units.
+    pub(super) unsafe fn get_minimum_period(
+        this: *mut Self,
+    ) -> Result<i64, HResult> {
+        let mut default = 0;
+        let mut minimum = 0;
+        check(((*(*this).vtbl).get_device_period)(
+            this,
+            &mut default,
+            &mut minimum,
+        ))?;
+        Ok(minimum)
+    }
+
+    /// Get the sample rate and channel count of the audio engine.
+    pub(super) unsafe fn get_mix_format(
+        this: *mut Self,
+    ) -> Result<(u32, u16), HResult> {
+        let mut format = ptr::null_mut();
+        check(((*(*this).vtbl).get_mix_format)(this, &mut format))?;
+        let WaveFormatEx {
+            samples_per_sec,
+            channels,
+            ..
+        } = *format;
+        CoTaskMemFree(format.cast());
+        Ok((samples_per_sec, channels))
+    }
+
+    pub(super) unsafe fn start(this: *mut Self) -> Result<(), HResult> {
+        check(((*(*this).vtbl).start)(this))
+    }
+
+    pub(super) unsafe fn stop(this: *mut Self) -> Result<(), HResult> {
+        check(((*(*this).vtbl).stop)(this))
+    }
+
+    pub(super) unsafe fn set_event_handle(
+        this: *mut Self,
+        event: Handle,
+    ) -> Result<(), HResult> {
+        check(((*(*this).vtbl).set_event_handle)(this, event))
+    }
+
+    pub(super) unsafe fn get_service<T>(
+        this: *mut Self,
+        iid: &Guid,
+    ) -> Result<*mut T, HResult> {
+        let mut service = ptr::null_mut();
+        check(((*(*this).vtbl).get_service)(this, iid, &mut service))?;
+        Ok(service.cast())
+    }
+}
+
+impl IAudioRenderClient {
+    pub(super) unsafe fn get_buffer(
+        this: *mut Self,
+        frames: u32,
+    ) -> Result<*mut u8, HResult> {
+        let mut data = ptr::null_mut();
+        check(((*(*this).vtbl).get_buffer)(this, frames, &mut data))?;
+        Ok(data)
+    }
+
+    pub(super) unsafe fn release_buffer(
+        this: *mut Self,
+        frames: u32,
+    ) -> Result<(), HResult> {
+        check(((*(*this).vtbl).release_buffer)(this, frames, 0))
+    }
+}
+
+impl IAudioCaptureClient {
+    /// Returns pointer to data, number of frames, and flags.
+    pub(super) unsafe fn get_buffer(
+        this: *mut Self,
+    ) -> Result<(*mut u8, u32, u32), HResult> {
+        let mut data = ptr::null_mut();
+        let mut frames = 0;
+        let mut flags = 0;
+        check(((*(*this).vtbl).get_buffer)(
+            this,
+            &mut data,
+            &mut frames,
+            &mut flags,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        ))?;
+        Ok((data, frames, flags))
+    }
+
+    pub(super) unsafe fn release_buffer(
+        this: *mut Self,
+        frames: u32,
+    ) -> Result<(), HResult> {
+        check(((*(*this).vtbl).release_buffer)(this, frames))
+    }
+
+    pub(super) unsafe fn get_next_packet_size(
+        this: *mut Self,
+    ) -> Result<u32, HResult> {
+        let mut frames = 0;
+        check(((*(*this).vtbl).get_next_packet_size)(this, &mut frames))?;
+        Ok(frames)
+    }
+}
+
+/// Convert a null-terminated wide string into a Rust `String`.
+pub(super) unsafe fn from_wide(string: *const u16) -> String {
+    let mut len = 0;
+    while *string.add(len) != 0 {
+        len += 1;
+    }
+    String::from_utf16_lossy(std::slice::from_raw_parts(string, len))
+}
+
+#[link(name = "ole32")]
+extern "system" {
+    fn CoInitializeEx(reserved: *mut c_void, co_init: u32) -> HResult;
+    fn CoCreateInstance(
+        clsid: *const Guid,
+        outer: *mut c_void,
+        cls_context: u32,
+        iid: *const Guid,
+        out: *mut *mut c_void,
+    ) -> HResult;
+    pub(super) fn CoTaskMemFree(ptr: *mut c_void);
+    fn PropVariantClear(pvar: *mut PropVariant) -> HResult;
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+    pub(super) fn CreateEventW(
+        attributes: *mut c_void,
+        manual_reset: c_int,
+        initial_state: c_int,
+        name: *const u16,
+    ) -> Handle;
+    pub(super) fn CloseHandle(handle: Handle) -> c_int;
+    fn RegisterWaitForSingleObject(
+        new_wait: *mut Handle,
+        object: Handle,
+        callback: unsafe extern "system" fn(*mut c_void, u8),
+        context: *mut c_void,
+        milliseconds: u32,
+        flags: u32,
+    ) -> c_int;
+    fn UnregisterWaitEx(wait: Handle, completion_event: Handle) -> c_int;
+}
+
+/// Shared state between the thread pool wait callback and the future.
+#[derive(Debug, Default)]
+pub(super) struct Notify {
+    /// Set by the wait callback when the event object gets signaled.
+    ready: AtomicBool,
+    /// Waker for the future waiting on the event.
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Notify {
+    /// Returns true if the event has been signaled since the last call.  If
+    /// not, the waker is registered to be woken up on the next signal.
+    pub(super) fn should_yield(&self, waker: &Waker) -> bool {
+        if self.ready.swap(false, SeqCst) {
+            return false;
+        }
+        *self.waker.lock().unwrap() = Some(waker.clone());
+        // Check again, in case signaled before the waker was registered.
+        !self.ready.swap(false, SeqCst)
+    }
+}
+
+/// Called from the Windows thread pool when the event object is signaled.
+unsafe extern "system" fn wake(context: *mut c_void, _timed_out: u8) {
+    let notify = &*context.cast::<Notify>();
+    notify.ready.store(true, SeqCst);
+    if let Some(waker) = notify.waker.lock().unwrap().take() {
+        waker.wake();
+    }
+}
+
+/// An auto-reset Windows event object that wakes a future when signaled,
+/// registered with the thread pool (the equivalent of the epoll file
+/// descriptors used on Linux).
+#[derive(Debug)]
+pub(crate) struct Event {
+    /// The event object handle.
+    pub(super) handle: Handle,
+    /// Registered wait handle.
+    wait: Handle,
+    /// Leaked shared box
+    pub(super) notify: *mut Notify,
+}
+
+impl Event {
+    pub(super) fn new() -> Option<Self> {
+        unsafe {
+            let handle = CreateEventW(ptr::null_mut(), 0, 0, ptr::null());
+            if handle.is_null() {
+                return None;
+            }
+            let notify = Box::into_raw(Box::new(Notify::default()));
+            let mut wait = ptr::null_mut();
+            if RegisterWaitForSingleObject(
+                &mut wait,
+                handle,
+                wake,
+                notify.cast(),
+                INFINITE,
+                0,
+            ) == 0
+            {
+                drop(Box::from_raw(notify));
+                CloseHandle(handle);
+                return None;
+            }
+            Some(Self {
+                handle,
+                wait,
+                notify,
+            })
+        }
+    }
+
+    pub(super) fn notify(&self) -> &Notify {
+        unsafe { &*self.notify }
+    }
+}
+
+impl Drop for Event {
+    fn drop(&mut self) {
+        unsafe {
+            // Wait for any running callbacks to complete before freeing.
+            UnregisterWaitEx(self.wait, INVALID_HANDLE_VALUE);
+            CloseHandle(self.handle);
+            drop(Box::from_raw(self.notify));
+        }
+    }
+}
+
+/// Per-thread handle to the device enumerator (the COM equivalent of the
+/// dynamically loaded ALSA library on Linux).
+pub(super) struct Enumerator(pub(super) *mut IMMDeviceEnumerator);
+
+impl Enumerator {
+    fn new() -> Option<Self> {
+        unsafe {
+            let hr = CoInitializeEx(ptr::null_mut(), COINIT_MULTITHREADED);
+            if hr < 0 && hr != RPC_E_CHANGED_MODE {
+                return None;
+            }
+            let mut enumerator = ptr::null_mut();
+            check(CoCreateInstance(
+                &CLSID_MM_DEVICE_ENUMERATOR,
+                ptr::null_mut(),
+                CLSCTX_ALL,
+                &IID_IMM_DEVICE_ENUMERATOR,
+                &mut enumerator,
+            ))
+            .ok()?;
+            Some(Self(enumerator.cast()))
+        }
+    }
+}
+
+impl Drop for Enumerator {
+    fn drop(&mut self) {
+        unsafe { IMMDeviceEnumerator::release(self.0) }
+    }
+}
+
+thread_local! {
+    static ENUMERATOR: Option<Enumerator> = Enumerator::new();
+}
+
+/// Returns true if the WASAPI device enumerator could be created.
+pub(crate) fn available() -> bool {
+    ENUMERATOR.with(|enumerator| enumerator.is_some())
+}
+
+#[path = "device_list.rs"]
+pub(super) mod device_list;
@@ -0,0 +1,75 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+//! `futures_core::Stream` implementations, with the `futures` feature.
+//!
+//! Speakers, microphones and device watchers never end, so the streams never
+//! produce `None`.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use pasts::Notifier;
+
+use crate::{
+    microphone::MicrophoneProperties, speakers::SpeakersProperties,
+    DeviceWatcher, Microphone, Speakers,
+};
+
+impl<const N: usize> Stream for Speakers<N>
+where
+    Speakers<N>: SpeakersProperties,
+{
+    type Item = <Self as Notifier>::Event;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        Notifier::poll_next(self, cx).map(Some)
+    }
+}
+
+impl<const N: usize> Stream for Microphone<N>
+where
+    Microphone<N>: MicrophoneProperties,
+{
+    type Item = <Self as Notifier>::Event;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        Notifier::poll_next(self, cx).map(Some)
+    }
+}
+
+impl Stream for DeviceWatcher<Speakers<0>> {
+    type Item = <Self as Notifier>::Event;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        Notifier::poll_next(self, cx).map(Some)
+    }
+}
+
+impl Stream for DeviceWatcher<Microphone<0>> {
+    type Item = <Self as Notifier>::Event;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        Notifier::poll_next(self, cx).map(Some)
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use std::time::Duration;
+
+/// Latency of an audio stream: buffered audio plus the hardware delay.
+///
+/// For speakers, this is how long it will take for the next sample written to
+/// the sink to be heard.  For microphones, it's how long ago the first sample
+/// of the stream was captured.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Latency {
+    frames: usize,
+    sample_rate: f64,
+}
+
+impl Latency {
+    pub(crate) fn new(frames: usize, sample_rate: f64) -> Self {
+        Self {
+            frames,
+            sample_rate,
+        }
+    }
+
+    /// Get the latency in audio frames (at the stream's sample rate).
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Get the latency as a duration of time.
+    pub fn duration(&self) -> Duration {
+        if self.sample_rate <= 0.0 {
+            return Duration::default();
+        }
+        Duration::from_secs_f64(self.frames as f64 / self.sample_rate)
+    }
+}
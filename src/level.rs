@@ -0,0 +1,39 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+/// Level of one channel of a [`MicrophoneStream`](crate::MicrophoneStream).
+///
+/// Both values are linear amplitudes from `0.0` to `1.0` (full scale),
+/// measured over a rectangular window of exactly the frames in the stream, so
+/// the window length is [`len()`](fon::Stream::len) of the stream when it was
+/// produced.  Convert to dBFS with `20.0 * level.log10()` (`0.0` is silence,
+/// which is negative infinity dBFS).  A full scale sine wave has a peak of
+/// `1.0` (0 dBFS) and an RMS of about `0.707` (-3 dBFS).
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ChannelLevel {
+    peak: f32,
+    rms: f32,
+}
+
+impl ChannelLevel {
+    pub(crate) fn new(peak: f32, rms: f32) -> Self {
+        Self { peak, rms }
+    }
+
+    /// Get the largest absolute sample value (true sample peak, not
+    /// oversampled).
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+
+    /// Get the root mean square of the samples.
+    pub fn rms(&self) -> f32 {
+        self.rms
+    }
+}
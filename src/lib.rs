@@ -25,7 +25,9 @@
 //! ```rust
 //! use fon::{mono::Mono32, Audio, Sink};
 //! use pasts::{prelude::*, Join};
-//! use wavy::{Microphone, MicrophoneStream, Speakers, SpeakersSink};
+//! use wavy::{
+//!     AudioError, Microphone, MicrophoneStream, Speakers, SpeakersSink,
+//! };
 //!
 //! /// Shared state between tasks on the thread.
 //! struct App {
@@ -39,13 +41,27 @@
 //!
 //! impl App {
 //!     /// Speaker is ready to play more audio.
-//!     fn play(&mut self, mut sink: SpeakersSink<Mono32>) -> Poll<()> {
+//!     fn play(
+//!         &mut self,
+//!         sink: Result<SpeakersSink<Mono32>, AudioError>,
+//!     ) -> Poll<()> {
+//!         let mut sink = match sink {
+//!             Ok(sink) => sink,
+//!             Err(_) => return Ready(()),
+//!         };
 //!         sink.stream(self.buffer.drain());
 //!         Pending
 //!     }
 //!
 //!     /// Microphone has recorded some audio.
-//!     fn record(&mut self, stream: MicrophoneStream<Mono32>) -> Poll<()> {
+//!     fn record(
+//!         &mut self,
+//!         stream: Result<MicrophoneStream<Mono32>, AudioError>,
+//!     ) -> Poll<()> {
+//!         let stream = match stream {
+//!             Ok(stream) => stream,
+//!             Err(_) => return Ready(()),
+//!         };
 //!         self.buffer.extend(stream);
 //!         Pending
 //!     }
@@ -94,10 +110,17 @@
 #[cfg_attr(target_arch = "wasm32", path = "ffi/wasm/ffi.rs")]
 #[cfg_attr(
     not(target_arch = "wasm32"),
-    cfg_attr(target_os = "linux", path = "ffi/linux/ffi.rs"),
+    cfg_attr(
+        all(target_os = "linux", not(feature = "jack")),
+        path = "ffi/linux/ffi.rs"
+    ),
+    cfg_attr(
+        all(target_os = "linux", feature = "jack"),
+        path = "ffi/jack/ffi.rs"
+    ),
     cfg_attr(target_os = "android", path = "ffi/android/ffi.rs"),
     cfg_attr(target_os = "macos", path = "ffi/macos/ffi.rs"),
-    cfg_attr(target_os = "ios", path = "ffi/ios/ffi.rs"),
+    cfg_attr(target_os = "ios", path = "ffi/macos/ffi.rs"),
     cfg_attr(target_os = "windows", path = "ffi/windows/ffi.rs"),
     cfg_attr(
         any(
@@ -115,10 +138,68 @@
     cfg_attr(target_os = "dummy", path = "ffi/dummy/ffi.rs")
 )]
 mod ffi;
+#[path = "ffi/memory/ffi.rs"]
+mod memory;
+#[cfg(feature = "testing")]
+#[path = "ffi/mock/ffi.rs"]
+mod mock;
+#[path = "ffi/null/ffi.rs"]
+mod null;
 
+mod caps;
 mod consts;
+mod device_id;
+mod device_info;
+mod downmix;
+mod error;
+#[cfg(feature = "futures")]
+mod futures;
+mod latency;
+mod level;
+mod loudness;
 mod microphone;
+mod sample_format;
+mod silence;
+mod speaker_position;
 mod speakers;
+#[cfg(feature = "testing")]
+mod testing;
+mod watcher;
+mod wav;
+mod xrun;
 
+pub use caps::DeviceCaps;
+pub use device_id::DeviceId;
+pub use device_info::{DeviceFilter, DeviceInfo};
+pub use downmix::Downmix;
+pub use error::{AudioError, StreamState};
+pub use latency::Latency;
+pub use level::ChannelLevel;
+pub use loudness::Loudness;
 pub use microphone::{Microphone, MicrophoneStream};
+pub use sample_format::SampleFormat;
+pub use silence::SilenceDetector;
+pub use speaker_position::SpeakerPosition;
 pub use speakers::{Speakers, SpeakersSink};
+#[cfg(feature = "testing")]
+pub use testing::{MockMicrophone, MockSpeakers};
+pub use watcher::{DeviceEvent, DeviceWatcher};
+pub use wav::{FileSink, FileSource};
+pub use xrun::{XrunKind, XrunPolicy};
+
+/// Stereo frame (left and right channels).
+pub type Stereo = fon::stereo::Stereo32;
+
+/// Mono frame (one channel).
+pub type Mono = fon::mono::Mono32;
+
+/// Check if the platform's audio backend is available.
+///
+/// This is `false` if the audio library couldn't be loaded (such as
+/// `libasound.so` in containers without ALSA), or if wavy has no backend for
+/// the platform.  Devices can't be opened then: [`Speakers::try_default()`]
+/// and [`Microphone::try_default()`] produce
+/// [`AudioError::BackendUnavailable`], and `query()` returns no devices.
+pub fn backend_available() -> bool {
+    ffi::available()
+}
@@ -0,0 +1,286 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use std::{collections::VecDeque, f64::consts::PI};
+
+use fon::{chan::Ch32, Frame};
+
+/// Number of 100 ms sub-blocks in the momentary (400 ms) window.
+const MOMENTARY: usize = 4;
+/// Number of 100 ms sub-blocks in the short-term (3 s) window.
+const SHORT_TERM: usize = 30;
+/// Blocks quieter than this (LUFS) are ignored by the integrated loudness.
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Blocks more than this far (LU) below the ungated loudness are ignored by
+/// the integrated loudness.
+const RELATIVE_GATE: f64 = -10.0;
+/// Width (LU) of the bins blocks are summarized in, for the integrated
+/// loudness.
+const BIN_WIDTH: f64 = 0.1;
+/// Number of bins, from the absolute gate up to +10 LUFS (louder blocks go in
+/// the last one).
+const BINS: usize = 800;
+
+/// Loudness meter, following EBU R128 (ITU-R BS.1770-4).
+///
+/// Audio is K-weighted, and the mean square of each channel is summed with
+/// the BS.1770 channel weights (with the LFE channel of 5.1 and 7.1 audio
+/// ignored, and the surround channels weighted by 1.41).  Measurements are in
+/// LUFS, and are negative infinity until there's enough (non-silent) audio.
+///
+/// Attach one to speakers with
+/// [`SpeakersSink::attach_loudness_meter()`](crate::SpeakersSink::attach_loudness_meter)
+/// or to a microphone with
+/// [`MicrophoneStream::attach_loudness_meter()`](crate::MicrophoneStream::attach_loudness_meter),
+/// or [`measure()`](Loudness::measure) frames directly.
+#[derive(Clone, Debug)]
+pub struct Loudness {
+    /// Frames per 100 ms sub-block.
+    step: usize,
+    /// Frames measured in the current sub-block.
+    frames: usize,
+    /// Weighted sum of squares of the current sub-block.
+    sum: f64,
+    /// K-weighting filter coefficients (high shelf, then high pass).
+    filters: [Biquad; 2],
+    /// Filter state of each channel.
+    state: Vec<[[f64; 2]; 2]>,
+    /// Mean square of the last `SHORT_TERM` sub-blocks, newest last.
+    recent: VecDeque<f64>,
+    /// Number and summed mean square of the 400 ms blocks (overlapping by
+    /// 75%) louder than the absolute gate, binned by loudness.
+    blocks: Vec<(u64, f64)>,
+}
+
+/// Coefficients of a second-order IIR filter, normalized so `a0` is `1.0`.
+#[derive(Copy, Clone, Debug)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Biquad {
+    /// Filter `input`, with the transposed direct form II `state`.
+    fn process(&self, state: &mut [f64; 2], input: f64) -> f64 {
+        let output = self.b[0] * input + state[0];
+        state[0] = self.b[1] * input - self.a[0] * output + state[1];
+        state[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
+
+impl Loudness {
+    /// Create a loudness meter for audio at `sample_rate`.
+    pub fn new(sample_rate: f64) -> Self {
+        // Pre-filter (high shelf, modelling the head).
+        let (f0, gain, q) =
+            (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * f0 / sample_rate).tan();
+        let vh = 10.0f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        };
+        // RLB filter (high pass).
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        };
+
+        Self {
+            step: ((sample_rate / 10.0).round() as usize).max(1),
+            frames: 0,
+            sum: 0.0,
+            filters: [shelf, high_pass],
+            state: Vec::new(),
+            recent: VecDeque::with_capacity(SHORT_TERM),
+            blocks: vec![(0, 0.0); BINS],
+        }
+    }
+
+    /// Measure `frames`, continuing from the previously measured frames.
+    pub fn measure<F: Frame<Chan = Ch32>>(&mut self, frames: &[F]) {
+        if self.state.len() != F::CHAN_COUNT {
+            self.state = vec![[[0.0; 2]; 2]; F::CHAN_COUNT];
+        }
+        for frame in frames {
+            for (i, chan) in frame.channels().iter().enumerate() {
+                let weight = weight(i, F::CHAN_COUNT);
+                if weight == 0.0 {
+                    continue;
+                }
+                let [shelf, high_pass] = &mut self.state[i];
+                let sample = f64::from(f32::from(*chan));
+                let sample = self.filters[0].process(shelf, sample);
+                let sample = self.filters[1].process(high_pass, sample);
+                self.sum += weight * sample * sample;
+            }
+            self.frames += 1;
+            if self.frames == self.step {
+                self.end_sub_block();
+            }
+        }
+    }
+
+    /// Finish a 100 ms sub-block, which also ends a 400 ms block.
+    fn end_sub_block(&mut self) {
+        if self.recent.len() == SHORT_TERM {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(self.sum / self.step as f64);
+        self.sum = 0.0;
+        self.frames = 0;
+        if self.recent.len() >= MOMENTARY {
+            let block = self.mean_square(MOMENTARY);
+            let loudness = lufs(block);
+            if loudness > ABSOLUTE_GATE {
+                let bin = ((loudness - ABSOLUTE_GATE) / BIN_WIDTH) as usize;
+                let (count, sum) = &mut self.blocks[bin.min(BINS - 1)];
+                *count += 1;
+                *sum += block;
+            }
+        }
+    }
+
+    /// Get the mean square of the last `count` sub-blocks, counting missing
+    /// ones as silence.
+    fn mean_square(&self, count: usize) -> f64 {
+        self.recent.iter().rev().take(count).sum::<f64>() / count as f64
+    }
+
+    /// Forget all measured audio.
+    pub fn reset(&mut self) {
+        self.frames = 0;
+        self.sum = 0.0;
+        self.state.clear();
+        self.recent.clear();
+        self.blocks.fill((0, 0.0));
+    }
+
+    /// Get the momentary loudness (over the last 400 ms) in LUFS.
+    pub fn momentary_lufs(&self) -> f64 {
+        lufs(self.mean_square(MOMENTARY))
+    }
+
+    /// Get the short-term loudness (over the last 3 s) in LUFS.
+    pub fn short_term_lufs(&self) -> f64 {
+        lufs(self.mean_square(SHORT_TERM))
+    }
+
+    /// Get the integrated (gated) loudness of all measured audio in LUFS.
+    ///
+    /// Blocks are gated by bins of 0.1 LU, so memory use doesn't grow with
+    /// the length of the audio.
+    pub fn integrated_lufs(&self) -> f64 {
+        let gated = |threshold: f64| {
+            let (sum, count) = self
+                .blocks
+                .iter()
+                .filter(|(count, sum)| {
+                    *count != 0 && lufs(sum / *count as f64) > threshold
+                })
+                .fold((0.0, 0), |(total, blocks), (count, sum)| {
+                    (total + sum, blocks + count)
+                });
+            if count == 0 {
+                0.0
+            } else {
+                sum / count as f64
+            }
+        };
+        let relative = lufs(gated(ABSOLUTE_GATE)) + RELATIVE_GATE;
+        lufs(gated(relative.max(ABSOLUTE_GATE)))
+    }
+}
+
+/// Get the BS.1770 weight of channel `i` of `count`.
+fn weight(i: usize, count: usize) -> f64 {
+    match (count, i) {
+        // Left, right and center of 5.0.
+        (5, 0..=2) => 1.0,
+        // Surround channels of 5.0.
+        (5, _) => 1.41,
+        // LFE of 5.1 and 7.1.
+        (6 | 8, 3) => 0.0,
+        // Surround channels of 5.1 and 7.1.
+        (6 | 8, 4..) => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// Convert a weighted mean square to LUFS.
+fn lufs(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use fon::stereo::Stereo32;
+
+    use super::*;
+
+    /// Get `seconds` of a 997 Hz sine at `dbfs` in both channels.
+    fn sine(dbfs: f64, seconds: usize) -> Vec<Stereo32> {
+        let amplitude = 10.0f64.powf(dbfs / 20.0);
+        (0..48_000 * seconds)
+            .map(|i| {
+                let phase = 2.0 * PI * 997.0 * i as f64 / 48_000.0;
+                let sample = Ch32::new((amplitude * phase.sin()) as f32);
+                Stereo32::from_channels(&[sample, sample])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reference_level() {
+        // EBU Tech 3341: a stereo 997 Hz sine at -23 dBFS is -23 LUFS.
+        let mut loudness = Loudness::new(48_000.0);
+        loudness.measure(&sine(-23.0, 20));
+        for lufs in [
+            loudness.momentary_lufs(),
+            loudness.short_term_lufs(),
+            loudness.integrated_lufs(),
+        ] {
+            assert!((lufs + 23.0).abs() <= 0.1, "measured {} LUFS", lufs);
+        }
+    }
+
+    #[test]
+    fn gated() {
+        // Quiet audio 20 LU down is below the relative gate, and silence is
+        // below the absolute gate, so neither lowers the integrated loudness.
+        let mut loudness = Loudness::new(48_000.0);
+        loudness.measure(&sine(-23.0, 10));
+        loudness.measure(&sine(-43.0, 10));
+        loudness.measure(&vec![Stereo32::default(); 48_000 * 10]);
+        let lufs = loudness.integrated_lufs();
+        assert!((lufs + 23.0).abs() <= 0.1, "measured {} LUFS", lufs);
+    }
+
+    #[test]
+    fn silence() {
+        let mut loudness = Loudness::new(48_000.0);
+        assert_eq!(loudness.integrated_lufs(), f64::NEG_INFINITY);
+        loudness.measure(&vec![Stereo32::default(); 48_000]);
+        assert_eq!(loudness.momentary_lufs(), f64::NEG_INFINITY);
+        assert_eq!(loudness.integrated_lufs(), f64::NEG_INFINITY);
+        loudness.measure(&sine(-23.0, 5));
+        loudness.reset();
+        assert_eq!(loudness.integrated_lufs(), f64::NEG_INFINITY);
+    }
+}
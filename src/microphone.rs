@@ -7,21 +7,299 @@
 // At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
 // LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
 
-use std::fmt::{Debug, Display, Formatter, Result};
+use std::{
+    any::Any,
+    cell::Cell,
+    fmt::{Debug, Display, Formatter, Result},
+    rc::Rc,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
 
-use fon::{chan::Ch32, Frame, Stream};
+use fon::{chan::Ch32, Audio, Frame, Stream};
 use pasts::prelude::*;
 
-use crate::ffi;
+use crate::{
+    consts, ffi, memory, null, AudioError, ChannelLevel, DeviceCaps,
+    DeviceFilter, DeviceId, DeviceInfo, DeviceWatcher, Latency, Loudness,
+    SampleFormat, SilenceDetector, XrunKind, XrunPolicy,
+};
 
 /// Record audio from connected microphone.  Notifier produces an audio stream,
 /// which contains the samples recorded since the previous call.
+///
+/// Polling again before the last stream is dropped produces
+/// [`AudioError::DeviceLocked`].
+///
+/// On the web, no streams are produced until the user allows access to the
+/// microphone, and [`AudioError::PermissionDenied`] is produced if they don't.
+pub struct Microphone<const N: usize>(Backend, Input);
+
+/// Level above which samples are soft clipped, when enabled.
+const KNEE: f32 = 0.75;
+
+/// Apply `gain` to a recorded `sample`, soft clipped if `soft_clip` (or else
+/// clipped to the range of [`Ch32`]).
+fn amplify(sample: f32, gain: f32, soft_clip: bool) -> f32 {
+    let sample = sample * gain;
+    if soft_clip && sample.abs() > KNEE {
+        // Continues the slope at the knee, approaching 1.0.
+        let over = (sample.abs() - KNEE) / (1.0 - KNEE);
+        (KNEE + (1.0 - KNEE) * over.tanh()).copysign(sample)
+    } else {
+        sample.clamp(-1.0, 1.0)
+    }
+}
+
+/// Input gain and mute, applied to streams as they're read.
+struct Input {
+    /// Gain set with [`Microphone::set_input_gain()`].
+    gain: f32,
+    /// If enabled with [`Microphone::set_soft_clip()`].
+    soft_clip: bool,
+    /// Number of channels the device was configured for, by the last stream.
+    channels: u8,
+    /// Set with [`Microphone::set_xrun_policy()`].
+    xrun_policy: XrunPolicy,
+    /// Overruns already handled by the policy.
+    xruns: usize,
+    /// If muted with [`Microphone::mute()`].
+    muted: AtomicBool,
+    /// Timestamps of the streams.
+    clock: Clock,
+    /// Analysis of the streams, held by the stream while it exists.
+    analysis: Rc<Cell<Analysis>>,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            soft_clip: false,
+            channels: 0,
+            xrun_policy: XrunPolicy::default(),
+            xruns: 0,
+            muted: AtomicBool::new(false),
+            clock: Clock::default(),
+            analysis: Rc::default(),
+        }
+    }
+}
+
+/// Analysis attached to the streams of a microphone, handed from each stream to
+/// the next.
 #[derive(Default)]
-pub struct Microphone<const N: usize>(pub(super) ffi::Microphone);
+struct Analysis {
+    /// Attached with [`MicrophoneStream::attach_loudness_meter()`].
+    loudness: Option<Loudness>,
+    /// Attached with [`MicrophoneStream::with_silence_detector()`].
+    silence: Option<SilenceDetector>,
+    /// Frames of the last stream (a `Vec` of its frame type), reused so
+    /// recording doesn't allocate each period.
+    frames: Option<Box<dyn Any>>,
+}
+
+impl Analysis {
+    /// Analyze the `frames` of a stream.
+    fn update<F: Frame<Chan = Ch32>>(&mut self, frames: &[F]) {
+        if let Some(loudness) = &mut self.loudness {
+            loudness.measure(frames);
+        }
+        if let Some(silence) = &mut self.silence {
+            silence.update(frames);
+        }
+    }
+}
+
+impl Input {
+    /// Get the multiplier for recorded samples.
+    fn multiplier(&self) -> f32 {
+        if self.muted.load(Ordering::Relaxed) {
+            0.0
+        } else {
+            self.gain
+        }
+    }
+}
+
+/// Timestamps of consecutive streams, which advance by exactly the duration of
+/// the frames recorded.
+#[derive(Default)]
+struct Clock {
+    /// Timestamp of the first frame since the clock was last set, and the
+    /// sample rate since then.
+    start: Option<(Instant, f64)>,
+    /// Frames recorded since the clock was last set.
+    frames: u64,
+}
+
+impl Clock {
+    /// Get the timestamp of a stream of `frames` at `rate`, which was
+    /// `measured` from the latency.
+    ///
+    /// The clock is set to the measured timestamp at first, after an overrun
+    /// (`xrun`) or a change of `rate`, or once it's more than a period late
+    /// (since the device's clock drifts from the system's).  Earlier
+    /// measurements don't set it, so timestamps never go backwards.
+    fn next(
+        &mut self,
+        measured: Instant,
+        frames: usize,
+        rate: f64,
+        xrun: bool,
+    ) -> Instant {
+        let period = frames.max(consts::PERIOD.into()) as f64 / rate;
+        let expected = match self.start {
+            Some((start, start_rate)) if !xrun && start_rate == rate => {
+                Some(start + Duration::from_secs_f64(self.frames as f64 / rate))
+            }
+            _ => None,
+        };
+        let timestamp = match expected {
+            Some(expected)
+                if measured <= expected + Duration::from_secs_f64(period) =>
+            {
+                expected
+            }
+            _ => {
+                self.start = Some((measured, rate));
+                self.frames = 0;
+                measured
+            }
+        };
+        self.frames += frames as u64;
+        timestamp
+    }
+}
+
+/// Microphone from the platform, a null microphone, or a microphone recording
+/// audio in memory (from a file or a mock).
+enum Backend {
+    Ffi(ffi::Microphone),
+    Null(null::Microphone),
+    Memory(memory::Microphone),
+}
+
+impl Backend {
+    fn record<F>(&mut self) -> std::result::Result<StreamBackend<F>, AudioError>
+    where
+        F: Frame<Chan = Ch32>,
+    {
+        match self {
+            Backend::Ffi(mic) => mic.record().map(StreamBackend::Ffi),
+            Backend::Null(mic) => mic.record().map(StreamBackend::Null),
+            Backend::Memory(mic) => mic.record().map(StreamBackend::Memory),
+        }
+    }
+
+    fn channels(&self) -> u8 {
+        match self {
+            Backend::Ffi(mic) => mic.channels(),
+            Backend::Null(mic) => mic.channels(),
+            Backend::Memory(mic) => mic.channels(),
+        }
+    }
+
+    fn id(&self) -> &str {
+        match self {
+            Backend::Ffi(mic) => mic.id(),
+            Backend::Null(mic) => mic.id(),
+            Backend::Memory(mic) => mic.id(),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Backend::Ffi(mic) => mic.name(),
+            Backend::Null(mic) => mic.name(),
+            Backend::Memory(mic) => mic.name(),
+        }
+    }
+
+    fn format(&self) -> SampleFormat {
+        match self {
+            Backend::Ffi(mic) => mic.format(),
+            Backend::Null(mic) => mic.format(),
+            Backend::Memory(mic) => mic.format(),
+        }
+    }
+
+    fn xruns(&self) -> usize {
+        match self {
+            Backend::Ffi(mic) => mic.xruns(),
+            Backend::Null(mic) => mic.xruns(),
+            Backend::Memory(mic) => mic.xruns(),
+        }
+    }
+
+    fn suspends(&self) -> usize {
+        match self {
+            Backend::Ffi(mic) => mic.suspends(),
+            Backend::Null(mic) => mic.suspends(),
+            Backend::Memory(mic) => mic.suspends(),
+        }
+    }
+
+    fn sample_rate(&self) -> Option<f64> {
+        match self {
+            Backend::Ffi(mic) => mic.sample_rate(),
+            Backend::Null(mic) => mic.sample_rate(),
+            Backend::Memory(mic) => mic.sample_rate(),
+        }
+    }
+
+    fn set_period(&mut self, frames: u16, count: u8) {
+        match self {
+            Backend::Ffi(microphone) => microphone.set_period(frames, count),
+            Backend::Null(microphone) => microphone.set_period(frames, count),
+            Backend::Memory(microphone) => microphone.set_period(frames, count),
+        }
+    }
+
+    fn period(&self) -> u16 {
+        match self {
+            Backend::Ffi(microphone) => microphone.period(),
+            Backend::Null(microphone) => microphone.period(),
+            Backend::Memory(microphone) => microphone.period(),
+        }
+    }
+
+    fn buffer_frames(&self) -> Option<usize> {
+        match self {
+            Backend::Ffi(microphone) => microphone.buffer_frames(),
+            Backend::Null(microphone) => microphone.buffer_frames(),
+            Backend::Memory(microphone) => microphone.buffer_frames(),
+        }
+    }
+
+    fn caps(&self) -> DeviceCaps {
+        match self {
+            Backend::Ffi(mic) => mic.caps(),
+            Backend::Null(mic) => mic.caps(),
+            Backend::Memory(mic) => mic.caps(),
+        }
+    }
+}
+
+impl Future for Backend {
+    type Output = std::result::Result<(), AudioError>;
+
+    fn poll(self: Pin<&mut Self>, e: &mut Exec<'_>) -> Poll<Self::Output> {
+        match self.get_mut() {
+            Backend::Ffi(mic) => Pin::new(mic).poll(e),
+            Backend::Null(mic) => Pin::new(mic).poll(e),
+            Backend::Memory(mic) => Pin::new(mic).poll(e),
+        }
+    }
+}
 
 impl<const N: usize> Display for Microphone<N> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        self.0.fmt(f)
+        match &self.0 {
+            Backend::Ffi(mic) => mic.fmt(f),
+            Backend::Null(mic) => mic.fmt(f),
+            Backend::Memory(mic) => mic.fmt(f),
+        }
     }
 }
 
@@ -31,15 +309,293 @@ impl<const N: usize> Debug for Microphone<N> {
     }
 }
 
+impl<const N: usize> Default for Microphone<N> {
+    /// Connect to the default microphone, or a [null](Microphone::null)
+    /// microphone at 48 kHz if it can't be opened.  Use
+    /// [`try_default()`](Microphone::try_default) to handle the error instead.
+    fn default() -> Self {
+        Self::try_default()
+            .unwrap_or_else(|_| Self::null(consts::SAMPLE_RATE.into()))
+    }
+}
+
 impl Microphone<0> {
     /// Query available audio sources.
     pub fn query() -> Vec<Self> {
-        ffi::device_list(Self)
+        Self::query_all().into_iter().flatten().collect()
+    }
+
+    /// Query available audio sources, including ones that were found but
+    /// couldn't be opened (with the device's name and the reason).
+    pub fn query_all() -> Vec<std::result::Result<Self, (String, AudioError)>> {
+        ffi::device_list(|mic| Self(Backend::Ffi(mic), Input::default()))
+    }
+
+    /// List available audio sources without opening them, which is faster
+    /// than [`query()`](Self::query) and doesn't make them busy for other
+    /// programs.  Open one with [`find_by_id()`](Self::find_by_id).
+    ///
+    /// On Linux, PipeWire nodes or ALSA device hints (chosen with `filter`)
+    /// are listed.  Other platforms open each device to list it.
+    pub fn list(filter: DeviceFilter) -> Vec<DeviceInfo> {
+        match ffi::device_names(true, filter) {
+            Some(names) => names
+                .iter()
+                .map(|(id, name)| DeviceInfo::new(id, name))
+                .collect(),
+            None => Self::query()
+                .iter()
+                .map(|device| DeviceInfo::new(device.0.id(), device.0.name()))
+                .collect(),
+        }
+    }
+
+    /// Find the device with a saved `id` (from [`id()`](Self::id) or
+    /// [`DeviceInfo::id()`]), or `None` if it isn't connected.
+    pub fn find_by_id(id: &DeviceId) -> Option<Self> {
+        Self::open(id.as_str()).ok()
+    }
+
+    /// Get the bitflags for numbers of channels (which of 1-8 are supported).
+    pub(crate) fn supported(&self) -> u8 {
+        self.0.channels()
+    }
+
+    /// Watch for microphones being plugged in or unplugged.  `interval` is how often
+    /// to check on platforms that can't notify when devices change.
+    pub fn watch(interval: Duration) -> DeviceWatcher<Self> {
+        DeviceWatcher::new(interval)
     }
 }
 
 impl<const N: usize> Microphone<N> {
+    /// Try to connect to the default microphone, returning an error if there
+    /// is none, it's busy, or it's unsupported.
+    ///
+    /// With PipeWire, the microphone follows the system default as it
+    /// changes.  On other platforms it stays on the device that was the
+    /// default when opened; reopen it on
+    /// [`DeviceEvent::DefaultChanged`](crate::DeviceEvent::DefaultChanged).
+    pub fn try_default() -> std::result::Result<Self, AudioError> {
+        ffi::Microphone::try_default()
+            .map(|mic| Self(Backend::Ffi(mic), Input::default()))
+    }
+
+    /// Open the microphone named `name`, returning an error if there is none,
+    /// it's busy, or it's unsupported.
+    ///
+    /// On Linux, `name` can be an ALSA PCM name (such as `hw:CARD=USB,DEV=0`),
+    /// which is opened directly rather than querying every device, and can
+    /// be a PCM that isn't listed (such as a `dsnoop` or `loopback` plugin).
+    /// Otherwise, the device with `name` as its ID or name is opened.
+    pub fn open(name: &str) -> std::result::Result<Self, AudioError> {
+        match ffi::Microphone::open(name) {
+            Err(AudioError::NoDevice) => {}
+            result => {
+                return result
+                    .map(|mic| Self(Backend::Ffi(mic), Input::default()))
+            }
+        }
+        for device in Microphone::query_all() {
+            match device {
+                Ok(device)
+                    if device.0.id() == name || device.0.name() == name =>
+                {
+                    return Ok(Self(device.0, Input::default()));
+                }
+                Err((device, error)) if device == name => return Err(error),
+                _ => {}
+            }
+        }
+        Err(AudioError::NoDevice)
+    }
+
+    /// Connect to a null microphone, which records silence at `sample_rate`.
+    /// Useful for running without a sound card, such as on CI servers.
+    ///
+    /// # Panics
+    /// If `sample_rate` is zero, or on the web (where threads can't be
+    /// spawned).
+    pub fn null(sample_rate: u32) -> Self {
+        Self(
+            Backend::Null(null::Microphone::new(sample_rate)),
+            Input::default(),
+        )
+    }
+
+    /// Use a microphone from the platform.
+    pub(crate) fn ffi(mic: ffi::Microphone) -> Self {
+        Self(Backend::Ffi(mic), Input::default())
+    }
+
+    /// Use a microphone recording audio in memory, from
+    /// [`FileSource`](crate::FileSource) or `MockMicrophone`.
+    pub(crate) fn memory(mic: memory::Microphone) -> Self {
+        Self(Backend::Memory(mic), Input::default())
+    }
+
+    /// Get the stable ID of this device, for reconnecting to it later.
+    pub fn id(&self) -> DeviceId {
+        DeviceId::new(self.0.id())
+    }
+
+    /// Get the human-readable name of this device.
+    pub fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    /// Get the sample format the device was opened with.
+    pub fn format(&self) -> SampleFormat {
+        self.0.format()
+    }
+
+    /// Get the number of overruns since the device was opened.
+    ///
+    /// An overrun happens when the stream isn't read in time, so recorded
+    /// audio is lost.  A growing count means the task recording audio is too
+    /// slow, or the latency is too low.
+    pub fn overruns(&self) -> u64 {
+        self.0.xruns() as u64
+    }
+
+    /// Get the number of times the device recovered from being suspended
+    /// (such as by the system sleeping) since it was opened.
+    ///
+    /// If it can't recover, polling produces
+    /// `AudioError::InvalidState(StreamState::Suspended)`, and the device
+    /// should be opened again.  Only ALSA reports suspends.
+    pub fn suspends(&self) -> u64 {
+        self.0.suspends() as u64
+    }
+
+    /// Set what to do when the microphone overruns (by default, it recovers).
+    pub fn set_xrun_policy(&mut self, policy: XrunPolicy) {
+        self.1.xrun_policy = policy;
+    }
+
+    /// Get the sample rate the device actually runs at, which may differ from
+    /// the preferred 48 kHz.  This is `None` until the microphone is first
+    /// polled (and configured).
+    pub fn actual_sample_rate(&self) -> Option<f64> {
+        self.0.sample_rate()
+    }
+
+    /// Request a period (the number of frames read from the device at a
+    /// time) of `frames`, and room for `count` periods in the device's
+    /// buffer.  Small periods lower latency, while large ones wake less often
+    /// (saving power).  The device is reconfigured for the next stream.
+    ///
+    /// The hardware may not support the exact sizes, so the nearest ones are
+    /// used; check them with [`period()`](Self::period) and
+    /// [`buffer_frames()`](Self::buffer_frames) once the stream is produced.
+    /// Only ALSA can be configured, other platforms (and PipeWire) pick the
+    /// period themselves.
+    pub fn set_period(&mut self, frames: u16, count: u8) {
+        self.0.set_period(frames, count);
+    }
+
+    /// Get the period the device was configured with, in frames (`0` until
+    /// the first stream is produced).
+    pub fn period(&self) -> u16 {
+        self.0.period()
+    }
+
+    /// Get the number of frames the device buffers, once configured.  This is
+    /// `None` on platforms that don't report it.
+    pub fn buffer_frames(&self) -> Option<usize> {
+        self.0.buffer_frames()
+    }
+
+    /// Get the capabilities of the device (channel counts, sample rates and
+    /// periods), for offering only the configurations it supports.  This
+    /// doesn't reconfigure the device, so it can be called while it's in use.
+    pub fn caps(&self) -> DeviceCaps {
+        self.0.caps()
+    }
+
+    /// Get the standard sample rates the device supports, for offering a
+    /// choice of rates (such as in a combo box).  See
+    /// [`DeviceCaps::standard_sample_rates()`].
+    pub fn supported_sample_rates(&self) -> Vec<u32> {
+        self.caps().standard_sample_rates().to_vec()
+    }
+
+    /// Get the numbers of channels the device supports (from 1 to 8), for
+    /// choosing a configuration with [`config()`](Self::config).
+    ///
+    /// This is empty while a stream from the device is alive, since the device
+    /// can't be reconfigured then.
+    pub fn supported_channels(&self) -> impl Iterator<Item = u8> {
+        let supported = self.0.channels();
+        (1..=8).filter(move |count| supported & (1 << (count - 1)) != 0)
+    }
+
+    /// Get the number of channels the device is recording, which is `0` until
+    /// the first stream is produced (the device is configured for the
+    /// stream's frame type then).
+    pub fn channel_count(&self) -> u8 {
+        self.1.channels
+    }
+
+    /// Mute the microphone, without closing it.  While muted, streams keep
+    /// being produced at the same rate, but only contain silence.
+    ///
+    /// Muting applies from the next stream.
+    pub fn mute(&mut self) {
+        self.1.muted.store(true, Ordering::Relaxed);
+    }
+
+    /// Unmute the microphone, muted with [`mute()`](Microphone::mute).
+    pub fn unmute(&mut self) {
+        self.1.muted.store(false, Ordering::Relaxed);
+    }
+
+    /// Mute or unmute the microphone, like [`mute()`](Microphone::mute) and
+    /// [`unmute()`](Microphone::unmute).
+    pub fn set_muted(&mut self, muted: bool) {
+        self.1.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Check if the microphone is muted.
+    pub fn is_muted(&self) -> bool {
+        self.1.muted.load(Ordering::Relaxed)
+    }
+
+    /// Set the gain (volume multiplier) for audio recorded in the following
+    /// streams.  `1.0` is unity gain.  Gains above `1.0` are allowed, but
+    /// samples are clipped to the range of [`Ch32`] (`-1.0` to `1.0`).
+    /// Negative gains are treated as `0.0`.
+    ///
+    /// Gain is applied to samples as they're read from the stream, so the
+    /// hardware input level isn't changed.
+    pub fn set_input_gain(&mut self, gain: f32) {
+        self.1.gain = gain.max(0.0);
+    }
+
+    /// Get the gain set with
+    /// [`set_input_gain()`](Microphone::set_input_gain).
+    pub fn input_gain(&self) -> f32 {
+        self.1.gain
+    }
+
+    /// Saturate samples smoothly instead of clipping them, when input gain
+    /// pushes them past the range of [`Ch32`].  Samples below `0.75` are left
+    /// alone, and louder samples are compressed to approach (but never reach)
+    /// `1.0`.  Off by default.
+    ///
+    /// Like gain, this isn't applied at unity gain.
+    pub fn set_soft_clip(&mut self, enabled: bool) {
+        self.1.soft_clip = enabled;
+    }
+
+    /// Check if soft clipping is enabled with
+    /// [`set_soft_clip()`](Microphone::set_soft_clip).
+    pub fn soft_clip(&self) -> bool {
+        self.1.soft_clip
+    }
+
     /// Try a reconfiguration of microphone.
+    #[allow(clippy::result_large_err)]
     pub fn config<const C: usize>(
         self,
     ) -> std::result::Result<Microphone<C>, Self>
@@ -48,7 +604,7 @@ impl<const N: usize> Microphone<N> {
     {
         let bit = C - 1;
         if (self.0.channels() & (1 << bit)) != 0 {
-            Ok(Microphone(self.0))
+            Ok(Microphone(self.0, self.1))
         } else {
             Err(self)
         }
@@ -75,20 +631,265 @@ impl<const N: usize> Notifier for Microphone<N>
 where
     Microphone<N>: MicrophoneProperties,
 {
-    type Event = MicrophoneStream<<Self as MicrophoneProperties>::Sample>;
+    type Event = std::result::Result<
+        MicrophoneStream<<Self as MicrophoneProperties>::Sample>,
+        AudioError,
+    >;
 
     fn poll_next(self: Pin<&mut Self>, e: &mut Exec<'_>) -> Poll<Self::Event> {
         let this = self.get_mut();
-        if let Ready(()) = Pin::new(&mut this.0).poll(e) {
-            Ready(MicrophoneStream(this.0.record()))
-        } else {
-            Pending
+        match Pin::new(&mut this.0).poll(e) {
+            Ready(Ok(())) => {
+                let multiplier = this.1.multiplier();
+                let soft_clip = this.1.soft_clip;
+                let analysis = this.1.analysis.clone();
+                let stream = this.0.record();
+                let xruns = this.0.xruns();
+                let new = xruns.saturating_sub(this.1.xruns);
+                this.1.xruns = xruns;
+                if let Err(error) =
+                    this.1.xrun_policy.apply(new, XrunKind::Overrun)
+                {
+                    // Dropping the stream unlocks the microphone.
+                    drop(stream);
+                    return Ready(Err(error));
+                }
+                if stream.is_ok() {
+                    this.1.channels = N as u8;
+                }
+                Ready(stream.map(|stream| {
+                    let mut stream = MicrophoneStream::new(
+                        stream, multiplier, soft_clip, analysis,
+                    );
+                    if let Some(rate) = stream.sample_rate() {
+                        stream.4 = this.1.clock.next(
+                            stream.4,
+                            stream.1.len(),
+                            rate,
+                            new != 0,
+                        );
+                    }
+                    stream
+                }))
+            }
+            Ready(Err(error)) => Ready(Err(error)),
+            Pending => Pending,
+        }
+    }
+}
+
+impl<const N: usize> Microphone<N>
+where
+    Microphone<N>: MicrophoneProperties,
+{
+    /// Record until a stream isn't silent, dropping the silent streams, and
+    /// return it.
+    ///
+    /// Silence is detected by the detector attached with
+    /// [`MicrophoneStream::with_silence_detector()`], so with none attached
+    /// this returns the next stream.
+    pub async fn wait_for_activity(
+        &mut self,
+    ) -> std::result::Result<
+        MicrophoneStream<<Self as MicrophoneProperties>::Sample>,
+        AudioError,
+    > {
+        loop {
+            let stream =
+                std::future::poll_fn(|e| Pin::new(&mut *self).poll_next(e))
+                    .await?;
+            if !stream.is_silent() {
+                return Ok(stream);
+            }
         }
     }
 }
 
 /// A stream of recorded audio samples from a microphone.
-pub struct MicrophoneStream<F: Frame<Chan = Ch32>>(ffi::MicrophoneStream<F>);
+pub struct MicrophoneStream<F: Frame<Chan = Ch32>>(
+    /// Kept until dropped, to hold the microphone's lock.
+    StreamBackend<F>,
+    /// Recorded frames, with input gain applied.
+    Vec<F>,
+    /// Level of each channel over the recorded frames.
+    Vec<ChannelLevel>,
+    /// Latency when the stream was produced.
+    Latency,
+    /// When the first frame was captured.
+    Instant,
+    /// Analysis of the stream, returned to the microphone when dropped.
+    Analysis,
+    /// Where the analysis is returned to.
+    Rc<Cell<Analysis>>,
+    /// Index of the next frame to read.
+    usize,
+);
+
+/// Stream from a microphone from the platform, a null microphone, or a
+/// microphone recording audio in memory.
+enum StreamBackend<F: Frame<Chan = Ch32>> {
+    Ffi(ffi::MicrophoneStream<F>),
+    Null(null::MicrophoneStream<F>),
+    Memory(memory::MicrophoneStream<F>),
+}
+
+impl<F: Frame<Chan = Ch32>> MicrophoneStream<F> {
+    /// Read the recorded frames from the backend, applying `gain` (soft
+    /// clipped if `soft_clip`) and measuring levels in the same pass.  The
+    /// frames are analyzed with the analysis in `slot` too.
+    fn new(
+        mut stream: StreamBackend<F>,
+        gain: f32,
+        soft_clip: bool,
+        slot: Rc<Cell<Analysis>>,
+    ) -> Self {
+        let latency = match &stream {
+            StreamBackend::Ffi(stream) => stream.latency(),
+            StreamBackend::Null(stream) => stream.latency(),
+            StreamBackend::Memory(stream) => stream.latency(),
+        };
+        // The first frame was captured one latency ago.
+        let now = Instant::now();
+        let timestamp = now.checked_sub(latency.duration()).unwrap_or(now);
+        let mut analysis = slot.take();
+        let mut frames = analysis
+            .frames
+            .take()
+            .and_then(|frames| frames.downcast::<Vec<F>>().ok())
+            .map_or_else(Vec::new, |frames| *frames);
+        frames.clear();
+        let mut peak = [0.0f32; 8];
+        let mut sum = [0.0f64; 8];
+        while let Some(mut frame) = match &mut stream {
+            StreamBackend::Ffi(stream) => stream.next(),
+            StreamBackend::Null(stream) => stream.next(),
+            StreamBackend::Memory(stream) => stream.next(),
+        } {
+            for (i, chan) in frame.channels_mut().iter_mut().enumerate() {
+                // Unity gain doesn't clip, to leave the audio untouched.
+                if gain != 1.0 {
+                    *chan =
+                        Ch32::new(amplify(f32::from(*chan), gain, soft_clip));
+                }
+                let sample = f32::from(*chan);
+                peak[i] = peak[i].max(sample.abs());
+                sum[i] += f64::from(sample) * f64::from(sample);
+            }
+            frames.push(frame);
+        }
+        let levels = (0..F::CHAN_COUNT)
+            .map(|i| {
+                let rms = if frames.is_empty() {
+                    0.0
+                } else {
+                    (sum[i] / frames.len() as f64).sqrt() as f32
+                };
+                ChannelLevel::new(peak[i], rms)
+            })
+            .collect();
+        analysis.update(&frames);
+        Self(
+            stream, frames, levels, latency, timestamp, analysis, slot, 0,
+        )
+    }
+
+    /// Get the capture latency: how long ago the first sample of the stream
+    /// was captured, when the stream was produced.
+    pub fn latency(&self) -> Latency {
+        self.3
+    }
+
+    /// Get when the first frame of the stream was captured, for
+    /// synchronizing with video or other sensors.
+    ///
+    /// This is measured as the time the stream was produced minus its
+    /// [`latency()`](Self::latency), so it's as precise as the platform's
+    /// delay reporting: ALSA, PipeWire, JACK, CoreAudio, AAudio and WASAPI
+    /// include the hardware delay, while on the web only the audio buffered
+    /// by wavy is accounted for.
+    ///
+    /// Consecutive streams' timestamps advance by exactly the duration of the
+    /// frames in between, without the jitter of measuring each one, and never
+    /// go backwards.  They're measured again after an overrun, or when
+    /// they're more than a period late (as the device's clock drifts).
+    pub fn timestamp(&self) -> Instant {
+        self.4
+    }
+
+    /// Get the level of each channel over all of the frames in this stream
+    /// (including ones that were already read), in channel order.
+    ///
+    /// Levels are measured after input gain is applied, over a rectangular
+    /// window of exactly the frames in the stream (see [`ChannelLevel`]).
+    pub fn levels(&self) -> &[ChannelLevel] {
+        &self.2
+    }
+
+    /// Get the loudness meter of the microphone, attaching one first if there
+    /// isn't one yet.
+    ///
+    /// The meter measures each stream from the microphone when it's produced.
+    /// A newly attached meter starts with the frames of this stream that
+    /// haven't been read yet.
+    pub fn attach_loudness_meter(&mut self) -> &mut Loudness {
+        let sample_rate = self.sample_rate().unwrap_or_default();
+        let frames = &self.1[self.7..];
+        self.5.loudness.get_or_insert_with(|| {
+            let mut loudness = Loudness::new(sample_rate);
+            loudness.measure(frames);
+            loudness
+        })
+    }
+
+    /// Attach a silence `detector` to the microphone, replacing the one
+    /// attached before.  It's updated with the frames of this stream that
+    /// haven't been read yet, and then with each following stream.
+    pub fn with_silence_detector(
+        mut self,
+        mut detector: SilenceDetector,
+    ) -> Self {
+        detector.update(self.unread());
+        self.5.silence = Some(detector);
+        self
+    }
+
+    /// Check if the silence detector attached with
+    /// [`with_silence_detector()`](Self::with_silence_detector) is in the
+    /// silent state, after this stream.  `false` if none is attached.
+    pub fn is_silent(&self) -> bool {
+        self.5
+            .silence
+            .as_ref()
+            .is_some_and(SilenceDetector::is_silent)
+    }
+
+    /// Get the frames that haven't been read yet, without copying.
+    pub fn frames(&self) -> &[F] {
+        self.unread()
+    }
+
+    /// Get the frames that haven't been read yet.
+    fn unread(&self) -> &[F] {
+        &self.1[self.7..]
+    }
+
+    /// Collect the frames that haven't been read yet into an [`Audio`]
+    /// buffer, converting them to frame type `G`.
+    pub fn into_audio<G: Frame>(self) -> Audio<G> {
+        let sample_rate = self.sample_rate().map_or(0, |rate| rate as u32);
+        let frames: Vec<G> =
+            self.unread().iter().map(|f| f.convert()).collect();
+        Audio::with_frames(sample_rate, frames)
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Drop for MicrophoneStream<F> {
+    fn drop(&mut self) {
+        let frames = std::mem::take(&mut self.1);
+        self.5.frames = Some(Box::new(frames));
+        self.6.set(std::mem::take(&mut self.5));
+    }
+}
 
 impl<F: Frame<Chan = Ch32>> Debug for MicrophoneStream<F> {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> Result {
@@ -100,16 +901,169 @@ impl<F: Frame<Chan = Ch32>> Iterator for MicrophoneStream<F> {
     type Item = F;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next()
+        let frame = self.1.get(self.7).copied();
+        self.7 += usize::from(frame.is_some());
+        frame
     }
 }
 
 impl<F: Frame<Chan = Ch32>> Stream<F> for MicrophoneStream<F> {
     fn sample_rate(&self) -> Option<f64> {
-        self.0.sample_rate()
+        match &self.0 {
+            StreamBackend::Ffi(stream) => stream.sample_rate(),
+            StreamBackend::Null(stream) => stream.sample_rate(),
+            StreamBackend::Memory(stream) => stream.sample_rate(),
+        }
     }
 
     fn len(&self) -> Option<usize> {
-        self.0.len()
+        Some(self.unread().len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn soft_clip_bounded() {
+        let mut last = 0.0;
+        for i in 0..=100_000 {
+            let sample = i as f32 * 0.0001;
+            let out = amplify(sample, 1.0, true);
+            assert!(out <= 1.0, "{} soft clipped to {}", sample, out);
+            assert!(out >= last, "{} soft clipped to {}", sample, out);
+            assert_eq!(amplify(-sample, 1.0, true), -out);
+            last = out;
+        }
+        // Gain pushes samples past the knee too.
+        for gain in [2.0, 10.0, 1000.0] {
+            assert!(amplify(1.0, gain, true) <= 1.0);
+            assert!(amplify(-1.0, gain, true) >= -1.0);
+        }
+    }
+
+    #[test]
+    fn soft_clip_continuous_at_knee() {
+        let below = amplify(KNEE, 1.0, true);
+        let above = amplify(KNEE + 1e-4, 1.0, true);
+        assert_eq!(below, KNEE);
+        // Continuous, with a slope of 1.0 on both sides.
+        assert!((above - below - 1e-4).abs() < 1e-5);
+        // Untouched below the knee.
+        assert_eq!(amplify(0.5, 1.0, true), 0.5);
+        assert_eq!(amplify(-0.5, 1.0, true), -0.5);
+    }
+
+    /// Get the duration of `frames` at 48 kHz.
+    fn frames(frames: u32) -> Duration {
+        Duration::from_secs_f64(f64::from(frames) / 48_000.0)
+    }
+
+    #[test]
+    fn clock_advances_by_frames() {
+        let mut clock = Clock::default();
+        let start = Instant::now();
+        assert_eq!(clock.next(start, 64, 48_000.0, false), start);
+        // Measurements jitter, but timestamps advance by the frames.
+        let jitter = Duration::from_micros(300);
+        let next = clock.next(start + frames(64) + jitter, 64, 48_000.0, false);
+        assert_eq!(next, start + frames(64));
+        let next =
+            clock.next(start + frames(128) - jitter, 32, 48_000.0, false);
+        assert_eq!(next, start + frames(128));
+        let next = clock.next(start + frames(160), 64, 48_000.0, false);
+        assert_eq!(next, start + frames(160));
+        // Measurements from before the expected timestamp (such as streams
+        // produced faster than real time) don't move it back.
+        let next = clock.next(start, 64, 48_000.0, false);
+        assert_eq!(next, start + frames(224));
+    }
+
+    #[test]
+    fn clock_remeasured() {
+        let mut clock = Clock::default();
+        let start = Instant::now();
+        clock.next(start, 64, 48_000.0, false);
+        // After an overrun, even when close to the expected timestamp.
+        let measured = start + frames(64) + Duration::from_micros(100);
+        assert_eq!(clock.next(measured, 64, 48_000.0, true), measured);
+        // And the clock carries on from there.
+        let next = clock.next(measured + frames(64), 64, 48_000.0, false);
+        assert_eq!(next, measured + frames(64));
+        // When more than a period late.
+        let late = measured + frames(256);
+        assert_eq!(clock.next(late, 64, 48_000.0, false), late);
+        // When the sample rate changes.
+        let measured = late + frames(64);
+        assert_eq!(clock.next(measured, 64, 44_100.0, false), measured);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn stream_timestamps() {
+        use fon::mono::Mono32;
+
+        use crate::{testing::poll_next, MockMicrophone};
+
+        let audio = Audio::<Mono32>::with_silence(48_000, 640);
+        let mut microphone: Microphone<1> =
+            MockMicrophone::from_frames(audio).microphone();
+        let mut first = None;
+        let mut last = None;
+        let mut count = 0;
+        for _ in 0..10 {
+            let stream = match poll_next(&mut microphone) {
+                Ready(Ok(stream)) => stream,
+                _ => panic!("mock microphone not ready"),
+            };
+            let timestamp = stream.timestamp();
+            let first = *first.get_or_insert(timestamp);
+            assert!(last.map_or(true, |last| timestamp > last));
+            assert_eq!(timestamp - first, frames(count));
+            count += stream.frames().len() as u32;
+            last = Some(timestamp);
+        }
+        assert_eq!(count, 640);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn stream_buffer_reused() {
+        use fon::mono::Mono32;
+
+        use crate::{testing::poll_next, MockMicrophone};
+
+        let frames = (0..640)
+            .map(|i| Mono32::from_channels(&[Ch32::new(i as f32 / 640.0)]));
+        let audio = Audio::with_frames(48_000, frames.collect::<Vec<_>>());
+        let mut microphone: Microphone<1> =
+            MockMicrophone::from_frames(audio).microphone();
+        let mut buffer = None;
+        let mut index = 0;
+        for _ in 0..10 {
+            let mut stream = match poll_next(&mut microphone) {
+                Ready(Ok(stream)) => stream,
+                _ => panic!("mock microphone not ready"),
+            };
+            // Each stream records into the last one's frames.
+            let pointer = stream.frames().as_ptr();
+            assert_eq!(*buffer.get_or_insert(pointer), pointer);
+            // Reading moves past the frame, leaving the rest unread.
+            let len = stream.frames().len();
+            let first = stream.next().unwrap();
+            assert_eq!(first.channels()[0], Ch32::new(index as f32 / 640.0));
+            assert_eq!(stream.frames().len(), len - 1);
+            assert_eq!(stream.len(), Some(len - 1));
+            index += len;
+        }
+        assert_eq!(index, 640);
+    }
+
+    #[test]
+    fn hard_clip() {
+        assert_eq!(amplify(0.75, 2.0, false), 1.0);
+        assert_eq!(amplify(-0.75, 2.0, false), -1.0);
+        assert_eq!(amplify(0.25, 2.0, false), 0.5);
     }
 }
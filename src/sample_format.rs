@@ -0,0 +1,25 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+/// Sample format the audio device was opened with.
+///
+/// Audio is always converted to and from `Ch32` for the sink and stream, so
+/// this is only informational (for example, for logging).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SampleFormat {
+    /// 32-bit floating point
+    F32,
+    /// Signed 16-bit integer
+    S16,
+    /// Signed 24-bit integer (in a 32-bit word)
+    S24,
+    /// Signed 32-bit integer
+    S32,
+}
@@ -0,0 +1,134 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use fon::{chan::Ch32, Frame};
+
+/// Detects when a microphone is only recording silence, for push-to-talk or
+/// voice activity detection.
+///
+/// Each stream is active if its RMS level (over all channels) is at or above
+/// the threshold.  The detector becomes active as soon as a stream is, and
+/// becomes silent again once the streams have been quiet for the hold time,
+/// so short pauses don't flip it back and forth.  It starts out silent.
+///
+/// Attach one with
+/// [`MicrophoneStream::with_silence_detector()`](crate::MicrophoneStream::with_silence_detector).
+#[derive(Copy, Clone, Debug)]
+pub struct SilenceDetector {
+    /// RMS level (linear) below which streams are quiet.
+    threshold: f32,
+    /// Quiet frames before becoming silent.
+    hold_frames: usize,
+    /// Quiet frames since the last active stream.
+    quiet: usize,
+    /// If in the silent state.
+    silent: bool,
+}
+
+impl SilenceDetector {
+    /// Create a silence detector for streams with an RMS level below
+    /// `threshold_db` (dBFS, such as `-50.0`), that becomes silent after
+    /// `hold_frames` quiet frames.
+    pub fn new(threshold_db: f32, hold_frames: usize) -> Self {
+        Self {
+            threshold: 10.0f32.powf(threshold_db / 20.0),
+            hold_frames,
+            quiet: 0,
+            silent: true,
+        }
+    }
+
+    /// Check if the detector is in the silent state.
+    pub fn is_silent(&self) -> bool {
+        self.silent
+    }
+
+    /// Update the state with the `frames` of a stream.
+    pub(crate) fn update<F: Frame<Chan = Ch32>>(&mut self, frames: &[F]) {
+        if frames.is_empty() {
+            return;
+        }
+        let sum: f64 = frames
+            .iter()
+            .flat_map(|frame| frame.channels())
+            .map(|&chan| f64::from(f32::from(chan * chan)))
+            .sum();
+        let rms = (sum / (frames.len() * F::CHAN_COUNT) as f64).sqrt();
+        if rms >= f64::from(self.threshold) {
+            self.quiet = 0;
+            self.silent = false;
+        } else {
+            self.quiet = self.quiet.saturating_add(frames.len());
+            if self.quiet >= self.hold_frames {
+                self.silent = true;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use std::task::Poll::Ready;
+
+    use fon::{mono::Mono32, Audio};
+
+    use super::*;
+    use crate::{
+        consts::PERIOD, testing::poll_next, Microphone, MockMicrophone,
+    };
+
+    /// Record a period at each of `levels` with a detector for -40 dBFS that
+    /// holds for two periods, returning if it's silent after each.
+    fn detect(levels: &[f32]) -> Vec<bool> {
+        let period = usize::from(PERIOD);
+        let frames: Vec<Mono32> = levels
+            .iter()
+            .flat_map(|&level| {
+                [Mono32::from_channels(&[level.into()]); PERIOD as usize]
+            })
+            .collect();
+        let audio = Audio::with_frames(48_000, frames);
+        let mut microphone: Microphone<1> =
+            MockMicrophone::from_frames(audio).microphone();
+        let mut detector = Some(SilenceDetector::new(-40.0, 2 * period));
+        let mut silent = Vec::new();
+        for _ in levels {
+            let mut stream = match poll_next(&mut microphone) {
+                Ready(Ok(stream)) => stream,
+                _ => panic!("mock microphone not ready"),
+            };
+            if let Some(detector) = detector.take() {
+                stream = stream.with_silence_detector(detector);
+            }
+            assert_eq!(stream.frames().len(), period);
+            silent.push(stream.is_silent());
+        }
+        silent
+    }
+
+    #[test]
+    fn silent_until_active() {
+        // -46 dBFS is under the threshold, -34 dBFS over it.
+        assert_eq!(detect(&[0.0, 0.005, 0.02]), [true, true, false]);
+    }
+
+    #[test]
+    fn short_pauses_held() {
+        // Pauses shorter than the hold time stay active.
+        let levels = [0.5, 0.0, 0.5, 0.005, 0.5];
+        assert_eq!(detect(&levels), [false; 5]);
+    }
+
+    #[test]
+    fn silent_after_hold() {
+        let levels = [0.5, 0.0, 0.0, 0.0, 0.5, 0.0];
+        let silent = [false, false, true, true, false, false];
+        assert_eq!(detect(&levels), silent);
+    }
+}
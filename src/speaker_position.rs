@@ -0,0 +1,133 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+/// Position of the speaker a device channel is played on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SpeakerPosition {
+    /// Not reported by the driver
+    Unknown,
+    /// Mono (the only channel)
+    Mono,
+    /// Front left
+    FrontLeft,
+    /// Front right
+    FrontRight,
+    /// Front center
+    FrontCenter,
+    /// Low frequency effects (subwoofer)
+    Lfe,
+    /// Rear left
+    RearLeft,
+    /// Rear right
+    RearRight,
+    /// Rear center
+    RearCenter,
+    /// Side left
+    SideLeft,
+    /// Side right
+    SideRight,
+}
+
+impl SpeakerPosition {
+    /// Get the positions of the channels of fon frames with `channels`
+    /// channels, in order.
+    fn frame_order(channels: usize) -> &'static [Self] {
+        use SpeakerPosition::*;
+
+        match channels {
+            1 => &[Mono],
+            2 => &[FrontLeft, FrontRight],
+            6 => {
+                &[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight]
+            }
+            _ => &[],
+        }
+    }
+
+    /// Check if a channel at this position can be played at `other`; the
+    /// surround channels of 5.1 can be on either the rear or side speakers.
+    fn fits(self, other: Self) -> bool {
+        use SpeakerPosition::*;
+
+        matches!((self, other), (RearLeft, SideLeft) | (RearRight, SideRight))
+            || self == other
+    }
+}
+
+/// Get the index of the frame channel to play on each device channel, for
+/// devices with channels at `map` positions.  Returns an empty list if no
+/// remapping is needed, or if `map` doesn't fit frames with `channels`.
+pub(crate) fn remap(map: &[SpeakerPosition], channels: usize) -> Vec<usize> {
+    let order = SpeakerPosition::frame_order(channels);
+    if map.len() != channels || order.len() != channels {
+        return Vec::new();
+    }
+    let mut remap = Vec::with_capacity(channels);
+    for position in map {
+        match (0..channels)
+            .find(|i| !remap.contains(i) && order[*i].fits(*position))
+        {
+            Some(i) => remap.push(i),
+            None => return Vec::new(),
+        }
+    }
+    if remap.iter().enumerate().all(|(j, i)| i == &j) {
+        remap.clear();
+    }
+    remap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{remap, SpeakerPosition::*};
+
+    #[test]
+    fn remap_identity() {
+        assert!(remap(&[Mono], 1).is_empty());
+        assert!(remap(&[FrontLeft, FrontRight], 2).is_empty());
+        let surround =
+            [FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight];
+        assert!(remap(&surround, 6).is_empty());
+    }
+
+    #[test]
+    fn remap_swapped() {
+        assert_eq!(remap(&[FrontRight, FrontLeft], 2), [1, 0]);
+        // Center and LFE swapped, like some HDMI outputs.
+        let map =
+            [FrontLeft, FrontRight, Lfe, FrontCenter, RearLeft, RearRight];
+        assert_eq!(remap(&map, 6), [0, 1, 3, 2, 4, 5]);
+    }
+
+    #[test]
+    fn remap_side_for_rear() {
+        // Surround channels are played on side speakers when there are no
+        // rear ones.
+        let map =
+            [FrontLeft, FrontRight, FrontCenter, Lfe, SideLeft, SideRight];
+        assert!(remap(&map, 6).is_empty());
+        let map =
+            [FrontLeft, FrontRight, SideRight, SideLeft, FrontCenter, Lfe];
+        assert_eq!(remap(&map, 6), [0, 1, 5, 4, 2, 3]);
+    }
+
+    #[test]
+    fn remap_mismatched() {
+        // Wrong number of positions for the frames.
+        assert!(remap(&[FrontLeft, FrontRight], 1).is_empty());
+        assert!(remap(&[FrontLeft, FrontRight, FrontCenter], 2).is_empty());
+        assert!(remap(&[], 2).is_empty());
+        // No frames with that many channels.
+        assert!(remap(&[FrontLeft, FrontRight, FrontCenter], 3).is_empty());
+        // Positions that frames don't have.
+        assert!(remap(&[FrontLeft, Unknown], 2).is_empty());
+        assert!(remap(&[FrontLeft, FrontLeft], 2).is_empty());
+    }
+}
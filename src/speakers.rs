@@ -9,17 +9,35 @@
 
 #![allow(clippy::needless_doctest_main)]
 
-use std::fmt::{Debug, Display, Formatter, Result};
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    fmt::{Debug, Display, Formatter, Result},
+    rc::Rc,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Waker,
+    time::Duration,
+};
 
 use fon::{chan::Ch32, Frame, Resampler, Sink};
 use pasts::prelude::*;
 
-use crate::ffi;
+#[cfg(feature = "testing")]
+use crate::mock;
+use crate::{
+    consts, ffi, null, speaker_position, AudioError, DeviceCaps, DeviceFilter,
+    DeviceId, DeviceInfo, DeviceWatcher, Downmix, Latency, Loudness,
+    Microphone, Mono, SampleFormat, SpeakerPosition, Stereo, XrunKind,
+    XrunPolicy,
+};
 
 /// Play audio through speakers.  Notifier produces an audio sink, which
 /// consumes an audio stream of played samples.  If you don't write to the sink,
 /// it will keep playing whatever was last streamed into it.
 ///
+/// Polling again before the last sink is dropped produces
+/// [`AudioError::DeviceLocked`].
+///
 /// # 440 HZ Sine Wave Example
 /// **note:** This example depends on `twang = "0.5"` to synthesize the sine
 /// wave.
@@ -27,7 +45,7 @@ use crate::ffi;
 /// use fon::{stereo::Stereo32, Sink};
 /// use pasts::{prelude::*, Join};
 /// use twang::{Fc, Signal, Synth};
-/// use wavy::{Speakers, SpeakersSink};
+/// use wavy::{AudioError, Speakers, SpeakersSink};
 ///
 /// /// Shared state between tasks on the thread.
 /// struct App {
@@ -39,7 +57,14 @@ use crate::ffi;
 ///
 /// impl App {
 ///     /// Speaker is ready to play more audio.
-///     fn play(&mut self, mut sink: SpeakersSink<Stereo32>) -> Poll<()> {
+///     fn play(
+///         &mut self,
+///         sink: Result<SpeakersSink<Stereo32>, AudioError>,
+///     ) -> Poll<()> {
+///         let mut sink = match sink {
+///             Ok(sink) => sink,
+///             Err(_) => return Ready(()),
+///         };
 ///         sink.stream(&mut self.synth);
 ///         Pending
 ///     }
@@ -58,12 +83,299 @@ use crate::ffi;
 ///     }
 /// }
 /// ```
-#[derive(Default)]
-pub struct Speakers<const N: usize>(pub(super) ffi::Speakers);
+pub struct Speakers<const N: usize>(Backend, Rc<Playback>);
+
+/// Number of frames to ramp gain changes over.
+const RAMP: usize = 64;
+
+/// Playback state, kept between sinks from the same speakers.
+struct Playback {
+    /// Gain set with [`SpeakersSink::set_gain()`].
+    target: Cell<f32>,
+    /// Gain at the end of the last sink, ramping towards the target.
+    current: Cell<f32>,
+    /// Frames handed to the device since it was opened.
+    frames: Cell<u64>,
+    /// If muted with [`Speakers::mute()`].
+    muted: AtomicBool,
+    /// Number of channels the device was configured for, by the last sink.
+    channels: Cell<u8>,
+    /// If paused with [`Speakers::pause()`].
+    paused: Cell<bool>,
+    /// Waker for the notifier while paused, woken on resume.
+    waker: Cell<Option<Waker>>,
+    /// Set with [`Speakers::set_xrun_policy()`].
+    xrun_policy: RefCell<XrunPolicy>,
+    /// Underruns already handled by the policy.
+    xruns: Cell<usize>,
+    /// Meter attached with [`SpeakersSink::attach_loudness_meter()`], held
+    /// by the sink while it exists.
+    loudness: Cell<Option<Loudness>>,
+    /// Set with [`Speakers::set_downmix()`].
+    downmix: Cell<Option<Downmix>>,
+    /// Frames and resampler of the last downmixed sink (a [`MixState`] of
+    /// its frame type), held by the sink while it exists.
+    mix_state: Cell<Option<Box<dyn Any>>>,
+    /// Set with [`Speakers::set_channel_map()`], empty to use the device's.
+    channel_map: RefCell<Vec<SpeakerPosition>>,
+    /// Frame channel for each device channel (empty if they're the same),
+    /// `None` until worked out for the configured device.
+    remap: RefCell<Option<Vec<usize>>>,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self {
+            target: Cell::new(1.0),
+            current: Cell::new(1.0),
+            frames: Cell::new(0),
+            muted: AtomicBool::new(false),
+            channels: Cell::new(0),
+            paused: Cell::new(false),
+            waker: Cell::new(None),
+            xrun_policy: RefCell::default(),
+            xruns: Cell::new(0),
+            loudness: Cell::new(None),
+            downmix: Cell::new(None),
+            mix_state: Cell::new(None),
+            channel_map: RefCell::default(),
+            remap: RefCell::default(),
+        }
+    }
+}
+
+/// Speakers from the platform, null speakers, or mock speakers.
+enum Backend {
+    Ffi(ffi::Speakers),
+    Null(null::Speakers),
+    #[cfg(feature = "testing")]
+    Mock(mock::Speakers),
+}
+
+impl Backend {
+    fn play<F>(&mut self) -> std::result::Result<DeviceSink<F>, AudioError>
+    where
+        F: Frame<Chan = Ch32>,
+    {
+        match self {
+            Backend::Ffi(speakers) => speakers.play().map(DeviceSink::Ffi),
+            Backend::Null(speakers) => speakers.play().map(DeviceSink::Null),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.play().map(DeviceSink::Mock),
+        }
+    }
+
+    /// Get a sink that mixes `F` frames down to stereo or mono, whichever
+    /// has the most channels in the `supported` bitflags (or a normal sink if
+    /// neither is supported).
+    fn play_downmix<F>(
+        &mut self,
+        downmix: Downmix,
+        supported: u8,
+        playback: &Rc<Playback>,
+    ) -> std::result::Result<SinkBackend<F>, AudioError>
+    where
+        F: Frame<Chan = Ch32>,
+    {
+        let mut target = if F::CHAN_COUNT > 2 && supported & 0b10 != 0 {
+            MixTarget::Stereo(self.play()?)
+        } else if F::CHAN_COUNT > 1 && supported & 0b01 != 0 {
+            MixTarget::Mono(self.play()?)
+        } else {
+            return self.play().map(SinkBackend::Device);
+        };
+        let len = match &mut target {
+            MixTarget::Stereo(sink) => sink.buffer().len(),
+            MixTarget::Mono(sink) => sink.buffer().len(),
+        };
+        // Carry on from the last downmixed sink, so the resampler doesn't
+        // restart (and nothing is allocated once the buffer is big enough).
+        let mut state = playback
+            .mix_state
+            .take()
+            .and_then(|state| state.downcast::<MixState<F>>().ok())
+            .unwrap_or_else(|| {
+                Box::new(MixState {
+                    frames: Vec::new(),
+                    resampler: Resampler::new(F::default(), 0.0),
+                })
+            });
+        state.frames.resize(len, F::default());
+        Ok(SinkBackend::Downmix(Mixer {
+            target,
+            state: Some(state),
+            downmix,
+            playback: playback.clone(),
+        }))
+    }
+
+    fn channels(&self) -> u8 {
+        match self {
+            Backend::Ffi(speakers) => speakers.channels(),
+            Backend::Null(speakers) => speakers.channels(),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.channels(),
+        }
+    }
+
+    fn id(&self) -> &str {
+        match self {
+            Backend::Ffi(speakers) => speakers.id(),
+            Backend::Null(speakers) => speakers.id(),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.id(),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Backend::Ffi(speakers) => speakers.name(),
+            Backend::Null(speakers) => speakers.name(),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.name(),
+        }
+    }
+
+    fn format(&self) -> SampleFormat {
+        match self {
+            Backend::Ffi(speakers) => speakers.format(),
+            Backend::Null(speakers) => speakers.format(),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.format(),
+        }
+    }
+
+    fn xruns(&self) -> usize {
+        match self {
+            Backend::Ffi(speakers) => speakers.xruns(),
+            Backend::Null(speakers) => speakers.xruns(),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.xruns(),
+        }
+    }
+
+    fn suspends(&self) -> usize {
+        match self {
+            Backend::Ffi(speakers) => speakers.suspends(),
+            Backend::Null(speakers) => speakers.suspends(),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.suspends(),
+        }
+    }
+
+    fn channel_map(&self) -> Vec<SpeakerPosition> {
+        match self {
+            Backend::Ffi(speakers) => speakers.channel_map(),
+            Backend::Null(speakers) => speakers.channel_map(),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.channel_map(),
+        }
+    }
+
+    fn sample_rate(&self) -> Option<f64> {
+        match self {
+            Backend::Ffi(speakers) => speakers.sample_rate(),
+            Backend::Null(speakers) => speakers.sample_rate(),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.sample_rate(),
+        }
+    }
+
+    fn loopback(&self) -> std::result::Result<ffi::Microphone, AudioError> {
+        match self {
+            Backend::Ffi(speakers) => speakers.loopback(),
+            // Nothing is played to record.
+            Backend::Null(_) => Err(AudioError::NoDevice),
+            #[cfg(feature = "testing")]
+            Backend::Mock(_) => Err(AudioError::NoDevice),
+        }
+    }
+
+    fn set_drain(&mut self, drain: bool) {
+        if let Backend::Ffi(speakers) = self {
+            speakers.set_drain(drain);
+        }
+    }
+
+    fn pause(&mut self) {
+        if let Backend::Ffi(speakers) = self {
+            speakers.pause();
+        }
+    }
+
+    fn resume(&mut self) {
+        if let Backend::Ffi(speakers) = self {
+            speakers.resume();
+        }
+    }
+
+    fn flush(&mut self) {
+        match self {
+            Backend::Ffi(speakers) => speakers.flush(),
+            Backend::Null(speakers) => speakers.flush(),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.flush(),
+        }
+    }
+
+    fn set_period(&mut self, frames: u16, count: u8) {
+        match self {
+            Backend::Ffi(speakers) => speakers.set_period(frames, count),
+            Backend::Null(speakers) => speakers.set_period(frames, count),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.set_period(frames, count),
+        }
+    }
+
+    fn period(&self) -> u16 {
+        match self {
+            Backend::Ffi(speakers) => speakers.period(),
+            Backend::Null(speakers) => speakers.period(),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.period(),
+        }
+    }
+
+    fn buffer_frames(&self) -> Option<usize> {
+        match self {
+            Backend::Ffi(speakers) => speakers.buffer_frames(),
+            Backend::Null(speakers) => speakers.buffer_frames(),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.buffer_frames(),
+        }
+    }
+
+    fn caps(&self) -> DeviceCaps {
+        match self {
+            Backend::Ffi(speakers) => speakers.caps(),
+            Backend::Null(speakers) => speakers.caps(),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.caps(),
+        }
+    }
+}
+
+impl Future for Backend {
+    type Output = std::result::Result<(), AudioError>;
+
+    fn poll(self: Pin<&mut Self>, e: &mut Exec<'_>) -> Poll<Self::Output> {
+        match self.get_mut() {
+            Backend::Ffi(speakers) => Pin::new(speakers).poll(e),
+            Backend::Null(speakers) => Pin::new(speakers).poll(e),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => Pin::new(speakers).poll(e),
+        }
+    }
+}
 
 impl<const N: usize> Display for Speakers<N> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        self.0.fmt(f)
+        match &self.0 {
+            Backend::Ffi(speakers) => speakers.fmt(f),
+            Backend::Null(speakers) => speakers.fmt(f),
+            #[cfg(feature = "testing")]
+            Backend::Mock(speakers) => speakers.fmt(f),
+        }
     }
 }
 
@@ -73,14 +385,399 @@ impl<const N: usize> Debug for Speakers<N> {
     }
 }
 
+impl<const N: usize> Default for Speakers<N> {
+    /// Connect to the default speakers, or [null](Speakers::null) speakers at
+    /// 48 kHz if they can't be opened.  Use
+    /// [`try_default()`](Speakers::try_default) to handle the error instead.
+    fn default() -> Self {
+        Self::try_default()
+            .unwrap_or_else(|_| Self::null(consts::SAMPLE_RATE.into()))
+    }
+}
+
 impl Speakers<0> {
     /// Query available audio destinations.
     pub fn query() -> Vec<Self> {
-        ffi::device_list(Self)
+        Self::query_all().into_iter().flatten().collect()
+    }
+
+    /// Query available audio destinations, including ones that were found but
+    /// couldn't be opened (with the device's name and the reason).
+    pub fn query_all() -> Vec<std::result::Result<Self, (String, AudioError)>> {
+        ffi::device_list(|speakers| Self(Backend::Ffi(speakers), Rc::default()))
+    }
+
+    /// List available audio destinations without opening them, which is faster
+    /// than [`query()`](Self::query) and doesn't make them busy for other
+    /// programs.  Open one with [`find_by_id()`](Self::find_by_id).
+    ///
+    /// On Linux, PipeWire nodes or ALSA device hints (chosen with `filter`)
+    /// are listed.  Other platforms open each device to list it.
+    pub fn list(filter: DeviceFilter) -> Vec<DeviceInfo> {
+        match ffi::device_names(false, filter) {
+            Some(names) => names
+                .iter()
+                .map(|(id, name)| DeviceInfo::new(id, name))
+                .collect(),
+            None => Self::query()
+                .iter()
+                .map(|device| DeviceInfo::new(device.0.id(), device.0.name()))
+                .collect(),
+        }
+    }
+
+    /// Find the device with a saved `id` (from [`id()`](Self::id) or
+    /// [`DeviceInfo::id()`]), or `None` if it isn't connected.
+    pub fn find_by_id(id: &DeviceId) -> Option<Self> {
+        Self::open(id.as_str()).ok()
+    }
+
+    /// Get the bitflags for numbers of channels (which of 1-8 are supported).
+    pub(crate) fn supported(&self) -> u8 {
+        self.0.channels()
+    }
+
+    /// Watch for speakers being plugged in or unplugged.  `interval` is how often
+    /// to check on platforms that can't notify when devices change.
+    pub fn watch(interval: Duration) -> DeviceWatcher<Self> {
+        DeviceWatcher::new(interval)
     }
 }
 
 impl<const N: usize> Speakers<N> {
+    /// Try to connect to the default speakers, returning an error if there are
+    /// none, they're busy, or they're unsupported.
+    ///
+    /// With PipeWire, the speakers follow the system default as it changes.
+    /// On other platforms they stay on the device that was the default when
+    /// opened; reopen them on
+    /// [`DeviceEvent::DefaultChanged`](crate::DeviceEvent::DefaultChanged).
+    pub fn try_default() -> std::result::Result<Self, AudioError> {
+        ffi::Speakers::try_default()
+            .map(|speakers| Self(Backend::Ffi(speakers), Rc::default()))
+    }
+
+    /// Open the speakers named `name`, returning an error if there are none,
+    /// they're busy, or they're unsupported.
+    ///
+    /// On Linux, `name` can be an ALSA PCM name (such as `hw:CARD=USB,DEV=0`),
+    /// which is opened directly rather than querying every device, and can
+    /// be a PCM that isn't listed (such as a `dsnoop` or `loopback` plugin).
+    /// Otherwise, the device with `name` as its ID or name is opened.
+    pub fn open(name: &str) -> std::result::Result<Self, AudioError> {
+        match ffi::Speakers::open(name) {
+            Err(AudioError::NoDevice) => {}
+            result => {
+                return result.map(|speakers| {
+                    Self(Backend::Ffi(speakers), Rc::default())
+                })
+            }
+        }
+        for device in Speakers::query_all() {
+            match device {
+                Ok(device)
+                    if device.0.id() == name || device.0.name() == name =>
+                {
+                    return Ok(Self(device.0, Rc::default()));
+                }
+                Err((device, error)) if device == name => return Err(error),
+                _ => {}
+            }
+        }
+        Err(AudioError::NoDevice)
+    }
+
+    /// Connect to null speakers, which discard audio at the rate it would be
+    /// played at `sample_rate`.  Useful for running without a sound card, such
+    /// as on CI servers:
+    ///
+    /// ```
+    /// use wavy::Speakers;
+    ///
+    /// let speakers: Speakers<2> =
+    ///     Speakers::try_default().unwrap_or_else(|_| Speakers::null(48_000));
+    /// ```
+    ///
+    /// # Panics
+    /// If `sample_rate` is zero, or on the web (where threads can't be
+    /// spawned).
+    pub fn null(sample_rate: u32) -> Self {
+        Self(
+            Backend::Null(null::Speakers::new(sample_rate)),
+            Rc::default(),
+        )
+    }
+
+    /// Use mock speakers, from [`MockSpeakers`](crate::MockSpeakers).
+    #[cfg(feature = "testing")]
+    pub(crate) fn mock(speakers: mock::Speakers) -> Self {
+        Self(Backend::Mock(speakers), Rc::default())
+    }
+
+    /// Get the stable ID of this device, for reconnecting to it later.
+    pub fn id(&self) -> DeviceId {
+        DeviceId::new(self.0.id())
+    }
+
+    /// Get the human-readable name of this device.
+    pub fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    /// Get the sample format the device was opened with.
+    pub fn format(&self) -> SampleFormat {
+        self.0.format()
+    }
+
+    /// Get the number of underruns since the device was opened.
+    ///
+    /// An underrun happens when the sink isn't filled in time, so the device
+    /// plays silence (an audible gap).  A growing count means the task
+    /// playing audio is too slow, or the latency is too low.
+    pub fn underruns(&self) -> u64 {
+        self.0.xruns() as u64
+    }
+
+    /// Get the speaker position of each channel of the device, in order.
+    ///
+    /// This is the map set with
+    /// [`set_channel_map()`](Speakers::set_channel_map), or else the one
+    /// reported by the device once configured (by the first sink).  It's
+    /// empty if neither is known; only ALSA reports channel maps.
+    pub fn channel_map(&self) -> Vec<SpeakerPosition> {
+        let map = self.1.channel_map.borrow();
+        if map.is_empty() {
+            self.0.channel_map()
+        } else {
+            map.clone()
+        }
+    }
+
+    /// Set the speaker position of each channel of the device, for drivers
+    /// that report the wrong map or none.  An empty `map` goes back to the
+    /// device's.
+    ///
+    /// Frame channels are moved to the device channels at the same positions
+    /// (the surround channels of 5.1 fit either rear or side speakers).  Maps
+    /// that don't fit the frame type leave the channels in order.
+    pub fn set_channel_map(&mut self, map: &[SpeakerPosition]) {
+        *self.1.channel_map.borrow_mut() = map.to_vec();
+        self.1.remap.replace(None);
+    }
+
+    /// Get the number of times the device recovered from being suspended
+    /// (such as by the system sleeping) since it was opened.
+    ///
+    /// If it can't recover, polling produces
+    /// `AudioError::InvalidState(StreamState::Suspended)`, and the device
+    /// should be opened again.  Only ALSA reports suspends.
+    pub fn suspends(&self) -> u64 {
+        self.0.suspends() as u64
+    }
+
+    /// Mix frames down for speakers that don't support their number of
+    /// channels (5.1 to stereo or mono, and stereo to mono), instead of
+    /// failing with [`AudioError::UnsupportedChannelCount`].  Off (`None`)
+    /// by default.
+    pub fn set_downmix(&mut self, downmix: Option<Downmix>) {
+        self.1.downmix.set(downmix);
+    }
+
+    /// Get the downmix set with [`set_downmix()`](Self::set_downmix).
+    pub fn downmix(&self) -> Option<Downmix> {
+        self.1.downmix.get()
+    }
+
+    /// Set what to do when the speakers underrun (by default, they recover).
+    pub fn set_xrun_policy(&mut self, policy: XrunPolicy) {
+        *self.1.xrun_policy.borrow_mut() = policy;
+    }
+
+    /// Get the sample rate the device actually runs at, which may differ from
+    /// the preferred 48 kHz.  This is `None` until the speakers are first
+    /// polled (and configured).
+    pub fn actual_sample_rate(&self) -> Option<f64> {
+        self.0.sample_rate()
+    }
+
+    /// Get the number of frames handed to the device since it was opened,
+    /// counting on through underruns.
+    ///
+    /// This includes frames that are still buffered, subtract
+    /// [`SpeakersSink::latency()`] for the frames that have been heard.
+    pub fn frames_played(&self) -> u64 {
+        self.1.frames.get()
+    }
+
+    /// Get [`frames_played()`](Self::frames_played) as a duration at the
+    /// [`actual_sample_rate()`](Self::actual_sample_rate).
+    pub fn duration_played(&self) -> Duration {
+        match self.0.sample_rate() {
+            Some(rate) => {
+                Duration::from_secs_f64(self.frames_played() as f64 / rate)
+            }
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Open a microphone that records what's played through the speakers
+    /// (by all programs, not only this one), for "what you hear" recording or
+    /// echo cancellation.  It runs at the speakers' sample rate, so it lines
+    /// up frame-for-frame when configured for the same number of channels.
+    ///
+    /// Only PipeWire has monitors to record from, on other platforms (and
+    /// with ALSA) this produces [`AudioError::NoDevice`].
+    pub fn loopback(&self) -> std::result::Result<Microphone<N>, AudioError> {
+        self.0.loopback().map(Microphone::ffi)
+    }
+
+    /// Mute the speakers, without interrupting the stream.  While muted, sinks
+    /// are still produced and streamed into, but silence is played.
+    ///
+    /// Muting applies to each sink as it's played, ramping like a gain
+    /// change.
+    pub fn mute(&mut self) {
+        self.1.muted.store(true, Ordering::Relaxed);
+    }
+
+    /// Unmute the speakers, muted with [`mute()`](Speakers::mute).
+    pub fn unmute(&mut self) {
+        self.1.muted.store(false, Ordering::Relaxed);
+    }
+
+    /// Mute or unmute the speakers, like [`mute()`](Speakers::mute) and
+    /// [`unmute()`](Speakers::unmute).
+    pub fn set_muted(&mut self, muted: bool) {
+        self.1.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Check if the speakers are muted.
+    pub fn is_muted(&self) -> bool {
+        self.1.muted.load(Ordering::Relaxed)
+    }
+
+    /// Set the gain (volume multiplier) for the following sinks, without
+    /// waiting for one.  This is the same gain as
+    /// [`SpeakersSink::set_gain()`], so it ramps the same way.
+    pub fn set_gain(&mut self, gain: f32) {
+        self.1.target.set(gain.max(0.0));
+    }
+
+    /// Get the gain set with [`set_gain()`](Speakers::set_gain) or
+    /// [`SpeakersSink::set_gain()`].
+    pub fn gain(&self) -> f32 {
+        self.1.target.get()
+    }
+
+    /// Set whether audio that was already written finishes playing when the
+    /// speakers are dropped, instead of being cut off.  This is on by default,
+    /// and waits at most twice as long as the audio takes to play, in case the
+    /// device stopped responding.
+    ///
+    /// Only ALSA and PipeWire drain, other platforms always stop immediately.
+    pub fn set_drain_on_drop(&mut self, drain: bool) {
+        self.0.set_drain(drain);
+    }
+
+    /// Pause playback without closing the device, so it can be resumed
+    /// within a period.  While paused, no sinks are produced (and the notifier
+    /// doesn't wake).
+    ///
+    /// ALSA devices are paused with `snd_pcm_pause()` if the hardware supports
+    /// it, otherwise they're stopped (dropping the audio queued in the device)
+    /// and prepared again on resume.  PipeWire streams are deactivated.  On
+    /// other platforms the device keeps running, playing silence.
+    pub fn pause(&mut self) {
+        if !self.1.paused.replace(true) {
+            self.0.pause();
+        }
+    }
+
+    /// Resume playback, paused with [`pause()`](Speakers::pause).
+    pub fn resume(&mut self) {
+        if self.1.paused.replace(false) {
+            self.0.resume();
+            if let Some(waker) = self.1.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Check if playback is paused.
+    pub fn is_paused(&self) -> bool {
+        self.1.paused.get()
+    }
+
+    /// Stop playing immediately, dropping the audio that's queued (the
+    /// opposite of draining), so the next sink starts fresh.  Playback goes
+    /// on with the next sink, within a period.
+    ///
+    /// ALSA devices are stopped with `snd_pcm_drop()` and prepared again, and
+    /// PipeWire streams drop their queue.  On other platforms, only audio
+    /// that wasn't handed to the device yet is dropped.
+    ///
+    /// Audio streamed into a sink that's still alive plays after the flush.
+    pub fn flush(&mut self) {
+        self.0.flush();
+    }
+
+    /// Request a period (the number of frames written to the device at a
+    /// time) of `frames`, and room for `count` periods in the device's
+    /// buffer.  Small periods lower latency, while large ones wake less often
+    /// (saving power).  The device is reconfigured for the next sink.
+    ///
+    /// The hardware may not support the exact sizes, so the nearest ones are
+    /// used; check them with [`period()`](Self::period) and
+    /// [`buffer_frames()`](Self::buffer_frames) once the sink is produced.
+    /// Only ALSA can be configured, other platforms (and PipeWire) pick the
+    /// period themselves.
+    pub fn set_period(&mut self, frames: u16, count: u8) {
+        self.0.set_period(frames, count);
+    }
+
+    /// Get the period the device was configured with, in frames (`0` until
+    /// the first sink is produced).
+    pub fn period(&self) -> u16 {
+        self.0.period()
+    }
+
+    /// Get the number of frames the device buffers, once configured.  This is
+    /// `None` on platforms that don't report it.
+    pub fn buffer_frames(&self) -> Option<usize> {
+        self.0.buffer_frames()
+    }
+
+    /// Get the capabilities of the device (channel counts, sample rates and
+    /// periods), for offering only the configurations it supports.  This
+    /// doesn't reconfigure the device, so it can be called while it's in use.
+    pub fn caps(&self) -> DeviceCaps {
+        self.0.caps()
+    }
+
+    /// Get the standard sample rates the device supports, for offering a
+    /// choice of rates (such as in a combo box).  See
+    /// [`DeviceCaps::standard_sample_rates()`].
+    pub fn supported_sample_rates(&self) -> Vec<u32> {
+        self.caps().standard_sample_rates().to_vec()
+    }
+
+    /// Get the numbers of channels the device supports (from 1 to 8), for
+    /// choosing a configuration with [`config()`](Self::config).
+    ///
+    /// This is empty while a sink from the device is alive, since the device
+    /// can't be reconfigured then.
+    pub fn supported_channels(&self) -> impl Iterator<Item = u8> {
+        let supported = self.0.channels();
+        (1..=8).filter(move |count| supported & (1 << (count - 1)) != 0)
+    }
+
+    /// Get the number of channels the device is playing, which is `0` until
+    /// the first sink is produced (the device is configured for the sink's
+    /// frame type then).
+    pub fn channel_count(&self) -> u8 {
+        self.1.channels.get()
+    }
+
     /// Try a reconfiguration of speakers.
     pub fn config<const C: usize>(
         self,
@@ -90,7 +787,7 @@ impl<const N: usize> Speakers<N> {
     {
         let bit = C - 1;
         if (self.0.channels() & (1 << bit)) != 0 {
-            Ok(Speakers(self.0))
+            Ok(Speakers(self.0, self.1))
         } else {
             Err(self)
         }
@@ -117,20 +814,338 @@ impl<const N: usize> Notifier for Speakers<N>
 where
     Speakers<N>: SpeakersProperties,
 {
-    type Event = SpeakersSink<<Self as SpeakersProperties>::Sample>;
+    type Event = std::result::Result<
+        SpeakersSink<<Self as SpeakersProperties>::Sample>,
+        AudioError,
+    >;
 
     fn poll_next(self: Pin<&mut Self>, e: &mut Exec<'_>) -> Poll<Self::Event> {
         let this = self.get_mut();
-        if let Ready(()) = Pin::new(&mut this.0).poll(e) {
-            Ready(SpeakersSink(this.0.play()))
-        } else {
-            Pending
+        if this.1.paused.get() {
+            this.1.waker.set(Some(e.waker().clone()));
+            return Pending;
+        }
+        // Read before polling, since the device hides its channels while
+        // locked for the sink.
+        let supported = this.0.channels();
+        match Pin::new(&mut this.0).poll(e) {
+            Ready(Ok(())) => {
+                let sink = match this.1.downmix.get() {
+                    Some(downmix) if supported & (1 << (N - 1)) == 0 => {
+                        this.0.play_downmix(downmix, supported, &this.1)
+                    }
+                    _ => this.0.play().map(SinkBackend::Device),
+                };
+                let xruns = this.0.xruns();
+                let new = xruns.saturating_sub(this.1.xruns.replace(xruns));
+                if let Err(error) =
+                    this.1.xrun_policy.borrow().apply(new, XrunKind::Underrun)
+                {
+                    // Dropping the sink unlocks the speakers.
+                    drop(sink);
+                    return Ready(Err(error));
+                }
+                Ready(sink.map(|sink| {
+                    let channels = sink.channels();
+                    if this.1.channels.replace(channels) != channels {
+                        this.1.remap.replace(None);
+                    }
+                    this.1.remap.borrow_mut().get_or_insert_with(|| {
+                        speaker_position::remap(&this.channel_map(), N)
+                    });
+                    let loudness = this.1.loudness.take();
+                    SpeakersSink(sink, this.1.clone(), false, loudness, 0)
+                }))
+            }
+            Ready(Err(error)) => Ready(Err(error)),
+            Pending => Pending,
         }
     }
 }
 
 /// A sink that consumes audio samples and plays them through the speakers.
-pub struct SpeakersSink<F: Frame<Chan = Ch32>>(ffi::SpeakersSink<F>);
+pub struct SpeakersSink<F: Frame<Chan = Ch32>>(
+    SinkBackend<F>,
+    Rc<Playback>,
+    /// If the buffer was written to (so gain should be applied).
+    bool,
+    /// Loudness meter, returned to the speakers when dropped.
+    Option<Loudness>,
+    /// Index of the next frame for [`SpeakersSink::write_stereo()`] and
+    /// [`SpeakersSink::write_mono()`].
+    usize,
+);
+
+/// Sink of the speakers, or a sink mixed down to them.
+enum SinkBackend<F: Frame<Chan = Ch32>> {
+    Device(DeviceSink<F>),
+    Downmix(Mixer<F>),
+}
+
+impl<F: Frame<Chan = Ch32>> SinkBackend<F> {
+    /// Get the number of channels the device plays.
+    fn channels(&self) -> u8 {
+        match self {
+            SinkBackend::Device(_) => F::CHAN_COUNT as u8,
+            SinkBackend::Downmix(mixer) => match mixer.target {
+                MixTarget::Stereo(_) => 2,
+                MixTarget::Mono(_) => 1,
+            },
+        }
+    }
+
+    fn latency(&self) -> Latency {
+        match self {
+            SinkBackend::Device(sink) => sink.latency(),
+            SinkBackend::Downmix(mixer) => mixer.target.latency(),
+        }
+    }
+
+    fn sample_rate(&self) -> f64 {
+        match self {
+            SinkBackend::Device(sink) => sink.sample_rate(),
+            SinkBackend::Downmix(mixer) => mixer.target.sample_rate(),
+        }
+    }
+
+    fn resampler(&mut self) -> &mut Resampler<F> {
+        match self {
+            SinkBackend::Device(sink) => sink.resampler(),
+            SinkBackend::Downmix(mixer) => &mut mixer.state().resampler,
+        }
+    }
+
+    fn buffer(&mut self) -> &mut [F] {
+        match self {
+            SinkBackend::Device(sink) => sink.buffer(),
+            SinkBackend::Downmix(mixer) => &mut mixer.state().frames,
+        }
+    }
+}
+
+/// Sink for speakers from the platform, null speakers, or mock speakers.
+enum DeviceSink<F: Frame<Chan = Ch32>> {
+    Ffi(ffi::SpeakersSink<F>),
+    Null(null::SpeakersSink<F>),
+    #[cfg(feature = "testing")]
+    Mock(mock::SpeakersSink<F>),
+}
+
+impl<F: Frame<Chan = Ch32>> DeviceSink<F> {
+    fn latency(&self) -> Latency {
+        match self {
+            DeviceSink::Ffi(sink) => sink.latency(),
+            DeviceSink::Null(sink) => sink.latency(),
+            #[cfg(feature = "testing")]
+            DeviceSink::Mock(sink) => sink.latency(),
+        }
+    }
+
+    fn sample_rate(&self) -> f64 {
+        match self {
+            DeviceSink::Ffi(sink) => sink.sample_rate(),
+            DeviceSink::Null(sink) => sink.sample_rate(),
+            #[cfg(feature = "testing")]
+            DeviceSink::Mock(sink) => sink.sample_rate(),
+        }
+    }
+
+    fn resampler(&mut self) -> &mut Resampler<F> {
+        match self {
+            DeviceSink::Ffi(sink) => sink.resampler(),
+            DeviceSink::Null(sink) => sink.resampler(),
+            #[cfg(feature = "testing")]
+            DeviceSink::Mock(sink) => sink.resampler(),
+        }
+    }
+
+    fn buffer(&mut self) -> &mut [F] {
+        match self {
+            DeviceSink::Ffi(sink) => sink.buffer(),
+            DeviceSink::Null(sink) => sink.buffer(),
+            #[cfg(feature = "testing")]
+            DeviceSink::Mock(sink) => sink.buffer(),
+        }
+    }
+}
+
+/// Sink for frames mixed down to speakers with fewer channels.
+struct Mixer<F: Frame<Chan = Ch32>> {
+    /// Sink of the speakers, with the channels they support.
+    target: MixTarget,
+    /// Frames written to the sink and their resampler, returned to the
+    /// speakers when dropped.
+    state: Option<Box<MixState<F>>>,
+    /// Set with [`Speakers::set_downmix()`].
+    downmix: Downmix,
+    /// State of the speakers, to return the frames and resampler to.
+    playback: Rc<Playback>,
+}
+
+/// Frames and resampler of downmixed sinks, reused by each of them.
+struct MixState<F: Frame<Chan = Ch32>> {
+    /// Frames written to the sink, mixed into the target when dropped.
+    frames: Vec<F>,
+    /// Resampler for the frames.
+    resampler: Resampler<F>,
+}
+
+impl<F: Frame<Chan = Ch32>> Mixer<F> {
+    fn state(&mut self) -> &mut MixState<F> {
+        // Only taken when dropped.
+        self.state.as_mut().unwrap()
+    }
+}
+
+/// Sink of the speakers, mixed down to.
+enum MixTarget {
+    Stereo(DeviceSink<Stereo>),
+    Mono(DeviceSink<Mono>),
+}
+
+impl MixTarget {
+    fn latency(&self) -> Latency {
+        match self {
+            MixTarget::Stereo(sink) => sink.latency(),
+            MixTarget::Mono(sink) => sink.latency(),
+        }
+    }
+
+    fn sample_rate(&self) -> f64 {
+        match self {
+            MixTarget::Stereo(sink) => sink.sample_rate(),
+            MixTarget::Mono(sink) => sink.sample_rate(),
+        }
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Drop for Mixer<F> {
+    fn drop(&mut self) {
+        let state = match self.state.take() {
+            Some(state) => state,
+            None => return,
+        };
+        let downmix = self.downmix;
+        let frames = state.frames.iter();
+        match &mut self.target {
+            MixTarget::Stereo(sink) => {
+                for (from, to) in frames.zip(sink.buffer()) {
+                    downmix.mix(from.channels(), to.channels_mut());
+                }
+            }
+            MixTarget::Mono(sink) => {
+                for (from, to) in frames.zip(sink.buffer()) {
+                    downmix.mix(from.channels(), to.channels_mut());
+                }
+            }
+        }
+        self.playback.mix_state.set(Some(state));
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> SpeakersSink<F> {
+    /// Get the current playback latency: audio buffered but not yet played
+    /// plus the hardware delay.
+    pub fn latency(&self) -> Latency {
+        self.0.latency()
+    }
+
+    /// Set the gain (volume multiplier) for audio streamed into this sink, and
+    /// the following sinks from the same speakers.  `1.0` is unity gain.
+    /// Gains above `1.0` are allowed, but samples are clipped to the range of
+    /// [`Ch32`] (`-1.0` to `1.0`).  Negative gains are treated as `0.0`.
+    ///
+    /// Changes ramp over a few frames, to avoid zipper noise.
+    pub fn set_gain(&mut self, gain: f32) {
+        self.1.target.set(gain.max(0.0));
+    }
+
+    /// Get the gain set with [`set_gain()`](SpeakersSink::set_gain).
+    pub fn gain(&self) -> f32 {
+        self.1.target.get()
+    }
+
+    /// Get the number of frames written to the device by earlier sinks since
+    /// it was opened, which is the position of this sink's first frame.
+    pub fn frames_written(&self) -> u64 {
+        self.1.frames.get()
+    }
+
+    /// Get the loudness meter of the speakers, attaching one first if there
+    /// isn't one yet.
+    ///
+    /// The meter measures the audio played (after gain) when each sink is
+    /// dropped, so it doesn't include the audio of this sink yet.
+    pub fn attach_loudness_meter(&mut self) -> &mut Loudness {
+        let sample_rate = self.sample_rate();
+        self.3.get_or_insert_with(|| Loudness::new(sample_rate))
+    }
+
+    /// Write one stereo frame after the ones written before with
+    /// `write_stereo()` or [`write_mono()`](Self::write_mono), converted to
+    /// the sink's frame type.  Returns `false` (without writing) once the
+    /// buffer is full.
+    pub fn write_stereo(&mut self, left: f32, right: f32) -> bool {
+        self.write(Stereo::from_channels(&[left.into(), right.into()]))
+    }
+
+    /// Write one mono frame after the ones written before with
+    /// [`write_stereo()`](Self::write_stereo) or `write_mono()`, converted to
+    /// the sink's frame type.  Returns `false` (without writing) once the
+    /// buffer is full.
+    pub fn write_mono(&mut self, sample: f32) -> bool {
+        self.write(Mono::from_channels(&[sample.into()]))
+    }
+
+    /// Write `frame` at the next index.
+    fn write<G: Frame<Chan = Ch32>>(&mut self, frame: G) -> bool {
+        let index = self.4;
+        match self.buffer().get_mut(index) {
+            Some(slot) => {
+                *slot = frame.convert();
+                self.4 += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Apply the gain ramp (or mute) to the buffer.
+    fn apply_gain(&mut self) {
+        // Audio that wasn't written to the sink already had gain applied,
+        // unless it needs to be silenced.
+        let muted = self.1.muted.load(Ordering::Relaxed);
+        if !self.2 && !muted {
+            return;
+        }
+        // Muting ramps down like a gain change, to avoid clicks.
+        let target = if muted { 0.0 } else { self.1.target.get() };
+        let current = self.1.current.get();
+        // Unity gain doesn't clip, to leave the audio untouched.
+        if target == 1.0 && current == 1.0 {
+            return;
+        }
+        let step = (target - current) / RAMP as f32;
+        let buffer = self.buffer();
+        let frames = buffer.len();
+        for (i, frame) in buffer.iter_mut().enumerate() {
+            let gain = if i < RAMP {
+                current + step * (i + 1) as f32
+            } else {
+                target
+            };
+            for chan in frame.channels_mut() {
+                let sample = f32::from(*chan) * gain;
+                *chan = Ch32::new(sample.clamp(-1.0, 1.0));
+            }
+        }
+        self.1.current.set(if frames < RAMP {
+            current + step * frames as f32
+        } else {
+            target
+        });
+    }
+}
 
 impl<F: Frame<Chan = Ch32>> Debug for SpeakersSink<F> {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> Result {
@@ -148,6 +1163,165 @@ impl<F: Frame<Chan = Ch32>> Sink<F> for SpeakersSink<F> {
     }
 
     fn buffer(&mut self) -> &mut [F] {
+        self.2 = true;
         self.0.buffer()
     }
 }
+
+impl<F: Frame<Chan = Ch32>> Drop for SpeakersSink<F> {
+    fn drop(&mut self) {
+        // The whole buffer is handed to the device, even if not written to.
+        let written = self.0.buffer().len();
+        self.1.frames.set(self.1.frames.get() + written as u64);
+
+        self.apply_gain();
+        if let Some(mut loudness) = self.3.take() {
+            loudness.measure(self.0.buffer());
+            self.1.loudness.set(Some(loudness));
+        }
+        // Move channels to the device's speaker positions.
+        let remap = self.1.remap.borrow();
+        if let Some(remap) = remap.as_ref().filter(|remap| !remap.is_empty()) {
+            for frame in self.0.buffer().iter_mut() {
+                let channels = *frame;
+                for (chan, &i) in frame.channels_mut().iter_mut().zip(remap) {
+                    *chan = channels.channels()[i];
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use fon::mono::Mono32;
+
+    use super::*;
+    use crate::{testing::poll_next, MockSpeakers};
+
+    /// Play `periods` sinks of full scale audio, setting the gain with
+    /// `gain` before each one, and return the samples played.
+    fn play(gain: impl Fn(usize, &mut Speakers<1>)) -> Vec<f32> {
+        let mut mock = MockSpeakers::<Mono32>::new(48_000);
+        let mut speakers: Speakers<1> = mock.speakers();
+        for period in 0..6 {
+            gain(period, &mut speakers);
+            let mut sink = match poll_next(&mut speakers) {
+                Ready(Ok(sink)) => sink,
+                _ => panic!("mock speakers not ready"),
+            };
+            for frame in sink.buffer() {
+                *frame = Mono32::from_channels(&[Ch32::new(1.0)]);
+            }
+        }
+        drop(speakers);
+        mock.into_audio()
+            .iter()
+            .map(|frame| f32::from(frame.channels()[0]))
+            .collect()
+    }
+
+    /// Check that no step between samples is steeper than the ramp.
+    fn assert_ramped(samples: &[f32], max_gain_change: f32) {
+        let slope = max_gain_change / RAMP as f32 + 1e-6;
+        for (i, pair) in samples.windows(2).enumerate() {
+            let step = (pair[1] - pair[0]).abs();
+            assert!(step <= slope, "step of {} at frame {}", step, i + 1);
+        }
+    }
+
+    #[test]
+    fn gain_change_ramps() {
+        let samples = play(|period, speakers| match period {
+            2 => speakers.set_gain(0.25),
+            4 => speakers.set_gain(1.0),
+            _ => {}
+        });
+        assert_ramped(&samples, 0.75);
+        let period = usize::from(consts::PERIOD);
+        // Unity gain before the change, and the target after the ramp.
+        assert_eq!(samples[2 * period - 1], 1.0);
+        assert_eq!(samples[2 * period + RAMP - 1], 0.25);
+        assert_eq!(samples[4 * period - 1], 0.25);
+        assert_eq!(samples[4 * period + RAMP - 1], 1.0);
+        // The ramp starts right at the change.
+        assert!(samples[2 * period] < 1.0);
+    }
+
+    #[test]
+    fn mute_ramps() {
+        let samples = play(|period, speakers| match period {
+            2 => speakers.mute(),
+            4 => speakers.unmute(),
+            _ => {}
+        });
+        assert_ramped(&samples, 1.0);
+        let period = usize::from(consts::PERIOD);
+        assert_eq!(samples[2 * period + RAMP - 1], 0.0);
+        assert_eq!(samples[4 * period - 1], 0.0);
+        assert_eq!(samples[4 * period + RAMP - 1], 1.0);
+    }
+
+    #[test]
+    fn starved_sinks_underrun() {
+        let mut mock = MockSpeakers::<Mono32>::new(48_000);
+        let mut speakers: Speakers<1> = mock.speakers();
+        assert!(matches!(poll_next(&mut speakers), Ready(Ok(_))));
+        assert_eq!(speakers.underruns(), 0);
+
+        // Recovered by default, but counted.
+        mock.starve();
+        assert!(matches!(poll_next(&mut speakers), Ready(Ok(_))));
+        assert_eq!(speakers.underruns(), 1);
+
+        // Reported once, then playback keeps going.
+        speakers.set_xrun_policy(XrunPolicy::Fail);
+        mock.starve();
+        assert!(matches!(
+            poll_next(&mut speakers),
+            Ready(Err(AudioError::Xrun))
+        ));
+        assert!(matches!(poll_next(&mut speakers), Ready(Ok(_))));
+        assert_eq!(speakers.underruns(), 2);
+
+        // Silence is played for each starved period (and the sink dropped
+        // with the error).
+        drop(speakers);
+        let period = usize::from(consts::PERIOD);
+        assert_eq!(mock.into_audio().len(), 6 * period);
+    }
+
+    #[test]
+    fn surround_downmixed_to_stereo() {
+        use fon::{stereo::Stereo32, surround::Surround32};
+
+        let mut mock = MockSpeakers::<Stereo32>::new(48_000);
+        let mut speakers: Speakers<6> = mock.speakers();
+        // Strict by default.
+        assert!(matches!(
+            poll_next(&mut speakers),
+            Ready(Err(AudioError::UnsupportedChannelCount { .. }))
+        ));
+        speakers.set_downmix(Some(Downmix::default()));
+        let channels = [0.25, -0.25, 0.5, 1.0, 0.5, 0.0].map(Ch32::new);
+        for _ in 0..2 {
+            let mut sink = match poll_next(&mut speakers) {
+                Ready(Ok(sink)) => sink,
+                _ => panic!("mock speakers not ready"),
+            };
+            assert_eq!(speakers.channel_count(), 2);
+            for frame in sink.buffer() {
+                *frame = Surround32::from_channels(&channels);
+            }
+        }
+        drop(speakers);
+        let half = 0.5 * std::f32::consts::FRAC_1_SQRT_2;
+        let audio = mock.into_audio();
+        assert_eq!(audio.len(), 2 * usize::from(consts::PERIOD));
+        for frame in audio.iter() {
+            let [left, right] = [0, 1].map(|i| f32::from(frame.channels()[i]));
+            assert!((left - (0.25 + half + half)).abs() < 1e-6, "{}", left);
+            assert!((right - (-0.25 + half)).abs() < 1e-6, "{}", right);
+        }
+    }
+}
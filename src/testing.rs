@@ -0,0 +1,245 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use std::{
+    fmt::{Debug, Formatter, Result},
+    marker::PhantomData,
+    rc::Rc,
+};
+
+use fon::{
+    chan::{Ch32, Channel},
+    Audio, Frame,
+};
+
+use crate::{
+    consts::PERIOD,
+    memory::{self, End},
+    mock, Microphone, Speakers,
+};
+
+/// Microphone that records audio given ahead of time, for testing code that
+/// uses a [`Microphone`] without hardware.
+///
+/// The microphone is always ready, so streams are produced without waiting.
+/// Each stream contains up to one period of the remaining frames.
+///
+/// ```
+/// use fon::{mono::Mono32, Audio};
+/// use wavy::{Microphone, MockMicrophone};
+///
+/// let audio = Audio::<Mono32>::with_silence(48_000, 1024);
+/// let microphone: Microphone<1> =
+///     MockMicrophone::from_frames(audio).microphone();
+/// ```
+///
+/// To feed a recording through code under test, read it from a WAV file with
+/// a [`FileSource`](crate::FileSource).
+pub struct MockMicrophone(memory::Microphone);
+
+impl Debug for MockMicrophone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_str("MockMicrophone")
+    }
+}
+
+impl MockMicrophone {
+    /// Create a mock microphone that records `audio`, at its sample rate.
+    ///
+    /// The microphone must be used with the same number of channels as `F`.
+    pub fn from_frames<F: Frame<Chan = Ch32>>(audio: Audio<F>) -> Self {
+        let samples = audio
+            .iter()
+            .flat_map(|frame| frame.channels().to_vec())
+            .collect();
+        Self(memory::Microphone::new(
+            "Mock",
+            audio.sample_rate(),
+            F::CHAN_COUNT as u8,
+            samples,
+            End::Panic,
+        ))
+    }
+
+    /// Start over from the first frame after the last one is recorded,
+    /// instead of running out.
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.0.set_end(if looping { End::Loop } else { End::Panic });
+        self
+    }
+
+    /// Use the mock as a [`Microphone`].
+    ///
+    /// # Panics
+    /// When polled after all of the audio was recorded (unless looping).
+    pub fn microphone<const N: usize>(self) -> Microphone<N> {
+        Microphone::memory(self.0)
+    }
+}
+
+/// Speakers that keep played audio in memory, for testing code that uses
+/// [`Speakers`] without hardware.
+///
+/// The speakers are always ready, so sinks are produced without waiting.
+/// Each sink plays one period, which is silent where it wasn't written.
+///
+/// ```
+/// use fon::stereo::Stereo32;
+/// use wavy::{MockSpeakers, Speakers};
+///
+/// let mut mock = MockSpeakers::<Stereo32>::new(48_000);
+/// let speakers: Speakers<2> = mock.speakers();
+/// // Play audio with `speakers`…
+/// let audio = mock.into_audio();
+/// ```
+///
+/// For rendering offline to a WAV file, use a [`FileSink`](crate::FileSink)
+/// instead.
+pub struct MockSpeakers<F: Frame<Chan = Ch32>> {
+    /// Sample rate the speakers play at.
+    sample_rate: u32,
+    /// Speakers, until taken with [`speakers()`](MockSpeakers::speakers).
+    speakers: Option<mock::Speakers>,
+    /// State shared with the speakers.
+    shared: Rc<mock::Shared>,
+    _phantom: PhantomData<F>,
+}
+
+impl<F: Frame<Chan = Ch32>> Debug for MockSpeakers<F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "MockSpeakers(rate: {})", self.sample_rate)
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> MockSpeakers<F> {
+    /// Create mock speakers that play audio at `sample_rate`.
+    ///
+    /// # Panics
+    /// If `sample_rate` is zero.
+    pub fn new(sample_rate: u32) -> Self {
+        Self::with_capacity(sample_rate, 0)
+    }
+
+    /// Create mock speakers that play audio at `sample_rate`, with room for
+    /// `frames` frames of played audio.  Playing up to that many frames
+    /// doesn't allocate, for checking that code playing audio doesn't.
+    ///
+    /// # Panics
+    /// If `sample_rate` is zero.
+    pub fn with_capacity(sample_rate: u32, frames: usize) -> Self {
+        let (speakers, shared) =
+            mock::Speakers::new(sample_rate, F::CHAN_COUNT as u8, frames);
+        Self {
+            sample_rate,
+            speakers: Some(speakers),
+            shared,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Use the mock as [`Speakers`], which must have the same number of
+    /// channels as `F`.
+    ///
+    /// # Panics
+    /// If called more than once.
+    pub fn speakers<const N: usize>(&mut self) -> Speakers<N> {
+        let speakers = self
+            .speakers
+            .take()
+            .expect("MockSpeakers::speakers() called more than once");
+        Speakers::mock(speakers)
+    }
+
+    /// Starve the speakers for one period, as if the next sink wasn't filled
+    /// in time: a period of silence is played and counted as an underrun.
+    pub fn starve(&self) {
+        let silence = F::CHAN_COUNT * usize::from(PERIOD);
+        let mut played = self.shared.played.borrow_mut();
+        let len = played.len() + silence;
+        played.resize(len, Ch32::MID);
+        self.shared.underruns.set(self.shared.underruns.get() + 1);
+    }
+
+    /// Get the audio played so far, at the speakers' sample rate.
+    pub fn into_audio(self) -> Audio<F> {
+        let frames: Vec<F> = self
+            .shared
+            .played
+            .borrow()
+            .chunks_exact(F::CHAN_COUNT)
+            .map(F::from_channels)
+            .collect();
+        Audio::with_frames(self.sample_rate, frames)
+    }
+}
+
+/// Poll `notifier` once without an executor, for tests with the mocks (which
+/// are always ready).
+#[cfg(test)]
+pub(crate) fn poll_next<N>(notifier: &mut N) -> std::task::Poll<N::Event>
+where
+    N: pasts::Notifier + Unpin,
+{
+    use std::{
+        pin::Pin,
+        sync::Arc,
+        task::{Context, Wake, Waker},
+    };
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    let waker = Waker::from(Arc::new(Noop));
+    Pin::new(notifier).poll_next(&mut Context::from_waker(&waker))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll::Ready;
+
+    use fon::mono::Mono32;
+
+    use super::*;
+
+    /// Count the frames recorded from `mock` in `periods` streams.
+    fn record(mock: MockMicrophone, periods: usize) -> usize {
+        let mut microphone: Microphone<1> = mock.microphone();
+        let mut frames = 0;
+        for _ in 0..periods {
+            match poll_next(&mut microphone) {
+                Ready(Ok(stream)) => frames += stream.frames().len(),
+                _ => panic!("mock microphone not ready"),
+            }
+        }
+        frames
+    }
+
+    /// Mock that records 100 frames of silence.
+    fn mock() -> MockMicrophone {
+        MockMicrophone::from_frames(Audio::<Mono32>::with_silence(48_000, 100))
+    }
+
+    #[test]
+    fn mock_microphone_loops() {
+        // 64 frames, then 36, twice.
+        assert_eq!(record(mock().with_looping(true), 4), 200);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "MockMicrophone ran out of frames (100 were provided)"
+    )]
+    fn mock_microphone_runs_out() {
+        // 64 frames, then 36, then nothing left.
+        record(mock(), 3);
+    }
+}
@@ -0,0 +1,307 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use std::{
+    collections::VecDeque,
+    fmt::{Debug, Formatter, Result},
+    time::Duration,
+};
+
+use pasts::prelude::*;
+
+use crate::{ffi, DeviceId, Microphone, Speakers};
+
+/// An audio device was plugged in or unplugged.
+#[derive(Debug)]
+pub enum DeviceEvent<D> {
+    /// A device was connected (or was already connected when watching
+    /// started).
+    Added(D),
+    /// A device was disconnected.  It can't be opened anymore, so only the ID
+    /// is given (compare with [`Speakers::id()`] or [`Microphone::id()`]).
+    Removed(DeviceId),
+    /// The system default device changed to the device with this ID (also
+    /// produced when watching starts).  The `"default"` device follows it, so
+    /// this is for updating a device picker.
+    ///
+    /// Only produced on platforms that report the default device (PipeWire,
+    /// Windows and macOS).
+    DefaultChanged(DeviceId),
+}
+
+/// Notifier that produces a [`DeviceEvent`] when audio devices are plugged in
+/// or unplugged.
+///
+/// On Linux this is driven by PipeWire (or `/dev/snd` changes), on other
+/// platforms the device list is checked again at an interval.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use pasts::{prelude::*, Join};
+/// use wavy::{DeviceEvent, DeviceWatcher, Speakers};
+///
+/// struct App {
+///     watcher: DeviceWatcher<Speakers<0>>,
+/// }
+///
+/// impl App {
+///     /// Speakers were plugged in or unplugged.
+///     fn event(&mut self, event: DeviceEvent<Speakers<0>>) -> Poll<()> {
+///         match event {
+///             DeviceEvent::Added(speakers) => println!("Added {}", speakers),
+///             DeviceEvent::Removed(id) => println!("Removed {}", id),
+///             DeviceEvent::DefaultChanged(id) => println!("Default {}", id),
+///         }
+///         Pending
+///     }
+///
+///     /// Program start.
+///     async fn main(_executor: Executor) {
+///         let watcher = Speakers::watch(Duration::from_secs(1));
+///         let mut app = App { watcher };
+///
+///         Join::new(&mut app).on(|s| &mut s.watcher, App::event).await;
+///     }
+/// }
+/// ```
+pub struct DeviceWatcher<D> {
+    watcher: ffi::Watcher,
+    /// IDs of devices that have been reported as added.
+    known: Vec<DeviceId>,
+    /// ID of the default device, when it was last checked.
+    default: Option<DeviceId>,
+    /// Events waiting to be returned.
+    events: VecDeque<DeviceEvent<D>>,
+    /// If the initial device list has been queried.
+    started: bool,
+    /// Minimum number of channels devices must support.
+    min_channels: u8,
+    /// Lowercase text device names must contain.
+    name_contains: String,
+}
+
+impl<D> Debug for DeviceWatcher<D> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "DeviceWatcher({:?})", self.known)
+    }
+}
+
+impl<D> DeviceWatcher<D> {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            watcher: ffi::Watcher::new(interval),
+            known: Vec::new(),
+            default: None,
+            events: VecDeque::new(),
+            started: false,
+            min_channels: 0,
+            name_contains: String::new(),
+        }
+    }
+
+    /// Only produce devices that support at least `channels` channels (for
+    /// example, `6` for 5.1 surround).
+    pub fn with_min_channels(mut self, channels: u8) -> Self {
+        self.min_channels = channels;
+        self
+    }
+
+    /// Only produce devices with names that contain `text`, ignoring case.
+    pub fn with_name_contains(mut self, text: impl Into<String>) -> Self {
+        self.name_contains = text.into().to_lowercase();
+        self
+    }
+
+    /// Check if a device with `name`, supporting the `supported` bitflags of
+    /// channel counts, passes the filters.
+    fn matches(&self, name: &str, supported: u8) -> bool {
+        let max_channels = 8 - supported.leading_zeros() as u8;
+        max_channels >= self.min_channels
+            && name.to_lowercase().contains(&self.name_contains)
+    }
+
+    fn poll_devices(
+        &mut self,
+        e: &mut Exec<'_>,
+        input: bool,
+        query: fn() -> Vec<D>,
+        id: fn(&D) -> DeviceId,
+        name: fn(&D) -> &str,
+        supported: fn(&D) -> u8,
+    ) -> Poll<DeviceEvent<D>> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ready(event);
+            }
+            if self.started && self.watcher.poll(e).is_pending() {
+                return Pending;
+            }
+            self.started = true;
+
+            // Compare the new device list to the last one, ignoring devices
+            // that don't pass the filters.
+            let devices: Vec<D> = query()
+                .into_iter()
+                .filter(|device| self.matches(name(device), supported(device)))
+                .collect();
+            let ids: Vec<DeviceId> = devices.iter().map(id).collect();
+            for old in self.known.iter() {
+                if !ids.contains(old) {
+                    self.events.push_back(DeviceEvent::Removed(old.clone()));
+                }
+            }
+            for device in devices {
+                if !self.known.contains(&id(&device)) {
+                    self.events.push_back(DeviceEvent::Added(device));
+                }
+            }
+            self.known = ids;
+
+            let default = ffi::default_id(input).map(|id| DeviceId::new(&id));
+            if let Some(id) = default
+                .as_ref()
+                .filter(|&id| self.default.as_ref() != Some(id))
+            {
+                self.events
+                    .push_back(DeviceEvent::DefaultChanged(id.clone()));
+            }
+            self.default = default;
+        }
+    }
+}
+
+impl Notifier for DeviceWatcher<Speakers<0>> {
+    type Event = DeviceEvent<Speakers<0>>;
+
+    fn poll_next(self: Pin<&mut Self>, e: &mut Exec<'_>) -> Poll<Self::Event> {
+        self.get_mut().poll_devices(
+            e,
+            false,
+            Speakers::query,
+            Speakers::id,
+            Speakers::name,
+            Speakers::supported,
+        )
+    }
+}
+
+impl Notifier for DeviceWatcher<Microphone<0>> {
+    type Event = DeviceEvent<Microphone<0>>;
+
+    fn poll_next(self: Pin<&mut Self>, e: &mut Exec<'_>) -> Poll<Self::Event> {
+        self.get_mut().poll_devices(
+            e,
+            true,
+            Microphone::query,
+            Microphone::id,
+            Microphone::name,
+            Microphone::supported,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        sync::Arc,
+        task::{Context, Wake, Waker},
+    };
+
+    use super::*;
+
+    /// Device with a name, supporting bitflags of channel counts.
+    type Device = (&'static str, u8);
+
+    thread_local! {
+        static DEVICES: RefCell<Vec<Device>> = RefCell::default();
+    }
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// Check the device list again, now with `devices`, returning the names of
+    /// added devices and the IDs of removed ones.
+    fn scan(
+        watcher: &mut DeviceWatcher<Device>,
+        devices: &[Device],
+    ) -> (Vec<&'static str>, Vec<String>) {
+        DEVICES.with(|list| *list.borrow_mut() = devices.to_vec());
+        // Skip waiting for the platform to report a change.
+        watcher.started = false;
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+        let (mut added, mut removed) = (Vec::new(), Vec::new());
+        while let Ready(event) = watcher.poll_devices(
+            &mut cx,
+            false,
+            || DEVICES.with(|list| list.borrow().clone()),
+            |device| DeviceId::new(device.0),
+            |device| device.0,
+            |device| device.1,
+        ) {
+            match event {
+                DeviceEvent::Added(device) => added.push(device.0),
+                DeviceEvent::Removed(id) => removed.push(id.as_str().into()),
+                DeviceEvent::DefaultChanged(_) => {}
+            }
+        }
+        (added, removed)
+    }
+
+    fn watcher() -> DeviceWatcher<Device> {
+        DeviceWatcher::new(Duration::from_secs(3600))
+    }
+
+    #[test]
+    fn matches_channels() {
+        // Mono and stereo, and mono, stereo and 5.1 surround.
+        let (stereo, surround) = (0b11, 0b100011);
+        let watcher = watcher().with_min_channels(2);
+        assert!(!watcher.matches("Speakers", 0b1));
+        assert!(watcher.matches("Speakers", stereo));
+        assert!(watcher.matches("Speakers", surround));
+        let watcher = watcher.with_min_channels(6);
+        assert!(!watcher.matches("Speakers", stereo));
+        assert!(watcher.matches("Speakers", surround));
+        // No filter passes devices that don't report any channels.
+        assert!(self::watcher().matches("Speakers", 0));
+    }
+
+    #[test]
+    fn matches_name_ignoring_case() {
+        let watcher = watcher().with_name_contains("USB");
+        assert!(watcher.matches("USB Audio", 0b11));
+        assert!(watcher.matches("Headset (usb)", 0b11));
+        assert!(watcher.matches("uSb", 0b11));
+        assert!(!watcher.matches("HDMI Output", 0b11));
+        assert!(!watcher.matches("US B", 0b11));
+    }
+
+    #[test]
+    fn filtered_devices_not_reported() {
+        let mut watcher =
+            watcher().with_min_channels(2).with_name_contains("usb");
+        let usb = ("USB Audio", 0b11);
+        let mono = ("USB Mono", 0b1);
+        let hdmi = ("HDMI", 0b100011);
+        assert_eq!(
+            scan(&mut watcher, &[usb, mono, hdmi]),
+            (vec![usb.0], vec![])
+        );
+        // Unplugging devices that were filtered out isn't reported.
+        assert_eq!(scan(&mut watcher, &[usb]), (vec![], vec![]));
+        assert_eq!(scan(&mut watcher, &[]), (vec![], vec![usb.0.to_string()]));
+        assert_eq!(scan(&mut watcher, &[mono, hdmi]), (vec![], vec![]));
+    }
+}
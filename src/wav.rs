@@ -0,0 +1,798 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+use std::{
+    fmt::{Debug, Formatter, Result},
+    io::{self, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+};
+
+use fon::{chan::Ch32, Frame, Resampler, Sink, Stream};
+
+use crate::{
+    consts::PERIOD,
+    memory::{self, End},
+    microphone::MicrophoneProperties,
+    Microphone, SampleFormat,
+};
+
+/// Size of the RIFF header written before the samples.
+const HEADER: u64 = 44;
+
+/// Sink that writes audio to a WAV file instead of speakers, for rendering
+/// offline or running code that plays audio without a sound card.
+///
+/// Like a [`SpeakersSink`](crate::SpeakersSink), the sink holds one period of
+/// frames at its sample rate (streams at other rates are resampled into it).
+/// Each period is added to the file with
+/// [`write_period()`](FileSink::write_period), and the header is completed
+/// with the final length when the sink is finished or dropped.
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// use fon::{stereo::Stereo32, Audio, Sink};
+/// use wavy::{FileSink, SampleFormat};
+///
+/// let mut audio = Audio::<Stereo32>::with_silence(44_100, 44_100);
+/// let file = File::create("render.wav").unwrap();
+/// let mut sink =
+///     FileSink::<Stereo32, _>::new(file, 48_000, SampleFormat::S16).unwrap();
+/// while !audio.is_empty() {
+///     sink.stream(audio.drain());
+///     sink.write_period().unwrap();
+/// }
+/// sink.finish().unwrap();
+/// ```
+pub struct FileSink<F: Frame<Chan = Ch32>, W: Write + Seek> {
+    /// File being written, until finished.
+    writer: Option<W>,
+    /// Format of the samples in the file.
+    format: SampleFormat,
+    /// Sample rate of the file.
+    sample_rate: u32,
+    /// Bytes of samples written so far.
+    data: u64,
+    /// Resampler context for streams at other rates.
+    resampler: Resampler<F>,
+    /// One period of frames, written to the file as the next period.
+    buffer: Vec<F>,
+    /// If the buffer was handed out since it was last written.
+    written: bool,
+    /// Encoded samples of a period, reused for each one.
+    bytes: Vec<u8>,
+}
+
+impl<F: Frame<Chan = Ch32>, W: Write + Seek> Debug for FileSink<F, W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "FileSink(rate: {})", self.sample_rate)
+    }
+}
+
+impl<F: Frame<Chan = Ch32>, W: Write + Seek> FileSink<F, W> {
+    /// Start a WAV file in `writer` with samples in `format` (32-bit float,
+    /// or 16, 24 or 32-bit integer) at `sample_rate`.
+    pub fn new(
+        mut writer: W,
+        sample_rate: u32,
+        format: SampleFormat,
+    ) -> io::Result<Self> {
+        if sample_rate == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Sample rate is zero",
+            ));
+        }
+        writer.write_all(&header(format, F::CHAN_COUNT, sample_rate, 0)?)?;
+        Ok(Self {
+            writer: Some(writer),
+            format,
+            sample_rate,
+            data: 0,
+            resampler: Resampler::new(F::default(), 0.0),
+            buffer: vec![F::default(); PERIOD.into()],
+            written: false,
+            bytes: Vec::new(),
+        })
+    }
+
+    /// Add the buffer to the file as the next period (silent where nothing
+    /// was streamed into it), and clear it for the next one.
+    ///
+    /// Produces an error if writing fails, or if the file would be too long
+    /// for the sizes in a WAV header (4 GiB).
+    pub fn write_period(&mut self) -> io::Result<()> {
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        self.bytes.clear();
+        for frame in &self.buffer {
+            for &sample in frame.channels() {
+                encode(sample, self.format, &mut self.bytes);
+            }
+        }
+        let data = self.data + self.bytes.len() as u64;
+        // Check before writing, so the file stays valid.
+        riff_sizes(data)?;
+        writer.write_all(&self.bytes)?;
+        self.data = data;
+        self.buffer.fill(F::default());
+        self.written = false;
+        Ok(())
+    }
+
+    /// Get the number of frames written to the file so far.
+    pub fn frames_written(&self) -> u64 {
+        let size = sample_size(self.format) as u64;
+        self.data / (size * F::CHAN_COUNT as u64)
+    }
+
+    /// Write the last period (if anything was streamed into it), and
+    /// complete the header with the final length, returning the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.complete()?;
+        Ok(self.writer.take().unwrap())
+    }
+
+    /// Write the last period and the sizes in the header.
+    fn complete(&mut self) -> io::Result<()> {
+        if self.written {
+            self.write_period()?;
+        }
+        let (riff, data) = riff_sizes(self.data)?;
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        writer.seek(SeekFrom::Start(4))?;
+        writer.write_all(&riff.to_le_bytes())?;
+        writer.seek(SeekFrom::Start(HEADER - 4))?;
+        writer.write_all(&data.to_le_bytes())?;
+        writer.seek(SeekFrom::End(0))?;
+        writer.flush()
+    }
+}
+
+impl<F: Frame<Chan = Ch32>, W: Write + Seek> Sink<F> for FileSink<F, W> {
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate.into()
+    }
+
+    fn resampler(&mut self) -> &mut Resampler<F> {
+        &mut self.resampler
+    }
+
+    fn buffer(&mut self) -> &mut [F] {
+        self.written = true;
+        &mut self.buffer
+    }
+}
+
+impl<F: Frame<Chan = Ch32>, W: Write + Seek> Drop for FileSink<F, W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            // Errors can't be reported, use `finish()` to check for them.
+            let _ = self.complete();
+        }
+    }
+}
+
+/// Source that records audio from a WAV file instead of a microphone, for
+/// feeding test signals through code that records audio.
+///
+/// The file is read ahead of time and recorded with a [`Microphone`], so its
+/// audio comes in [`MicrophoneStream`](crate::MicrophoneStream)s of up to one
+/// period each, without waiting.  Once all of it is recorded, polling the
+/// microphone produces [`AudioError::EndOfStream`](crate::AudioError), unless
+/// it's [looping](FileSource::with_looping).
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// use wavy::{FileSource, Microphone};
+///
+/// let file = File::open("test.wav").unwrap();
+/// let microphone: Microphone<1> = FileSource::new(file)
+///     .unwrap()
+///     .with_sample_rate(48_000)
+///     .with_looping(true)
+///     .microphone();
+/// ```
+pub struct FileSource {
+    /// Audio read from the file.
+    wav: Wav,
+    /// Sample rate to record at.
+    sample_rate: u32,
+    /// Start over after the last frame, instead of ending.
+    looping: bool,
+}
+
+impl Debug for FileSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "FileSource(rate: {})", self.sample_rate)
+    }
+}
+
+impl FileSource {
+    /// Read a WAV file from `reader`, with samples in 32-bit float, or 16, 24
+    /// or 32-bit integer (the formats written by [`FileSink`]).
+    pub fn new<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let wav = decode(&bytes)?;
+        Ok(Self {
+            sample_rate: wav.sample_rate,
+            wav,
+            looping: false,
+        })
+    }
+
+    /// Get the number of channels in the file, which the microphone must be
+    /// used with.
+    pub fn channels(&self) -> u8 {
+        self.wav.channels
+    }
+
+    /// Get the sample rate the file is recorded at (by default, the file's).
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Record at `sample_rate`, resampling the file's audio with
+    /// `fon::Resampler` if it's at another rate.
+    ///
+    /// # Panics
+    /// If `sample_rate` is zero.
+    pub fn with_sample_rate(mut self, sample_rate: u32) -> Self {
+        assert_ne!(sample_rate, 0, "Sample rate must not be zero");
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Start over from the first frame after the last one is recorded,
+    /// instead of ending.
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// Record the file with a [`Microphone`], which must have the same number
+    /// of channels as the file.
+    pub fn microphone<const N: usize>(self) -> Microphone<N>
+    where
+        Microphone<N>: MicrophoneProperties,
+    {
+        let samples = if self.sample_rate == self.wav.sample_rate
+            || usize::from(self.wav.channels) != N
+        {
+            self.wav.samples
+        } else {
+            resample::<<Microphone<N> as MicrophoneProperties>::Sample>(
+                &self.wav,
+                self.sample_rate,
+            )
+        };
+        let end = if self.looping { End::Loop } else { End::Stop };
+        Microphone::memory(memory::Microphone::new(
+            "File",
+            self.sample_rate.into(),
+            self.wav.channels,
+            samples,
+            end,
+        ))
+    }
+}
+
+/// Resample the audio of `wav` (with `F` frames) to `sample_rate`, by
+/// streaming it into a sink at that rate.
+fn resample<F: Frame<Chan = Ch32>>(wav: &Wav, sample_rate: u32) -> Vec<Ch32> {
+    let frames = wav.samples.len() / F::CHAN_COUNT;
+    let ratio = f64::from(sample_rate) / f64::from(wav.sample_rate);
+    let mut sink = Resample {
+        sample_rate: sample_rate.into(),
+        resampler: Resampler::new(F::default(), 0.0),
+        buffer: vec![F::default(); (frames as f64 * ratio).round() as usize],
+    };
+    sink.stream(Frames {
+        samples: &wav.samples,
+        sample_rate: wav.sample_rate.into(),
+        index: 0,
+        _phantom: PhantomData::<F>,
+    });
+    sink.buffer
+        .iter()
+        .flat_map(|frame| frame.channels().iter().copied())
+        .collect()
+}
+
+/// Sink holding all of the frames of a resampled file.
+struct Resample<F: Frame<Chan = Ch32>> {
+    sample_rate: f64,
+    resampler: Resampler<F>,
+    buffer: Vec<F>,
+}
+
+impl<F: Frame<Chan = Ch32>> Debug for Resample<F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "Resample(rate: {})", self.sample_rate)
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Sink<F> for Resample<F> {
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    fn resampler(&mut self) -> &mut Resampler<F> {
+        &mut self.resampler
+    }
+
+    fn buffer(&mut self) -> &mut [F] {
+        &mut self.buffer
+    }
+}
+
+/// Stream of the frames in interleaved `samples`, at the file's rate.
+struct Frames<'a, F: Frame<Chan = Ch32>> {
+    samples: &'a [Ch32],
+    sample_rate: f64,
+    /// Index of the next frame.
+    index: usize,
+    _phantom: PhantomData<F>,
+}
+
+impl<F: Frame<Chan = Ch32>> Debug for Frames<'_, F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "Frames(rate: {})", self.sample_rate)
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Iterator for Frames<'_, F> {
+    type Item = F;
+
+    fn next(&mut self) -> Option<F> {
+        let start = self.index * F::CHAN_COUNT;
+        let frame = self.samples.get(start..start + F::CHAN_COUNT)?;
+        self.index += 1;
+        Some(F::from_channels(frame))
+    }
+}
+
+impl<F: Frame<Chan = Ch32>> Stream<F> for Frames<'_, F> {
+    fn sample_rate(&self) -> Option<f64> {
+        Some(self.sample_rate)
+    }
+
+    fn len(&self) -> Option<usize> {
+        Some(self.samples.len() / F::CHAN_COUNT - self.index)
+    }
+}
+
+/// Audio read from a WAV file.
+struct Wav {
+    /// Number of channels (1 to 8).
+    channels: u8,
+    /// Sample rate of the file.
+    sample_rate: u32,
+    /// Interleaved samples, whole frames only.
+    samples: Vec<Ch32>,
+}
+
+/// Read a WAV file from `bytes`, with samples in any [`SampleFormat`].
+fn decode(bytes: &[u8]) -> io::Result<Wav> {
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("Not a WAV file"));
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    let u32_at = |i: usize| {
+        u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]])
+    };
+
+    // Find the format and data chunks.
+    let mut format = None;
+    let mut data = None;
+    let mut i = 12;
+    while i + 8 <= bytes.len() {
+        let size = u32_at(i + 4) as usize;
+        let body = i + 8;
+        let end = body.saturating_add(size).min(bytes.len());
+        match &bytes[i..i + 4] {
+            b"fmt " if end - body >= 16 => {
+                let mut tag = u16_at(body);
+                // WAVE_FORMAT_EXTENSIBLE stores the tag in the sub-format.
+                if tag == 0xFFFE && end - body >= 26 {
+                    tag = u16_at(body + 24);
+                }
+                let channels = u16_at(body + 2);
+                let rate = u32_at(body + 4);
+                let bits = u16_at(body + 14);
+                format = Some((tag, channels, rate, bits));
+            }
+            b"data" => data = Some(body..end),
+            _ => {}
+        }
+        // Chunks are padded to an even size.
+        i = body.saturating_add(size).saturating_add(size & 1);
+    }
+    let (tag, channels, sample_rate, bits) =
+        format.ok_or_else(|| invalid("Missing fmt chunk"))?;
+    let data = data.ok_or_else(|| invalid("Missing data chunk"))?;
+    if !(1..=8).contains(&channels) {
+        return Err(invalid("Unsupported number of channels"));
+    }
+    if sample_rate == 0 {
+        return Err(invalid("Sample rate is zero"));
+    }
+    let format = match (tag, bits) {
+        (3, 32) => SampleFormat::F32,
+        (1, 16) => SampleFormat::S16,
+        (1, 24) => SampleFormat::S24,
+        (1, 32) => SampleFormat::S32,
+        _ => return Err(invalid("Unsupported sample format")),
+    };
+
+    // Drop a trailing partial frame.
+    let frame = sample_size(format) * usize::from(channels);
+    let data = &bytes[data];
+    let data = &data[..data.len() / frame * frame];
+    let samples = data
+        .chunks_exact(sample_size(format))
+        .map(|s| decode_sample(s, format))
+        .collect();
+
+    Ok(Wav {
+        channels: channels as u8,
+        sample_rate,
+        samples,
+    })
+}
+
+/// Get the number of bytes of each sample in `format`.
+fn sample_size(format: SampleFormat) -> usize {
+    match format {
+        SampleFormat::F32 | SampleFormat::S32 => 4,
+        SampleFormat::S24 => 3,
+        SampleFormat::S16 => 2,
+    }
+}
+
+/// Get the RIFF chunk size and data chunk size for `data` bytes of samples,
+/// or an error if they don't fit in a WAV header.
+fn riff_sizes(data: u64) -> io::Result<(u32, u32)> {
+    u32::try_from(data + HEADER - 8)
+        .ok()
+        .zip(u32::try_from(data).ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Audio too long for a WAV file",
+            )
+        })
+}
+
+/// Build the header of a WAV file with `data` bytes of samples.
+fn header(
+    format: SampleFormat,
+    channels: usize,
+    sample_rate: u32,
+    data: u64,
+) -> io::Result<[u8; HEADER as usize]> {
+    let (riff, data) = riff_sizes(data)?;
+    let tag: u16 = match format {
+        SampleFormat::F32 => 3,
+        _ => 1,
+    };
+    let size = sample_size(format) as u16;
+    let channels = channels as u16;
+    let align = channels * size;
+    let byte_rate = sample_rate
+        .checked_mul(align.into())
+        .ok_or_else(|| invalid("Sample rate too high for a WAV file"))?;
+
+    let mut header = [0; HEADER as usize];
+    header[..4].copy_from_slice(b"RIFF");
+    header[4..8].copy_from_slice(&riff.to_le_bytes());
+    header[8..16].copy_from_slice(b"WAVEfmt ");
+    header[16..20].copy_from_slice(&16u32.to_le_bytes());
+    header[20..22].copy_from_slice(&tag.to_le_bytes());
+    header[22..24].copy_from_slice(&channels.to_le_bytes());
+    header[24..28].copy_from_slice(&sample_rate.to_le_bytes());
+    header[28..32].copy_from_slice(&byte_rate.to_le_bytes());
+    header[32..34].copy_from_slice(&align.to_le_bytes());
+    header[34..36].copy_from_slice(&(size * 8).to_le_bytes());
+    header[36..40].copy_from_slice(b"data");
+    header[40..44].copy_from_slice(&data.to_le_bytes());
+    Ok(header)
+}
+
+/// Append `sample` to `bytes`, in `format`.
+fn encode(sample: Ch32, format: SampleFormat, bytes: &mut Vec<u8>) {
+    let sample = f64::from(f32::from(sample)).clamp(-1.0, 1.0);
+    match format {
+        SampleFormat::F32 => {
+            bytes.extend_from_slice(&(sample as f32).to_le_bytes())
+        }
+        SampleFormat::S16 => bytes.extend_from_slice(
+            &((sample * f64::from(i16::MAX)) as i16).to_le_bytes(),
+        ),
+        SampleFormat::S24 => bytes.extend_from_slice(
+            &((sample * 8_388_607.0) as i32).to_le_bytes()[..3],
+        ),
+        SampleFormat::S32 => bytes.extend_from_slice(
+            &((sample * f64::from(i32::MAX)) as i32).to_le_bytes(),
+        ),
+    }
+}
+
+/// Read a sample in `format` from the start of `s`.
+fn decode_sample(s: &[u8], format: SampleFormat) -> Ch32 {
+    Ch32::new(match format {
+        SampleFormat::F32 => f32::from_le_bytes([s[0], s[1], s[2], s[3]]),
+        SampleFormat::S16 => {
+            f32::from(i16::from_le_bytes([s[0], s[1]])) / f32::from(i16::MAX)
+        }
+        SampleFormat::S24 => {
+            (i32::from_le_bytes([0, s[0], s[1], s[2]]) >> 8) as f32
+                / 8_388_607.0
+        }
+        SampleFormat::S32 => {
+            i32::from_le_bytes([s[0], s[1], s[2], s[3]]) as f32
+                / i32::MAX as f32
+        }
+    })
+}
+
+/// Error for a WAV file that can't be read.
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use fon::stereo::Stereo32;
+
+    use super::*;
+
+    /// Write `periods` periods of a ramp to a WAV file in `format`, and read
+    /// it back.
+    fn round_trip(format: SampleFormat, periods: usize) -> (Vec<u8>, Wav) {
+        let mut sink = FileSink::<Stereo32, _>::new(
+            Cursor::new(Vec::new()),
+            44_100,
+            format,
+        )
+        .unwrap();
+        let mut value = 0.0;
+        for _ in 0..periods {
+            for frame in sink.buffer() {
+                *frame = Stereo32::from_channels(&[
+                    Ch32::new(value),
+                    Ch32::new(-value),
+                ]);
+                value += 1.0 / 256.0;
+            }
+            sink.write_period().unwrap();
+        }
+        assert_eq!(sink.frames_written(), (periods * PERIOD as usize) as u64);
+        let bytes = sink.finish().unwrap().into_inner();
+        let wav = decode(&bytes).unwrap();
+        (bytes, wav)
+    }
+
+    #[test]
+    fn file_sink_round_trip() {
+        for (format, error) in [
+            (SampleFormat::F32, 0.0),
+            (SampleFormat::S16, 1.0 / 32_767.0),
+            (SampleFormat::S24, 1.0 / 8_388_607.0),
+            (SampleFormat::S32, 1e-6),
+        ] {
+            let (bytes, wav) = round_trip(format, 3);
+            let frames = 3 * usize::from(PERIOD);
+            let data = frames * 2 * sample_size(format);
+            assert_eq!(bytes.len(), HEADER as usize + data);
+            // Sizes in the header match what was written.
+            let riff =
+                u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+            assert_eq!(riff as usize, bytes.len() - 8);
+            assert_eq!(wav.channels, 2);
+            assert_eq!(wav.sample_rate, 44_100);
+            assert_eq!(wav.samples.len(), frames * 2);
+            for (i, pair) in wav.samples.chunks(2).enumerate() {
+                let expected = i as f32 / 256.0;
+                let [left, right] = [pair[0], pair[1]].map(f32::from);
+                assert!((left - expected).abs() <= error, "{:?}", format);
+                assert!((right + expected).abs() <= error, "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn file_sink_finished_on_drop() {
+        let mut bytes = Vec::new();
+        {
+            let mut sink = FileSink::<Stereo32, _>::new(
+                Cursor::new(&mut bytes),
+                48_000,
+                SampleFormat::F32,
+            )
+            .unwrap();
+            // Streamed into, but not written yet.
+            sink.buffer()[0] =
+                Stereo32::from_channels(&[Ch32::new(0.5), Ch32::new(0.25)]);
+        }
+        let wav = decode(&bytes).unwrap();
+        assert_eq!(wav.samples.len(), 2 * usize::from(PERIOD));
+        assert_eq!(wav.samples[..2], [Ch32::new(0.5), Ch32::new(0.25)]);
+    }
+
+    #[test]
+    fn riff_sizes_checked() {
+        assert_eq!(riff_sizes(0).unwrap(), (36, 0));
+        let max = u64::from(u32::MAX) - 36;
+        assert_eq!(riff_sizes(max).unwrap(), (u32::MAX, max as u32));
+        assert!(riff_sizes(max + 1).is_err());
+        assert!(riff_sizes(u64::from(u32::MAX) + 1).is_err());
+        assert!(header(SampleFormat::S32, 8, u32::MAX, 0).is_err());
+    }
+
+    /// Build a WAV file with a `fmt ` chunk of `tag`, `channels`, `rate` and
+    /// `bits`, and a `data` chunk of `data`, with an odd-sized chunk between
+    /// them.  `extensible` stores the tag in a `WAVE_FORMAT_EXTENSIBLE`
+    /// sub-format.
+    fn wav_bytes(
+        tag: u16,
+        channels: u16,
+        rate: u32,
+        bits: u16,
+        data: &[u8],
+        extensible: bool,
+    ) -> Vec<u8> {
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(
+            &(if extensible { 0xFFFE } else { tag }).to_le_bytes(),
+        );
+        fmt.extend_from_slice(&channels.to_le_bytes());
+        fmt.extend_from_slice(&rate.to_le_bytes());
+        fmt.extend_from_slice(
+            &(rate * u32::from(bits / 8 * channels)).to_le_bytes(),
+        );
+        fmt.extend_from_slice(&(bits / 8 * channels).to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+        if extensible {
+            fmt.extend_from_slice(&22u16.to_le_bytes());
+            fmt.extend_from_slice(&bits.to_le_bytes());
+            fmt.extend_from_slice(&0u32.to_le_bytes());
+            fmt.extend_from_slice(&tag.to_le_bytes());
+            fmt.extend_from_slice(&[0; 14]);
+        }
+        let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+        for (id, body) in
+            [(b"fmt ", &fmt[..]), (b"LIST", b"odd"), (b"data", data)]
+        {
+            bytes.extend_from_slice(id);
+            bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(body);
+            if body.len() % 2 == 1 {
+                bytes.push(0);
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn decode_header() {
+        let data: Vec<u8> = [1000i16, -1000, 2000, -2000]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        for extensible in [false, true] {
+            let bytes = wav_bytes(1, 2, 22_050, 16, &data, extensible);
+            let wav = decode(&bytes).unwrap();
+            assert_eq!(wav.channels, 2);
+            assert_eq!(wav.sample_rate, 22_050);
+            assert_eq!(wav.samples.len(), 4);
+            assert_eq!(wav.samples[0], Ch32::new(1000.0 / 32_767.0));
+            assert_eq!(wav.samples[3], Ch32::new(-2000.0 / 32_767.0));
+        }
+        // A trailing partial frame is dropped.
+        let bytes = wav_bytes(1, 2, 22_050, 16, &data[..6], false);
+        assert_eq!(decode(&bytes).unwrap().samples.len(), 2);
+    }
+
+    #[test]
+    fn decode_unsupported() {
+        let data = [0; 8];
+        for (tag, channels, rate, bits) in [
+            (1, 1, 48_000, 8),
+            (3, 1, 48_000, 64),
+            (2, 1, 48_000, 16),
+            (1, 0, 48_000, 16),
+            (1, 9, 48_000, 16),
+            (1, 1, 0, 16),
+        ] {
+            let bytes = wav_bytes(tag, channels, rate, bits, &data, false);
+            let error = decode(&bytes).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        assert!(decode(b"RIFF\0\0\0\0AVI ").is_err());
+        assert!(decode(b"RIFF\0\0\0\0WAVE").is_err());
+        assert!(FileSource::new(&b"not a wav file"[..]).is_err());
+    }
+
+    /// Record up to `periods` periods from `source` with a mono microphone,
+    /// stopping early at the end of the stream.
+    #[cfg(feature = "testing")]
+    fn record(source: FileSource, periods: usize) -> (Vec<f32>, bool) {
+        use std::task::Poll::Ready;
+
+        use crate::{testing::poll_next, AudioError};
+
+        let mut microphone: Microphone<1> = source.microphone();
+        let mut recorded = Vec::new();
+        for _ in 0..periods {
+            match poll_next(&mut microphone) {
+                Ready(Ok(stream)) => recorded.extend(
+                    stream.frames().iter().map(|f| f32::from(f.channels()[0])),
+                ),
+                Ready(Err(AudioError::EndOfStream)) => return (recorded, true),
+                _ => panic!("file microphone not ready"),
+            }
+        }
+        (recorded, false)
+    }
+
+    /// Mono 16-bit WAV file of `samples` at `rate`.
+    #[cfg(feature = "testing")]
+    fn mono(rate: u32, samples: &[i16]) -> FileSource {
+        let data: Vec<u8> =
+            samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        FileSource::new(&wav_bytes(1, 1, rate, 16, &data, false)[..]).unwrap()
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn file_source_ends() {
+        let source = mono(48_000, &[16_384; 100]);
+        assert_eq!(source.channels(), 1);
+        assert_eq!(source.sample_rate(), 48_000);
+        let (recorded, ended) = record(source, 10);
+        assert!(ended);
+        assert_eq!(recorded.len(), 100);
+        assert!(recorded.iter().all(|&s| (s - 0.5).abs() < 0.001));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn file_source_loops() {
+        let samples: Vec<i16> = (0..100).map(|i| i * 100).collect();
+        let (recorded, ended) =
+            record(mono(48_000, &samples).with_looping(true), 5);
+        assert!(!ended);
+        assert!(recorded.len() > 200);
+        for (i, sample) in recorded.iter().enumerate() {
+            let expected = f32::from(samples[i % 100]) / 32_767.0;
+            assert!((sample - expected).abs() < 0.001, "frame {}", i);
+        }
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn file_source_resamples() {
+        let source = mono(24_000, &[16_384; 500]).with_sample_rate(48_000);
+        assert_eq!(source.sample_rate(), 48_000);
+        let (recorded, ended) = record(source, 100);
+        assert!(ended);
+        assert!((998..=1002).contains(&recorded.len()), "{}", recorded.len());
+        assert!(recorded[8..].iter().all(|&s| (s - 0.5).abs() < 0.01));
+    }
+}
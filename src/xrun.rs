@@ -0,0 +1,70 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+use std::fmt::{Debug, Formatter, Result};
+
+use crate::AudioError;
+
+/// Kind of xrun passed to [`XrunPolicy::Notify`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum XrunKind {
+    /// The speakers ran out of audio to play, so silence was played.
+    Underrun,
+    /// The microphone recorded more audio than was read, so some was lost.
+    Overrun,
+}
+
+/// What to do when [`Speakers`](crate::Speakers) underrun or a
+/// [`Microphone`](crate::Microphone) overruns.
+///
+/// Xruns are checked each time the device is polled, so they're handled on
+/// the task polling it (not the platform's audio thread).
+#[derive(Default)]
+pub enum XrunPolicy {
+    /// Recover and keep going (counted by `underruns()` or `overruns()`).
+    #[default]
+    Recover,
+    /// Call the closure once for each xrun, then recover.  It's called right
+    /// before the sink or stream is produced, so it should be quick (and not
+    /// block).
+    Notify(Box<dyn Fn(XrunKind)>),
+    /// Produce [`AudioError::Xrun`] instead of the next sink or stream.  The
+    /// device recovers, so polling it again keeps going.
+    Fail,
+}
+
+impl Debug for XrunPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Recover => f.write_str("Recover"),
+            Self::Notify(_) => f.write_str("Notify(..)"),
+            Self::Fail => f.write_str("Fail"),
+        }
+    }
+}
+
+impl XrunPolicy {
+    /// Handle `count` new xruns of `kind`.
+    pub(crate) fn apply(
+        &self,
+        count: usize,
+        kind: XrunKind,
+    ) -> std::result::Result<(), AudioError> {
+        if count == 0 {
+            return Ok(());
+        }
+        match self {
+            Self::Recover => Ok(()),
+            Self::Notify(notify) => {
+                (0..count).for_each(|_| notify(kind));
+                Ok(())
+            }
+            Self::Fail => Err(AudioError::Xrun),
+        }
+    }
+}
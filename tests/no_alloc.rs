@@ -0,0 +1,94 @@
+// Copyright © 2019-2022 The Wavy Contributors.
+//
+// Licensed under any of:
+// - Apache License, Version 2.0 (https://www.apache.org/licenses/LICENSE-2.0)
+// - Boost Software License, Version 1.0 (https://www.boost.org/LICENSE_1_0.txt)
+// - MIT License (https://mit-license.org/)
+// At your choosing (See accompanying files LICENSE_APACHE_2_0.txt,
+// LICENSE_MIT.txt and LICENSE_BOOST_1_0.txt).
+
+//! Check that playing audio doesn't allocate once the speakers are warmed up.
+//! This is its own test binary, since it replaces the global allocator.
+
+#![cfg(feature = "testing")]
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+};
+
+use fon::stereo::Stereo32;
+use pasts::Notifier;
+use wavy::{MockSpeakers, Speakers};
+
+/// Allocator that counts the allocations made by each thread.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(
+        &self,
+        ptr: *mut u8,
+        layout: Layout,
+        size: usize,
+    ) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+struct Noop;
+
+impl Wake for Noop {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// Play `periods` periods through `speakers`, changing the gain and muting
+/// along the way.  Returns the number of allocations after the first 10
+/// periods (the warm-up).
+fn play(speakers: &mut Speakers<2>, periods: usize) -> usize {
+    let waker = Waker::from(Arc::new(Noop));
+    let mut cx = Context::from_waker(&waker);
+    let mut allocations = 0;
+    for i in 0..periods {
+        if i == 10 {
+            allocations = ALLOCATIONS.with(Cell::get);
+        }
+        speakers.set_muted(i % 7 == 3);
+        let mut sink = match Pin::new(&mut *speakers).poll_next(&mut cx) {
+            Poll::Ready(Ok(sink)) => sink,
+            _ => panic!("mock speakers not ready"),
+        };
+        sink.set_gain(if i % 5 == 0 { 0.5 } else { 1.0 });
+        let mut sample = 0.0;
+        while sink.write_stereo(sample, -sample) {
+            sample = (sample + 0.01) % 1.0;
+        }
+    }
+    ALLOCATIONS.with(Cell::get) - allocations
+}
+
+#[test]
+fn no_allocations_after_warm_up() {
+    let mut mock = MockSpeakers::<Stereo32>::with_capacity(48_000, 1 << 20);
+    let mut speakers: Speakers<2> = mock.speakers();
+    assert_eq!(play(&mut speakers, 10_000), 0);
+}